@@ -0,0 +1,331 @@
+//! Distribution Spec smoke tests that run against a live registry over HTTP.
+//!
+//! Each check pushes its own uniquely-generated content using the same [`Image`] and [`Layer`]
+//! types used to exercise backends in-process, so checks are independent of each other and of
+//! whatever is already stored in the target registry.
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use headers::authorization::{Authorization, Basic};
+use headers::HeaderMapExt;
+use hyper::body::Body;
+use hyper::client::HttpConnector;
+use hyper::header;
+use hyper::http::request::Builder;
+use hyper::{Client, Method, Request, Response, StatusCode};
+use oci_spec::distribution::TagList;
+use oci_spec::image::{ImageIndex, MediaType};
+
+use portfolio_core::OciDigest;
+
+use super::errors::{Error, Result};
+use super::{Image, Layer, ManifestReference};
+
+/// Outcome of a single conformance check.
+#[derive(Debug)]
+pub struct Check {
+    pub name: &'static str,
+    pub result: Result<()>,
+}
+
+/// Runs conformance checks against the registry at `base_url`, pushing content to `repository`.
+pub struct ConformanceClient {
+    base_url: String,
+    repository: String,
+    client: Client<HttpConnector>,
+    credentials: Option<Authorization<Basic>>,
+    seed: u128,
+    counter: AtomicUsize,
+}
+
+impl ConformanceClient {
+    pub fn new(base_url: &str, repository: &str) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            repository: repository.to_string(),
+            client: Client::new(),
+            credentials: None,
+            // make generated content unique to this run so that content left over from earlier
+            // runs can't make checks pass (or fail) spuriously
+            seed: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .expect("system time should be after the unix epoch")
+                .as_nanos(),
+            counter: AtomicUsize::new(0),
+        }
+    }
+
+    /// Authenticate every request with the given Basic credentials, for registries configured
+    /// with an `htpasswd_file`.
+    pub fn with_credentials(mut self, username: &str, password: &str) -> Self {
+        self.credentials = Some(Authorization::basic(username, password));
+        self
+    }
+
+    /// Run every check, in order, returning the outcome of each.
+    pub async fn run(&self) -> Vec<Check> {
+        vec![
+            Check {
+                name: "push blob",
+                result: self.check_push_blob().await,
+            },
+            Check {
+                name: "push manifest",
+                result: self.check_push_manifest().await,
+            },
+            Check {
+                name: "list tags",
+                result: self.check_list_tags().await,
+            },
+            Check {
+                name: "get referrers",
+                result: self.check_get_referrers().await,
+            },
+            Check {
+                name: "delete manifest",
+                result: self.check_delete_manifest().await,
+            },
+        ]
+    }
+
+    async fn check_push_blob(&self) -> Result<()> {
+        let data = self.unique("blob");
+        let digest = self.push_blob(data.as_bytes()).await?;
+
+        let path = format!("blobs/{}", String::from(&digest));
+        self.send(Method::HEAD, &path, None, Vec::new(), StatusCode::OK)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn check_push_manifest(&self) -> Result<()> {
+        let tag = self.unique("tag");
+        let mut image = self.image(ManifestReference::Tag(tag));
+        let digest = self.push_image(&mut image).await?;
+
+        let path = format!("manifests/{}", String::from(&digest));
+        let response = self
+            .send(Method::GET, &path, None, Vec::new(), StatusCode::OK)
+            .await?;
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        if OciDigest::from(body.as_ref()) != digest {
+            return Err(Error::ConformanceCheckFailed(format!(
+                "manifest pulled by digest {} doesn't match what was pushed",
+                String::from(&digest)
+            )));
+        }
+
+        Ok(())
+    }
+
+    async fn check_list_tags(&self) -> Result<()> {
+        let tag = self.unique("tag");
+        let mut image = self.image(ManifestReference::Tag(tag.clone()));
+        self.push_image(&mut image).await?;
+
+        // the new tag may land on any page, so follow the listing to the end
+        let mut last: Option<String> = None;
+        loop {
+            let path = match &last {
+                Some(last) => format!("tags/list?n=100&last={last}"),
+                None => "tags/list?n=100".to_string(),
+            };
+            let response = self
+                .send(Method::GET, &path, None, Vec::new(), StatusCode::OK)
+                .await?;
+            let body = hyper::body::to_bytes(response.into_body()).await?;
+            let tags: TagList = serde_json::from_slice(&body)?;
+            if tags.tags().contains(&tag) {
+                return Ok(());
+            }
+            match tags.tags().last() {
+                Some(t) if tags.tags().len() == 100 => last = Some(t.clone()),
+                _ => break,
+            }
+        }
+
+        Err(Error::ConformanceCheckFailed(format!(
+            "pushed tag {tag} missing from tag listing"
+        )))
+    }
+
+    async fn check_get_referrers(&self) -> Result<()> {
+        let mut subject = self.image(ManifestReference::Digest);
+        let subject_digest = self.push_image(&mut subject).await?;
+
+        let mut referrer = self.image(ManifestReference::Digest);
+        referrer.artifact_type = Some(MediaType::Other(
+            "application/vnd.portfolio.conformance".to_string(),
+        ));
+        referrer.subject = Some(subject.descriptor());
+        let referrer_digest = self.push_image(&mut referrer).await?;
+
+        let path = format!("referrers/{}", String::from(&subject_digest));
+        let response = self
+            .send(Method::GET, &path, None, Vec::new(), StatusCode::OK)
+            .await?;
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        let index: ImageIndex = serde_json::from_slice(&body)?;
+        let referrer_digest = String::from(&referrer_digest);
+        if !index
+            .manifests()
+            .iter()
+            .any(|d| d.digest() == &referrer_digest)
+        {
+            return Err(Error::ConformanceCheckFailed(format!(
+                "referrer {referrer_digest} missing from referrers listing"
+            )));
+        }
+
+        Ok(())
+    }
+
+    async fn check_delete_manifest(&self) -> Result<()> {
+        let mut image = self.image(ManifestReference::Digest);
+        let digest = self.push_image(&mut image).await?;
+
+        let path = format!("manifests/{}", String::from(&digest));
+        self.send(
+            Method::DELETE,
+            &path,
+            None,
+            Vec::new(),
+            StatusCode::ACCEPTED,
+        )
+        .await?;
+        self.send(Method::GET, &path, None, Vec::new(), StatusCode::NOT_FOUND)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Generate a string that is unique to this client and run.
+    fn unique(&self, prefix: &str) -> String {
+        let n = self.counter.fetch_add(1, Ordering::SeqCst);
+        format!("{prefix}-{}-{n}", self.seed)
+    }
+
+    fn image(&self, manifest_ref: ManifestReference) -> Image {
+        Image {
+            manifest_ref,
+            layers: vec![Arc::new(Mutex::new(Layer {
+                data: self.unique("layer"),
+                ..Default::default()
+            }))],
+            ..Default::default()
+        }
+    }
+
+    /// Push the image's layers, config, and manifest, returning the manifest digest.
+    async fn push_image(&self, image: &mut Image) -> Result<OciDigest> {
+        for layer in &image.layers {
+            let data = layer.lock().unwrap().data.clone();
+            self.push_blob(data.as_bytes()).await?;
+        }
+        let config = serde_json::to_vec(&image.config())?;
+        self.push_blob(&config).await?;
+
+        let manifest = serde_json::to_vec(&image.manifest())?;
+        let digest = image.digest();
+        let reference = match &image.manifest_ref {
+            ManifestReference::Digest => String::from(&digest),
+            ManifestReference::Tag(tag) => tag.clone(),
+        };
+        self.send(
+            Method::PUT,
+            &format!("manifests/{reference}"),
+            Some(MediaType::ImageManifest.to_string().as_str()),
+            manifest,
+            StatusCode::CREATED,
+        )
+        .await?;
+
+        Ok(digest)
+    }
+
+    /// Push a blob using a POST-PUT monolithic upload, returning its digest.
+    async fn push_blob(&self, data: &[u8]) -> Result<OciDigest> {
+        let digest = OciDigest::from(data);
+
+        let response = self
+            .send(
+                Method::POST,
+                "blobs/uploads/",
+                None,
+                Vec::new(),
+                StatusCode::ACCEPTED,
+            )
+            .await?;
+        let location = response
+            .headers()
+            .get(header::LOCATION)
+            .and_then(|l| l.to_str().ok())
+            .ok_or_else(|| {
+                Error::ConformanceCheckFailed("upload session missing Location".to_string())
+            })?;
+        let separator = if location.contains('?') { '&' } else { '?' };
+        let url = format!(
+            "{}{separator}digest={}",
+            self.url(location),
+            String::from(&digest)
+        );
+
+        let request = self
+            .request(Method::PUT, url)
+            .header(header::CONTENT_TYPE, "application/octet-stream")
+            .body(Body::from(data.to_vec()))?;
+        expect_status(self.client.request(request).await?, StatusCode::CREATED)?;
+
+        Ok(digest)
+    }
+
+    /// Resolve a `Location` header, which may be relative to the registry root, to a full URL.
+    fn url(&self, location: &str) -> String {
+        if location.starts_with('/') {
+            format!("{}{location}", self.base_url)
+        } else {
+            location.to_string()
+        }
+    }
+
+    /// Send a request for `path`, relative to the repository's API root, failing unless the
+    /// response has the `expected` status.
+    async fn send(
+        &self,
+        method: Method,
+        path: &str,
+        content_type: Option<&str>,
+        body: Vec<u8>,
+        expected: StatusCode,
+    ) -> Result<Response<Body>> {
+        let mut builder = self.request(
+            method,
+            format!("{}/v2/{}/{path}", self.base_url, self.repository),
+        );
+        if let Some(content_type) = content_type {
+            builder = builder.header(header::CONTENT_TYPE, content_type);
+        }
+        let response = self.client.request(builder.body(Body::from(body))?).await?;
+        expect_status(response, expected)
+    }
+
+    /// Start building a request, authenticated if the client has credentials.
+    fn request(&self, method: Method, url: String) -> Builder {
+        let mut builder = Request::builder().method(method).uri(url);
+        if let (Some(credentials), Some(headers)) = (&self.credentials, builder.headers_mut()) {
+            headers.typed_insert(credentials.clone());
+        }
+        builder
+    }
+}
+
+fn expect_status(response: Response<Body>, expected: StatusCode) -> Result<Response<Body>> {
+    if response.status() != expected {
+        return Err(Error::ConformanceCheckFailed(format!(
+            "expected status {expected}, got {}",
+            response.status()
+        )));
+    }
+    Ok(response)
+}
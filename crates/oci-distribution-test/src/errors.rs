@@ -39,4 +39,13 @@ pub enum Error {
 
     #[error("pushed index not found in repository: {0:?}")]
     PushedIndexNotInPulledIndices(portfolio_core::registry::ManifestRef),
+
+    #[error("{0}")]
+    HyperError(#[from] hyper::Error),
+
+    #[error("{0}")]
+    HttpError(#[from] hyper::http::Error),
+
+    #[error("conformance check failed: {0}")]
+    ConformanceCheckFailed(String),
 }
@@ -1,16 +1,18 @@
+use std::collections::HashMap;
 use std::sync::Arc;
 use std::sync::Mutex;
 
 use oci_spec::image::{
     Arch, Descriptor, DescriptorBuilder, History, ImageConfiguration, ImageConfigurationBuilder,
     ImageIndex, ImageIndexBuilder, ImageManifest, ImageManifestBuilder, MediaType, Os,
-    RootFsBuilder,
+    PlatformBuilder, RootFsBuilder,
 };
 use serde::{Deserialize, Serialize};
 
 use portfolio_core::registry::ManifestRef;
 use portfolio_core::OciDigest;
 
+pub mod conformance;
 mod errors;
 mod loader;
 mod testdata;
@@ -74,6 +76,7 @@ pub struct Image {
     pub layers: Vec<Arc<Mutex<Layer>>>,
     pub artifact_type: Option<MediaType>,
     pub subject: Option<Descriptor>,
+    pub annotations: Option<HashMap<String, String>>,
 
     #[serde(skip)]
     pub(crate) tags: Vec<String>,
@@ -169,6 +172,10 @@ impl Image {
             manifest_builder = manifest_builder.subject(subject.clone());
         }
 
+        if let Some(ref annotations) = self.annotations {
+            manifest_builder = manifest_builder.annotations(annotations.clone());
+        }
+
         let manifest = manifest_builder
             .build()
             .expect("must set all required fields for image manifest");
@@ -231,7 +238,17 @@ impl Index {
         let manifest_descriptors = self
             .manifests
             .iter_mut()
-            .map(|m| m.lock().unwrap().descriptor())
+            .map(|m| {
+                let mut image = m.lock().unwrap();
+                let mut descriptor = image.descriptor();
+                let platform = PlatformBuilder::default()
+                    .os(image.os.clone())
+                    .architecture(image.architecture.clone())
+                    .build()
+                    .expect("must set all required fields for platform");
+                descriptor.set_platform(Some(platform));
+                descriptor
+            })
             .collect::<Vec<Descriptor>>();
 
         let mut manifest_builder = ImageIndexBuilder::default()
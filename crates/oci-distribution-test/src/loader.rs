@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::sync::Mutex;
 
@@ -12,6 +13,7 @@ use oci_spec::image::ImageIndex;
 use oci_spec::image::ImageManifest;
 use oci_spec::image::MediaType;
 use oci_spec::image::{Descriptor, DescriptorBuilder};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 use tokio::task::JoinSet;
 
 use portfolio_core::registry::BlobStore;
@@ -19,6 +21,7 @@ use portfolio_core::registry::BoxedRepositoryStoreManager;
 use portfolio_core::registry::ManifestRef;
 use portfolio_core::registry::ManifestSpec;
 use portfolio_core::registry::ManifestStore;
+use portfolio_core::registry::UploadSessionStore;
 use portfolio_core::registry::{BoxedRepositoryStore, RepositoryStoreManager};
 use portfolio_core::OciDigest;
 
@@ -28,16 +31,57 @@ use super::{Image, Index, Layer};
 pub(crate) type ArcRepositoryStoreManager = Arc<dyn RepositoryStoreManager + Send + Sync>;
 pub(crate) type ArcManifestStore = Arc<dyn ManifestStore + Send + Sync>;
 pub(crate) type ArcBlobStore = Arc<dyn BlobStore + Send + Sync>;
+pub(crate) type ArcUploadSessionStore = Arc<dyn UploadSessionStore + Send + Sync>;
 
 #[derive(Clone)]
 pub struct RepositoryLoader {
     mgr: ArcRepositoryStoreManager,
+    // bounds the number of images pushed or pulled at once, unbounded when None
+    concurrency: Option<Arc<Semaphore>>,
+    // the number of images being pushed or pulled right now and the most there have been at once
+    in_flight: Arc<AtomicUsize>,
+    max_in_flight: Arc<AtomicUsize>,
 }
 
 impl RepositoryLoader {
     pub fn new(mgr: BoxedRepositoryStoreManager) -> Self {
         Self {
             mgr: Arc::from(mgr),
+            concurrency: None,
+            in_flight: Arc::new(AtomicUsize::new(0)),
+            max_in_flight: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Limit the number of images pushed or pulled at once, including the images of any indices
+    /// being pushed or pulled.
+    pub fn with_concurrency(mut self, limit: usize) -> Self {
+        self.concurrency = Some(Arc::new(Semaphore::new(limit)));
+        self
+    }
+
+    /// The most images this loader (or any of its clones) has pushed or pulled at once.
+    pub fn max_in_flight(&self) -> usize {
+        self.max_in_flight.load(Ordering::SeqCst)
+    }
+
+    /// Wait for a permit to proceed if concurrency is limited; the image counts as in flight
+    /// until the returned guard is dropped.
+    async fn admit(&self) -> InFlight {
+        let permit = match self.concurrency.clone() {
+            Some(semaphore) => Some(
+                semaphore
+                    .acquire_owned()
+                    .await
+                    .expect("the semaphore is never closed"),
+            ),
+            None => None,
+        };
+        let n = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        self.max_in_flight.fetch_max(n, Ordering::SeqCst);
+        InFlight {
+            _permit: permit,
+            in_flight: self.in_flight.clone(),
         }
     }
 
@@ -57,11 +101,19 @@ impl RepositoryLoader {
         Arc::from(repo_store.get_blob_store())
     }
 
+    pub async fn get_upload_session_store(&self, repo_name: &str) -> ArcUploadSessionStore {
+        let repo_store = self
+            .get_or_create_repo(repo_name)
+            .await
+            .expect("must be able to get or create repo");
+        Arc::from(repo_store.get_upload_session_store())
+    }
+
     pub async fn get_or_create_repo(&self, name: &str) -> Result<BoxedRepositoryStore> {
         if let Some(repo) = self.mgr.get(name).await? {
             Ok(repo)
         } else {
-            Ok(self.mgr.create("repo_1").await?)
+            Ok(self.mgr.create(name).await?)
         }
     }
 
@@ -130,6 +182,7 @@ impl RepositoryLoader {
                 &manifest_ref,
                 &ManifestSpec::Image(manifest),
                 Bytes::from(manifest_bytes),
+                None,
             )
             .await?;
         Ok(())
@@ -164,11 +217,16 @@ impl RepositoryLoader {
             let manifest_store = manifest_store.clone();
             let blob_store = blob_store.clone();
             let image = image.clone();
-            set.spawn(async move { Self::upload_image(manifest_store, blob_store, image).await });
+            let loader = self.clone();
+            set.spawn(async move {
+                let _in_flight = loader.admit().await;
+                Self::upload_image(manifest_store, blob_store, image).await
+            });
         }
         while let Some(res) = set.join_next().await {
             match res {
                 Err(e) => return Err(e.into()),
+                Ok(Err(e)) => return Err(e),
                 _ => (),
             }
         }
@@ -203,6 +261,7 @@ impl RepositoryLoader {
                 &manifest_ref,
                 &ManifestSpec::Index(manifest),
                 Bytes::from(manifest_bytes),
+                None,
             )
             .await?;
         Ok(())
@@ -315,6 +374,7 @@ impl RepositoryLoader {
             architecture: image_config.architecture().to_owned(),
             artifact_type: manifest.artifact_type().clone(),
             subject: manifest.subject().clone(),
+            annotations: manifest.annotations().clone(),
             layers,
             tags,
             config: Some(image_config),
@@ -390,6 +450,7 @@ impl RepositoryLoader {
             let manifest_ref = manifest_ref.clone();
             let name = name.to_string();
             set.spawn(async move {
+                let _in_flight = loader.admit().await;
                 let image = loader.pull_image(name, &manifest_ref).await?;
                 Ok(image)
             });
@@ -443,3 +504,15 @@ impl RepositoryLoader {
         Ok(pulled_images)
     }
 }
+
+/// An image being pushed or pulled, holding its concurrency permit if any.
+struct InFlight {
+    _permit: Option<OwnedSemaphorePermit>,
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
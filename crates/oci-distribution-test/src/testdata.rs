@@ -1,7 +1,12 @@
+use std::sync::{Arc, Mutex};
+
 use lazy_static::lazy_static;
+use oci_spec::image::{Arch, MediaType, Os};
 
 use super::Image;
 use super::Index;
+use super::Layer;
+use super::ManifestReference;
 
 lazy_static! {
     pub static ref BASIC_IMAGES: Vec<Image> = initialize_basic_images();
@@ -83,6 +88,88 @@ fn initialize_basic_indices() -> Vec<Index> {
     .expect("expect valid image")
 }
 
+/// Generate `count` artifact images whose subject is the given image. `seed` distinguishes the
+/// contents (and therefore digests) of referrers generated by separate calls.
+pub fn referrers_of(subject: &mut Image, seed: &str, count: usize) -> Vec<Image> {
+    let descriptor = subject.descriptor();
+    (0..count)
+        .map(|i| Image {
+            layers: vec![Arc::new(Mutex::new(Layer {
+                data: format!("referrer {seed} {i}"),
+                ..Default::default()
+            }))],
+            artifact_type: Some(MediaType::Other(
+                "application/vnd.portfolio.test.referrer".to_string(),
+            )),
+            subject: Some(descriptor.clone()),
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// Generate `count` distinct images tagged `{prefix}-0` through `{prefix}-{count - 1}`.
+pub fn tagged_images(prefix: &str, count: usize) -> Vec<Image> {
+    (0..count)
+        .map(|i| Image {
+            manifest_ref: ManifestReference::Tag(format!("{prefix}-{i}")),
+            layers: vec![Arc::new(Mutex::new(Layer {
+                data: format!("layer for {prefix}-{i}"),
+                ..Default::default()
+            }))],
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// Generate `count` distinct images that are all tagged `tag`.
+pub fn retagged_images(tag: &str, count: usize) -> Vec<Image> {
+    (0..count)
+        .map(|i| Image {
+            manifest_ref: ManifestReference::Tag(tag.to_string()),
+            layers: vec![Arc::new(Mutex::new(Layer {
+                data: format!("layer {i} for {tag}"),
+                ..Default::default()
+            }))],
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// Generate an index with one image for each of a large number of distinct platforms.
+pub fn multi_platform_index() -> Index {
+    let oses = [Os::Linux, Os::Windows, Os::Darwin, Os::FreeBSD];
+    let arches = [
+        Arch::Amd64,
+        Arch::ARM64,
+        Arch::ARM,
+        Arch::i386,
+        Arch::PowerPC64le,
+        Arch::s390x,
+        Arch::RISCV64,
+        Arch::Mips64le,
+    ];
+    let manifests = oses
+        .iter()
+        .flat_map(|os| arches.iter().map(move |arch| (os.clone(), arch.clone())))
+        .map(|(os, architecture)| Image {
+            layers: vec![Arc::new(Mutex::new(Layer {
+                data: format!("layer for {os}/{architecture}"),
+                ..Default::default()
+            }))],
+            os,
+            architecture,
+            ..Default::default()
+        })
+        .map(Mutex::new)
+        .map(Arc::new)
+        .collect();
+
+    Index {
+        manifests,
+        ..Default::default()
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -104,6 +191,15 @@ mod test {
         }
     }
 
+    #[test]
+    fn validate_multi_platform_index() {
+        let mut index = multi_platform_index();
+        let index_manifest = index.manifest();
+        for descriptor in index_manifest.manifests() {
+            assert!(descriptor.platform().is_some());
+        }
+    }
+
     #[test]
     fn validate_empty_image() {
         let mut image = EMPTY_IMAGE.clone();
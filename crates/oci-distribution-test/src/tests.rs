@@ -1,11 +1,20 @@
 #![allow(dead_code)]
+use std::collections::HashSet;
 use std::sync::Arc;
 use std::sync::Mutex;
 
+use bytes::BytesMut;
+use futures::stream::TryStreamExt;
+use hyper::body::Body;
+
 use portfolio_core::registry::ManifestRef;
+use portfolio_core::Digester;
+use portfolio_core::Error as CoreError;
+use portfolio_core::OciDigest;
 
 use super::errors::{Error, Result};
 use super::loader::RepositoryLoader;
+use super::testdata;
 use super::Image;
 use super::Index;
 
@@ -116,21 +125,510 @@ impl RepositoryTester {
 
         Ok(())
     }
+
+    /// Push an index covering many platforms and verify each platform resolves to the matching
+    /// child manifest.
+    pub async fn resolve_index_platforms(&self, index: Index) -> Result<()> {
+        let index = Arc::new(Mutex::new(index));
+        self.loader
+            .upload_indices("testrepo".to_string(), vec![index.clone()])
+            .await?;
+
+        let mut index = index.lock().unwrap().clone();
+        let index_ref = index.manifest_ref();
+        let mstore = self.loader.get_manifest_store("testrepo").await;
+
+        for descriptor in index.manifest().manifests() {
+            let platform = descriptor
+                .platform()
+                .clone()
+                .expect("test index descriptors always include a platform");
+            let resolved = mstore
+                .get_platform_manifest(&index_ref, &platform)
+                .await?
+                .ok_or_else(|| Error::ManifestNotFound(format!("{platform:?}")))?;
+            assert_eq!(
+                String::from(resolved.digest()).as_str(),
+                descriptor.digest().as_str()
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Page through the referrers of `subject` while pushing a new referrer between pages,
+    /// verifying that no referrer is returned twice and none of the originals are skipped.
+    pub async fn paginate_referrers_while_pushing(&self, mut subject: Image) -> Result<()> {
+        let subject_digest = subject.digest();
+        let referrers = testdata::referrers_of(&mut subject, "initial", 4);
+        let late_referrers = testdata::referrers_of(&mut subject, "late", 1);
+        let mut expected: HashSet<OciDigest> = HashSet::new();
+        let referrers = referrers
+            .into_iter()
+            .map(|mut r| {
+                expected.insert(r.digest());
+                Arc::new(Mutex::new(r))
+            })
+            .collect::<Vec<_>>();
+
+        self.loader
+            .clone()
+            .upload_images("testrepo".to_string(), vec![Arc::new(Mutex::new(subject))])
+            .await?;
+        self.loader
+            .clone()
+            .upload_images("testrepo".to_string(), referrers)
+            .await?;
+
+        let mstore = self.loader.get_manifest_store("testrepo").await;
+        let mut seen: Vec<OciDigest> = Vec::new();
+        let mut last: Option<String> = None;
+        let mut late_referrers = Some(late_referrers);
+        loop {
+            let page = mstore
+                .get_referrers(&subject_digest, None, Some(2), last.clone())
+                .await?;
+            if page.manifests().is_empty() {
+                break;
+            }
+            for d in page.manifests() {
+                seen.push(d.digest().as_str().try_into()?);
+            }
+            last = page.manifests().last().map(|d| d.digest().clone());
+
+            if let Some(late) = late_referrers.take() {
+                self.loader
+                    .clone()
+                    .upload_images(
+                        "testrepo".to_string(),
+                        late.into_iter().map(Mutex::new).map(Arc::new).collect(),
+                    )
+                    .await?;
+            }
+        }
+
+        let unique: HashSet<OciDigest> = seen.iter().cloned().collect();
+        assert_eq!(
+            unique.len(),
+            seen.len(),
+            "referrers returned more than once"
+        );
+        assert!(expected.is_subset(&unique), "referrers skipped");
+
+        Ok(())
+    }
+
+    /// Upload a blob in two chunks then attempt to finalize it with a digest that doesn't match
+    /// the uploaded content, verifying that the upload is rejected and nothing is stored.
+    pub async fn finalize_with_wrong_digest(&self) -> Result<()> {
+        let chunks = ["first chunk of a mismatched blob, ", "second chunk"];
+        let actual = OciDigest::from(chunks.concat().as_bytes());
+        let wrong = OciDigest::from("some other content".as_bytes());
+
+        let session_store = self.loader.get_upload_session_store("testrepo").await;
+        let blob_store = self.loader.get_blob_store("testrepo").await;
+        let session = session_store.new_upload_session().await?;
+
+        let mut start = 0;
+        for chunk in chunks {
+            let mut writer = blob_store.resume(session.uuid(), Some(start)).await?;
+            let session = writer
+                .write(chunk.len() as u64, Body::from(chunk), None)
+                .await?;
+            start = session.last_range_end() as u64 + 1;
+        }
+
+        let mut writer = blob_store.resume(session.uuid(), None).await?;
+        match writer.finalize(&wrong).await {
+            Err(CoreError::DigestInvalid(_)) => (),
+            Err(e) => panic!("expected DigestInvalid, got {e:?}"),
+            Ok(_) => panic!("expected DigestInvalid, got a finalized session"),
+        }
+
+        assert!(blob_store.head(&wrong).await?.is_none());
+        assert!(blob_store.head(&actual).await?.is_none());
+        assert!(session_store
+            .get_upload_session(session.uuid())
+            .await
+            .is_err());
+
+        Ok(())
+    }
+
+    /// Push an image whose blobs add up to more than the configured `max_image_size`, verifying
+    /// that the push is denied.
+    pub async fn push_oversized_image(&self, image: Image) -> Result<()> {
+        match self
+            .loader
+            .clone()
+            .upload_images("testrepo".to_string(), vec![Arc::new(Mutex::new(image))])
+            .await
+        {
+            Err(Error::CoreError(CoreError::Denied(_))) => Ok(()),
+            Err(e) => panic!("expected Denied, got {e:?}"),
+            Ok(_) => panic!("expected Denied, got a successful push"),
+        }
+    }
+
+    /// Push an image referring to its layers by a digest algorithm the store doesn't allow,
+    /// verifying that the push is rejected as an invalid manifest.
+    pub async fn push_image_with_disallowed_algorithm(&self, image: Image) -> Result<()> {
+        match self
+            .loader
+            .clone()
+            .upload_images("testrepo".to_string(), vec![Arc::new(Mutex::new(image))])
+            .await
+        {
+            Err(Error::CoreError(CoreError::ManifestInvalid(_))) => Ok(()),
+            Err(e) => panic!("expected ManifestInvalid, got {e:?}"),
+            Ok(_) => panic!("expected ManifestInvalid, got a successful push"),
+        }
+    }
+
+    /// Write a chunk whose body is shorter than its declared length, verifying that it is rejected
+    /// without advancing the upload session.
+    pub async fn write_short_chunk(&self) -> Result<()> {
+        let session_store = self.loader.get_upload_session_store("testrepo").await;
+        let blob_store = self.loader.get_blob_store("testrepo").await;
+        let session = session_store.new_upload_session().await?;
+
+        let mut writer = blob_store.resume(session.uuid(), Some(0)).await?;
+        match writer.write(100, Body::from(vec![0u8; 50]), None).await {
+            Err(CoreError::SizeInvalid(_)) => (),
+            Err(e) => panic!("expected SizeInvalid, got {e:?}"),
+            Ok(_) => panic!("expected SizeInvalid, got an updated session"),
+        }
+
+        let after = session_store.get_upload_session(session.uuid()).await?;
+        assert_eq!(after.last_range_end(), session.last_range_end());
+
+        session_store.delete_session(session.uuid()).await?;
+
+        Ok(())
+    }
+
+    /// Write a chunk along with a chunk digest that doesn't match its content, verifying that it
+    /// is rejected without advancing the upload session and that the chunk can then be retried.
+    pub async fn write_corrupted_chunk(&self) -> Result<()> {
+        let chunk = "a chunk that gets corrupted in transit";
+        let corrupted = "a chunk that got corrupted in transit";
+        let chunk_digest = OciDigest::from(chunk.as_bytes());
+
+        let session_store = self.loader.get_upload_session_store("testrepo").await;
+        let blob_store = self.loader.get_blob_store("testrepo").await;
+        let session = session_store.new_upload_session().await?;
+
+        let mut writer = blob_store.resume(session.uuid(), Some(0)).await?;
+        match writer
+            .write(
+                corrupted.len() as u64,
+                Body::from(corrupted),
+                Some(&chunk_digest),
+            )
+            .await
+        {
+            Err(CoreError::BlobUploadInvalid(_)) => (),
+            Err(e) => panic!("expected BlobUploadInvalid, got {e:?}"),
+            Ok(_) => panic!("expected BlobUploadInvalid, got an updated session"),
+        }
+
+        let after = session_store.get_upload_session(session.uuid()).await?;
+        assert_eq!(after.last_range_end(), session.last_range_end());
+
+        let mut writer = blob_store.resume(session.uuid(), Some(0)).await?;
+        writer
+            .write(chunk.len() as u64, Body::from(chunk), Some(&chunk_digest))
+            .await?;
+        let mut writer = blob_store.resume(session.uuid(), None).await?;
+        writer.finalize(&chunk_digest).await?;
+        self.assert_blob_content(&chunk_digest, chunk.as_bytes())
+            .await?;
+
+        blob_store.delete(&chunk_digest).await?;
+
+        Ok(())
+    }
+
+    /// Push an image to one repository and mount one of its layers into another, verifying that
+    /// blobs only mount from repositories that reference them.
+    pub async fn mount_blob_across_repositories(&self, image: Image) -> Result<()> {
+        let layer = image.layers[0].lock().unwrap().descriptor();
+        let layer_digest: OciDigest = layer.digest().as_str().try_into()?;
+        let unknown_digest = OciDigest::from("never pushed anywhere".as_bytes());
+
+        self.loader
+            .clone()
+            .upload_images(
+                "mount-source".to_string(),
+                vec![Arc::new(Mutex::new(image))],
+            )
+            .await?;
+
+        let target = self.loader.get_blob_store("mount-target").await;
+        let mounted = target
+            .mount(&layer_digest, "mount-source")
+            .await?
+            .expect("layer should mount from the repository that references it");
+        assert_eq!(mounted.bytes_on_disk(), layer.size() as u64);
+
+        // the mounted blob is available in the target repository before any manifest there
+        // refers to it, so it can be mounted from there in turn
+        let onward = self.loader.get_blob_store("mount-onward").await;
+        assert!(onward.mount(&layer_digest, "mount-target").await?.is_some());
+
+        assert!(target
+            .mount(&unknown_digest, "mount-source")
+            .await?
+            .is_none());
+        assert!(target
+            .mount(&layer_digest, "mount-nonexistent")
+            .await?
+            .is_none());
+
+        Ok(())
+    }
+
+    /// Upload the empty blob through both the chunked and monolithic upload flows, verifying that
+    /// it can be retrieved after each.
+    pub async fn upload_zero_byte_blob(&self) -> Result<()> {
+        let digest = OciDigest::from("".as_bytes());
+        let session_store = self.loader.get_upload_session_store("testrepo").await;
+        let blob_store = self.loader.get_blob_store("testrepo").await;
+
+        // other tests may have left the empty blob behind
+        if blob_store.head(&digest).await?.is_some() {
+            blob_store.delete(&digest).await?;
+        }
+
+        // POST-PATCH-PUT with an empty chunk
+        let session = session_store.new_upload_session().await?;
+        let mut writer = blob_store.resume(session.uuid(), Some(0)).await?;
+        let session = writer.write(0, Body::empty(), None).await?;
+        assert_eq!(session.last_range_end(), 0);
+        // nothing was written, so the object store upload hasn't been started
+        assert!(session.upload_id().is_none());
+        let mut writer = blob_store.resume(session.uuid(), None).await?;
+        writer.finalize(&digest).await?;
+        self.assert_empty_blob(&digest).await?;
+
+        blob_store.delete(&digest).await?;
+        assert!(blob_store.head(&digest).await?.is_none());
+
+        // monolithic
+        blob_store.put(&digest, 0, Body::empty()).await?;
+        self.assert_empty_blob(&digest).await?;
+
+        Ok(())
+    }
+
+    async fn assert_empty_blob(&self, digest: &OciDigest) -> Result<()> {
+        let blob_store = self.loader.get_blob_store("testrepo").await;
+        let (blob, stream) = blob_store
+            .get(digest)
+            .await?
+            .expect("empty blob should have been stored");
+        assert_eq!(blob.bytes_on_disk(), 0);
+        let bytes: BytesMut = stream
+            .try_collect()
+            .await
+            .map_err(|e| Error::StreamCollectFailed(format!("{e:?}")))?;
+        assert!(bytes.is_empty());
+
+        Ok(())
+    }
+
+    /// Push sha512-addressed blobs both monolithically and in chunks, verifying that each can be
+    /// pulled back by its sha512 digest with its content intact. The chunked upload's session is
+    /// started for its digest if `declare`, otherwise the store must be configured to calculate
+    /// sha512 digests of uploads.
+    pub async fn push_and_pull_sha512_blobs(&self, declare: bool) -> Result<()> {
+        // make the content unique to this run so that earlier runs can't have stored it already
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .expect("system time should be after the unix epoch")
+            .as_nanos();
+        let session_store = self.loader.get_upload_session_store("testrepo").await;
+        let blob_store = self.loader.get_blob_store("testrepo").await;
+
+        // monolithic
+        let content = format!("monolithic sha512 blob {seed}");
+        let digest = sha512(content.as_bytes());
+        blob_store
+            .put(&digest, content.len() as u64, Body::from(content.clone()))
+            .await?;
+        self.assert_blob_content(&digest, content.as_bytes())
+            .await?;
+
+        // POST-PATCH-PUT
+        let chunks = [
+            format!("first chunk of a sha512 blob {seed}, "),
+            "second chunk".to_string(),
+        ];
+        let digest = sha512(chunks.concat().as_bytes());
+        let session = if declare {
+            session_store.new_upload_session_for(&digest).await?
+        } else {
+            session_store.new_upload_session().await?
+        };
+        let mut start = 0;
+        for chunk in &chunks {
+            let mut writer = blob_store.resume(session.uuid(), Some(start)).await?;
+            let session = writer
+                .write(chunk.len() as u64, Body::from(chunk.clone()), None)
+                .await?;
+            start = session.last_range_end() as u64 + 1;
+        }
+        let mut writer = blob_store.resume(session.uuid(), None).await?;
+        writer.finalize(&digest).await?;
+        self.assert_blob_content(&digest, chunks.concat().as_bytes())
+            .await?;
+
+        Ok(())
+    }
+
+    async fn assert_blob_content(&self, digest: &OciDigest, expected: &[u8]) -> Result<()> {
+        let blob_store = self.loader.get_blob_store("testrepo").await;
+        let (blob, stream) = blob_store
+            .get(digest)
+            .await?
+            .expect("blob should have been stored");
+        assert_eq!(blob.bytes_on_disk(), expected.len() as u64);
+        let bytes: BytesMut = stream
+            .try_collect()
+            .await
+            .map_err(|e| Error::StreamCollectFailed(format!("{e:?}")))?;
+        assert_eq!(bytes.as_ref(), expected);
+
+        Ok(())
+    }
+
+    /// Push an image to a tag then, after `delay`, push a different image to the same tag. If
+    /// `mutable` the tag should follow the second image, otherwise the second push should be
+    /// denied and the tag should still refer to the first image.
+    pub async fn retag_after(
+        &self,
+        repository: &str,
+        delay: std::time::Duration,
+        mutable: bool,
+    ) -> Result<()> {
+        // make the tag unique to this run so that earlier runs can't have started its window
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .expect("system time should be after the unix epoch")
+            .as_nanos();
+        self.retag(repository, &format!("release-{seed}"), delay, mutable)
+            .await
+    }
+
+    /// Like [`Self::retag_after`], but pushing to the given tag.
+    pub async fn retag(
+        &self,
+        repository: &str,
+        tag: &str,
+        delay: std::time::Duration,
+        mutable: bool,
+    ) -> Result<()> {
+        let mut images = testdata::retagged_images(tag, 2);
+        let second = images.pop().expect("two images were generated");
+        let first = images.pop().expect("two images were generated");
+        let (first_digest, second_digest) = (first.clone().digest(), second.clone().digest());
+
+        self.loader
+            .clone()
+            .upload_images(repository.to_string(), vec![Arc::new(Mutex::new(first))])
+            .await?;
+
+        tokio::time::sleep(delay).await;
+
+        let expected = match self
+            .loader
+            .clone()
+            .upload_images(repository.to_string(), vec![Arc::new(Mutex::new(second))])
+            .await
+        {
+            Ok(_) if mutable => second_digest,
+            Ok(_) => panic!("expected Denied, got a successful push"),
+            Err(Error::CoreError(CoreError::Denied(_))) if !mutable => first_digest,
+            Err(e) => return Err(e),
+        };
+
+        let mstore = self.loader.get_manifest_store(repository).await;
+        let tags = mstore.get_tags(&ManifestRef::Tag(tag.to_string())).await?;
+        assert_eq!(tags.len(), 1);
+        assert_eq!(tags[0].manifest_digest(), &expected);
+
+        Ok(())
+    }
+
+    /// Push two different images to the given tag at the same time, verifying that both pushes
+    /// succeed and leave the tag pointing at exactly one of them.
+    pub async fn push_concurrently_to_tag(&self, repository: &str, tag: &str) -> Result<()> {
+        let mut images = testdata::retagged_images(tag, 2);
+        let second = images.pop().expect("two images were generated");
+        let first = images.pop().expect("two images were generated");
+        let digests = [first.clone().digest(), second.clone().digest()];
+
+        let (first, second) = tokio::join!(
+            self.loader
+                .clone()
+                .upload_images(repository.to_string(), vec![Arc::new(Mutex::new(first))]),
+            self.loader
+                .clone()
+                .upload_images(repository.to_string(), vec![Arc::new(Mutex::new(second))]),
+        );
+        first?;
+        second?;
+
+        let mstore = self.loader.get_manifest_store(repository).await;
+        let tags = mstore.get_tags(&ManifestRef::Tag(tag.to_string())).await?;
+        assert_eq!(tags.len(), 1);
+        assert!(digests.contains(tags[0].manifest_digest()));
+        let manifest = mstore
+            .head(&ManifestRef::Tag(tag.to_string()))
+            .await?
+            .expect("the tag should refer to a manifest");
+        assert_eq!(manifest.digest(), tags[0].manifest_digest());
+
+        Ok(())
+    }
+}
+
+fn sha512(data: &[u8]) -> OciDigest {
+    let mut digester = Digester::sha512();
+    digester.update(data);
+    digester
+        .digest()
+        .expect("a new digester always tracks its hash state")
 }
 
 #[cfg(test)]
 mod test {
+    use std::collections::HashMap;
     use std::fs::File;
     use std::io::Read;
     use std::path::PathBuf;
     use std::sync::Once;
 
     use anyhow::Result;
-    use portfolio_backend_postgres::PgRepositoryConfig;
+    use axum::body::Body;
+    use axum::http::{Method, Request, StatusCode};
+    use axum::middleware;
+    use futures::stream::StreamExt;
+    use oci_spec::distribution::TagList;
+    use oci_spec::image::{Descriptor, ImageIndex, ImageManifest, MediaType};
+    use portfolio_backend_postgres::{PgRepositoryConfig, PgRepositoryFactory, PostgresConfig};
+    use portfolio_core::registry::{RepositoryStoreManager, Visibility};
+    use portfolio_http::{
+        add_basic_repository_extensions, basic_auth, BasicAuthenticator, HttpConfig, Portfolio,
+        RepositoryCreationPolicy,
+    };
     use serde::Deserialize;
+    use tower::ServiceExt;
 
-    use super::super::testdata;
     use super::*;
+    use crate::conformance::ConformanceClient;
+    use crate::Layer;
+    use crate::ManifestReference;
 
     static INIT: Once = Once::new();
 
@@ -159,15 +657,53 @@ mod test {
         pub backend: RepositoryBackend,
     }
 
-    async fn init_backend(path: PathBuf) -> Result<RepositoryTester> {
+    /// Load the config at `path`, overlaying `settings` (a YAML mapping) onto its backend section
+    /// so that tests can exercise non-default store behavior.
+    fn load_config(path: PathBuf, settings: &str) -> Result<Config> {
+        init();
+
+        let mut dev_config = File::open(path)?;
+        let mut s = String::new();
+        dev_config.read_to_string(&mut s)?;
+        let mut config: serde_yaml::Value = serde_yaml::from_str(&s)?;
+        if let serde_yaml::Value::Mapping(settings) = serde_yaml::from_str(settings)? {
+            if let Some(serde_yaml::Value::Mapping(backend)) = config.get_mut("backend") {
+                backend.extend(settings);
+            }
+        }
+        Ok(serde_yaml::from_value(config)?)
+    }
+
+    /// The backend's `postgres` settings, for tests that need to talk to the database directly.
+    fn load_postgres_settings(path: PathBuf) -> Result<serde_yaml::Value> {
         init();
 
         let mut dev_config = File::open(path)?;
         let mut s = String::new();
         dev_config.read_to_string(&mut s)?;
-        let config: Config = serde_yaml::from_str(&s)?;
+        let config: serde_yaml::Value = serde_yaml::from_str(&s)?;
+        Ok(config["backend"]["postgres"].clone())
+    }
+
+    async fn init_factory(path: PathBuf) -> Result<PgRepositoryFactory> {
+        init_factory_with_settings(path, "").await
+    }
+
+    async fn init_factory_with_settings(
+        path: PathBuf,
+        settings: &str,
+    ) -> Result<PgRepositoryFactory> {
+        match load_config(path, settings)?.backend {
+            RepositoryBackend::Postgres(cfg) => Ok(cfg.get_manager().await?),
+        }
+    }
+
+    async fn init_backend(path: PathBuf) -> Result<RepositoryTester> {
+        init_backend_with_settings(path, "").await
+    }
 
-        match config.backend {
+    async fn init_backend_with_settings(path: PathBuf, settings: &str) -> Result<RepositoryTester> {
+        match load_config(path, settings)?.backend {
             RepositoryBackend::Postgres(cfg) => {
                 let manager = cfg.get_manager().await?;
                 Ok(RepositoryTester::new(RepositoryLoader::new(Box::new(
@@ -177,6 +713,26 @@ mod test {
         }
     }
 
+    async fn init_router(path: PathBuf) -> Result<axum::Router> {
+        init_router_with_settings(path, "").await
+    }
+
+    async fn init_router_with_settings(path: PathBuf, settings: &str) -> Result<axum::Router> {
+        let portfolio = match load_config(path, settings)?.backend {
+            RepositoryBackend::Postgres(cfg) => {
+                let manager = cfg.get_manager().await?;
+                Portfolio::new(std::sync::Arc::new(manager))
+            }
+        };
+
+        Ok(portfolio
+            .router()?
+            .route_layer(middleware::from_fn_with_state(
+                portfolio.clone(),
+                add_basic_repository_extensions,
+            )))
+    }
+
     #[tokio::test]
     async fn push_and_pull_image() -> Result<()> {
         let tester = init_backend(PathBuf::from("../../dev-config-linode.yml")).await?;
@@ -196,4 +752,3678 @@ mod test {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn push_and_pull_with_concurrency_limit() -> Result<()> {
+        let factory = init_factory(PathBuf::from("../../dev-config-linode.yml")).await?;
+        let tester =
+            RepositoryTester::new(RepositoryLoader::new(Box::new(factory)).with_concurrency(2));
+
+        tester
+            .push_and_pull_images(testdata::BASIC_IMAGES.clone())
+            .await?;
+        tester
+            .push_and_pull_indices(testdata::BASIC_INDEXES.clone())
+            .await?;
+
+        let max_in_flight = tester.loader.max_in_flight();
+        assert!(max_in_flight > 0, "no images were counted");
+        assert!(
+            max_in_flight <= 2,
+            "{max_in_flight} images were pushed or pulled at once"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn resolve_platform_from_large_index() -> Result<()> {
+        let tester = init_backend(PathBuf::from("../../dev-config-linode.yml")).await?;
+        let index = testdata::multi_platform_index();
+
+        tester.resolve_index_platforms(index).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn referrers_pagination_is_stable() -> Result<()> {
+        let tester = init_backend(PathBuf::from("../../dev-config-linode.yml")).await?;
+        let subject = testdata::BASIC_IMAGES[0].clone();
+
+        tester.paginate_referrers_while_pushing(subject).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn tags_list_link_headers() -> Result<()> {
+        let tester = init_backend(PathBuf::from("../../dev-config-linode.yml")).await?;
+        let router = init_router(PathBuf::from("../../dev-config-linode.yml")).await?;
+        let images = testdata::tagged_images("paginated", 5);
+
+        tester
+            .loader
+            .clone()
+            .upload_images(
+                "tagsrepo".to_string(),
+                images.into_iter().map(Mutex::new).map(Arc::new).collect(),
+            )
+            .await?;
+
+        let mut uri = "/v2/tagsrepo/tags/list?n=2".to_string();
+        let mut tags: Vec<String> = Vec::new();
+        loop {
+            let response = router
+                .clone()
+                .oneshot(Request::get(uri.as_str()).body(Body::empty())?)
+                .await?;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let next = response
+                .headers()
+                .get("link")
+                .map(|v| v.to_str())
+                .transpose()?
+                .map(|v| {
+                    v.trim_start_matches('<')
+                        .split_once('>')
+                        .expect("link header should enclose its uri in angle brackets")
+                        .0
+                        .to_string()
+                });
+
+            let body = hyper::body::to_bytes(response.into_body()).await?;
+            let page: TagList = serde_json::from_slice(&body)?;
+            assert!(page.tags().len() <= 2);
+            tags.extend(page.tags().iter().cloned());
+
+            match next {
+                Some(next) => uri = next,
+                None => break,
+            }
+        }
+
+        let expected: Vec<String> = (0..5).map(|i| format!("paginated-{i}")).collect();
+        let unique: HashSet<String> = tags.iter().cloned().collect();
+        assert_eq!(unique.len(), tags.len(), "tags returned more than once");
+        assert!(
+            expected.iter().all(|t| unique.contains(t)),
+            "tags skipped while following link headers"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn tag_digests_listed() -> Result<()> {
+        let tester = init_backend(PathBuf::from("../../dev-config-linode.yml")).await?;
+
+        // make the tags unique to this run so earlier runs can't have pointed them elsewhere
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let prefix = format!("digests-{seed}");
+        let mut images = testdata::tagged_images(&prefix, 3);
+        let expected: Vec<(String, OciDigest)> = images
+            .iter_mut()
+            .enumerate()
+            .map(|(i, image)| (format!("{prefix}-{i}"), image.digest()))
+            .collect();
+
+        tester
+            .loader
+            .clone()
+            .upload_images(
+                "tagsrepo".to_string(),
+                images.into_iter().map(Mutex::new).map(Arc::new).collect(),
+            )
+            .await?;
+
+        let mstore = tester.loader.get_manifest_store("tagsrepo").await;
+        let tags: Vec<(String, OciDigest)> = mstore
+            .get_tag_digests(None, None)
+            .await?
+            .into_iter()
+            .filter(|(name, _)| name.starts_with(&prefix))
+            .collect();
+        assert_eq!(tags, expected);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn upload_with_wrong_digest_is_rejected() -> Result<()> {
+        let tester = init_backend(PathBuf::from("../../dev-config-linode.yml")).await?;
+
+        tester.finalize_with_wrong_digest().await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn oversized_image_is_denied() -> Result<()> {
+        let tester = init_backend_with_settings(
+            PathBuf::from("../../dev-config-linode.yml"),
+            "max_image_size: 16",
+        )
+        .await?;
+        let image = testdata::tagged_images("oversized", 1).remove(0);
+
+        tester.push_oversized_image(image).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn disallowed_reference_digest_algorithm_is_rejected() -> Result<()> {
+        // test images refer to their layers by sha256 digests
+        let tester = init_backend_with_settings(
+            PathBuf::from("../../dev-config-linode.yml"),
+            "allowed_reference_digest_algorithms: [sha512]",
+        )
+        .await?;
+        let image = testdata::tagged_images("disallowed-algorithm", 1).remove(0);
+
+        tester.push_image_with_disallowed_algorithm(image).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn short_chunk_is_rejected() -> Result<()> {
+        let tester = init_backend(PathBuf::from("../../dev-config-linode.yml")).await?;
+
+        tester.write_short_chunk().await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn zero_byte_blob_round_trip() -> Result<()> {
+        let tester = init_backend(PathBuf::from("../../dev-config-linode.yml")).await?;
+
+        tester.upload_zero_byte_blob().await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn corrupted_chunk_is_rejected() -> Result<()> {
+        let tester = init_backend_with_settings(
+            PathBuf::from("../../dev-config-linode.yml"),
+            "verify_chunk_digests: true",
+        )
+        .await?;
+
+        tester.write_corrupted_chunk().await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn sha512_blob_round_trip() -> Result<()> {
+        let tester = init_backend(PathBuf::from("../../dev-config-linode.yml")).await?;
+
+        tester.push_and_pull_sha512_blobs(true).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn sha512_blob_round_trip_with_configured_algorithms() -> Result<()> {
+        let tester = init_backend_with_settings(
+            PathBuf::from("../../dev-config-linode.yml"),
+            "upload_digest_algorithms: [sha256, sha512]",
+        )
+        .await?;
+
+        tester.push_and_pull_sha512_blobs(false).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn tag_mutable_within_window() -> Result<()> {
+        let tester = init_backend_with_settings(
+            PathBuf::from("../../dev-config-linode.yml"),
+            "tag_mutability_windows: {tag-window-open: 3600}",
+        )
+        .await?;
+
+        tester
+            .retag_after("tag-window-open", std::time::Duration::ZERO, true)
+            .await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn tag_immutable_past_window() -> Result<()> {
+        let tester = init_backend_with_settings(
+            PathBuf::from("../../dev-config-linode.yml"),
+            "tag_mutability_windows: {tag-window-closed: 1}",
+        )
+        .await?;
+
+        tester
+            .retag_after(
+                "tag-window-closed",
+                std::time::Duration::from_secs(2),
+                false,
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn immutable_tags_are_denied() -> Result<()> {
+        let factory = init_factory(PathBuf::from("../../dev-config-linode.yml")).await?;
+        let tester = RepositoryTester::new(RepositoryLoader::new(Box::new(factory.clone())));
+
+        // a fresh repository so that earlier runs can't have pushed its tags
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let repository = format!("immutable{seed}");
+        factory.create(&repository).await?;
+        factory
+            .set_immutable_tags(&repository, &["v*".to_string()])
+            .await?;
+
+        tester
+            .retag(&repository, "v1.0", std::time::Duration::ZERO, false)
+            .await?;
+        tester
+            .retag(&repository, "latest", std::time::Duration::ZERO, true)
+            .await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn mount_blob_from_other_repository() -> Result<()> {
+        let tester = init_backend(PathBuf::from("../../dev-config-linode.yml")).await?;
+        let image = testdata::BASIC_IMAGES[0].clone();
+
+        tester.mount_blob_across_repositories(image).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn garbage_collect_keeps_shared_layers() -> Result<()> {
+        let factory = init_factory(PathBuf::from("../../dev-config-linode.yml")).await?;
+        let tester = RepositoryTester::new(RepositoryLoader::new(Box::new(factory.clone())));
+
+        // make layer contents unique to this run so earlier runs can't affect reference counts
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let shared = Arc::new(Mutex::new(Layer {
+            data: format!("shared layer {seed}"),
+            ..Default::default()
+        }));
+        let mut images: Vec<Image> = (0..2)
+            .map(|i| Image {
+                layers: vec![
+                    shared.clone(),
+                    Arc::new(Mutex::new(Layer {
+                        data: format!("unique layer {i} {seed}"),
+                        ..Default::default()
+                    })),
+                ],
+                ..Default::default()
+            })
+            .collect();
+        let shared_digest: OciDigest = shared
+            .lock()
+            .unwrap()
+            .descriptor()
+            .digest()
+            .as_str()
+            .try_into()?;
+        let unique_digest: OciDigest = images[0].layers[1]
+            .lock()
+            .unwrap()
+            .descriptor()
+            .digest()
+            .as_str()
+            .try_into()?;
+        let deleted = images[0].manifest_ref();
+
+        tester
+            .loader
+            .clone()
+            .upload_images(
+                "gc-repo".to_string(),
+                images.drain(..).map(Mutex::new).map(Arc::new).collect(),
+            )
+            .await?;
+        assert_eq!(factory.reference_count(&shared_digest).await?, 2);
+
+        let mstore = tester.loader.get_manifest_store("gc-repo").await;
+        mstore.delete(&deleted).await?;
+        assert_eq!(factory.reference_count(&shared_digest).await?, 1);
+        assert_eq!(factory.reference_count(&unique_digest).await?, 0);
+
+        let collected = factory.garbage_collect().await?;
+        assert!(collected.contains(&unique_digest));
+        assert!(!collected.contains(&shared_digest));
+
+        let bstore = tester.loader.get_blob_store("gc-repo").await;
+        assert!(bstore.head(&unique_digest).await?.is_none());
+        assert!(bstore.head(&shared_digest).await?.is_some());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn garbage_collect_backfills_config_associations() -> Result<()> {
+        use sqlx::Connection;
+
+        let path = PathBuf::from("../../dev-config-linode.yml");
+        let factory = init_factory(path.clone()).await?;
+        let tester = RepositoryTester::new(RepositoryLoader::new(Box::new(factory.clone())));
+
+        // make the config unique to this run so earlier runs can't affect reference counts
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let mut image = Image {
+            layers: vec![Arc::new(Mutex::new(Layer {
+                data: format!("pre-config-tracking layer {seed}"),
+                ..Default::default()
+            }))],
+            ..Default::default()
+        };
+        let config_digest: OciDigest = image.manifest().config().digest().as_str().try_into()?;
+        let manifest_digest = String::from(&image.digest());
+
+        tester
+            .loader
+            .clone()
+            .upload_images(
+                "gc-backfill-repo".to_string(),
+                vec![Arc::new(Mutex::new(image))],
+            )
+            .await?;
+        assert_eq!(factory.reference_count(&config_digest).await?, 1);
+
+        // put the manifest back in the state it would have been in had it been pushed before
+        // configs were associated with manifests
+        let postgres = load_postgres_settings(path)?;
+        let connection_string = postgres["connection_string"]
+            .as_str()
+            .expect("dev config includes a connection string");
+        let mut conn = sqlx::PgConnection::connect(connection_string).await?;
+        sqlx::query(
+            "DELETE FROM layers USING manifests
+             WHERE layers.manifest = manifests.id AND layers.config AND manifests.digest = $1",
+        )
+        .bind(&manifest_digest)
+        .execute(&mut conn)
+        .await?;
+        sqlx::query("UPDATE manifests SET config_backfill_pending = true WHERE digest = $1")
+            .bind(&manifest_digest)
+            .execute(&mut conn)
+            .await?;
+        assert_eq!(factory.reference_count(&config_digest).await?, 0);
+
+        let collected = factory.garbage_collect().await?;
+        assert!(!collected.contains(&config_digest));
+        assert_eq!(factory.reference_count(&config_digest).await?, 1);
+
+        let bstore = tester.loader.get_blob_store("gc-backfill-repo").await;
+        assert!(bstore.head(&config_digest).await?.is_some());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn platform_resolution_fetches_only_matching_child() -> Result<()> {
+        use sqlx::{Connection, Row};
+
+        let path = PathBuf::from("../../dev-config-linode.yml");
+        let factory = init_factory(path.clone()).await?;
+        let tester = RepositoryTester::new(RepositoryLoader::new(Box::new(factory.clone())));
+
+        // make the children unique to this run so that making them unreadable can't affect
+        // other tests
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let mut index = testdata::multi_platform_index();
+        for image in &index.manifests {
+            image.lock().unwrap().annotations =
+                Some(HashMap::from([("seed".to_string(), seed.to_string())]));
+        }
+        tester
+            .loader
+            .upload_indices(
+                "testrepo".to_string(),
+                vec![Arc::new(Mutex::new(index.clone()))],
+            )
+            .await?;
+        let index_ref = index.manifest_ref();
+        let index_digest = String::from(&index.digest());
+        let descriptors = index.manifest().manifests().clone();
+        let (target, others) = descriptors
+            .split_last()
+            .expect("the index has many children");
+
+        // only the matching child can be read, so resolution fails if it reads any other
+        for desc in others {
+            let key = factory
+                .object_key(&OciDigest::try_from(desc.digest().as_str())?)
+                .await?
+                .expect("child should have been pushed");
+            factory.objects().delete(&key).await?;
+        }
+
+        // put the index back in the state it would have been in had it been pushed before the
+        // platforms of its children were recorded
+        let postgres = load_postgres_settings(path)?;
+        let connection_string = postgres["connection_string"]
+            .as_str()
+            .expect("dev config includes a connection string");
+        let mut conn = sqlx::PgConnection::connect(connection_string).await?;
+        sqlx::query(
+            "UPDATE index_manifests
+             SET platform_os = NULL, platform_architecture = NULL, platform_variant = NULL,
+                 position = NULL
+             FROM manifests
+             WHERE index_manifests.parent_manifest = manifests.id AND manifests.digest = $1",
+        )
+        .bind(&index_digest)
+        .execute(&mut conn)
+        .await?;
+        sqlx::query("UPDATE manifests SET platform_backfill_pending = true WHERE digest = $1")
+            .bind(&index_digest)
+            .execute(&mut conn)
+            .await?;
+
+        let mstore = tester.loader.get_manifest_store("testrepo").await;
+        let platform = target
+            .platform()
+            .clone()
+            .expect("test index descriptors always include a platform");
+        let resolved = mstore
+            .get_platform_manifest(&index_ref, &platform)
+            .await?
+            .expect("the platform should resolve");
+        assert_eq!(
+            String::from(resolved.digest()).as_str(),
+            target.digest().as_str()
+        );
+        let (_, body) = mstore
+            .get(&ManifestRef::Digest(resolved.digest().clone()))
+            .await?
+            .expect("the resolved child should exist");
+        assert!(body.try_collect::<Vec<_>>().await.is_ok());
+
+        let row = sqlx::query("SELECT platform_backfill_pending FROM manifests WHERE digest = $1")
+            .bind(&index_digest)
+            .fetch_one(&mut conn)
+            .await?;
+        assert!(!row.try_get::<bool, _>("platform_backfill_pending")?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn delete_repository_removes_objects() -> Result<()> {
+        let path = PathBuf::from("../../dev-config-linode.yml");
+        let factory = init_factory(path.clone()).await?;
+        let tester = RepositoryTester::new(RepositoryLoader::new(Box::new(factory.clone())));
+        let router = init_router(path).await?;
+
+        // make layer contents unique to this run so earlier runs can't affect reference counts
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let shared = Arc::new(Mutex::new(Layer {
+            data: format!("shared layer {seed}"),
+            ..Default::default()
+        }));
+        let unique = Arc::new(Mutex::new(Layer {
+            data: format!("unique layer {seed}"),
+            ..Default::default()
+        }));
+        let shared_digest: OciDigest = shared
+            .lock()
+            .unwrap()
+            .descriptor()
+            .digest()
+            .as_str()
+            .try_into()?;
+        let unique_digest: OciDigest = unique
+            .lock()
+            .unwrap()
+            .descriptor()
+            .digest()
+            .as_str()
+            .try_into()?;
+
+        for (repository, layers) in [
+            ("delete-repo", vec![shared.clone(), unique.clone()]),
+            ("delete-repo-survivor", vec![shared.clone()]),
+        ] {
+            let image = Image {
+                layers,
+                ..Default::default()
+            };
+            tester
+                .loader
+                .clone()
+                .upload_images(repository.to_string(), vec![Arc::new(Mutex::new(image))])
+                .await?;
+        }
+
+        let shared_key = factory
+            .object_key(&shared_digest)
+            .await?
+            .expect("shared layer should have been pushed");
+        let unique_key = factory
+            .object_key(&unique_digest)
+            .await?
+            .expect("unique layer should have been pushed");
+
+        let delete = || {
+            Request::builder()
+                .method("DELETE")
+                .uri("/v2/delete-repo")
+                .body(Body::empty())
+        };
+
+        let response = router.clone().oneshot(delete()?).await?;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+        let objects = factory.objects();
+        assert!(!objects.exists(&unique_key).await?);
+        assert!(objects.exists(&shared_key).await?);
+        assert!(factory.object_key(&unique_digest).await?.is_none());
+        assert_eq!(factory.reference_count(&shared_digest).await?, 1);
+
+        let response = router.oneshot(delete()?).await?;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn lazy_deletion_sweeps_objects_once() -> Result<()> {
+        // a long interval keeps the background sweeper out of the way of the sweeps below
+        let factory = init_factory_with_settings(
+            PathBuf::from("../../dev-config-linode.yml"),
+            "{object_deletion: lazy, object_sweep_interval_secs: 3600}",
+        )
+        .await?;
+        let tester = RepositoryTester::new(RepositoryLoader::new(Box::new(factory.clone())));
+
+        // make the content unique to this run so earlier runs can't have stored it already
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let content = format!("lazily deleted blob {seed}");
+        let digest = OciDigest::from(content.as_bytes());
+
+        let blob_store = tester.loader.get_blob_store("testrepo").await;
+        blob_store
+            .put(&digest, content.len() as u64, Body::from(content))
+            .await?;
+        let key = factory
+            .object_key(&digest)
+            .await?
+            .expect("blob should have been pushed");
+
+        blob_store.delete(&digest).await?;
+        assert!(factory.object_key(&digest).await?.is_none());
+        let objects = factory.objects();
+        assert!(objects.exists(&key).await?);
+
+        let sweeper = factory.object_sweeper();
+        assert!(sweeper.sweep().await? >= 1);
+        assert!(!objects.exists(&key).await?);
+        assert_eq!(sweeper.sweep().await?, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn soft_deleted_blob_removed_after_grace_period() -> Result<()> {
+        let path = PathBuf::from("../../dev-config-linode.yml");
+        let settings = "{object_deletion: lazy, object_sweep_interval_secs: 3600, \
+                        object_deletion_grace_period_secs: 2}";
+        let factory = init_factory_with_settings(path.clone(), settings).await?;
+        let router = init_router_with_settings(path, settings).await?;
+
+        // make the content unique to this run so earlier runs can't have stored it already
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let content = format!("soft deleted blob {seed}");
+        let digest = String::from(OciDigest::from(content.as_bytes()));
+        let uri = format!("/v2/testrepo/blobs/{digest}");
+
+        let response = router
+            .clone()
+            .oneshot(
+                Request::post(format!("/v2/testrepo/blobs/uploads/?digest={digest}"))
+                    .header("content-type", "application/octet-stream")
+                    .header("content-length", content.len())
+                    .body(Body::from(content))?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let key = factory
+            .object_key(&OciDigest::try_from(digest.as_str())?)
+            .await?
+            .expect("blob should have been pushed");
+
+        let response = router
+            .clone()
+            .oneshot(Request::delete(uri.as_str()).body(Body::empty())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+        // gone as far as clients are concerned, but still recoverable from the object store
+        let response = router
+            .oneshot(Request::get(uri.as_str()).body(Body::empty())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let objects = factory.objects();
+        let sweeper = factory.object_sweeper();
+        sweeper.sweep().await?;
+        assert!(objects.exists(&key).await?);
+
+        tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+        sweeper.sweep().await?;
+        assert!(!objects.exists(&key).await?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn metadata_only_manifest_reported_and_healed() -> Result<()> {
+        let path = PathBuf::from("../../dev-config-linode.yml");
+        let factory = init_factory(path.clone()).await?;
+        let router = init_router(path.clone()).await?;
+        let healing_factory =
+            init_factory_with_settings(path.clone(), "heal_missing_manifests: true").await?;
+        let healing_router =
+            init_router_with_settings(path, "heal_missing_manifests: true").await?;
+        let tester = RepositoryTester::new(RepositoryLoader::new(Box::new(healing_factory)));
+
+        // make the image unique to this run so earlier runs can't have pushed it already
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let prefix = format!("metadata-only-{seed}");
+        let mut image = testdata::tagged_images(&prefix, 1).remove(0);
+        let digest = image.digest();
+        let manifest = image.manifest();
+        let push = |image: &Image| vec![Arc::new(Mutex::new(image.clone()))];
+        tester
+            .loader
+            .clone()
+            .upload_images("testrepo".to_string(), push(&image))
+            .await?;
+
+        // remove the content of the manifest, its config and its layers behind the metadata
+        // database's back, leaving them metadata-only
+        let digests = std::iter::once(&digest)
+            .cloned()
+            .chain(
+                std::iter::once(manifest.config())
+                    .chain(manifest.layers())
+                    .map(|desc| OciDigest::try_from(desc.digest().as_str()))
+                    .collect::<std::result::Result<Vec<_>, _>>()?,
+            )
+            .collect::<Vec<_>>();
+        for digest in &digests {
+            let key = factory
+                .object_key(digest)
+                .await?
+                .expect("content should have been pushed");
+            factory.objects().delete(&key).await?;
+        }
+
+        let uri = format!("/v2/testrepo/manifests/{prefix}-0");
+        let response = router
+            .oneshot(Request::get(uri.as_str()).body(Body::empty())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let response = healing_router
+            .clone()
+            .oneshot(Request::get(uri.as_str()).body(Body::empty())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        // pushing the image again restores the missing content
+        tester
+            .loader
+            .clone()
+            .upload_images("testrepo".to_string(), push(&image))
+            .await?;
+        let mut pulled = tester
+            .loader
+            .pull_image("testrepo".to_string(), &ManifestRef::Digest(digest.clone()))
+            .await?;
+        assert_eq!(pulled.manifest(), manifest);
+        let response = healing_router
+            .oneshot(Request::get(uri.as_str()).body(Body::empty())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn conformance_checks_pass() -> Result<()> {
+        let router = init_router(PathBuf::from("../../dev-config-linode.yml")).await?;
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let server = axum::Server::from_tcp(listener)?.serve(router.into_make_service());
+        tokio::spawn(server);
+
+        let client = ConformanceClient::new(&format!("http://{addr}"), "conformance");
+        for check in client.run().await {
+            if let Err(e) = check.result {
+                panic!("conformance check {:?} failed: {e}", check.name);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn conformance_checks_pass_with_credentials() -> Result<()> {
+        let router = init_router(PathBuf::from("../../dev-config-linode.yml")).await?;
+        // the minimum cost keeps the test fast
+        let hash = bcrypt::hash("hunter2", 4)?;
+        let authenticator = BasicAuthenticator::from_htpasswd(&format!("meow:{hash}\n"))?;
+        let router = router.layer(middleware::from_fn_with_state(
+            std::sync::Arc::new(authenticator),
+            basic_auth,
+        ));
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let server = axum::Server::from_tcp(listener)?.serve(router.into_make_service());
+        tokio::spawn(server);
+
+        let client = ConformanceClient::new(&format!("http://{addr}"), "conformance")
+            .with_credentials("meow", "hunter2");
+        for check in client.run().await {
+            if let Err(e) = check.result {
+                panic!("conformance check {:?} failed: {e}", check.name);
+            }
+        }
+
+        // without credentials the registry turns the client away
+        let client = ConformanceClient::new(&format!("http://{addr}"), "conformance");
+        assert!(client.run().await.iter().all(|check| check.result.is_err()));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn corrupted_blob_fails_verification_on_read() -> Result<()> {
+        let factory = init_factory_with_settings(
+            PathBuf::from("../../dev-config-linode.yml"),
+            "verify_on_read: true",
+        )
+        .await?;
+        let tester = RepositoryTester::new(RepositoryLoader::new(Box::new(factory.clone())));
+        let blob_store = tester.loader.get_blob_store("testrepo").await;
+
+        // make the content unique to this run so earlier runs can't have stored it already
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let content = format!("blob that gets corrupted at rest {seed}");
+        let digest = OciDigest::from(content.as_bytes());
+        blob_store
+            .put(&digest, content.len() as u64, Body::from(content.clone()))
+            .await?;
+
+        // overwrite the stored object behind the metadata database's back
+        let key = factory
+            .object_key(&digest)
+            .await?
+            .expect("blob should have been pushed");
+        let corrupted = content.replace("corrupted", "CORRUPTED");
+        factory
+            .objects()
+            .put(&key, Body::from(corrupted.clone()), corrupted.len() as u64)
+            .await?;
+
+        let (_, stream) = blob_store
+            .get(&digest)
+            .await?
+            .expect("blob metadata should still exist");
+        let items: Vec<_> = stream.collect().await;
+        let (last, rest) = items.split_last().expect("stream should not be empty");
+        assert!(rest.iter().all(|i| i.is_ok()));
+        match last {
+            Err(e) => assert!(matches!(
+                e.downcast_ref::<CoreError>(),
+                Some(CoreError::ContentCorrupt(_))
+            )),
+            Ok(_) => panic!("expected the stream to end with ContentCorrupt"),
+        }
+
+        blob_store.delete(&digest).await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn monolithic_post_with_digest() -> Result<()> {
+        let router = init_router(PathBuf::from("../../dev-config-linode.yml")).await?;
+
+        // make the content unique to this run so earlier runs can't have stored it already
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let content = format!("blob pushed in a single POST {seed}");
+        let digest = String::from(OciDigest::from(content.as_bytes()));
+        let post = |digest: &str| {
+            Request::post(format!("/v2/testrepo/blobs/uploads/?digest={digest}"))
+                .header("content-type", "application/octet-stream")
+                .header("content-length", content.len())
+                .body(Body::from(content.clone()))
+        };
+
+        let wrong = String::from(OciDigest::from("some other content".as_bytes()));
+        let response = router.clone().oneshot(post(&wrong)?).await?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = router.clone().oneshot(post(&digest)?).await?;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(
+            response.headers().get("docker-content-digest"),
+            Some(&digest.parse()?),
+        );
+        assert_eq!(
+            response.headers().get("location"),
+            Some(&format!("/v2/testrepo/blobs/{digest}").parse()?),
+        );
+
+        let response = router
+            .oneshot(Request::head(format!("/v2/testrepo/blobs/{digest}")).body(Body::empty())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn upload_range_is_inclusive() -> Result<()> {
+        let router = init_router(PathBuf::from("../../dev-config-linode.yml")).await?;
+
+        let response = router
+            .clone()
+            .oneshot(Request::post("/v2/testrepo/blobs/uploads/").body(Body::empty())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let location = response
+            .headers()
+            .get("location")
+            .expect("upload session should have a location")
+            .to_str()?
+            .to_string();
+
+        let mut start = 0;
+        for size in [10usize, 20, 5] {
+            let end = start + size - 1;
+            let response = router
+                .clone()
+                .oneshot(
+                    Request::patch(location.as_str())
+                        .header("content-type", "application/octet-stream")
+                        .header("content-length", size)
+                        .header("content-range", format!("{start}-{end}"))
+                        .body(Body::from(vec![b'x'; size]))?,
+                )
+                .await?;
+            assert_eq!(response.status(), StatusCode::ACCEPTED);
+            assert_eq!(
+                response.headers().get("range").unwrap(),
+                &format!("0-{end}")
+            );
+            start = end + 1;
+        }
+
+        let response = router
+            .oneshot(Request::get(location.as_str()).body(Body::empty())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(response.headers().get("range").unwrap(), "0-34");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn bodyless_put_completes_upload_without_content() -> Result<()> {
+        let router = init_router(PathBuf::from("../../dev-config-linode.yml")).await?;
+        let digest = String::from(OciDigest::from("".as_bytes()));
+
+        for empty_patch in [true, false] {
+            let response = router
+                .clone()
+                .oneshot(Request::post("/v2/testrepo/blobs/uploads/").body(Body::empty())?)
+                .await?;
+            assert_eq!(response.status(), StatusCode::ACCEPTED);
+            let location = response
+                .headers()
+                .get("location")
+                .expect("upload session should have a location")
+                .to_str()?
+                .to_string();
+
+            if empty_patch {
+                let response = router
+                    .clone()
+                    .oneshot(
+                        Request::patch(location.as_str())
+                            .header("content-type", "application/octet-stream")
+                            .header("content-length", 0)
+                            .body(Body::empty())?,
+                    )
+                    .await?;
+                assert_eq!(response.status(), StatusCode::ACCEPTED);
+            }
+
+            let response = router
+                .clone()
+                .oneshot(Request::put(format!("{location}?digest={digest}")).body(Body::empty())?)
+                .await?;
+            assert_eq!(response.status(), StatusCode::CREATED);
+
+            let response = router
+                .clone()
+                .oneshot(Request::head(format!("/v2/testrepo/blobs/{digest}")).body(Body::empty())?)
+                .await?;
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn overlapping_chunk_is_not_satisfiable() -> Result<()> {
+        let router = init_router(PathBuf::from("../../dev-config-linode.yml")).await?;
+
+        let response = router
+            .clone()
+            .oneshot(Request::post("/v2/testrepo/blobs/uploads/").body(Body::empty())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let location = response
+            .headers()
+            .get("location")
+            .expect("upload session should have a location")
+            .to_str()?
+            .to_string();
+        let patch = |start: usize, end: usize| {
+            Request::patch(location.as_str())
+                .header("content-type", "application/octet-stream")
+                .header("content-length", end - start + 1)
+                .header("content-range", format!("{start}-{end}"))
+                .body(Body::from(vec![b'x'; end - start + 1]))
+        };
+
+        let response = router.clone().oneshot(patch(0, 99)?).await?;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        assert_eq!(response.headers().get("range").unwrap(), "0-99");
+
+        let response = router.clone().oneshot(patch(50, 149)?).await?;
+        assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(response.headers().get("range").unwrap(), "0-99");
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        let body: serde_json::Value = serde_json::from_slice(&body)?;
+        assert_eq!(body["errors"][0]["code"], "BLOB_UPLOAD_INVALID");
+        assert!(body["errors"][0]["message"]
+            .as_str()
+            .is_some_and(|msg| msg.contains("overlaps")));
+
+        // the session is left where it was so the client can resume from the reported range
+        let response = router.oneshot(patch(100, 149)?).await?;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        assert_eq!(response.headers().get("range").unwrap(), "0-149");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn referrers_filtered_by_artifact_type() -> Result<()> {
+        let tester = init_backend(PathBuf::from("../../dev-config-linode.yml")).await?;
+        let router = init_router(PathBuf::from("../../dev-config-linode.yml")).await?;
+
+        // make the subject unique to this run so that referrers pushed by earlier runs don't show
+        // up in the listing
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let mut subject = Image {
+            layers: vec![Arc::new(Mutex::new(Layer {
+                data: format!("referrers filter subject {seed}"),
+                ..Default::default()
+            }))],
+            ..Default::default()
+        };
+        let subject_digest = String::from(subject.digest());
+
+        let mut referrers = testdata::referrers_of(&mut subject, "filtered", 2);
+        referrers[1].artifact_type = Some(MediaType::Other(
+            "application/vnd.portfolio.test.other".to_string(),
+        ));
+        let wanted = String::from(referrers[0].digest());
+
+        let mut images = vec![Arc::new(Mutex::new(subject))];
+        images.extend(referrers.into_iter().map(Mutex::new).map(Arc::new));
+        tester
+            .loader
+            .clone()
+            .upload_images("testrepo".to_string(), images)
+            .await?;
+
+        let uri = format!(
+            "/v2/testrepo/referrers/{subject_digest}?artifactType=application/vnd.portfolio.test.referrer"
+        );
+        let response = router
+            .clone()
+            .oneshot(Request::get(uri.as_str()).body(Body::empty())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get("oci-filters-applied").unwrap(),
+            "artifactType"
+        );
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        let index: ImageIndex = serde_json::from_slice(&body)?;
+        let digests: Vec<&String> = index.manifests().iter().map(|d| d.digest()).collect();
+        assert_eq!(digests, vec![&wanted]);
+
+        let uri = format!("/v2/testrepo/referrers/{subject_digest}");
+        let response = router
+            .oneshot(Request::get(uri.as_str()).body(Body::empty())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().get("oci-filters-applied").is_none());
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        let index: ImageIndex = serde_json::from_slice(&body)?;
+        assert_eq!(index.manifests().len(), 2);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn referrers_link_headers() -> Result<()> {
+        let tester = init_backend(PathBuf::from("../../dev-config-linode.yml")).await?;
+        let router = init_router(PathBuf::from("../../dev-config-linode.yml")).await?;
+
+        // make the subject unique to this run so that referrers pushed by earlier runs don't show
+        // up in the listing
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let mut subject = Image {
+            layers: vec![Arc::new(Mutex::new(Layer {
+                data: format!("referrers pagination subject {seed}"),
+                ..Default::default()
+            }))],
+            ..Default::default()
+        };
+        let subject_digest = String::from(subject.digest());
+
+        let referrers = testdata::referrers_of(&mut subject, "paginated", 3);
+        let mut expected: Vec<String> = Vec::new();
+        let mut images = vec![Arc::new(Mutex::new(subject))];
+        for mut referrer in referrers {
+            expected.push(String::from(referrer.digest()));
+            images.push(Arc::new(Mutex::new(referrer)));
+        }
+        expected.sort();
+        tester
+            .loader
+            .clone()
+            .upload_images("testrepo".to_string(), images)
+            .await?;
+
+        let mut uri = format!("/v2/testrepo/referrers/{subject_digest}?n=2");
+        let mut pages: Vec<Vec<String>> = Vec::new();
+        loop {
+            let response = router
+                .clone()
+                .oneshot(Request::get(uri.as_str()).body(Body::empty())?)
+                .await?;
+            assert_eq!(response.status(), StatusCode::OK);
+
+            let next = response
+                .headers()
+                .get("link")
+                .map(|v| v.to_str())
+                .transpose()?
+                .map(|v| {
+                    v.trim_start_matches('<')
+                        .split_once('>')
+                        .expect("link header should enclose its uri in angle brackets")
+                        .0
+                        .to_string()
+                });
+
+            let body = hyper::body::to_bytes(response.into_body()).await?;
+            let page: ImageIndex = serde_json::from_slice(&body)?;
+            pages.push(
+                page.manifests()
+                    .iter()
+                    .map(|d| d.digest().clone())
+                    .collect(),
+            );
+
+            match next {
+                Some(next) => uri = next,
+                None => break,
+            }
+        }
+
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[0].len(), 2);
+        assert_eq!(pages.concat(), expected);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn referrers_pages_full_despite_missing_media_types() -> Result<()> {
+        use sqlx::Connection;
+
+        let path = PathBuf::from("../../dev-config-linode.yml");
+        let tester = init_backend(path.clone()).await?;
+        let router = init_router(path.clone()).await?;
+
+        // make the subject unique to this run so that referrers pushed by earlier runs don't show
+        // up in the listing
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let mut subject = Image {
+            layers: vec![Arc::new(Mutex::new(Layer {
+                data: format!("referrers media type subject {seed}"),
+                ..Default::default()
+            }))],
+            ..Default::default()
+        };
+        let subject_digest = String::from(subject.digest());
+
+        let referrers = testdata::referrers_of(&mut subject, "untyped", 4);
+        let mut expected: Vec<String> = Vec::new();
+        let mut images = vec![Arc::new(Mutex::new(subject))];
+        for mut referrer in referrers {
+            expected.push(String::from(referrer.digest()));
+            images.push(Arc::new(Mutex::new(referrer)));
+        }
+        expected.sort();
+        tester
+            .loader
+            .clone()
+            .upload_images("testrepo".to_string(), images)
+            .await?;
+
+        // the first referrer in digest order loses its media type, so a page filtered after the
+        // limit was applied would come up short
+        let untyped = expected.remove(0);
+        let postgres = load_postgres_settings(path)?;
+        let connection_string = postgres["connection_string"]
+            .as_str()
+            .expect("dev config includes a connection string");
+        let mut conn = sqlx::PgConnection::connect(connection_string).await?;
+        sqlx::query("UPDATE manifests SET media_type = NULL WHERE digest = $1")
+            .bind(&untyped)
+            .execute(&mut conn)
+            .await?;
+
+        let response = router
+            .clone()
+            .oneshot(
+                Request::get(format!("/v2/testrepo/referrers/{subject_digest}?n=2"))
+                    .body(Body::empty())?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().contains_key("link"));
+
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        let page: ImageIndex = serde_json::from_slice(&body)?;
+        let digests: Vec<String> = page
+            .manifests()
+            .iter()
+            .map(|d| d.digest().clone())
+            .collect();
+        assert_eq!(digests, expected[..2]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn unknown_manifest_is_not_found() -> Result<()> {
+        let router = init_router(PathBuf::from("../../dev-config-linode.yml")).await?;
+
+        // make the references unique to this run so earlier runs can't have pushed them already
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let by_digest = String::from(OciDigest::from(
+            format!("no such manifest {seed}").as_bytes(),
+        ));
+        let by_tag = format!("missing-{seed}");
+
+        for reference in [by_digest, by_tag] {
+            let uri = format!("/v2/testrepo/manifests/{reference}");
+
+            let response = router
+                .clone()
+                .oneshot(Request::get(uri.as_str()).body(Body::empty())?)
+                .await?;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+            let body = hyper::body::to_bytes(response.into_body()).await?;
+            let body: serde_json::Value = serde_json::from_slice(&body)?;
+            assert_eq!(body["errors"][0]["code"], "MANIFEST_UNKNOWN");
+
+            let response = router
+                .clone()
+                .oneshot(Request::head(uri.as_str()).body(Body::empty())?)
+                .await?;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn metrics_recorded_after_push() -> Result<()> {
+        let router = init_router(PathBuf::from("../../dev-config-linode.yml")).await?;
+
+        // make the content unique to this run so earlier runs can't have stored it already
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let content = format!("blob pushed before scraping metrics {seed}");
+        let digest = String::from(OciDigest::from(content.as_bytes()));
+
+        let response = router
+            .clone()
+            .oneshot(
+                Request::post(format!("/v2/testrepo/blobs/uploads/?digest={digest}"))
+                    .header("content-type", "application/octet-stream")
+                    .header("content-length", content.len())
+                    .body(Body::from(content.clone()))?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let response = router
+            .clone()
+            .oneshot(Request::get(format!("/v2/testrepo/blobs/{digest}")).body(Body::empty())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        hyper::body::to_bytes(response.into_body()).await?;
+
+        let response = router
+            .oneshot(Request::get("/metrics").body(Body::empty())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers()["content-type"]
+            .to_str()?
+            .starts_with("text/plain"));
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        let body = std::str::from_utf8(&body)?;
+        for name in [
+            "portfolio_http_requests_total",
+            "portfolio_http_request_duration_seconds",
+            "portfolio_blob_bytes_uploaded_total",
+            "portfolio_blob_bytes_downloaded_total",
+            "portfolio_manifest_puts_total",
+            "portfolio_objectstore_operation_duration_seconds",
+        ] {
+            assert!(body.contains(name), "missing metric {name}");
+        }
+        assert!(body.contains(r#"route="/v2/:repository/blobs/uploads/""#));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn digest_algorithm_audit_exported_as_metrics() -> Result<()> {
+        let path = PathBuf::from("../../dev-config-linode.yml");
+        let factory =
+            init_factory_with_settings(path.clone(), "audit_digest_algorithms: true").await?;
+        let router = init_router(path).await?;
+        let tester = RepositoryTester::new(RepositoryLoader::new(Box::new(factory.clone())));
+
+        // make the content unique to this run so earlier runs can't have stored it already
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let images = testdata::tagged_images(&format!("audited-{seed}"), 1);
+        tester
+            .loader
+            .clone()
+            .upload_images(
+                "testrepo".to_string(),
+                images.into_iter().map(Mutex::new).map(Arc::new).collect(),
+            )
+            .await?;
+
+        let audit = factory
+            .digest_algorithm_audit()
+            .expect("auditing is enabled");
+        assert!(audit.blob_counts().get("sha256").copied().unwrap_or(0) >= 2);
+        assert_eq!(audit.manifest_counts().get("sha256"), Some(&1));
+
+        // the counts are recorded in the registry served at /metrics
+        let response = router
+            .oneshot(Request::get("/metrics").body(Body::empty())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        let body = std::str::from_utf8(&body)?;
+        for kind in ["blob", "manifest"] {
+            assert!(
+                body.lines().any(|line| {
+                    line.starts_with("portfolio_digest_algorithm_stored_total{")
+                        && line.contains(r#"algorithm="sha256""#)
+                        && line.contains(&format!(r#"kind="{kind}""#))
+                }),
+                "missing {kind} audit metric"
+            );
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn features_reflect_config() -> Result<()> {
+        let router = init_router_with_settings(
+            PathBuf::from("../../dev-config-linode.yml"),
+            "max_image_size: 1234",
+        )
+        .await?;
+
+        let response = router
+            .oneshot(Request::get("/v2/_catalog/features").body(Body::empty())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        let features: serde_json::Value = serde_json::from_slice(&body)?;
+        assert_eq!(
+            features,
+            serde_json::json!({
+                "referrers": true,
+                "delete": true,
+                "mount": true,
+                "chunkMinLength": 5 * 1024 * 1024,
+                "maxImageSize": 1234,
+                "maxManifestSize": 4 * 1024 * 1024,
+            })
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn manifest_content_type_matches_media_type() -> Result<()> {
+        let tester = init_backend(PathBuf::from("../../dev-config-linode.yml")).await?;
+        let router = init_router(PathBuf::from("../../dev-config-linode.yml")).await?;
+        let mut index = testdata::BASIC_INDEXES[0].clone();
+        let index_digest = String::from(index.digest());
+        let mut image = testdata::BASIC_IMAGES[0].clone();
+        let image_digest = String::from(image.digest());
+
+        tester
+            .loader
+            .clone()
+            .upload_indices("testrepo".to_string(), vec![Arc::new(Mutex::new(index))])
+            .await?;
+        tester
+            .loader
+            .clone()
+            .upload_images("testrepo".to_string(), vec![Arc::new(Mutex::new(image))])
+            .await?;
+
+        for (digest, content_type) in [
+            (index_digest, "application/vnd.oci.image.index.v1+json"),
+            (image_digest, "application/vnd.oci.image.manifest.v1+json"),
+        ] {
+            let response = router
+                .clone()
+                .oneshot(
+                    Request::get(format!("/v2/testrepo/manifests/{digest}")).body(Body::empty())?,
+                )
+                .await?;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response.headers()["content-type"], content_type);
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn unacceptable_manifest_media_type_is_not_found() -> Result<()> {
+        let tester = init_backend(PathBuf::from("../../dev-config-linode.yml")).await?;
+        let router = init_router(PathBuf::from("../../dev-config-linode.yml")).await?;
+
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let prefix = format!("accept-{seed}");
+        let images = testdata::tagged_images(&prefix, 1);
+        tester
+            .loader
+            .clone()
+            .upload_images(
+                "testrepo".to_string(),
+                images.into_iter().map(Mutex::new).map(Arc::new).collect(),
+            )
+            .await?;
+
+        let uri = format!("/v2/testrepo/manifests/{prefix}-0");
+        let index_types = "application/vnd.oci.image.index.v1+json, \
+                           application/vnd.docker.distribution.manifest.list.v2+json";
+        for method in [Method::GET, Method::HEAD] {
+            let response = router
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .method(method.clone())
+                        .uri(&uri)
+                        .header("accept", index_types)
+                        .body(Body::empty())?,
+                )
+                .await?;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+            // a wildcard alongside the index types accepts the image manifest
+            let response = router
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .method(method)
+                        .uri(&uri)
+                        .header("accept", format!("{index_types}, */*;q=0.1"))
+                        .body(Body::empty())?,
+                )
+                .await?;
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn untagging_leaves_manifest_and_other_tags() -> Result<()> {
+        let tester = init_backend(PathBuf::from("../../dev-config-linode.yml")).await?;
+
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let prefix = format!("untag-{seed}");
+        let mut image = testdata::tagged_images(&prefix, 1).remove(0);
+        let digest = image.digest();
+        let mut retagged = image.clone();
+        retagged.manifest_ref = ManifestReference::Tag(format!("{prefix}-other"));
+
+        tester
+            .loader
+            .clone()
+            .upload_images(
+                "testrepo".to_string(),
+                vec![Arc::new(Mutex::new(image)), Arc::new(Mutex::new(retagged))],
+            )
+            .await?;
+
+        let mstore = tester.loader.get_manifest_store("testrepo").await;
+        mstore
+            .delete(&ManifestRef::Tag(format!("{prefix}-0")))
+            .await?;
+
+        assert!(mstore
+            .head(&ManifestRef::Tag(format!("{prefix}-0")))
+            .await?
+            .is_none());
+        assert!(mstore
+            .head(&ManifestRef::Tag(format!("{prefix}-other")))
+            .await?
+            .is_some());
+        assert!(mstore.head(&ManifestRef::Digest(digest)).await?.is_some());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn delete_by_digest_removes_manifest_and_tags() -> Result<()> {
+        let tester = init_backend(PathBuf::from("../../dev-config-linode.yml")).await?;
+
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let prefix = format!("undigest-{seed}");
+        let mut image = testdata::tagged_images(&prefix, 1).remove(0);
+        let digest = image.digest();
+
+        tester
+            .loader
+            .clone()
+            .upload_images("testrepo".to_string(), vec![Arc::new(Mutex::new(image))])
+            .await?;
+
+        let mstore = tester.loader.get_manifest_store("testrepo").await;
+        mstore.delete(&ManifestRef::Digest(digest.clone())).await?;
+
+        assert!(mstore
+            .head(&ManifestRef::Tag(format!("{prefix}-0")))
+            .await?
+            .is_none());
+        assert!(mstore.head(&ManifestRef::Digest(digest)).await?.is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn manifests_filtered_by_annotation() -> Result<()> {
+        let tester = init_backend(PathBuf::from("../../dev-config-linode.yml")).await?;
+        let router = init_router(PathBuf::from("../../dev-config-linode.yml")).await?;
+
+        // make the annotation key unique to this run so earlier runs can't match the filters
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let key = format!("org.example.{seed}.source");
+        let mut images = testdata::tagged_images(&format!("annotated-{seed}"), 4);
+        for (image, value) in images.iter_mut().zip(["a", "a", "b"]) {
+            image.annotations = Some(HashMap::from([(key.clone(), value.to_string())]));
+        }
+        let mut digests: Vec<String> = images
+            .iter_mut()
+            .map(|image| String::from(image.digest()))
+            .collect();
+
+        tester
+            .loader
+            .clone()
+            .upload_images(
+                "testrepo".to_string(),
+                images.into_iter().map(Mutex::new).map(Arc::new).collect(),
+            )
+            .await?;
+
+        // the last image isn't annotated
+        digests.pop();
+        let annotated_b = digests.pop().unwrap();
+        let mut annotated_a = digests;
+        annotated_a.sort();
+        let mut annotated = annotated_a.clone();
+        annotated.push(annotated_b.clone());
+        annotated.sort();
+
+        let mstore = tester.loader.get_manifest_store("testrepo").await;
+        let index = mstore.get_annotated(&key, Some("a")).await?;
+        let found: Vec<String> = index
+            .manifests()
+            .iter()
+            .map(|d| d.digest().clone())
+            .collect();
+        assert_eq!(found, annotated_a);
+
+        let index = mstore.get_annotated(&key, None).await?;
+        let found: Vec<String> = index
+            .manifests()
+            .iter()
+            .map(|d| d.digest().clone())
+            .collect();
+        assert_eq!(found, annotated);
+
+        let response = router
+            .oneshot(
+                Request::get(format!("/v2/testrepo/annotations?key={key}&value=b"))
+                    .body(Body::empty())?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        let index: ImageIndex = serde_json::from_slice(&body)?;
+        assert_eq!(index.manifests().len(), 1);
+        assert_eq!(index.manifests()[0].digest(), &annotated_b);
+        assert_eq!(
+            index.manifests()[0]
+                .annotations()
+                .as_ref()
+                .and_then(|a| a.get(&key))
+                .map(String::as_str),
+            Some("b")
+        );
+
+        Ok(())
+    }
+
+    /// Push an image to a new repository with the given visibility and return the status of an
+    /// anonymous request to pull its manifest from a registry requiring authentication.
+    async fn anonymous_pull_status(visibility: Visibility) -> Result<StatusCode> {
+        let factory = init_factory(PathBuf::from("../../dev-config-linode.yml")).await?;
+        let tester = RepositoryTester::new(RepositoryLoader::new(Box::new(factory.clone())));
+
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let name = format!("visibility{seed}");
+        factory.create(&name).await?;
+        factory.set_visibility(&name, visibility).await?;
+
+        let mut image = testdata::tagged_images(&name, 1).remove(0);
+        let digest = image.digest();
+        tester
+            .loader
+            .clone()
+            .upload_images(name.clone(), vec![Arc::new(Mutex::new(image))])
+            .await?;
+
+        let portfolio = Portfolio::new(std::sync::Arc::new(factory));
+        // no credentials are configured, so only anonymous requests can succeed
+        let authenticator = BasicAuthenticator::from_htpasswd("")
+            .expect("empty htpasswd is valid")
+            .allow_anonymous_pulls(portfolio.clone());
+        let router = portfolio
+            .router()?
+            .route_layer(middleware::from_fn_with_state(
+                portfolio.clone(),
+                add_basic_repository_extensions,
+            ))
+            .layer(middleware::from_fn_with_state(
+                std::sync::Arc::new(authenticator),
+                basic_auth,
+            ));
+
+        let response = router
+            .oneshot(
+                Request::get(format!("/v2/{name}/manifests/{}", String::from(digest)))
+                    .body(Body::empty())?,
+            )
+            .await?;
+        Ok(response.status())
+    }
+
+    #[tokio::test]
+    async fn anonymous_pull_from_public_repository() -> Result<()> {
+        assert_eq!(
+            anonymous_pull_status(Visibility::Public).await?,
+            StatusCode::OK
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn anonymous_pull_from_private_repository_is_denied() -> Result<()> {
+        assert_eq!(
+            anonymous_pull_status(Visibility::Private).await?,
+            StatusCode::UNAUTHORIZED
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn docker_manifest_converted_to_oci_on_pull() -> Result<()> {
+        let factory = init_factory(PathBuf::from("../../dev-config-linode.yml")).await?;
+        let tester = RepositoryTester::new(RepositoryLoader::new(Box::new(factory.clone())));
+
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let prefix = format!("docker-{seed}");
+        let images = testdata::tagged_images(&prefix, 1);
+        tester
+            .loader
+            .clone()
+            .upload_images(
+                "testrepo".to_string(),
+                images.into_iter().map(Mutex::new).map(Arc::new).collect(),
+            )
+            .await?;
+
+        let portfolio = Portfolio::new(std::sync::Arc::new(factory)).with_config(HttpConfig {
+            convert_docker_manifests: true,
+            ..Default::default()
+        });
+        let router = portfolio
+            .router()?
+            .route_layer(middleware::from_fn_with_state(
+                portfolio.clone(),
+                add_basic_repository_extensions,
+            ));
+
+        // push a docker schema 2 manifest referencing the blobs of the uploaded image
+        let response = router
+            .clone()
+            .oneshot(
+                Request::get(format!("/v2/testrepo/manifests/{prefix}-0")).body(Body::empty())?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        let mut manifest: serde_json::Value = serde_json::from_slice(&body)?;
+        let docker_type = "application/vnd.docker.distribution.manifest.v2+json";
+        manifest["mediaType"] = docker_type.into();
+        manifest["config"]["mediaType"] = "application/vnd.docker.container.image.v1+json".into();
+        for layer in manifest["layers"]
+            .as_array_mut()
+            .expect("manifest has layers")
+        {
+            layer["mediaType"] = "application/vnd.docker.image.rootfs.diff.tar.gzip".into();
+        }
+        let docker_bytes = serde_json::to_vec(&manifest)?;
+
+        let response = router
+            .clone()
+            .oneshot(
+                Request::put(format!("/v2/testrepo/manifests/{prefix}-docker"))
+                    .header("content-type", docker_type)
+                    .body(Body::from(docker_bytes.clone()))?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let digest = response
+            .headers()
+            .get("docker-content-digest")
+            .expect("digest header is set")
+            .to_str()?
+            .to_string();
+
+        // clients preferring OCI get the converted manifest when pulling by tag
+        let response = router
+            .clone()
+            .oneshot(
+                Request::get(format!("/v2/testrepo/manifests/{prefix}-docker"))
+                    .header(
+                        "accept",
+                        format!("application/vnd.oci.image.manifest.v1+json, {docker_type}"),
+                    )
+                    .body(Body::empty())?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()["content-type"],
+            "application/vnd.oci.image.manifest.v1+json"
+        );
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        let converted: ImageManifest = serde_json::from_slice(&body)?;
+        assert_eq!(converted.media_type(), &Some(MediaType::ImageManifest));
+        assert_eq!(converted.config().media_type(), &MediaType::ImageConfig);
+        assert!(converted
+            .layers()
+            .iter()
+            .all(|layer| layer.media_type() == &MediaType::ImageLayerGzip));
+
+        // the stored manifest is still served unchanged by its original digest
+        let response = router
+            .oneshot(
+                Request::get(format!("/v2/testrepo/manifests/{digest}"))
+                    .header("accept", docker_type)
+                    .body(Body::empty())?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], docker_type);
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        assert_eq!(body.as_ref(), docker_bytes.as_slice());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn upload_resumes_from_digest_checkpoint() -> Result<()> {
+        let router = init_router_with_settings(
+            PathBuf::from("../../dev-config-linode.yml"),
+            "checkpoint_digest_state: true",
+        )
+        .await?;
+
+        // make the content unique to this run so earlier runs can't have stored it already
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let content = format!("blob uploaded in chunks with checkpointed digest state {seed}");
+        let digest = String::from(OciDigest::from(content.as_bytes()));
+
+        let response = router
+            .clone()
+            .oneshot(Request::post("/v2/testrepo/blobs/uploads/").body(Body::empty())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let location = response
+            .headers()
+            .get("location")
+            .expect("upload session should have a location")
+            .to_str()?
+            .to_string();
+
+        // each chunk resumes the session, and with it the digest state, from storage
+        let (first, second) = content.as_bytes().split_at(content.len() / 2);
+        let mut start = 0;
+        for chunk in [first, second] {
+            let end = start + chunk.len() - 1;
+            let response = router
+                .clone()
+                .oneshot(
+                    Request::patch(location.as_str())
+                        .header("content-type", "application/octet-stream")
+                        .header("content-length", chunk.len())
+                        .header("content-range", format!("{start}-{end}"))
+                        .body(Body::from(chunk.to_vec()))?,
+                )
+                .await?;
+            assert_eq!(response.status(), StatusCode::ACCEPTED);
+            start = end + 1;
+        }
+
+        let response = router
+            .clone()
+            .oneshot(
+                Request::put(format!("{location}?digest={digest}"))
+                    .header("content-length", 0)
+                    .body(Body::empty())?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let response = router
+            .oneshot(Request::get(format!("/v2/testrepo/blobs/{digest}")).body(Body::empty())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        assert_eq!(body.as_ref(), content.as_bytes());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn blob_head_and_get_set_same_digest_header() -> Result<()> {
+        let router = init_router(PathBuf::from("../../dev-config-linode.yml")).await?;
+
+        // make the content unique to this run so earlier runs can't have stored it already
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let content = format!("blob fetched with both HEAD and GET {seed}");
+        let digest = String::from(OciDigest::from(content.as_bytes()));
+        let response = router
+            .clone()
+            .oneshot(
+                Request::post(format!("/v2/testrepo/blobs/uploads/?digest={digest}"))
+                    .header("content-type", "application/octet-stream")
+                    .header("content-length", content.len())
+                    .body(Body::from(content.clone()))?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let mut digest_headers = Vec::new();
+        for method in [Method::HEAD, Method::GET] {
+            let response = router
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .method(method)
+                        .uri(format!("/v2/testrepo/blobs/{digest}"))
+                        .body(Body::empty())?,
+                )
+                .await?;
+            assert_eq!(response.status(), StatusCode::OK);
+            let headers = response.headers();
+            assert_eq!(
+                headers["content-length"],
+                content.len().to_string().as_str()
+            );
+            digest_headers.push(
+                headers
+                    .get("Docker-Content-Digest")
+                    .expect("digest header is set")
+                    .clone(),
+            );
+        }
+        assert_eq!(digest_headers[0], digest_headers[1]);
+        assert_eq!(digest_headers[0], digest.as_str());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn tags_list_default_page_size() -> Result<()> {
+        let factory = init_factory(PathBuf::from("../../dev-config-linode.yml")).await?;
+        let tester = RepositoryTester::new(RepositoryLoader::new(Box::new(factory.clone())));
+
+        // a fresh repository so that tags pushed by earlier runs don't show up
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let name = format!("defaultpage{seed}");
+        factory.create(&name).await?;
+        let images = testdata::tagged_images("paged", 3);
+        tester
+            .loader
+            .clone()
+            .upload_images(
+                name.clone(),
+                images.into_iter().map(Mutex::new).map(Arc::new).collect(),
+            )
+            .await?;
+
+        let portfolio = Portfolio::new(std::sync::Arc::new(factory)).with_config(HttpConfig {
+            default_tags_page_size: Some(2),
+            ..Default::default()
+        });
+        let router = portfolio
+            .router()?
+            .route_layer(middleware::from_fn_with_state(
+                portfolio.clone(),
+                add_basic_repository_extensions,
+            ));
+
+        let response = router
+            .clone()
+            .oneshot(Request::get(format!("/v2/{name}/tags/list")).body(Body::empty())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let link = response
+            .headers()
+            .get("link")
+            .expect("a capped listing links to the next page")
+            .to_str()?
+            .to_string();
+        let next = link
+            .trim_start_matches('<')
+            .split_once('>')
+            .expect("link header should enclose its uri in angle brackets")
+            .0
+            .to_string();
+        assert!(next.contains("n=2"));
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        let first: TagList = serde_json::from_slice(&body)?;
+        assert_eq!(first.tags().len(), 2);
+
+        let response = router
+            .oneshot(Request::get(next).body(Body::empty())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().get("link").is_none());
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        let second: TagList = serde_json::from_slice(&body)?;
+        assert_eq!(second.tags().len(), 1);
+
+        let tags: HashSet<&String> = first.tags().iter().chain(second.tags()).collect();
+        assert_eq!(tags.len(), 3);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn oversized_referrer_is_rejected() -> Result<()> {
+        let path = PathBuf::from("../../dev-config-linode.yml");
+        let tester = init_backend(path.clone()).await?;
+        let router = init_router_with_settings(path, "max_manifest_read_bytes: 1024").await?;
+
+        // make the subject unique to this run so that referrers pushed by earlier runs don't show
+        // up in the listing
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let mut subject = Image {
+            layers: vec![Arc::new(Mutex::new(Layer {
+                data: format!("oversized referrer subject {seed}"),
+                ..Default::default()
+            }))],
+            ..Default::default()
+        };
+        let subject_digest = String::from(subject.digest());
+
+        let mut referrers = testdata::referrers_of(&mut subject, "oversized", 1);
+        referrers[0].annotations = Some(HashMap::from([(
+            "org.example.padding".to_string(),
+            "x".repeat(2048),
+        )]));
+
+        let mut images = vec![Arc::new(Mutex::new(subject))];
+        images.extend(referrers.into_iter().map(Mutex::new).map(Arc::new));
+        tester
+            .loader
+            .clone()
+            .upload_images("testrepo".to_string(), images)
+            .await?;
+
+        let response = router
+            .oneshot(
+                Request::get(format!("/v2/testrepo/referrers/{subject_digest}"))
+                    .body(Body::empty())?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        let body: serde_json::Value = serde_json::from_slice(&body)?;
+        assert_eq!(body["errors"][0]["code"], "MANIFEST_INVALID");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn export_image_as_oci_layout() -> Result<()> {
+        let tester = init_backend(PathBuf::from("../../dev-config-linode.yml")).await?;
+        let router = init_router(PathBuf::from("../../dev-config-linode.yml")).await?;
+
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let prefix = format!("export-{seed}");
+        let mut image = testdata::tagged_images(&prefix, 1).remove(0);
+        let digest = String::from(image.digest());
+        tester
+            .loader
+            .clone()
+            .upload_images("testrepo".to_string(), vec![Arc::new(Mutex::new(image))])
+            .await?;
+
+        let response = router
+            .oneshot(
+                Request::get(format!("/v2/testrepo/images/{prefix}-0/export"))
+                    .body(Body::empty())?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "application/x-tar");
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+
+        let mut files = HashMap::new();
+        for entry in tar::Archive::new(body.as_ref()).entries()? {
+            let mut entry = entry?;
+            let path = entry.path()?.to_string_lossy().into_owned();
+            let mut contents = Vec::new();
+            entry.read_to_end(&mut contents)?;
+            files.insert(path, contents);
+        }
+
+        let layout: serde_json::Value = serde_json::from_slice(&files["oci-layout"])?;
+        assert_eq!(layout["imageLayoutVersion"], "1.0.0");
+
+        let index: ImageIndex = serde_json::from_slice(&files["index.json"])?;
+        assert_eq!(index.manifests().len(), 1);
+        let descriptor = &index.manifests()[0];
+        assert_eq!(descriptor.digest(), &digest);
+        assert_eq!(
+            descriptor.annotations().as_ref().unwrap()["org.opencontainers.image.ref.name"],
+            format!("{prefix}-0")
+        );
+
+        // every blob is stored under its own digest, including the config and layers referenced
+        // by the manifest
+        let manifest: ImageManifest =
+            serde_json::from_slice(&files[&format!("blobs/{}", digest.replace(':', "/"))])?;
+        let referenced = std::iter::once(manifest.config()).chain(manifest.layers());
+        for d in referenced {
+            assert!(files.contains_key(&format!("blobs/{}", d.digest().replace(':', "/"))));
+        }
+        for (path, contents) in files.iter().filter(|(p, _)| p.starts_with("blobs/")) {
+            let expected = path.trim_start_matches("blobs/").replacen('/', ":", 1);
+            assert_eq!(String::from(OciDigest::from(contents.as_slice())), expected);
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn manifest_push_size_limit() -> Result<()> {
+        let factory = init_factory(PathBuf::from("../../dev-config-linode.yml")).await?;
+        let portfolio = Portfolio::new(std::sync::Arc::new(factory)).with_config(HttpConfig {
+            max_manifest_bytes: Some(1024),
+            ..Default::default()
+        });
+        let router = portfolio
+            .router()?
+            .route_layer(middleware::from_fn_with_state(
+                portfolio.clone(),
+                add_basic_repository_extensions,
+            ));
+
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let index_type = "application/vnd.oci.image.index.v1+json";
+        // an empty index padded out with an annotation to exactly `size` bytes
+        let padded_index = |size: usize| -> Result<Vec<u8>> {
+            let mut index = serde_json::json!({
+                "schemaVersion": 2,
+                "mediaType": index_type,
+                "manifests": [],
+                "annotations": {"seed": seed.to_string(), "padding": ""},
+            });
+            let unpadded = serde_json::to_vec(&index)?.len();
+            index["annotations"]["padding"] = "x".repeat(size - unpadded).into();
+            let bytes = serde_json::to_vec(&index)?;
+            assert_eq!(bytes.len(), size);
+            Ok(bytes)
+        };
+
+        let response = router
+            .clone()
+            .oneshot(
+                Request::put(format!("/v2/testrepo/manifests/limit-{seed}"))
+                    .header("content-type", index_type)
+                    .body(Body::from(padded_index(1024)?))?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        // rejected while reading the body when the client doesn't declare its length
+        let response = router
+            .clone()
+            .oneshot(
+                Request::put(format!("/v2/testrepo/manifests/limit-{seed}"))
+                    .header("content-type", index_type)
+                    .body(Body::from(padded_index(1025)?))?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        let body: serde_json::Value = serde_json::from_slice(&body)?;
+        assert_eq!(body["errors"][0]["code"], "MANIFEST_INVALID");
+
+        // and up front when it does
+        let response = router
+            .oneshot(
+                Request::put(format!("/v2/testrepo/manifests/limit-{seed}"))
+                    .header("content-type", index_type)
+                    .header("content-length", "1025")
+                    .body(Body::from(padded_index(1025)?))?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn postgres_pool_waits_for_connections() -> Result<()> {
+        let mut postgres = load_postgres_settings(PathBuf::from("../../dev-config-linode.yml"))?;
+        postgres["max_connections"] = 1.into();
+        let postgres: PostgresConfig = serde_yaml::from_value(postgres)?;
+        let pool = postgres.new_metadata().await?;
+
+        // with the only connection checked out, acquiring another waits rather than failing
+        let held = pool.get_conn().await?;
+        let waiting =
+            tokio::time::timeout(std::time::Duration::from_millis(500), pool.get_conn()).await;
+        assert!(waiting.is_err());
+
+        drop(held);
+        tokio::time::timeout(std::time::Duration::from_secs(5), pool.get_conn()).await??;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn manifest_children_associated_in_batches() -> Result<()> {
+        let tester = init_backend_with_settings(
+            PathBuf::from("../../dev-config-linode.yml"),
+            "association_batch_size: 3",
+        )
+        .await?;
+
+        // an image with many more layers than fit in a single batch
+        let image = Image {
+            layers: (0..50)
+                .map(|i| {
+                    Arc::new(Mutex::new(Layer {
+                        data: format!("batched layer {i}"),
+                        ..Default::default()
+                    }))
+                })
+                .collect(),
+            ..Default::default()
+        };
+        tester.push_and_pull_images(vec![image]).await?;
+
+        // and an index whose 32 children are spread over 11 batches, all of which must be
+        // associated for every platform to resolve
+        tester
+            .resolve_index_platforms(testdata::multi_platform_index())
+            .await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn referrers_can_be_disabled() -> Result<()> {
+        let path = PathBuf::from("../../dev-config-linode.yml");
+        let tester = init_backend_with_settings(path.clone(), "enable_referrers: false").await?;
+        let router = init_router_with_settings(path.clone(), "enable_referrers: false").await?;
+
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let mut subject = Image {
+            layers: vec![Arc::new(Mutex::new(Layer {
+                data: format!("referrers disabled subject {seed}"),
+                ..Default::default()
+            }))],
+            ..Default::default()
+        };
+        let subject_digest = String::from(subject.digest());
+        let referrers = testdata::referrers_of(&mut subject, "disabled", 1);
+
+        // pushing manifests with a subject still works
+        let mut images = vec![Arc::new(Mutex::new(subject))];
+        images.extend(referrers.into_iter().map(Mutex::new).map(Arc::new));
+        tester
+            .loader
+            .clone()
+            .upload_images("testrepo".to_string(), images)
+            .await?;
+
+        let response = router
+            .clone()
+            .oneshot(
+                Request::get(format!("/v2/testrepo/referrers/{subject_digest}"))
+                    .body(Body::empty())?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        let body: serde_json::Value = serde_json::from_slice(&body)?;
+        assert_eq!(body["errors"][0]["code"], "UNSUPPORTED");
+
+        let response = router
+            .oneshot(Request::get("/v2/_catalog/features").body(Body::empty())?)
+            .await?;
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        let body: serde_json::Value = serde_json::from_slice(&body)?;
+        assert_eq!(body["referrers"], false);
+
+        // the referrer's subject wasn't recorded, so it doesn't show up once referrers are enabled
+        let response = init_router(path)
+            .await?
+            .oneshot(
+                Request::get(format!("/v2/testrepo/referrers/{subject_digest}"))
+                    .body(Body::empty())?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        let index: ImageIndex = serde_json::from_slice(&body)?;
+        assert!(index.manifests().is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn migrations_run_against_fresh_database() -> Result<()> {
+        use sqlx::Connection;
+
+        let mut postgres = load_postgres_settings(PathBuf::from("../../dev-config-linode.yml"))?;
+        let connection_string = postgres["connection_string"]
+            .as_str()
+            .expect("dev config includes a connection string")
+            .to_string();
+
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let database = format!("portfolio_migrations_{seed}");
+        let mut conn = sqlx::PgConnection::connect(&connection_string).await?;
+        sqlx::query(&format!("CREATE DATABASE {database}"))
+            .execute(&mut conn)
+            .await?;
+
+        // point the connection string at the new database, keeping any parameters
+        let (url, params) = match connection_string.split_once('?') {
+            Some((url, params)) => (url, format!("?{params}")),
+            None => (connection_string.as_str(), String::new()),
+        };
+        let (server, _) = url
+            .rsplit_once('/')
+            .expect("connection string names a database");
+        let fresh = format!("{server}/{database}{params}");
+        postgres["connection_string"] = fresh.clone().into();
+        let postgres: PostgresConfig = serde_yaml::from_value(postgres)?;
+
+        let pool = postgres.new_metadata().await?;
+        // already applied migrations are skipped
+        pool.migrate().await?;
+
+        let mut conn = sqlx::PgConnection::connect(&fresh).await?;
+        let tables: HashSet<String> = sqlx::query_scalar(
+            "SELECT table_name::TEXT FROM information_schema.tables WHERE table_schema = 'public'",
+        )
+        .fetch_all(&mut conn)
+        .await?
+        .into_iter()
+        .collect();
+        for table in [
+            "repositories",
+            "blobs",
+            "manifests",
+            "index_manifests",
+            "layers",
+            "tags",
+            "upload_sessions",
+            "chunks",
+            "object_deletions",
+        ] {
+            assert!(tables.contains(table), "missing table {table}");
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn blob_missing_from_object_store() -> Result<()> {
+        use sqlx::Connection;
+
+        let path = PathBuf::from("../../dev-config-linode.yml");
+        let router = init_router(path.clone()).await?;
+
+        // record a blob whose content was never written to the object store
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let digest = String::from(OciDigest::from(
+            format!("blob missing from object store {seed}").as_bytes(),
+        ));
+        let postgres = load_postgres_settings(path)?;
+        let connection_string = postgres["connection_string"]
+            .as_str()
+            .expect("dev config includes a connection string");
+        let mut conn = sqlx::PgConnection::connect(connection_string).await?;
+        sqlx::query("INSERT INTO blobs (digest, bytes_on_disk) VALUES ($1, 42)")
+            .bind(&digest)
+            .execute(&mut conn)
+            .await?;
+
+        let response = router
+            .oneshot(Request::get(format!("/v2/testrepo/blobs/{digest}")).body(Body::empty())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        let body: serde_json::Value = serde_json::from_slice(&body)?;
+        assert_eq!(body["errors"][0]["code"], "BlobContentMissing");
+        assert_eq!(
+            body["errors"][0]["message"],
+            "blob content missing from storage"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn digest_round_trips_through_postgres() -> Result<()> {
+        use sqlx::Connection;
+
+        let postgres = load_postgres_settings(PathBuf::from("../../dev-config-linode.yml"))?;
+        let connection_string = postgres["connection_string"]
+            .as_str()
+            .expect("dev config includes a connection string");
+        let mut conn = sqlx::PgConnection::connect(connection_string).await?;
+
+        let digest = OciDigest::from(b"digest round trip".as_slice());
+        let decoded: OciDigest = sqlx::query_scalar("SELECT $1::VARCHAR")
+            .bind(&digest)
+            .fetch_one(&mut conn)
+            .await?;
+        assert_eq!(decoded, digest);
+
+        let decoded: Option<OciDigest> = sqlx::query_scalar("SELECT NULL::VARCHAR")
+            .fetch_one(&mut conn)
+            .await?;
+        assert_eq!(decoded, None);
+
+        // malformed digests fail to decode rather than being passed along
+        let malformed = sqlx::query_scalar::<_, OciDigest>("SELECT 'meow'::VARCHAR")
+            .fetch_one(&mut conn)
+            .await;
+        assert!(matches!(malformed, Err(sqlx::Error::ColumnDecode { .. })));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn catalog_prefix_search() -> Result<()> {
+        let factory = init_factory(PathBuf::from("../../dev-config-linode.yml")).await?;
+        let router = init_router(PathBuf::from("../../dev-config-linode.yml")).await?;
+
+        // root the names in a directory unique to this run so other repositories can't match
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let root = format!("search-{seed}");
+        for name in ["a/x", "a/y", "b/z"] {
+            factory.create(&format!("{root}/{name}")).await?;
+        }
+
+        let first = factory.search(&format!("{root}/a/"), Some(1), None).await?;
+        assert_eq!(first, vec![format!("{root}/a/x")]);
+        let second = factory
+            .search(&format!("{root}/a/"), Some(1), first.last().cloned())
+            .await?;
+        assert_eq!(second, vec![format!("{root}/a/y")]);
+        let third = factory
+            .search(&format!("{root}/a/"), Some(1), second.last().cloned())
+            .await?;
+        assert!(third.is_empty());
+
+        // wildcards are matched literally
+        assert!(factory
+            .search(&format!("{root}/_/"), None, None)
+            .await?
+            .is_empty());
+        assert!(factory
+            .search(&format!("{root}/%"), None, None)
+            .await?
+            .is_empty());
+
+        let response = router
+            .clone()
+            .oneshot(
+                Request::get(format!("/v2/_catalog?prefix={root}/a/&n=1")).body(Body::empty())?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let next = response
+            .headers()
+            .get("link")
+            .expect("a full page should link to the next one")
+            .to_str()?
+            .to_string();
+        assert!(next.contains(&format!("prefix={root}%2Fa%2F")));
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        let catalog: serde_json::Value = serde_json::from_slice(&body)?;
+        assert_eq!(
+            catalog,
+            serde_json::json!({ "repositories": [format!("{root}/a/x")] })
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn deleted_manifest_restorable_until_purged() -> Result<()> {
+        let path = PathBuf::from("../../dev-config-linode.yml");
+        let settings = "{object_sweep_interval_secs: 3600, manifest_retention_secs: 2}";
+        let factory = init_factory_with_settings(path.clone(), settings).await?;
+        let router = init_router_with_settings(path, settings).await?;
+        let tester = RepositoryTester::new(RepositoryLoader::new(Box::new(factory.clone())));
+
+        // make the manifest unique to this run so earlier runs can't have pushed it already
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let prefix = format!("tombstoned-{seed}");
+        let mut images = testdata::tagged_images(&prefix, 1);
+        let digest = images[0].digest();
+        tester
+            .loader
+            .clone()
+            .upload_images(
+                "tombstones".to_string(),
+                images.into_iter().map(Mutex::new).map(Arc::new).collect(),
+            )
+            .await?;
+        let by_digest = format!("/v2/tombstones/manifests/{}", String::from(&digest));
+        let by_tag = format!("/v2/tombstones/manifests/{prefix}-0");
+        let get = |uri: &str| Request::get(uri).body(Body::empty());
+
+        let response = router
+            .clone()
+            .oneshot(Request::delete(by_digest.as_str()).body(Body::empty())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        for uri in [&by_digest, &by_tag] {
+            let response = router.clone().oneshot(get(uri)?).await?;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        }
+
+        factory.undelete_manifest("tombstones", &digest).await?;
+        for uri in [&by_digest, &by_tag] {
+            let response = router.clone().oneshot(get(uri)?).await?;
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+
+        let response = router
+            .clone()
+            .oneshot(Request::delete(by_digest.as_str()).body(Body::empty())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let reaper = factory
+            .manifest_reaper()
+            .expect("a retention window is configured");
+        reaper.reap().await?;
+        assert!(factory.object_key(&digest).await?.is_some());
+
+        tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+        assert!(reaper.reap().await? >= 1);
+        assert!(factory.object_key(&digest).await?.is_none());
+        assert!(matches!(
+            factory.undelete_manifest("tombstones", &digest).await,
+            Err(CoreError::ManifestUnknown(_))
+        ));
+        let response = router.oneshot(get(&by_digest)?).await?;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn deleted_tag_restorable() -> Result<()> {
+        let path = PathBuf::from("../../dev-config-linode.yml");
+        let settings = "{object_sweep_interval_secs: 3600, manifest_retention_secs: 3600}";
+        let factory = init_factory_with_settings(path.clone(), settings).await?;
+        let router = init_router_with_settings(path, settings).await?;
+        let tester = RepositoryTester::new(RepositoryLoader::new(Box::new(factory.clone())));
+
+        // make the manifest unique to this run so earlier runs can't have pushed it already
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let prefix = format!("untagged-{seed}");
+        let images = testdata::tagged_images(&prefix, 1);
+        tester
+            .loader
+            .clone()
+            .upload_images(
+                "tombstones".to_string(),
+                images.into_iter().map(Mutex::new).map(Arc::new).collect(),
+            )
+            .await?;
+        let tag = format!("{prefix}-0");
+        let by_tag = format!("/v2/tombstones/manifests/{tag}");
+        let get = |uri: &str| Request::get(uri).body(Body::empty());
+
+        let response = router
+            .clone()
+            .oneshot(Request::delete(by_tag.as_str()).body(Body::empty())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let response = router.clone().oneshot(get(&by_tag)?).await?;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        factory.undelete_tag("tombstones", &tag).await?;
+        let response = router.clone().oneshot(get(&by_tag)?).await?;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // a tag can only be restored once
+        assert!(matches!(
+            factory.undelete_tag("tombstones", &tag).await,
+            Err(CoreError::ManifestUnknown(_))
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn repushing_deleted_manifest_applies_tag() -> Result<()> {
+        let path = PathBuf::from("../../dev-config-linode.yml");
+        let settings = "{object_sweep_interval_secs: 3600, manifest_retention_secs: 3600}";
+        let router = init_router_with_settings(path, settings).await?;
+
+        // make the manifest unique to this run so earlier runs can't have pushed it already
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let index = serde_json::to_vec(&serde_json::json!({
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.oci.image.index.v1+json",
+            "manifests": [],
+            "annotations": {"seed": seed.to_string()},
+        }))?;
+        let digest = String::from(OciDigest::from(index.as_slice()));
+        let put = |reference: String| {
+            Request::put(format!("/v2/tombstones/manifests/{reference}"))
+                .header("content-type", "application/vnd.oci.image.index.v1+json")
+                .body(Body::from(index.clone()))
+        };
+        let get = |reference: String| {
+            Request::get(format!("/v2/tombstones/manifests/{reference}")).body(Body::empty())
+        };
+
+        let response = router
+            .clone()
+            .oneshot(put(format!("first-{seed}"))?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let response = router
+            .clone()
+            .oneshot(
+                Request::delete(format!("/v2/tombstones/manifests/{digest}")).body(Body::empty())?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+        // pushing the tombstoned manifest again restores it and points the new tag at it
+        let response = router
+            .clone()
+            .oneshot(put(format!("second-{seed}"))?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        for reference in [format!("second-{seed}"), digest] {
+            let response = router.clone().oneshot(get(reference)?).await?;
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn manifest_content_type_served_verbatim() -> Result<()> {
+        let router = init_router(PathBuf::from("../../dev-config-linode.yml")).await?;
+
+        // make the manifest unique to this run so earlier runs can't have pushed it already
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let index = serde_json::to_vec(&serde_json::json!({
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.oci.image.index.v1+json",
+            "manifests": [],
+            "annotations": {"seed": seed.to_string()},
+        }))?;
+        let digest = String::from(OciDigest::from(index.as_slice()));
+        let content_type = "application/vnd.oci.image.index.v1+json; charset=UTF-8";
+
+        let response = router
+            .clone()
+            .oneshot(
+                Request::put(format!("/v2/testrepo/manifests/verbatim-{seed}"))
+                    .header("content-type", content_type)
+                    .body(Body::from(index))?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        for reference in [format!("verbatim-{seed}"), digest] {
+            let response = router
+                .clone()
+                .oneshot(
+                    Request::get(format!("/v2/testrepo/manifests/{reference}"))
+                        .body(Body::empty())?,
+                )
+                .await?;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response.headers()["content-type"], content_type);
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn readiness_probes() -> Result<()> {
+        let router = init_router(PathBuf::from("../../dev-config-linode.yml")).await?;
+
+        let response = router
+            .clone()
+            .oneshot(Request::get("/healthz").body(Body::empty())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = router
+            .oneshot(Request::get("/readyz").body(Body::empty())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        let readiness: serde_json::Value = serde_json::from_slice(&body)?;
+        assert_eq!(readiness, serde_json::json!({"ready": true, "failed": {}}));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn request_id_echoed() -> Result<()> {
+        let router = init_router(PathBuf::from("../../dev-config-linode.yml")).await?;
+
+        let response = router
+            .clone()
+            .oneshot(
+                Request::get("/v2/")
+                    .header("x-request-id", "meow-1234")
+                    .body(Body::empty())?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["x-request-id"], "meow-1234");
+
+        // one is generated for requests that don't carry their own
+        let response = router
+            .oneshot(Request::get("/v2/").body(Body::empty())?)
+            .await?;
+        let request_id = response.headers()["x-request-id"].to_str()?;
+        assert!(!request_id.is_empty());
+        assert_ne!(request_id, "meow-1234");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn validate_manifest_reports_missing_blobs() -> Result<()> {
+        let tester = init_backend(PathBuf::from("../../dev-config-linode.yml")).await?;
+        let router = init_router(PathBuf::from("../../dev-config-linode.yml")).await?;
+
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let mut images = testdata::tagged_images(&format!("validate-{seed}"), 1);
+        let mut manifest = images[0].manifest();
+        tester
+            .loader
+            .clone()
+            .upload_images(
+                "testrepo".to_string(),
+                images.into_iter().map(Mutex::new).map(Arc::new).collect(),
+            )
+            .await?;
+
+        let validate = |manifest: &ImageManifest| -> Result<Request<Body>> {
+            Ok(Request::post("/v2/testrepo/manifests/validate")
+                .header("content-type", "application/vnd.oci.image.manifest.v1+json")
+                .body(Body::from(serde_json::to_vec(manifest)?))?)
+        };
+
+        let response = router.clone().oneshot(validate(&manifest)?).await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        let validation: serde_json::Value = serde_json::from_slice(&body)?;
+        assert_eq!(validation, serde_json::json!({"missing": []}));
+
+        // a layer that was never pushed
+        let unpushed = OciDigest::from(format!("unpushed-{seed}").as_bytes());
+        let mut layers = manifest.layers().clone();
+        layers.push(Descriptor::new(
+            MediaType::ImageLayerGzip,
+            32,
+            String::from(&unpushed),
+        ));
+        manifest.set_layers(layers);
+        let bytes = serde_json::to_vec(&manifest)?;
+
+        let response = router.clone().oneshot(validate(&manifest)?).await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        let validation: serde_json::Value = serde_json::from_slice(&body)?;
+        assert_eq!(
+            validation,
+            serde_json::json!({"missing": [String::from(&unpushed)]})
+        );
+
+        // nothing was stored
+        let digest = OciDigest::from(bytes.as_slice());
+        let response = router
+            .oneshot(
+                Request::head(format!("/v2/testrepo/manifests/{}", String::from(&digest)))
+                    .body(Body::empty())?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn repository_creation_policies() -> Result<()> {
+        let factory = init_factory(PathBuf::from("../../dev-config-linode.yml")).await?;
+
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let allow_list =
+            RepositoryCreationPolicy::AllowList(vec![format!("policy-{seed}/allowed/*")]);
+        for (policy, name, created) in [
+            (RepositoryCreationPolicy::AutoCreate, "auto", true),
+            (RepositoryCreationPolicy::Deny, "denied", false),
+            (allow_list.clone(), "allowed/meow", true),
+            (allow_list, "unlisted/meow", false),
+        ] {
+            let name = format!("policy-{seed}/{name}");
+            let portfolio =
+                Portfolio::new(std::sync::Arc::new(factory.clone())).with_config(HttpConfig {
+                    repository_creation: policy.clone(),
+                    ..Default::default()
+                });
+            let router = portfolio
+                .router()?
+                .route_layer(middleware::from_fn_with_state(
+                    portfolio.clone(),
+                    add_basic_repository_extensions,
+                ));
+
+            let response = router
+                .oneshot(Request::get(format!("/v2/{name}/tags/list")).body(Body::empty())?)
+                .await?;
+            if created {
+                assert_eq!(response.status(), StatusCode::OK, "{policy:?}");
+            } else {
+                assert_eq!(response.status(), StatusCode::NOT_FOUND, "{policy:?}");
+            }
+            assert_eq!(factory.get(&name).await?.is_some(), created, "{policy:?}");
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn stale_upload_sessions_cleaned_up() -> Result<()> {
+        use portfolio_objectstore::Key;
+        use sqlx::Connection;
+
+        let path = PathBuf::from("../../dev-config-linode.yml");
+        let factory = init_factory(path.clone()).await?;
+        let router = init_router(path.clone()).await?;
+
+        // start an upload and write a chunk to it so that a multipart upload is initiated
+        let response = router
+            .clone()
+            .oneshot(Request::post("/v2/testrepo/blobs/uploads/").body(Body::empty())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let location = response
+            .headers()
+            .get("location")
+            .expect("upload session should have a location")
+            .to_str()?
+            .to_string();
+        let session_uuid = location
+            .rsplit('/')
+            .next()
+            .expect("location should end with the session uuid")
+            .to_string();
+        let response = router
+            .clone()
+            .oneshot(
+                Request::patch(location.as_str())
+                    .header("content-type", "application/octet-stream")
+                    .header("content-length", 10)
+                    .header("content-range", "0-9")
+                    .body(Body::from(vec![b'x'; 10]))?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+        let postgres = load_postgres_settings(path)?;
+        let connection_string = postgres["connection_string"]
+            .as_str()
+            .expect("dev config includes a connection string");
+        let mut conn = sqlx::PgConnection::connect(connection_string).await?;
+        let upload_id: Option<String> =
+            sqlx::query_scalar("SELECT upload_id FROM upload_sessions WHERE uuid = $1::UUID")
+                .bind(&session_uuid)
+                .fetch_one(&mut conn)
+                .await?;
+        let upload_id = upload_id.expect("writing a chunk should initiate a multipart upload");
+
+        // a fresh session is left alone
+        factory
+            .cleanup_stale_sessions(std::time::Duration::from_secs(3600))
+            .await?;
+        let response = router
+            .clone()
+            .oneshot(Request::get(location.as_str()).body(Body::empty())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+        sqlx::query(
+            "UPDATE upload_sessions SET start_date = now() - interval '2 hours' \
+             WHERE uuid = $1::UUID",
+        )
+        .bind(&session_uuid)
+        .execute(&mut conn)
+        .await?;
+        assert!(
+            factory
+                .cleanup_stale_sessions(std::time::Duration::from_secs(3600))
+                .await?
+                >= 1
+        );
+
+        let response = router
+            .oneshot(Request::get(location.as_str()).body(Body::empty())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        // the multipart upload was aborted, so no more parts can be uploaded to it
+        let chunk = factory
+            .objects()
+            .upload_chunk(
+                &upload_id,
+                &Key::from_pathbuf(PathBuf::from(&session_uuid))?,
+                2,
+                1,
+                Body::from("x"),
+            )
+            .await;
+        assert!(chunk.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn upload_progress_reported() -> Result<()> {
+        let router = init_router(PathBuf::from("../../dev-config-linode.yml")).await?;
+
+        let response = router
+            .clone()
+            .oneshot(Request::post("/v2/testrepo/blobs/uploads/").body(Body::empty())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let location = response
+            .headers()
+            .get("location")
+            .expect("upload session should have a location")
+            .to_str()?
+            .to_string();
+
+        let mut start = 0;
+        for size in [10usize, 20] {
+            let response = router
+                .clone()
+                .oneshot(
+                    Request::patch(location.as_str())
+                        .header("content-type", "application/octet-stream")
+                        .header("content-length", size)
+                        .header("content-range", format!("{start}-{}", start + size - 1))
+                        .body(Body::from(vec![b'x'; size]))?,
+                )
+                .await?;
+            assert_eq!(response.status(), StatusCode::ACCEPTED);
+            start += size;
+        }
+
+        let response = router
+            .oneshot(Request::get(location.as_str()).body(Body::empty())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert_eq!(response.headers().get("range").unwrap(), "0-29");
+        assert_eq!(
+            response.headers().get("x-upload-bytes-received").unwrap(),
+            "30"
+        );
+        assert_eq!(response.headers().get("x-upload-chunk-count").unwrap(), "2");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn concurrent_tag_pushes_are_consistent() -> Result<()> {
+        let tester = init_backend(PathBuf::from("../../dev-config-linode.yml")).await?;
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+
+        for round in 0..5 {
+            tester
+                .push_concurrently_to_tag("testrepo", &format!("concurrent-{seed}-{round}"))
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn repository_creation_rate_limited() -> Result<()> {
+        let factory = init_factory(PathBuf::from("../../dev-config-linode.yml")).await?;
+        let portfolio =
+            Portfolio::new(std::sync::Arc::new(factory.clone())).with_config(HttpConfig {
+                max_repository_creations_per_minute: Some(2),
+                ..Default::default()
+            });
+        let router = portfolio
+            .router()?
+            .route_layer(middleware::from_fn_with_state(
+                portfolio.clone(),
+                add_basic_repository_extensions,
+            ));
+
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let expected_statuses = [
+            StatusCode::OK,
+            StatusCode::OK,
+            StatusCode::TOO_MANY_REQUESTS,
+        ];
+        for (i, expected) in expected_statuses.into_iter().enumerate() {
+            let name = format!("rate-limited-{seed}/{i}");
+            let response = router
+                .clone()
+                .oneshot(Request::get(format!("/v2/{name}/tags/list")).body(Body::empty())?)
+                .await?;
+            assert_eq!(response.status(), expected, "{name}");
+            assert_eq!(
+                factory.get(&name).await?.is_some(),
+                expected == StatusCode::OK,
+                "{name}"
+            );
+        }
+
+        // accessing repositories that already exist isn't limited
+        let response = router
+            .oneshot(
+                Request::get(format!("/v2/rate-limited-{seed}/0/tags/list")).body(Body::empty())?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn manifest_put_returns_digest() -> Result<()> {
+        let path = PathBuf::from("../../dev-config-linode.yml");
+        let factory = init_factory(path.clone()).await?;
+        let router = init_router(path).await?;
+        let tester = RepositoryTester::new(RepositoryLoader::new(Box::new(factory)));
+
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let prefix = format!("put-digest-{seed}");
+        let images = testdata::tagged_images(&prefix, 1);
+        tester
+            .loader
+            .clone()
+            .upload_images(
+                "testrepo".to_string(),
+                images.into_iter().map(Mutex::new).map(Arc::new).collect(),
+            )
+            .await?;
+
+        let response = router
+            .clone()
+            .oneshot(
+                Request::get(format!("/v2/testrepo/manifests/{prefix}-0")).body(Body::empty())?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let content_type = response.headers()["content-type"].clone();
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+
+        // annotate the manifest so that its digest differs from the uploaded image's
+        let mut manifest: serde_json::Value = serde_json::from_slice(&body)?;
+        manifest["annotations"] = serde_json::json!({ "seed": seed.to_string() });
+        let bytes = serde_json::to_vec(&manifest)?;
+        let digest = String::from(OciDigest::from(bytes.as_slice()));
+
+        let response = router
+            .clone()
+            .oneshot(
+                Request::put(format!("/v2/testrepo/manifests/{prefix}-annotated"))
+                    .header("content-type", content_type)
+                    .body(Body::from(bytes))?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(
+            response.headers().get("docker-content-digest"),
+            Some(&digest.parse()?),
+        );
+
+        let response = router
+            .oneshot(Request::get(format!("/v2/testrepo/manifests/{digest}")).body(Body::empty())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn metadata_snapshot_restores_deleted_rows() -> Result<()> {
+        use portfolio_backend_postgres::MetadataSnapshot;
+        use sqlx::Connection;
+
+        let path = PathBuf::from("../../dev-config-linode.yml");
+        let factory = init_factory(path.clone()).await?;
+        let router = init_router(path.clone()).await?;
+        let tester = RepositoryTester::new(RepositoryLoader::new(Box::new(factory.clone())));
+
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let prefix = format!("snapshot-{seed}");
+        let images = testdata::tagged_images(&prefix, 1);
+        tester
+            .loader
+            .clone()
+            .upload_images(
+                "testrepo".to_string(),
+                images.into_iter().map(Mutex::new).map(Arc::new).collect(),
+            )
+            .await?;
+        let tag = format!("{prefix}-0");
+
+        let snapshot = factory.export_snapshot().await?;
+        assert!(snapshot
+            .repositories
+            .iter()
+            .any(|r| r["name"] == "testrepo"));
+        let snapshot: MetadataSnapshot = serde_json::from_str(&serde_json::to_string(&snapshot)?)?;
+        // other tests delete content concurrently, so only this test's tag is restored
+        let snapshot = MetadataSnapshot {
+            tags: snapshot
+                .tags
+                .into_iter()
+                .filter(|t| t["name"] == tag.as_str())
+                .collect(),
+            ..Default::default()
+        };
+        assert_eq!(snapshot.tags.len(), 1);
+
+        let postgres = load_postgres_settings(path)?;
+        let connection_string = postgres["connection_string"]
+            .as_str()
+            .expect("dev config includes a connection string");
+        let mut conn = sqlx::PgConnection::connect(connection_string).await?;
+        sqlx::query("DELETE FROM tags WHERE name = $1")
+            .bind(&tag)
+            .execute(&mut conn)
+            .await?;
+        let response = router
+            .clone()
+            .oneshot(Request::get(format!("/v2/testrepo/manifests/{tag}")).body(Body::empty())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        assert_eq!(factory.import_snapshot(&snapshot).await?, 1);
+        // rows that already exist are skipped
+        assert_eq!(factory.import_snapshot(&snapshot).await?, 0);
+        let response = router
+            .oneshot(Request::get(format!("/v2/testrepo/manifests/{tag}")).body(Body::empty())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn manifest_head_returns_headers_without_body() -> Result<()> {
+        let path = PathBuf::from("../../dev-config-linode.yml");
+        let factory = init_factory(path.clone()).await?;
+        let router = init_router(path).await?;
+        let tester = RepositoryTester::new(RepositoryLoader::new(Box::new(factory)));
+
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let prefix = format!("head-{seed}");
+        let images = testdata::tagged_images(&prefix, 1);
+        tester
+            .loader
+            .clone()
+            .upload_images(
+                "testrepo".to_string(),
+                images.into_iter().map(Mutex::new).map(Arc::new).collect(),
+            )
+            .await?;
+        let tag = format!("{prefix}-0");
+
+        let response = router
+            .clone()
+            .oneshot(Request::get(format!("/v2/testrepo/manifests/{tag}")).body(Body::empty())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let expected = response.headers().clone();
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        let digest = String::from(OciDigest::from(body.as_ref()));
+        assert_eq!(expected["docker-content-digest"], digest.as_str());
+        assert_eq!(expected["content-length"], body.len().to_string().as_str());
+
+        for reference in [tag.as_str(), digest.as_str()] {
+            let response = router
+                .clone()
+                .oneshot(
+                    Request::head(format!("/v2/testrepo/manifests/{reference}"))
+                        .body(Body::empty())?,
+                )
+                .await?;
+            assert_eq!(response.status(), StatusCode::OK, "{reference}");
+            for name in ["content-length", "content-type", "docker-content-digest"] {
+                assert_eq!(
+                    response.headers().get(name),
+                    expected.get(name),
+                    "{reference} {name}"
+                );
+            }
+            let body = hyper::body::to_bytes(response.into_body()).await?;
+            assert!(body.is_empty(), "{reference}");
+        }
+
+        let response = router
+            .oneshot(
+                Request::head(format!("/v2/testrepo/manifests/{prefix}-missing"))
+                    .body(Body::empty())?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn manifest_put_returns_oci_subject() -> Result<()> {
+        let tester = init_backend(PathBuf::from("../../dev-config-linode.yml")).await?;
+        let router = init_router(PathBuf::from("../../dev-config-linode.yml")).await?;
+
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let mut subject = Image {
+            layers: vec![Arc::new(Mutex::new(Layer {
+                data: format!("oci-subject subject {seed}"),
+                ..Default::default()
+            }))],
+            ..Default::default()
+        };
+        let subject_digest = String::from(subject.digest());
+        let mut referrers = testdata::referrers_of(&mut subject, "oci-subject", 1);
+        let referrer_digest = String::from(referrers[0].digest());
+        let images = vec![
+            Arc::new(Mutex::new(subject)),
+            Arc::new(Mutex::new(referrers.remove(0))),
+        ];
+        tester
+            .loader
+            .clone()
+            .upload_images("testrepo".to_string(), images)
+            .await?;
+
+        // push each manifest again through the router to see the headers returned for it
+        for (digest, expected) in [
+            (&subject_digest, None),
+            (&referrer_digest, Some(subject_digest.as_str())),
+        ] {
+            let response = router
+                .clone()
+                .oneshot(
+                    Request::get(format!("/v2/testrepo/manifests/{digest}")).body(Body::empty())?,
+                )
+                .await?;
+            assert_eq!(response.status(), StatusCode::OK);
+            let content_type = response.headers()["content-type"].clone();
+            let body = hyper::body::to_bytes(response.into_body()).await?;
+
+            let response = router
+                .clone()
+                .oneshot(
+                    Request::put(format!("/v2/testrepo/manifests/{digest}"))
+                        .header("content-type", content_type)
+                        .body(Body::from(body))?,
+                )
+                .await?;
+            assert_eq!(response.status(), StatusCode::CREATED);
+            assert_eq!(
+                response
+                    .headers()
+                    .get("oci-subject")
+                    .map(|v| v.to_str())
+                    .transpose()?,
+                expected,
+                "{digest}"
+            );
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn referrers_fallback_tag_maintained() -> Result<()> {
+        let factory = init_factory_with_settings(
+            PathBuf::from("../../dev-config-linode.yml"),
+            "referrers_fallback_tag: true",
+        )
+        .await?;
+        let tester = RepositoryTester::new(RepositoryLoader::new(Box::new(factory.clone())));
+        let portfolio = Portfolio::new(std::sync::Arc::new(factory));
+        let router = portfolio
+            .router()?
+            .route_layer(middleware::from_fn_with_state(
+                portfolio.clone(),
+                add_basic_repository_extensions,
+            ));
+
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let mut subject = Image {
+            layers: vec![Arc::new(Mutex::new(Layer {
+                data: format!("fallback tag subject {seed}"),
+                ..Default::default()
+            }))],
+            ..Default::default()
+        };
+        let subject_digest = String::from(subject.digest());
+        let mut referrers = testdata::referrers_of(&mut subject, "fallback", 1);
+        let referrer_digest = String::from(referrers[0].digest());
+        let images = vec![
+            Arc::new(Mutex::new(subject)),
+            Arc::new(Mutex::new(referrers.remove(0))),
+        ];
+        tester
+            .loader
+            .clone()
+            .upload_images("testrepo".to_string(), images)
+            .await?;
+
+        let tag = subject_digest.replacen(':', "-", 1);
+        let response = router
+            .clone()
+            .oneshot(Request::get(format!("/v2/testrepo/manifests/{tag}")).body(Body::empty())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        let index: ImageIndex = serde_json::from_slice(&body)?;
+        let digests: Vec<&String> = index.manifests().iter().map(|d| d.digest()).collect();
+        assert_eq!(digests, vec![&referrer_digest]);
+
+        // the tag goes away along with the subject's last referrer
+        let response = router
+            .clone()
+            .oneshot(
+                Request::delete(format!("/v2/testrepo/manifests/{referrer_digest}"))
+                    .body(Body::empty())?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let response = router
+            .oneshot(Request::get(format!("/v2/testrepo/manifests/{tag}")).body(Body::empty())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn storage_stats_broken_down_by_kind() -> Result<()> {
+        use portfolio_backend_postgres::StorageStats;
+
+        let factory = init_factory(PathBuf::from("../../dev-config-linode.yml")).await?;
+        let tester = RepositoryTester::new(RepositoryLoader::new(Box::new(factory.clone())));
+
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let repository = format!("stats-{seed}");
+        let mut image = testdata::tagged_images(&repository, 1).remove(0);
+        image.layers.push(Arc::new(Mutex::new(Layer {
+            data: format!("second layer for {repository}"),
+            ..Default::default()
+        })));
+        let manifest = image.manifest();
+        let expected = StorageStats {
+            manifest_bytes: image.descriptor().size() as u64,
+            layer_bytes: manifest.layers().iter().map(|l| l.size() as u64).sum(),
+            config_bytes: manifest.config().size() as u64,
+            ..Default::default()
+        };
+        tester
+            .loader
+            .clone()
+            .upload_images(repository.clone(), vec![Arc::new(Mutex::new(image))])
+            .await?;
+
+        assert_eq!(factory.storage_stats(Some(&repository)).await?, expected);
+
+        let total = factory.storage_stats(None).await?;
+        assert!(total.manifest_bytes >= expected.manifest_bytes);
+        assert!(total.layer_bytes >= expected.layer_bytes);
+        assert!(total.config_bytes >= expected.config_bytes);
+
+        assert!(matches!(
+            factory.storage_stats(Some("stats-missing")).await,
+            Err(CoreError::NameUnknown(_))
+        ));
+
+        // every object pushed to the repository is stored under some storage class
+        let by_class = factory.storage_class_bytes(Some(&repository)).await?;
+        assert_eq!(
+            by_class.values().sum::<u64>(),
+            expected.manifest_bytes + expected.layer_bytes + expected.config_bytes
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn skipped_ahead_chunk_is_not_satisfiable() -> Result<()> {
+        let router = init_router(PathBuf::from("../../dev-config-linode.yml")).await?;
+
+        let response = router
+            .clone()
+            .oneshot(Request::post("/v2/testrepo/blobs/uploads/").body(Body::empty())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let location = response
+            .headers()
+            .get("location")
+            .expect("upload session should have a location")
+            .to_str()?
+            .to_string();
+        let patch = |start: usize, end: usize| {
+            Request::patch(location.as_str())
+                .header("content-type", "application/octet-stream")
+                .header("content-length", end - start + 1)
+                .header("content-range", format!("{start}-{end}"))
+                .body(Body::from(vec![b'x'; end - start + 1]))
+        };
+
+        // nothing has been uploaded yet, so the first chunk has to start at the beginning
+        let response = router.clone().oneshot(patch(10, 19)?).await?;
+        assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+
+        let response = router.clone().oneshot(patch(0, 99)?).await?;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+        // starting past the byte after the last one received would leave a gap
+        let response = router.clone().oneshot(patch(101, 149)?).await?;
+        assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(response.headers().get("range").unwrap(), "0-99");
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        let body: serde_json::Value = serde_json::from_slice(&body)?;
+        assert_eq!(body["errors"][0]["code"], "BLOB_UPLOAD_INVALID");
+        assert!(body["errors"][0]["message"]
+            .as_str()
+            .is_some_and(|msg| msg.contains("gap")));
+
+        let response = router.oneshot(patch(100, 149)?).await?;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        assert_eq!(response.headers().get("range").unwrap(), "0-149");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn images_listed_by_creation_time() -> Result<()> {
+        use chrono::{DateTime, Utc};
+
+        let factory = init_factory_with_settings(
+            PathBuf::from("../../dev-config-linode.yml"),
+            "image_creation: record",
+        )
+        .await?;
+        let tester = RepositoryTester::new(RepositoryLoader::new(Box::new(factory.clone())));
+
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let repository = format!("created-{seed}");
+        let mut images = testdata::tagged_images(&repository, 3);
+        // the annotation takes precedence over the config
+        images[0].annotations = Some(HashMap::from([(
+            "org.opencontainers.image.created".to_string(),
+            "2001-01-01T00:00:00Z".to_string(),
+        )]));
+        let mut config = images[0].config();
+        config.set_created(Some("2010-01-01T00:00:00Z".to_string()));
+        images[0].config = Some(config);
+        let mut config = images[1].config();
+        config.set_created(Some("2002-01-01T12:00:00+02:00".to_string()));
+        images[1].config = Some(config);
+        // the third image has no creation time to record
+        let digests: Vec<OciDigest> = images.iter_mut().map(|i| i.digest()).collect();
+        tester
+            .loader
+            .clone()
+            .upload_images(
+                repository.clone(),
+                images
+                    .into_iter()
+                    .map(|i| Arc::new(Mutex::new(i)))
+                    .collect(),
+            )
+            .await?;
+
+        let date = |s: &str| -> Result<DateTime<Utc>> { Ok(s.parse::<DateTime<Utc>>()?) };
+        let listed = factory
+            .images_created_before(Some(&repository), date("2003-01-01T00:00:00Z")?)
+            .await?;
+        assert!(listed.iter().all(|i| i.repository == repository));
+        assert_eq!(
+            listed
+                .iter()
+                .map(|i| (&i.digest, i.created))
+                .collect::<Vec<_>>(),
+            vec![
+                (&digests[0], date("2001-01-01T00:00:00Z")?),
+                (&digests[1], date("2002-01-01T10:00:00Z")?),
+            ]
+        );
+
+        let listed = factory
+            .images_created_before(Some(&repository), date("2002-01-01T00:00:00Z")?)
+            .await?;
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].digest, digests[0]);
+
+        let listed = factory
+            .images_created_before(None, date("2003-01-01T00:00:00Z")?)
+            .await?;
+        assert!(listed.iter().any(|i| i.digest == digests[1]));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn image_creation_time_required() -> Result<()> {
+        let factory = init_factory_with_settings(
+            PathBuf::from("../../dev-config-linode.yml"),
+            "image_creation: require",
+        )
+        .await?;
+        let tester = RepositoryTester::new(RepositoryLoader::new(Box::new(factory.clone())));
+
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let repository = format!("created-required-{seed}");
+        let mut images = testdata::tagged_images(&repository, 2);
+        let mut config = images[0].config();
+        config.set_created(Some("2001-01-01T00:00:00Z".to_string()));
+        images[0].config = Some(config);
+        let undated = Arc::new(Mutex::new(images.pop().unwrap()));
+        let dated = Arc::new(Mutex::new(images.pop().unwrap()));
+
+        tester
+            .loader
+            .clone()
+            .upload_images(repository.clone(), vec![dated])
+            .await?;
+        assert!(tester
+            .loader
+            .clone()
+            .upload_images(repository.clone(), vec![undated])
+            .await
+            .is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn upload_put_conflicting_with_declared_digest_rejected() -> Result<()> {
+        let router = init_router(PathBuf::from("../../dev-config-linode.yml")).await?;
+
+        let declared: &[u8] = b"blob the client meant to mount";
+        let declared_digest = String::from(OciDigest::from(declared));
+        // the blob isn't in the source repository, so the mount falls back to an upload session
+        // for it
+        let response = router
+            .clone()
+            .oneshot(
+                Request::post(format!(
+                    "/v2/testrepo/blobs/uploads/?mount={declared_digest}&from=mount-nowhere"
+                ))
+                .body(Body::empty())?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let location = response
+            .headers()
+            .get("location")
+            .expect("upload session should have a location")
+            .to_str()?
+            .to_string();
+        let put = |body: &'static [u8]| {
+            Request::put(format!(
+                "{location}?digest={}",
+                String::from(OciDigest::from(body))
+            ))
+            .header("content-type", "application/octet-stream")
+            .header("content-length", body.len())
+            .body(Body::from(body))
+        };
+
+        let response = router.clone().oneshot(put(b"some other blob")?).await?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        let body: serde_json::Value = serde_json::from_slice(&body)?;
+        assert_eq!(body["errors"][0]["code"], "DIGEST_INVALID");
+
+        let response = router.oneshot(put(declared)?).await?;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(
+            response.headers().get("location").unwrap(),
+            format!("/v2/testrepo/blobs/{declared_digest}").as_str()
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn concurrent_repository_creation_is_idempotent() -> Result<()> {
+        let factory = init_factory(PathBuf::from("../../dev-config-linode.yml")).await?;
+
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let name = format!("concurrent-{seed}");
+        // every task finds the repository missing and inserts it unless another beat it to it
+        let mut tasks = tokio::task::JoinSet::new();
+        for _ in 0..8 {
+            let factory = factory.clone();
+            let name = name.clone();
+            tasks.spawn(async move { factory.create(&name).await.map(|r| r.name().to_string()) });
+        }
+        while let Some(created) = tasks.join_next().await {
+            assert_eq!(created??, name);
+        }
+
+        assert!(factory.get(&name).await?.is_some());
+        let listed = factory.search(&name, None, None).await?;
+        assert_eq!(listed, vec![name]);
+
+        Ok(())
+    }
 }
@@ -1,12 +1,18 @@
+use std::path::PathBuf;
+
 use serde::Deserialize;
 
 use portfolio_backend_postgres::PgRepositoryConfig;
-use portfolio_http::RepositoryDefinition;
+use portfolio_http::{HttpConfig, RepositoryDefinition};
 
 #[derive(Clone, Deserialize)]
 pub struct Config {
     pub backend: RepositoryBackend,
     pub static_repositories: Option<Vec<RepositoryDefinition>>,
+    /// Require HTTP Basic authentication against the bcrypt hashes in this `htpasswd` file.
+    pub htpasswd_file: Option<PathBuf>,
+    #[serde(default)]
+    pub http: HttpConfig,
 }
 
 #[derive(Clone, Deserialize)]
@@ -5,9 +5,12 @@ use std::sync::Arc;
 
 use anyhow::Result;
 use axum::middleware;
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
-use portfolio_http::{add_basic_repository_extensions, Portfolio};
+#[cfg(feature = "conformance")]
+use oci_distribution_test::conformance::ConformanceClient;
+use portfolio_backend_postgres::MetadataSnapshot;
+use portfolio_http::{add_basic_repository_extensions, basic_auth, BasicAuthenticator, Portfolio};
 
 mod config;
 use crate::config::{Config, RepositoryBackend};
@@ -16,6 +19,55 @@ use crate::config::{Config, RepositoryBackend};
 struct Cli {
     #[arg(short, long)]
     config_file: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run the registry server (default)
+    Serve,
+    /// Run distribution spec conformance checks against a running registry
+    #[cfg(feature = "conformance")]
+    Conformance {
+        /// Base URL of the target registry, eg http://localhost:13030
+        url: String,
+
+        /// Repository to push test content to
+        #[arg(long, default_value = "conformance")]
+        repository: String,
+
+        /// Username to authenticate with, for registries configured with an `htpasswd_file`
+        #[arg(long, requires = "password")]
+        username: Option<String>,
+
+        /// Password to authenticate with
+        #[arg(long, requires = "username")]
+        password: Option<String>,
+    },
+    /// Export a consistent snapshot of the registry's metadata as JSON
+    Snapshot {
+        /// File to write the snapshot to; written to stdout if unset
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Restore the registry's metadata from a snapshot taken with `snapshot`
+    Restore {
+        /// Snapshot file to restore from
+        input: PathBuf,
+    },
+    /// Print the bytes of stored content by kind as JSON
+    Stats {
+        /// Only count content referred to by this repository
+        #[arg(long)]
+        repository: Option<String>,
+
+        /// Also break down stored bytes by object store storage class, which looks up every
+        /// stored object
+        #[arg(long)]
+        storage_classes: bool,
+    },
 }
 
 #[tokio::main]
@@ -33,17 +85,41 @@ async fn main() -> Result<()> {
     tracing::debug!("debug enabled");
     tracing::trace!("trace enabled");
 
-    // load configuration
-    let mut dev_config = File::open(cli.config_file.unwrap_or("./dev-config.yml".into()))?;
+    match cli.command.unwrap_or(Command::Serve) {
+        Command::Serve => serve(cli.config_file).await,
+        #[cfg(feature = "conformance")]
+        Command::Conformance {
+            url,
+            repository,
+            username,
+            password,
+        } => conformance(&url, &repository, username.zip(password)).await,
+        Command::Snapshot { output } => snapshot(cli.config_file, output).await,
+        Command::Restore { input } => restore(cli.config_file, input).await,
+        Command::Stats {
+            repository,
+            storage_classes,
+        } => stats(cli.config_file, repository, storage_classes).await,
+    }
+}
+
+fn load_config(config_file: Option<PathBuf>) -> Result<Config> {
+    let mut dev_config = File::open(config_file.unwrap_or("./dev-config.yml".into()))?;
     let mut s = String::new();
     dev_config.read_to_string(&mut s)?;
-    let config: Config = serde_yaml::from_str(&s)?;
+    Ok(serde_yaml::from_str(&s)?)
+}
+
+async fn serve(config_file: Option<PathBuf>) -> Result<()> {
+    // load configuration
+    let config = load_config(config_file)?;
+    let title_case_headers = config.http.title_case_headers;
 
     // initialize persistence layer
     let portfolio = match config.backend {
         RepositoryBackend::Postgres(cfg) => {
             let manager = cfg.get_manager().await?;
-            Portfolio::new(Arc::new(manager))
+            Portfolio::new(Arc::new(manager)).with_config(config.http)
         }
     };
 
@@ -63,10 +139,95 @@ async fn main() -> Result<()> {
         add_basic_repository_extensions,
     ));
 
+    let router = match config.htpasswd_file {
+        Some(path) => router.layer(middleware::from_fn_with_state(
+            // repositories are private unless made public, so anonymous pulls are opt-in
+            Arc::new(BasicAuthenticator::from_file(path)?.allow_anonymous_pulls(portfolio.clone())),
+            basic_auth,
+        )),
+        None => router,
+    };
+
     // run HTTP server
     axum::Server::bind(&"0.0.0.0:13030".parse()?)
+        .http1_title_case_headers(title_case_headers)
         .serve(router.into_make_service())
         .await?;
 
     Ok(())
 }
+
+#[cfg(feature = "conformance")]
+async fn conformance(
+    url: &str,
+    repository: &str,
+    credentials: Option<(String, String)>,
+) -> Result<()> {
+    let mut client = ConformanceClient::new(url, repository);
+    if let Some((username, password)) = credentials {
+        client = client.with_credentials(&username, &password);
+    }
+
+    let checks = client.run().await;
+
+    let mut failed = 0;
+    for check in &checks {
+        match &check.result {
+            Ok(()) => println!("PASS {}", check.name),
+            Err(e) => {
+                failed += 1;
+                println!("FAIL {}: {e}", check.name);
+            }
+        }
+    }
+
+    if failed > 0 {
+        anyhow::bail!("{failed} of {} conformance checks failed", checks.len());
+    }
+    Ok(())
+}
+
+async fn snapshot(config_file: Option<PathBuf>, output: Option<PathBuf>) -> Result<()> {
+    let snapshot = match load_config(config_file)?.backend {
+        RepositoryBackend::Postgres(cfg) => cfg.get_manager().await?.export_snapshot().await?,
+    };
+
+    match output {
+        Some(path) => serde_json::to_writer(File::create(path)?, &snapshot)?,
+        None => serde_json::to_writer(std::io::stdout().lock(), &snapshot)?,
+    }
+    Ok(())
+}
+
+async fn restore(config_file: Option<PathBuf>, input: PathBuf) -> Result<()> {
+    let snapshot: MetadataSnapshot = serde_json::from_reader(File::open(input)?)?;
+    let inserted = match load_config(config_file)?.backend {
+        RepositoryBackend::Postgres(cfg) => {
+            cfg.get_manager().await?.import_snapshot(&snapshot).await?
+        }
+    };
+
+    println!("restored {inserted} rows");
+    Ok(())
+}
+
+async fn stats(
+    config_file: Option<PathBuf>,
+    repository: Option<String>,
+    storage_classes: bool,
+) -> Result<()> {
+    let stats = match load_config(config_file)?.backend {
+        RepositoryBackend::Postgres(cfg) => {
+            let manager = cfg.get_manager().await?;
+            let mut stats = manager.storage_stats(repository.as_deref()).await?;
+            if storage_classes {
+                stats.storage_class_bytes =
+                    manager.storage_class_bytes(repository.as_deref()).await?;
+            }
+            stats
+        }
+    };
+
+    println!("{}", serde_json::to_string_pretty(&stats)?);
+    Ok(())
+}
@@ -0,0 +1,6 @@
+DROP INDEX IF EXISTS index_manifests_platform;
+
+ALTER TABLE index_manifests
+	DROP COLUMN platform_os,
+	DROP COLUMN platform_architecture,
+	DROP COLUMN platform_variant;
@@ -0,0 +1,10 @@
+-- the platform of each child manifest as described by the parent index's
+-- descriptor, making it possible to resolve a platform-specific manifest
+-- without fetching every child of the index
+ALTER TABLE index_manifests
+	ADD COLUMN platform_os VARCHAR(128) DEFAULT NULL,
+	ADD COLUMN platform_architecture VARCHAR(128) DEFAULT NULL,
+	ADD COLUMN platform_variant VARCHAR(128) DEFAULT NULL;
+
+CREATE INDEX index_manifests_platform
+	ON index_manifests (parent_manifest, platform_os, platform_architecture);
@@ -0,0 +1,4 @@
+-- when each tag last moved to a different manifest, so that tags can be frozen
+-- once they've gone unchanged for long enough
+ALTER TABLE tags
+	ADD COLUMN updated_at TIMESTAMPTZ NOT NULL DEFAULT now();
@@ -0,0 +1,6 @@
+-- object store content whose metadata has been deleted but which hasn't been
+-- removed from the object store yet, when blob deletion is lazy
+CREATE TABLE object_deletions (
+	object_id UUID PRIMARY KEY,
+	marked_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
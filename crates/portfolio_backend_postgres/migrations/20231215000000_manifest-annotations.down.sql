@@ -0,0 +1,4 @@
+DROP INDEX manifests_annotations_idx;
+
+ALTER TABLE manifests
+	DROP COLUMN annotations;
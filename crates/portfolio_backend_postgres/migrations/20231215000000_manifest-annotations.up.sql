@@ -0,0 +1,6 @@
+-- annotations from each manifest so that operators can filter images by eg
+-- org.opencontainers.image.source
+ALTER TABLE manifests
+	ADD COLUMN annotations JSONB;
+
+CREATE INDEX manifests_annotations_idx ON manifests USING GIN (annotations);
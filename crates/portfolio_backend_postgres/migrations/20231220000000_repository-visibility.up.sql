@@ -0,0 +1,4 @@
+-- whether each repository can be pulled from without authenticating
+ALTER TABLE repositories
+	ADD COLUMN visibility TEXT NOT NULL DEFAULT 'private'
+	CHECK (visibility IN ('public', 'private'));
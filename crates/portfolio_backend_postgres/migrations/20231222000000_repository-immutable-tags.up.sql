@@ -0,0 +1,4 @@
+-- glob patterns matching tags in each repository that can't be moved to a
+-- different manifest once they exist
+ALTER TABLE repositories
+	ADD COLUMN immutable_tags JSONB NOT NULL DEFAULT '[]';
@@ -0,0 +1,4 @@
+-- object store key of the digest state checkpoint of sessions whose digest_state
+-- is kept in the object store rather than in the database
+ALTER TABLE upload_sessions
+	ADD COLUMN digest_checkpoint VARCHAR(256);
@@ -0,0 +1,2 @@
+ALTER TABLE manifests
+	DROP COLUMN deleted_at;
@@ -0,0 +1,4 @@
+-- when each manifest was deleted, if deletion only tombstones manifests so that
+-- they can be restored until their retention window has passed
+ALTER TABLE manifests
+	ADD COLUMN deleted_at TIMESTAMPTZ;
@@ -0,0 +1,3 @@
+-- the Content-Type header each manifest was pushed with, served verbatim on pull
+ALTER TABLE manifests
+	ADD COLUMN content_type VARCHAR(512) DEFAULT NULL;
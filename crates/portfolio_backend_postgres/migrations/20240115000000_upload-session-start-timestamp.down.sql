@@ -0,0 +1,2 @@
+ALTER TABLE upload_sessions
+	ALTER COLUMN start_date TYPE DATE USING start_date::DATE;
@@ -0,0 +1,4 @@
+-- record when each upload session was started to the second rather than the
+-- day so that sessions abandoned for less than a day can be cleaned up
+ALTER TABLE upload_sessions
+	ALTER COLUMN start_date TYPE TIMESTAMPTZ USING start_date::TIMESTAMPTZ;
@@ -0,0 +1,4 @@
+-- the number of bytes in each chunk so that upload progress can be reported;
+-- chunks recorded before this was tracked count as empty
+ALTER TABLE chunks
+	ADD COLUMN size BIGINT NOT NULL DEFAULT 0;
@@ -0,0 +1,2 @@
+ALTER TABLE layers
+	DROP COLUMN config;
@@ -0,0 +1,5 @@
+-- whether each blob associated with an image manifest is its config rather than
+-- one of its layers, so that storage can be broken down by kind of content;
+-- associations recorded before this was tracked count as layers
+ALTER TABLE layers
+	ADD COLUMN config BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,4 @@
+-- when each image was created, taken from its org.opencontainers.image.created annotation or
+-- the created field of its config, for retention policies based on image age
+ALTER TABLE manifests
+	ADD COLUMN created TIMESTAMPTZ DEFAULT NULL;
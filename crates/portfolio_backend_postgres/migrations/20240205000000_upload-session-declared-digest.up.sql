@@ -0,0 +1,5 @@
+-- the digest clients declared an upload session's content would have when starting it, eg
+-- when a cross-repository mount falls back to an upload, so that completing the session with
+-- a different digest can be rejected
+ALTER TABLE upload_sessions
+	ADD COLUMN declared_digest VARCHAR(256) DEFAULT NULL;
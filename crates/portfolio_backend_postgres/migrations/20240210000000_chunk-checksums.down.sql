@@ -0,0 +1,5 @@
+ALTER TABLE chunks
+	DROP COLUMN checksum_crc32,
+	DROP COLUMN checksum_crc32_c,
+	DROP COLUMN checksum_sha1,
+	DROP COLUMN checksum_sha256;
@@ -0,0 +1,7 @@
+-- integrity checksums the object store calculated for each chunk, which it requires again to
+-- complete a chunked upload whose chunks were checksummed
+ALTER TABLE chunks
+	ADD COLUMN checksum_crc32 VARCHAR(128),
+	ADD COLUMN checksum_crc32_c VARCHAR(128),
+	ADD COLUMN checksum_sha1 VARCHAR(128),
+	ADD COLUMN checksum_sha256 VARCHAR(128);
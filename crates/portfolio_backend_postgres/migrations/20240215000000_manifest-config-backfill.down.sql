@@ -0,0 +1,2 @@
+ALTER TABLE manifests
+	DROP COLUMN config_backfill_pending;
@@ -0,0 +1,15 @@
+-- whether an image manifest's config blob still has to be associated with it. configs were only
+-- associated with manifests pushed once garbage collection was introduced, and the config
+-- digest is only recorded in the stored manifest itself, so manifests without a config
+-- association are flagged here and backfilled from their content before garbage collection
+-- looks for unreferenced blobs. index manifests are flagged too and cleared once their content
+-- shows they have no config
+ALTER TABLE manifests
+	ADD COLUMN config_backfill_pending BOOLEAN NOT NULL DEFAULT false;
+
+UPDATE manifests
+SET config_backfill_pending = true
+WHERE NOT EXISTS (
+	SELECT 1 FROM layers
+	WHERE layers.manifest = manifests.id AND layers.config
+);
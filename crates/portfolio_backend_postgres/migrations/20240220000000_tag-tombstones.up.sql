@@ -0,0 +1,10 @@
+-- tags deleted while deletion only tombstones manifests, recording the manifest
+-- each pointed to so that they can be restored until their retention window has
+-- passed
+CREATE TABLE deleted_tags (
+	repository_id UUID NOT NULL REFERENCES repositories (id),
+	manifest_id UUID NOT NULL REFERENCES manifests (id),
+	name VARCHAR(256) NOT NULL,
+	deleted_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+	PRIMARY KEY (repository_id, name)
+);
@@ -0,0 +1,5 @@
+ALTER TABLE manifests
+	DROP COLUMN platform_backfill_pending;
+
+ALTER TABLE index_manifests
+	DROP COLUMN position;
@@ -0,0 +1,18 @@
+-- the position of each child manifest among the parent index's descriptors, so
+-- that resolving a platform picks the first matching child as clients do
+ALTER TABLE index_manifests
+	ADD COLUMN position INTEGER DEFAULT NULL;
+
+-- whether the platforms and positions of an index's children still have to be
+-- recorded. indices pushed before they were recorded only describe them in their
+-- stored content, so every existing index is flagged here and backfilled from its
+-- content the first time a platform is resolved from it
+ALTER TABLE manifests
+	ADD COLUMN platform_backfill_pending BOOLEAN NOT NULL DEFAULT false;
+
+UPDATE manifests
+SET platform_backfill_pending = true
+WHERE EXISTS (
+	SELECT 1 FROM index_manifests
+	WHERE index_manifests.parent_manifest = manifests.id
+);
@@ -0,0 +1,8 @@
+-- blobs made available in a repository other than through its manifests, eg by
+-- mounting them from another repository, so that they can be found there before
+-- any manifest in the repository refers to them
+CREATE TABLE repository_blobs (
+	repository_id UUID NOT NULL REFERENCES repositories (id),
+	blob_id UUID NOT NULL REFERENCES blobs (id),
+	PRIMARY KEY (repository_id, blob_id)
+);
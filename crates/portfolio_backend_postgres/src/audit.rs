@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+#[cfg(feature = "metrics")]
+use once_cell::sync::Lazy;
+#[cfg(feature = "metrics")]
+use prometheus::{register_int_counter_vec, IntCounterVec};
+
+use portfolio_core::OciDigest;
+
+#[cfg(feature = "metrics")]
+static STORED_BY_ALGORITHM: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "portfolio_digest_algorithm_stored_total",
+        "Number of blobs and manifests stored, by kind and digest algorithm.",
+        &["kind", "algorithm"]
+    )
+    .expect("audit metrics are registered once")
+});
+
+/// Running tallies of the digest algorithms used to address stored blobs and manifests.
+///
+/// Operators planning a migration from one digest algorithm to another can use these counts to
+/// see how much content still depends on the old algorithm. Note that manifest content is itself
+/// stored as a blob and so is also reflected in the blob counts.
+///
+/// With the `metrics` feature enabled the counts are also exported as
+/// `portfolio_digest_algorithm_stored_total` in the default [`prometheus`] registry, and so are
+/// served by the registry's `/metrics` endpoint.
+#[derive(Debug, Default)]
+pub struct DigestAlgorithmAudit {
+    blobs: Mutex<HashMap<String, u64>>,
+    manifests: Mutex<HashMap<String, u64>>,
+}
+
+impl DigestAlgorithmAudit {
+    pub(crate) fn record_blob(&self, digest: &OciDigest) {
+        Self::record(&self.blobs, "blob", digest);
+    }
+
+    pub(crate) fn record_manifest(&self, digest: &OciDigest) {
+        Self::record(&self.manifests, "manifest", digest);
+    }
+
+    /// Number of blobs stored so far, keyed by digest algorithm.
+    pub fn blob_counts(&self) -> HashMap<String, u64> {
+        self.blobs.lock().expect("audit lock poisoned").clone()
+    }
+
+    /// Number of manifests stored so far, keyed by digest algorithm.
+    pub fn manifest_counts(&self) -> HashMap<String, u64> {
+        self.manifests.lock().expect("audit lock poisoned").clone()
+    }
+
+    fn record(counts: &Mutex<HashMap<String, u64>>, kind: &str, digest: &OciDigest) {
+        let algorithm = digest.algorithm();
+        #[cfg(feature = "metrics")]
+        STORED_BY_ALGORITHM
+            .with_label_values(&[kind, &algorithm])
+            .inc();
+        #[cfg(not(feature = "metrics"))]
+        let _ = kind;
+        *counts
+            .lock()
+            .expect("audit lock poisoned")
+            .entry(algorithm)
+            .or_default() += 1;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn digest(s: &str) -> OciDigest {
+        s.try_into().expect("test digests should be well-formed")
+    }
+
+    #[test]
+    fn counts_by_algorithm() {
+        let audit = DigestAlgorithmAudit::default();
+
+        audit.record_blob(&digest("sha256:meow"));
+        audit.record_blob(&digest("sha256:woof"));
+        audit.record_blob(&digest("sha512:meow"));
+        audit.record_manifest(&digest("sha512:meow"));
+
+        let blobs = audit.blob_counts();
+        assert_eq!(blobs.get("sha256"), Some(&2));
+        assert_eq!(blobs.get("sha512"), Some(&1));
+
+        let manifests = audit.manifest_counts();
+        assert_eq!(manifests.get("sha256"), None);
+        assert_eq!(manifests.get("sha512"), Some(&1));
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn counts_exported_as_metrics() {
+        let exported = |kind: &str, algorithm: &str| {
+            STORED_BY_ALGORITHM
+                .with_label_values(&[kind, algorithm])
+                .get()
+        };
+        // other tests record into the same process-wide counters, so only count increments
+        let blobs = exported("blob", "sha512");
+        let manifests = exported("manifest", "sha512");
+
+        let audit = DigestAlgorithmAudit::default();
+        audit.record_blob(&digest("sha512:meow"));
+        audit.record_blob(&digest("sha512:woof"));
+        audit.record_manifest(&digest("sha512:meow"));
+
+        assert!(exported("blob", "sha512") >= blobs + 2);
+        assert!(exported("manifest", "sha512") > manifests);
+        assert!(prometheus::gather()
+            .iter()
+            .any(|family| family.get_name() == "portfolio_digest_algorithm_stored_total"));
+    }
+}
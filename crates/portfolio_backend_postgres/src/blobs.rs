@@ -1,85 +1,75 @@
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 
 use async_trait::async_trait;
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
 use futures::stream::BoxStream;
 use futures::stream::StreamExt;
 use futures::stream::TryStreamExt;
 use hyper::body::Body;
 use uuid::Uuid;
 
+use portfolio_core::events::{ContentEvent, EventSink};
+use portfolio_core::registry;
 use portfolio_core::registry::BoxedUploadSession;
 use portfolio_core::registry::{BlobStore, BlobWriter};
 use portfolio_core::registry::{BoxedBlob, BoxedBlobWriter};
 use portfolio_core::Error as CoreError;
+use portfolio_core::PortfolioErrorCode;
 use portfolio_core::Result;
-use portfolio_core::{ChunkedBody, DigestBody, Digester, OciDigest};
+use portfolio_core::{ChunkedBody, DigestBody, DigestState, Digester, OciDigest, VerifiedBody};
+use portfolio_objectstore::Error as ObjectStoreError;
 use portfolio_objectstore::{Chunk, Key, ObjectStore};
 
+use super::audit::DigestAlgorithmAudit;
+use super::deletion::ObjectDeletion;
 use super::errors::Error;
+use super::fan_out::FanOutLimiter;
 use super::metadata::{
     Chunk as MetadataChunk, PostgresMetadataPool, PostgresMetadataTx, UploadSession,
 };
+use super::repositories::StoreConfig;
 
 pub struct PgBlobStore {
     pub(crate) metadata: PostgresMetadataPool,
     pub(crate) objects: Arc<dyn ObjectStore>,
+    pub(crate) config: StoreConfig,
+    pub(crate) audit: Option<Arc<DigestAlgorithmAudit>>,
+    pub(crate) fan_out: Option<Arc<FanOutLimiter>>,
+    pub(crate) events: Option<Arc<dyn EventSink>>,
+    pub(crate) repository: String,
 }
 
 impl PgBlobStore {
-    pub fn new(metadata: PostgresMetadataPool, objects: Arc<dyn ObjectStore>) -> Self {
+    pub fn new(
+        metadata: PostgresMetadataPool,
+        objects: Arc<dyn ObjectStore>,
+        config: StoreConfig,
+        audit: Option<Arc<DigestAlgorithmAudit>>,
+        fan_out: Option<Arc<FanOutLimiter>>,
+        events: Option<Arc<dyn EventSink>>,
+        repository: String,
+    ) -> Self {
         Self {
             metadata,
             objects: objects,
+            config,
+            audit,
+            fan_out,
+            events,
+            repository,
         }
     }
-}
-
-type TryBytes = std::result::Result<Bytes, Box<dyn std::error::Error + Send + Sync>>;
 
-#[async_trait]
-impl BlobStore for PgBlobStore {
-    async fn resume(
+    /// Store content under the given digest without emitting a [`ContentEvent`], for content
+    /// such as manifests that is reported by its own kind of event. Also returns whether the
+    /// content was actually stored, which it isn't if it already existed.
+    pub(crate) async fn put_content(
         &self,
-        session_uuid: &Uuid,
-        start_of_range: Option<u64>,
-    ) -> Result<BoxedBlobWriter> {
-        // retrieve the session or fail if it doesn't exist
-        let mut session = self
-            .metadata
-            .get_conn()
-            .await?
-            .get_session(session_uuid)
-            .await
-            .map_err(|_| CoreError::BlobUploadInvalid(None))?;
-
-        if let Some(start) = start_of_range {
-            if !session.validate_range(start) {
-                tracing::debug!("content range start {start} is invalid");
-                return Err(CoreError::BlobUploadInvalid(Some(
-                    "content range start is invalid".to_string(),
-                ))
-                .into());
-            }
-        }
-
-        if session.upload_id.is_none() {
-            session.upload_id = Some(
-                self.objects
-                    .initiate_chunked_upload(&Key::from(&session.uuid))
-                    .await
-                    .map_err(Error::from)?,
-            );
-        }
-
-        Ok(Box::new(PgBlobWriter {
-            metadata: self.metadata.clone(),
-            objects: self.objects.clone(),
-            session: Some(session),
-        }))
-    }
-
-    async fn put(&self, digest: &OciDigest, content_length: u64, body: Body) -> Result<Uuid> {
+        digest: &OciDigest,
+        content_length: u64,
+        body: Body,
+    ) -> Result<(Uuid, bool)> {
         let mut tx = self.metadata.get_tx().await?;
         let uuid = match tx.get_blob(digest).await? {
             Some(b) => {
@@ -90,7 +80,7 @@ impl BlobStore for PgBlobStore {
                     .await
                     .map_err(Error::from)?
                 {
-                    return Ok(b.id);
+                    return Ok((b.id, false));
                 }
                 b.id
             }
@@ -102,17 +92,88 @@ impl BlobStore for PgBlobStore {
 
         // upload blob
         let digester = Arc::new(Mutex::new(digest.digester()));
-        let stream_body = DigestBody::from_body(body, digester);
+        let stream_body = DigestBody::from_body(body, digester.clone());
         self.objects
             .put(&Key::from(&uuid), stream_body.into(), content_length)
             .await
             .map_err(Error::from)?;
 
-        // TODO: validate digest
+        let calculated = digester
+            .lock()
+            .expect("the body has been consumed so nothing else holds the lock")
+            .digest();
+        if calculated.as_ref() != Some(digest) {
+            tracing::warn!(
+                "uploaded content digest {:?} does not match provided digest {}",
+                calculated.as_ref().map(String::from),
+                String::from(digest),
+            );
+            self.objects
+                .delete(&Key::from(&uuid))
+                .await
+                .map_err(Error::from)?;
+            return Err(CoreError::DigestInvalid(None));
+        }
+
         // TODO: validate content length
 
         tx.commit().await.map_err(Error::from)?;
 
+        if let Some(audit) = &self.audit {
+            audit.record_blob(digest);
+        }
+
+        Ok((uuid, true))
+    }
+}
+
+type TryBytes = std::result::Result<Bytes, Box<dyn std::error::Error + Send + Sync>>;
+
+#[async_trait]
+impl BlobStore for PgBlobStore {
+    async fn resume(
+        &self,
+        session_uuid: &Uuid,
+        start_of_range: Option<u64>,
+    ) -> Result<BoxedBlobWriter> {
+        // retrieve the session or fail if it doesn't exist
+        let session = self
+            .metadata
+            .get_conn()
+            .await?
+            .get_session(session_uuid)
+            .await
+            .map_err(|_| CoreError::BlobUploadUnknown(None))?;
+
+        if let Some(start) = start_of_range {
+            if let Err(e) = session.validate_range(start) {
+                tracing::debug!("content range start {start} is invalid: {e:?}");
+                return Err(e);
+            }
+        }
+
+        Ok(Box::new(PgBlobWriter {
+            metadata: self.metadata.clone(),
+            objects: self.objects.clone(),
+            config: self.config.clone(),
+            audit: self.audit.clone(),
+            events: self.events.clone(),
+            repository: self.repository.clone(),
+            session: Some(session),
+        }))
+    }
+
+    async fn put(&self, digest: &OciDigest, content_length: u64, body: Body) -> Result<Uuid> {
+        let (uuid, stored) = self.put_content(digest, content_length, body).await?;
+
+        if let (Some(events), true) = (&self.events, stored) {
+            events.blob_pushed(ContentEvent {
+                repository: self.repository.clone(),
+                digest: digest.clone(),
+                media_type: None,
+            });
+        }
+
         Ok(uuid)
     }
 
@@ -121,12 +182,24 @@ impl BlobStore for PgBlobStore {
         key: &OciDigest,
     ) -> Result<Option<(BoxedBlob, BoxStream<'static, TryBytes>)>> {
         if let Some(blob) = self.metadata.get_conn().await?.get_blob(key).await? {
-            let body = self
-                .objects
-                .get(&Key::from(&blob.id))
-                .await
-                .map_err(Error::from)?;
-            Ok(Some((Box::new(blob), body.map_err(|e| e.into()).boxed())))
+            let body = match self.objects.get(&Key::from(&blob.id)).await {
+                Ok(body) => body.map_err(|e| e.into()),
+                Err(ObjectStoreError::ObjectNotFound(_)) => {
+                    tracing::error!(
+                        "blob {} exists in metadata but its content is missing from the object store",
+                        String::from(key),
+                    );
+                    return Err(CoreError::PortfolioSpecError(
+                        PortfolioErrorCode::BlobContentMissing,
+                    ));
+                }
+                Err(e) => return Err(Error::from(e).into()),
+            };
+            if self.config.verify_on_read {
+                let body = VerifiedBody::new(body, key.clone());
+                return Ok(Some((Box::new(blob), body.boxed())));
+            }
+            Ok(Some((Box::new(blob), body.boxed())))
         } else {
             Ok(None)
         }
@@ -147,27 +220,147 @@ impl BlobStore for PgBlobStore {
             .await?
             .ok_or(CoreError::BlobUnknown(None))?;
 
-        // TODO: handle the case where the blob is referenced
+        if tx.blob_reference_count(digest).await? > 0 {
+            return Err(CoreError::PortfolioSpecError(
+                PortfolioErrorCode::ContentReferenced,
+            ));
+        }
+
         tx.delete_blob(&blob.id).await?;
+        if self.config.object_deletion == ObjectDeletion::Lazy {
+            tx.mark_object_for_deletion(&blob.id).await?;
+        }
         tx.commit().await?;
+
+        // deleted only once the metadata is, so that content is never missing for a blob that
+        // still exists
+        if self.config.object_deletion == ObjectDeletion::Eager {
+            self.objects
+                .delete(&Key::from(&blob.id))
+                .await
+                .map_err(Error::from)?;
+        }
         Ok(())
     }
+
+    async fn mount(&self, digest: &OciDigest, from: &str) -> Result<Option<BoxedBlob>> {
+        let mut tx = self.metadata.get_tx().await?;
+        let blob = match tx.get_repository_blob(from, digest).await? {
+            Some(b) => b,
+            None => return Ok(None),
+        };
+
+        // repositories share blobs under the same object key, so mounting only has to record that
+        // the blob is now available here too
+        let repository = tx
+            .get_repository(&self.repository)
+            .await?
+            .ok_or(CoreError::NameUnknown(None))?;
+        tx.associate_repository_blob(&repository.id, &blob.id)
+            .await?;
+        tx.commit().await?;
+
+        Ok(Some(Box::new(blob)))
+    }
 }
 
 pub struct PgBlobWriter {
     metadata: PostgresMetadataPool,
     objects: Arc<dyn ObjectStore>,
+    config: StoreConfig,
+    audit: Option<Arc<DigestAlgorithmAudit>>,
+    events: Option<Arc<dyn EventSink>>,
+    repository: String,
 
     session: Option<UploadSession>,
 }
 
 impl PgBlobWriter {
+    /// Initiate the object store's chunked upload for this session unless that was already done
+    /// by an earlier request.
+    ///
+    /// This is deferred until content is actually written so that monolithic uploads, which
+    /// resume the session only to inspect it, don't leave dangling chunked uploads behind.
+    async fn initiate_chunked_upload(&self, session: &mut UploadSession) -> Result<()> {
+        if session.upload_id.is_none() {
+            session.upload_id = Some(
+                self.objects
+                    .initiate_chunked_upload(&Key::from(&session.uuid))
+                    .await
+                    .map_err(Error::from)?,
+            );
+        }
+        Ok(())
+    }
+
+    /// Resume calculating the digest of the content uploaded so far in this session, reading its
+    /// state back from the object store if it was checkpointed there.
+    async fn load_digester(&self, session: &mut UploadSession) -> Result<Digester> {
+        let checkpoint = match (&session.digest_state, &session.digest_checkpoint) {
+            (None, Some(checkpoint)) => checkpoint,
+            _ => return Ok(session.take_digester()),
+        };
+        let bytes: Bytes = self
+            .objects
+            .get(&Key::from_pathbuf(PathBuf::from(checkpoint)).map_err(Error::from)?)
+            .await
+            .map_err(Error::from)?
+            .try_collect::<Vec<Bytes>>()
+            .await
+            .map_err(Error::from)?
+            .into_iter()
+            .fold(BytesMut::new(), |mut acc, bs| {
+                acc.extend_from_slice(&bs);
+                acc
+            })
+            .into();
+        let state: DigestState = serde_json::from_slice(&bytes).map_err(Error::from)?;
+        Ok(state.into())
+    }
+
+    /// Record the digest calculation state so it can be resumed by the next chunk, checkpointing
+    /// it to the object store when `checkpoint_digest_state` is enabled so that only its key is
+    /// written to the database.
+    async fn save_digester(&self, session: &mut UploadSession, digester: Digester) -> Result<()> {
+        if !self.config.checkpoint_digest_state {
+            session.store_digester(digester);
+            return Ok(());
+        }
+
+        let state = serde_json::to_vec(&DigestState::from(digester)).map_err(Error::from)?;
+        let content_length = state.len() as u64;
+        let checkpoint = format!("digest-checkpoints/{}", session.uuid);
+        self.objects
+            .put(
+                &Key::from_pathbuf(PathBuf::from(&checkpoint)).map_err(Error::from)?,
+                state.into(),
+                content_length,
+            )
+            .await
+            .map_err(Error::from)?;
+        session.digest_state = None;
+        session.digest_checkpoint = Some(checkpoint);
+        Ok(())
+    }
+
+    /// Remove the session's digest state checkpoint, if any, once the upload no longer needs it.
+    async fn delete_digest_checkpoint(&self, session: &UploadSession) -> Result<()> {
+        if let Some(checkpoint) = &session.digest_checkpoint {
+            self.objects
+                .delete(&Key::from_pathbuf(PathBuf::from(checkpoint)).map_err(Error::from)?)
+                .await
+                .map_err(Error::from)?;
+        }
+        Ok(())
+    }
+
     async fn write_chunk(
         &self,
         tx: &mut PostgresMetadataTx<'_>,
         session: &mut UploadSession,
         bytes: Bytes,
     ) -> Result<()> {
+        let bytes_len = bytes.len() as u64;
         let chunk = self
             .objects
             .upload_chunk(
@@ -178,13 +371,13 @@ impl PgBlobWriter {
                     .as_str(),
                 &Key::from(&session.uuid),
                 session.chunk_number,
-                bytes.len() as u64,
+                bytes_len,
                 bytes.into(),
             )
             .await
             .map_err(Error::from)?;
 
-        tx.insert_chunk(&session, &MetadataChunk::from(chunk))
+        tx.insert_chunk(&session, &MetadataChunk::from(chunk), bytes_len)
             .await?;
         Ok(())
     }
@@ -192,16 +385,54 @@ impl PgBlobWriter {
 
 #[async_trait]
 impl BlobWriter for PgBlobWriter {
-    async fn write(&mut self, content_length: u64, body: Body) -> Result<BoxedUploadSession> {
+    async fn write(
+        &mut self,
+        content_length: u64,
+        body: Body,
+        chunk_digest: Option<&OciDigest>,
+    ) -> Result<BoxedUploadSession> {
         let mut session = if let Some(session) = self.session.take() {
             session
         } else {
             return Err(CoreError::BlobWriterFinished);
         };
         tracing::debug!("before chunk upload: {:?}", session);
-        let digester = Arc::new(Mutex::new(Digester::default()));
+
+        // object stores may reject empty chunks (S3 does for multipart uploads) and an empty chunk
+        // doesn't change the upload anyway, so there is nothing to write as long as the body
+        // really is empty
+        if content_length == 0 {
+            let bytes = hyper::body::to_bytes(body).await.map_err(Error::from)?;
+            if !bytes.is_empty() {
+                return Err(CoreError::SizeInvalid(Some(format!(
+                    "chunk declared 0 bytes but contained {}",
+                    bytes.len()
+                ))));
+            }
+            return Ok(Box::new(session));
+        }
+
+        self.initiate_chunked_upload(&mut session).await?;
+
+        // the chunk digest is calculated separately from the digest of the upload as a whole
+        let chunk_digester = match chunk_digest {
+            Some(d) if self.config.verify_chunk_digests => Some(Arc::new(Mutex::new(d.digester()))),
+            Some(_) => {
+                tracing::debug!("ignoring chunk digest since chunk verification is disabled");
+                None
+            }
+            None => None,
+        };
+        let body = match &chunk_digester {
+            Some(d) => Body::wrap_stream(Box::into_pin(DigestBody::from_body(body, d.clone()))),
+            None => body,
+        };
+
+        let digester = self.load_digester(&mut session).await?;
+        let bytes_before = digester.bytes();
+        let digester = Arc::new(Mutex::new(digester));
         let stream_body = DigestBody::from_body(body, digester.clone());
-        let chunk = self
+        let uploaded = self
             .objects
             .upload_chunk(
                 &session
@@ -214,20 +445,58 @@ impl BlobWriter for PgBlobWriter {
                 content_length,
                 stream_body.into(),
             )
-            .await
-            .map_err(Error::from)?;
+            .await;
+
+        // the object store may still hold a reference to the body if the upload failed, so take
+        // the digester out from under the lock rather than unwrapping the Arc
+        let digester = std::mem::take(
+            &mut *digester
+                .lock()
+                .expect("the body has been consumed so nothing else holds the lock"),
+        );
+
+        // a failed upload leaves fewer bytes hashed than were declared, so report its error rather
+        // than blaming the client for the length of the chunk
+        let chunk = uploaded.map_err(Error::from)?;
+
+        // a body that doesn't match its declared length would desync last_range_end from what was
+        // actually stored, so reject it before touching the session
+        let written = digester.bytes() - bytes_before;
+        if written != content_length {
+            tracing::debug!("chunk declared {content_length} bytes but contained {written}");
+            return Err(CoreError::SizeInvalid(Some(format!(
+                "chunk declared {content_length} bytes but contained {written}"
+            ))));
+        }
+
+        // the part just uploaded is replaced when the chunk is retried since the session's chunk
+        // number isn't advanced
+        if let (Some(expected), Some(chunk_digester)) = (chunk_digest, chunk_digester) {
+            let calculated = chunk_digester
+                .lock()
+                .expect("the body has been consumed so nothing else holds the lock")
+                .digest();
+            if calculated.as_ref() != Some(expected) {
+                tracing::warn!(
+                    "chunk content digest {:?} does not match provided chunk digest {}",
+                    calculated.as_ref().map(String::from),
+                    String::from(expected),
+                );
+                return Err(CoreError::BlobUploadInvalid(Some(
+                    "chunk content does not match chunk digest".to_string(),
+                )));
+            }
+        }
 
         let mut conn = self.metadata.get_conn().await?;
-        conn.insert_chunk(&session, &MetadataChunk::from(chunk))
+        conn.insert_chunk(&session, &MetadataChunk::from(chunk), content_length)
             .await?;
 
-        let digester = Arc::into_inner(digester)
-            .expect("no other references should exist at this point")
-            .into_inner()
-            .expect("the mutex cannot be locked if there are no other Arc references");
-
         session.chunk_number += 1;
-        session.last_range_end += digester.bytes() as i64 - 1;
+        // last_range_end is the inclusive index of the last byte received, which can't be derived
+        // by adding chunk lengths to it since it starts out at 0 before any bytes are received
+        session.last_range_end = digester.bytes() as i64 - 1;
+        self.save_digester(&mut session, digester).await?;
 
         conn.update_session(&session).await?;
 
@@ -241,9 +510,12 @@ impl BlobWriter for PgBlobWriter {
         } else {
             return Err(CoreError::BlobWriterFinished);
         };
+        self.initiate_chunked_upload(&mut session).await?;
+
         let md = self.metadata.clone();
         let mut tx = md.get_tx().await?;
-        let mut digester = Digester::default();
+        let mut digester = self.load_digester(&mut session).await?;
+        let bytes_before = digester.bytes();
 
         let chunked = ChunkedBody::from_body(body);
         tokio::pin!(chunked);
@@ -256,7 +528,11 @@ impl BlobWriter for PgBlobWriter {
             }
         }
 
-        session.last_range_end += digester.bytes() as i64 - 1;
+        let written = digester.bytes() - bytes_before;
+        if written > 0 {
+            session.last_range_end = digester.bytes() as i64 - 1;
+        }
+        self.save_digester(&mut session, digester).await?;
         tx.update_session(&session).await?;
 
         tx.commit().await?;
@@ -264,41 +540,93 @@ impl BlobWriter for PgBlobWriter {
     }
 
     async fn finalize(&mut self, digest: &OciDigest) -> Result<BoxedUploadSession> {
-        let session = if let Some(session) = self.session.take() {
+        let mut session = if let Some(session) = self.session.take() {
             session
         } else {
             return Err(CoreError::BlobWriterFinished);
         };
-        // TODO: validate digest
+        self.initiate_chunked_upload(&mut session).await?;
+
+        let digester = self.load_digester(&mut session).await?;
+        let bytes_on_disk = digester.bytes() as i64;
+        let calculated = digester.digest_for(digest);
+        if calculated.as_ref() != Some(digest) {
+            tracing::warn!(
+                "uploaded content digest {:?} does not match provided digest {}",
+                calculated.as_ref().map(String::from),
+                String::from(digest),
+            );
+            self.objects
+                .abort_chunked_upload(
+                    session
+                        .upload_id
+                        .as_ref()
+                        .expect("UploadSession.upload_id should always be Some here")
+                        .as_str(),
+                    &Key::from(&session.uuid),
+                )
+                .await
+                .map_err(Error::from)?;
+
+            // the multipart upload is gone, so there is nothing left to resume
+            let mut tx = self.metadata.get_tx().await?;
+            tx.delete_chunks(&session.uuid).await?;
+            tx.delete_session(&session.uuid).await?;
+            tx.commit().await?;
+            self.delete_digest_checkpoint(&session).await?;
+
+            return Err(CoreError::DigestInvalid(None));
+        }
+
         let mut tx = self.metadata.get_tx().await?;
         let uuid = match tx.get_blob(&digest).await? {
             Some(b) => b.id,
-            None => tx.insert_blob(&digest, &session.last_range_end + 1).await?,
+            None => tx.insert_blob(&digest, bytes_on_disk).await?,
         };
 
         let blob_key = Key::from(&uuid);
         let session_key = Key::from(&session.uuid);
 
         if !self.objects.exists(&blob_key).await.map_err(Error::from)? {
-            let chunks = tx
+            let chunks: Vec<Chunk> = tx
                 .get_chunks(&session)
                 .await?
                 .into_iter()
                 .map(Chunk::from)
                 .collect();
-            self.objects
-                .finalize_chunked_upload(
-                    session
-                        .upload_id
-                        .as_ref()
-                        .expect("UploadSession.upload_id should always be Some here")
-                        .as_str(),
-                    &session_key,
-                    chunks,
-                    &blob_key,
-                )
-                .await
-                .map_err(Error::from)?;
+            if chunks.is_empty() {
+                // a multipart upload can't be completed without any parts, so store the empty blob
+                // directly instead
+                self.objects
+                    .abort_chunked_upload(
+                        session
+                            .upload_id
+                            .as_ref()
+                            .expect("UploadSession.upload_id should always be Some here")
+                            .as_str(),
+                        &session_key,
+                    )
+                    .await
+                    .map_err(Error::from)?;
+                self.objects
+                    .put(&blob_key, Body::empty(), 0)
+                    .await
+                    .map_err(Error::from)?;
+            } else {
+                self.objects
+                    .finalize_chunked_upload(
+                        session
+                            .upload_id
+                            .as_ref()
+                            .expect("UploadSession.upload_id should always be Some here")
+                            .as_str(),
+                        &session_key,
+                        chunks,
+                        &blob_key,
+                    )
+                    .await
+                    .map_err(Error::from)?;
+            }
         } else {
             self.objects
                 .abort_chunked_upload(
@@ -314,6 +642,26 @@ impl BlobWriter for PgBlobWriter {
         }
 
         tx.commit().await?;
+        self.delete_digest_checkpoint(&session).await?;
+
+        if let Some(audit) = &self.audit {
+            audit.record_blob(digest);
+        }
+
+        if let Some(events) = &self.events {
+            events.blob_pushed(ContentEvent {
+                repository: self.repository.clone(),
+                digest: digest.clone(),
+                media_type: None,
+            });
+        }
+
         Ok(Box::new(session))
     }
+
+    fn session(&self) -> Option<&dyn registry::UploadSession> {
+        self.session
+            .as_ref()
+            .map(|s| s as &dyn registry::UploadSession)
+    }
 }
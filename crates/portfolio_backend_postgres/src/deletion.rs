@@ -0,0 +1,277 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Deserialize;
+use uuid::Uuid;
+
+use portfolio_core::errors::Result;
+use portfolio_objectstore::{Key, ObjectStore};
+
+use super::errors::Error;
+use super::manifests::purge_manifest;
+use super::metadata::{PostgresMetadataPool, PostgresMetadataTx};
+
+/// Number of marked objects [`ObjectSweeper::sweep`] retrieves from the database at a time.
+const SWEEP_BATCH_SIZE: u64 = 100;
+
+/// Number of expired tombstones [`ManifestReaper::reap`] purges per transaction.
+const REAP_BATCH_SIZE: u64 = 100;
+
+/// Number of stale upload sessions [`SessionReaper::reap`] cleans up per transaction.
+const SESSION_REAP_BATCH_SIZE: u64 = 100;
+
+/// How object store content is removed once the metadata referring to it has been deleted.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ObjectDeletion {
+    /// Delete content with a single object store call while handling the request that deleted
+    /// it, trusting the object store to have removed it once that call succeeds.
+    #[default]
+    Eager,
+    /// Mark content for deletion in the same transaction that deletes its metadata and leave
+    /// removing it from the object store to an [`ObjectSweeper`].
+    ///
+    /// Content is no longer served as soon as its metadata is deleted, but the sweeper can be
+    /// given a grace period during which marked content is kept in the object store so that
+    /// accidental deletes can be recovered from.
+    Lazy,
+}
+
+impl ObjectDeletion {
+    /// Remove the object store content with the given id, whose metadata is being deleted in
+    /// `tx`.
+    pub(crate) async fn delete(
+        &self,
+        tx: &mut PostgresMetadataTx<'_>,
+        objects: &dyn ObjectStore,
+        object_id: &Uuid,
+    ) -> Result<()> {
+        match self {
+            Self::Eager => objects
+                .delete(&Key::from(object_id))
+                .await
+                .map_err(Error::from)?,
+            Self::Lazy => tx.mark_object_for_deletion(object_id).await?,
+        }
+        Ok(())
+    }
+}
+
+/// Removes content marked for deletion under [`ObjectDeletion::Lazy`] from the object store once
+/// its grace period has passed.
+#[derive(Clone)]
+pub struct ObjectSweeper {
+    metadata: PostgresMetadataPool,
+    objects: Arc<dyn ObjectStore>,
+    grace_period: Duration,
+}
+
+impl ObjectSweeper {
+    pub(crate) fn new(
+        metadata: PostgresMetadataPool,
+        objects: Arc<dyn ObjectStore>,
+        grace_period: Duration,
+    ) -> Self {
+        Self {
+            metadata,
+            objects,
+            grace_period,
+        }
+    }
+
+    /// Delete every object marked for deletion longer ago than the grace period, returning the
+    /// number deleted.
+    ///
+    /// Each object is unmarked only after it has been deleted, so an object whose deletion fails
+    /// is retried by the next sweep.
+    pub async fn sweep(&self) -> Result<usize> {
+        let mut conn = self.metadata.get_conn().await?;
+        let mut count = 0;
+        loop {
+            let object_ids = conn
+                .get_object_deletions(self.grace_period.as_secs(), SWEEP_BATCH_SIZE)
+                .await?;
+            if object_ids.is_empty() {
+                return Ok(count);
+            }
+            for object_id in object_ids {
+                self.objects
+                    .delete(&Key::from(&object_id))
+                    .await
+                    .map_err(Error::from)?;
+                conn.delete_object_deletion(&object_id).await?;
+                count += 1;
+            }
+        }
+    }
+
+    /// Sweep every `interval` on a background task, starting one `interval` from now.
+    pub(crate) fn spawn(self, interval: Duration) {
+        tokio::spawn(async move {
+            let mut ticker =
+                tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+            loop {
+                ticker.tick().await;
+                match self.sweep().await {
+                    Ok(0) => (),
+                    Ok(count) => tracing::debug!("swept {count} objects marked for deletion"),
+                    Err(e) => tracing::warn!("error sweeping objects marked for deletion: {e:?}"),
+                }
+            }
+        });
+    }
+}
+
+/// Purges manifests tombstoned by deletion once their retention window has passed, along with
+/// their associations, tags, and any content no longer referenced elsewhere. Deleted tags are
+/// forgotten once their retention window has passed too.
+#[derive(Clone)]
+pub struct ManifestReaper {
+    metadata: PostgresMetadataPool,
+    objects: Arc<dyn ObjectStore>,
+    object_deletion: ObjectDeletion,
+    retention: Duration,
+}
+
+impl ManifestReaper {
+    pub(crate) fn new(
+        metadata: PostgresMetadataPool,
+        objects: Arc<dyn ObjectStore>,
+        object_deletion: ObjectDeletion,
+        retention: Duration,
+    ) -> Self {
+        Self {
+            metadata,
+            objects,
+            object_deletion,
+            retention,
+        }
+    }
+
+    /// Purge every manifest tombstoned longer ago than the retention window, returning the number
+    /// purged.
+    ///
+    /// Tombstones are locked while they are purged, so a manifest can't be restored halfway
+    /// through being purged.
+    pub async fn reap(&self) -> Result<usize> {
+        let mut tx = self.metadata.get_tx().await?;
+        tx.delete_expired_tag_tombstones(self.retention.as_secs())
+            .await?;
+        tx.commit().await?;
+
+        let mut count = 0;
+        loop {
+            let mut tx = self.metadata.get_tx().await?;
+            let manifests = tx
+                .get_expired_manifest_tombstones(self.retention.as_secs(), REAP_BATCH_SIZE)
+                .await?;
+            if manifests.is_empty() {
+                return Ok(count);
+            }
+            for manifest in &manifests {
+                purge_manifest(
+                    &mut tx,
+                    manifest,
+                    self.object_deletion,
+                    self.objects.as_ref(),
+                )
+                .await?;
+            }
+            tx.commit().await?;
+            count += manifests.len();
+        }
+    }
+
+    /// Reap every `interval` on a background task, starting one `interval` from now.
+    pub(crate) fn spawn(self, interval: Duration) {
+        tokio::spawn(async move {
+            let mut ticker =
+                tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+            loop {
+                ticker.tick().await;
+                match self.reap().await {
+                    Ok(0) => (),
+                    Ok(count) => tracing::debug!("purged {count} deleted manifests"),
+                    Err(e) => tracing::warn!("error purging deleted manifests: {e:?}"),
+                }
+            }
+        });
+    }
+}
+
+/// Cleans up upload sessions that were started longer ago than a maximum age without being
+/// completed, along with their chunks, their digest state checkpoints, and the multipart uploads
+/// staging their content in the object store.
+#[derive(Clone)]
+pub struct SessionReaper {
+    metadata: PostgresMetadataPool,
+    objects: Arc<dyn ObjectStore>,
+    max_age: Duration,
+}
+
+impl SessionReaper {
+    pub(crate) fn new(
+        metadata: PostgresMetadataPool,
+        objects: Arc<dyn ObjectStore>,
+        max_age: Duration,
+    ) -> Self {
+        Self {
+            metadata,
+            objects,
+            max_age,
+        }
+    }
+
+    /// Clean up every upload session started longer ago than the maximum age, returning the
+    /// number cleaned up.
+    ///
+    /// Sessions are locked while they are cleaned up, so chunks can't be written to a session
+    /// whose multipart upload has already been aborted.
+    pub async fn reap(&self) -> Result<usize> {
+        let mut count = 0;
+        loop {
+            let mut tx = self.metadata.get_tx().await?;
+            let sessions = tx
+                .get_stale_sessions(self.max_age.as_secs(), SESSION_REAP_BATCH_SIZE)
+                .await?;
+            if sessions.is_empty() {
+                return Ok(count);
+            }
+            for session in &sessions {
+                if let Some(upload_id) = &session.upload_id {
+                    self.objects
+                        .abort_chunked_upload(upload_id, &Key::from(&session.uuid))
+                        .await
+                        .map_err(Error::from)?;
+                }
+                if let Some(checkpoint) = &session.digest_checkpoint {
+                    self.objects
+                        .delete(&Key::from_pathbuf(PathBuf::from(checkpoint)).map_err(Error::from)?)
+                        .await
+                        .map_err(Error::from)?;
+                }
+                tx.delete_chunks(&session.uuid).await?;
+                tx.delete_session(&session.uuid).await?;
+            }
+            tx.commit().await?;
+            count += sessions.len();
+        }
+    }
+
+    /// Reap every `interval` on a background task, starting one `interval` from now.
+    pub(crate) fn spawn(self, interval: Duration) {
+        tokio::spawn(async move {
+            let mut ticker =
+                tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+            loop {
+                ticker.tick().await;
+                match self.reap().await {
+                    Ok(0) => (),
+                    Ok(count) => tracing::debug!("cleaned up {count} stale upload sessions"),
+                    Err(e) => tracing::warn!("error cleaning up stale upload sessions: {e:?}"),
+                }
+            }
+        });
+    }
+}
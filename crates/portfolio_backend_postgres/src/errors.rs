@@ -19,6 +19,10 @@ pub enum Error {
 
     #[error("http error")]
     HTTPError(#[from] http::Error),
+    #[error("invalid uri: {0}")]
+    InvalidUri(#[from] http::uri::InvalidUri),
+    #[error("hyper error: {0}")]
+    HyperError(#[from] hyper::Error),
 
     #[error("{0}")]
     TokioJoinError(#[from] tokio::task::JoinError),
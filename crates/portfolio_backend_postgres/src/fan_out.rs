@@ -0,0 +1,73 @@
+use std::sync::Arc;
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Caps the number of object store reads that manifest fan-out operations, such as building a
+/// referrers listing, may have in flight at once.
+///
+/// A single limiter is shared by every store handed out by a
+/// [`PgRepositoryFactory`](super::PgRepositoryFactory), so the cap applies across all concurrent
+/// requests rather than to each request individually.
+#[derive(Debug)]
+pub struct FanOutLimiter {
+    permits: Arc<Semaphore>,
+    limit: usize,
+}
+
+impl FanOutLimiter {
+    /// Allow up to `limit` reads at once; a limit of 0 is treated as 1 so that reads can't stall
+    /// forever.
+    pub fn new(limit: usize) -> Self {
+        let limit = limit.max(1);
+        Self {
+            permits: Arc::new(Semaphore::new(limit)),
+            limit,
+        }
+    }
+
+    /// Wait for a read to be allowed; the read may proceed until the returned permit is dropped.
+    pub(crate) async fn acquire(&self) -> OwnedSemaphorePermit {
+        self.permits
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("fan-out semaphore is never closed")
+    }
+
+    /// Number of reads currently in flight.
+    pub fn in_flight(&self) -> usize {
+        self.limit - self.permits.available_permits()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn caps_reads_across_tasks() {
+        let limiter = Arc::new(FanOutLimiter::new(2));
+        let max_in_flight = Arc::new(AtomicUsize::new(0));
+
+        // each task stands in for a separate request fanning out to the object store
+        let mut set = tokio::task::JoinSet::new();
+        for _ in 0..16 {
+            let limiter = limiter.clone();
+            let max_in_flight = max_in_flight.clone();
+            set.spawn(async move {
+                let _permit = limiter.acquire().await;
+                max_in_flight.fetch_max(limiter.in_flight(), Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            });
+        }
+        while let Some(res) = set.join_next().await {
+            res.unwrap();
+        }
+
+        assert_eq!(max_in_flight.load(Ordering::SeqCst), 2);
+        assert_eq!(limiter.in_flight(), 0);
+    }
+}
@@ -1,10 +1,23 @@
+mod audit;
 mod blobs;
+mod deletion;
 mod errors;
+mod fan_out;
 mod manifests;
 mod metadata;
 mod repositories;
+mod snapshot;
 mod upload_sessions;
 
+pub use audit::DigestAlgorithmAudit;
+pub use deletion::{ManifestReaper, ObjectDeletion, ObjectSweeper, SessionReaper};
+pub use fan_out::FanOutLimiter;
+pub use manifests::ImageCreation;
+pub use metadata::ImageAge;
+pub use metadata::PostgresConfig;
+pub use metadata::StorageStats;
 pub use repositories::PgRepositoryConfig;
 pub use repositories::PgRepositoryFactory;
 pub use repositories::PgRepository;
+pub use repositories::StoreConfig;
+pub use snapshot::MetadataSnapshot;
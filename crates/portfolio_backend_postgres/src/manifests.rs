@@ -1,27 +1,194 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 use async_trait::async_trait;
 use bytes::Bytes;
 use bytes::BytesMut;
+use chrono::{DateTime, Utc};
 use futures::stream::BoxStream;
 use futures::stream::StreamExt;
 use futures::stream::TryStreamExt;
 use oci_spec::distribution::{TagList, TagListBuilder};
-use oci_spec::image::{Descriptor, ImageIndex, MediaType};
+use oci_spec::image::{
+    Descriptor, ImageIndex, ImageManifest, MediaType, Platform, ANNOTATION_CREATED,
+};
+use tracing::Instrument;
 
+use portfolio_core::events::ContentEvent;
 use portfolio_core::registry::{
-    BlobStore, BoxedManifest, BoxedTag, ManifestRef, ManifestSpec, ManifestStore,
+    glob_matches, BoxedManifest, BoxedTag, ManifestRef, ManifestSpec, ManifestStore,
 };
 use portfolio_core::Error as CoreError;
 use portfolio_core::OciDigest;
+use portfolio_core::PortfolioErrorCode;
 use portfolio_core::Result;
-use portfolio_objectstore::Key;
+use portfolio_objectstore::Error as ObjectStoreError;
+use portfolio_objectstore::{Key, ObjectStore};
+use serde::Deserialize;
+use uuid::Uuid;
 
 use super::blobs::PgBlobStore;
+use super::deletion::ObjectDeletion;
 use super::errors::Error;
+use super::metadata::Blob;
 use super::metadata::Manifest;
+use super::metadata::PostgresMetadataPool;
+use super::metadata::PostgresMetadataTx;
 use super::metadata::Repository;
 
+/// Default for [`StoreConfig::max_manifest_read_bytes`](super::repositories::StoreConfig).
+pub(crate) const DEFAULT_MAX_MANIFEST_READ_BYTES: u64 = 4 * 1024 * 1024;
+
+/// Default for [`StoreConfig::association_batch_size`](super::repositories::StoreConfig).
+pub(crate) const DEFAULT_ASSOCIATION_BATCH_SIZE: usize = 1000;
+
+/// Number of manifests [`backfill_config_associations`] processes per transaction.
+const CONFIG_BACKFILL_BATCH_SIZE: u64 = 100;
+
+/// Whether the creation time of pushed images is recorded, for retention policies based on the age
+/// of images rather than when they were pushed or last pulled.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageCreation {
+    /// Don't record when images were created.
+    #[default]
+    Ignore,
+    /// Record when images were created, taken from the `org.opencontainers.image.created`
+    /// annotation of their manifest or else from the `created` field of their config. Images
+    /// whose creation time can't be determined are accepted without one.
+    Record,
+    /// Record when images were created like `record`, rejecting images whose creation time can't
+    /// be determined with `MANIFEST_INVALID`.
+    Require,
+}
+
+/// Read a stored manifest into memory, giving up with [`CoreError::ManifestInvalid`] as soon as
+/// more than `limit` bytes have been read rather than buffering an arbitrarily large object.
+async fn read_manifest(
+    objects: &dyn ObjectStore,
+    manifest: &Manifest,
+    limit: u64,
+) -> Result<Bytes> {
+    read_object(
+        objects,
+        &Key::from(&manifest.blob_id),
+        manifest.bytes_on_disk,
+        limit,
+    )
+    .await?
+    .ok_or_else(|| {
+        CoreError::ManifestInvalid(Some(format!(
+            "manifest {} exceeds the maximum of {limit} bytes",
+            String::from(&manifest.digest)
+        )))
+    })
+}
+
+/// Read a stored object of `bytes_on_disk` bytes into memory, or return `None` as soon as more
+/// than `limit` bytes have been read.
+async fn read_object(
+    objects: &dyn ObjectStore,
+    key: &Key,
+    bytes_on_disk: i64,
+    limit: u64,
+) -> Result<Option<Bytes>> {
+    if bytes_on_disk as u64 > limit {
+        return Ok(None);
+    }
+
+    let mut stream = objects.get(key).await.map_err(Error::from)?;
+    let mut buf = BytesMut::with_capacity(bytes_on_disk as usize);
+    while let Some(bs) = stream.try_next().await.map_err(Error::from)? {
+        if (buf.len() + bs.len()) as u64 > limit {
+            return Ok(None);
+        }
+        buf.extend_from_slice(&bs);
+    }
+    Ok(Some(buf.freeze()))
+}
+
+/// Parse an RFC 3339 timestamp as used by the `org.opencontainers.image.created` annotation and
+/// the `created` field of image configs.
+fn parse_created(created: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(created)
+        .ok()
+        .map(|dt| dt.with_timezone(&Utc))
+}
+
+/// Return the `created` field of an image config, if it has a valid one.
+fn config_created(config: &[u8]) -> Option<DateTime<Utc>> {
+    #[derive(Deserialize)]
+    struct Config {
+        created: Option<String>,
+    }
+    let config: Config = serde_json::from_slice(config).ok()?;
+    config.created.as_deref().and_then(parse_created)
+}
+
+/// Delete the manifest's metadata along with its associations and tags, and its content if no
+/// other repository shares it.
+pub(crate) async fn purge_manifest(
+    tx: &mut PostgresMetadataTx<'_>,
+    manifest: &Manifest,
+    object_deletion: ObjectDeletion,
+    objects: &dyn ObjectStore,
+) -> Result<()> {
+    // NOTE: it's possible (but how likely?) for a manifest to include both layers and
+    // manifests; we don't support creating both types of association for now, but we should
+    // support deleting them here just in case
+    tx.delete_image_layers(&manifest.id).await?;
+    tx.delete_index_manifests(&manifest.id).await?;
+    tx.delete_tags_by_manifest_id(&manifest.id).await?;
+    tx.delete_tag_tombstones_by_manifest_id(&manifest.id)
+        .await?;
+    tx.delete_manifest(&manifest.id).await?;
+
+    // the same manifest content may have been pushed to other repositories, in which case
+    // they share the underlying blob
+    if tx.blob_reference_count(&manifest.digest).await? == 0 {
+        tx.delete_blob(&manifest.blob_id).await?;
+        object_deletion
+            .delete(tx, objects, &manifest.blob_id)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Associate image manifests pushed before configs were associated with them with their config
+/// blobs, returning the number associated. Such manifests are flagged by a migration, since their
+/// config digests are only recorded in their stored content.
+///
+/// Until this has run their configs look unreferenced, so it must run before looking for
+/// unreferenced blobs.
+pub(crate) async fn backfill_config_associations(
+    metadata: &PostgresMetadataPool,
+    objects: &dyn ObjectStore,
+    limit: u64,
+) -> Result<usize> {
+    let mut count = 0;
+    loop {
+        let mut tx = metadata.get_tx().await?;
+        let manifests = tx
+            .get_manifests_pending_config_backfill(CONFIG_BACKFILL_BATCH_SIZE)
+            .await?;
+        if manifests.is_empty() {
+            return Ok(count);
+        }
+        for manifest in &manifests {
+            let bytes = read_manifest(objects, manifest, limit).await?;
+            if let ManifestSpec::Image(img) = ManifestSpec::try_from(&bytes)? {
+                let digest: OciDigest = img.config().digest().as_str().try_into()?;
+                if let Some(config) = tx.get_blob(&digest).await? {
+                    tx.associate_image_config(&manifest.id, &config.id).await?;
+                    count += 1;
+                }
+            }
+            tx.clear_config_backfill(&manifest.id).await?;
+        }
+        tx.commit().await?;
+    }
+}
+
 pub struct PgManifestStore {
     blobstore: PgBlobStore,
     repository: Repository,
@@ -34,10 +201,372 @@ impl PgManifestStore {
             repository,
         }
     }
+
+    /// Determine when the image was created, according to its `org.opencontainers.image.created`
+    /// annotation or else the `created` field of its config; see
+    /// [`StoreConfig::image_creation`](super::StoreConfig).
+    async fn image_created(
+        &self,
+        tx: &mut PostgresMetadataTx<'_>,
+        img: &ImageManifest,
+    ) -> Result<Option<DateTime<Utc>>> {
+        let mode = self.blobstore.config.image_creation;
+        if mode == ImageCreation::Ignore {
+            return Ok(None);
+        }
+
+        let mut created = img
+            .annotations()
+            .as_ref()
+            .and_then(|a| a.get(ANNOTATION_CREATED))
+            .and_then(|v| parse_created(v));
+        if created.is_none() && img.config().media_type() == &MediaType::ImageConfig {
+            let config = tx
+                .get_blob(&img.config().digest().as_str().try_into()?)
+                .await?;
+            if let Some(config) = config {
+                let limit = self
+                    .blobstore
+                    .config
+                    .max_manifest_read_bytes
+                    .unwrap_or(DEFAULT_MAX_MANIFEST_READ_BYTES);
+                let bytes = read_object(
+                    self.blobstore.objects.as_ref(),
+                    &Key::from(&config.id),
+                    config.bytes_on_disk,
+                    limit,
+                )
+                .await?;
+                created = bytes.as_deref().and_then(config_created);
+            }
+        }
+
+        if created.is_none() && mode == ImageCreation::Require {
+            let msg = "image creation time could not be determined from its annotations or config";
+            tracing::warn!("{msg}");
+            return Err(CoreError::ManifestInvalid(Some(msg.to_string())));
+        }
+        Ok(created)
+    }
+
+    /// Point the pushed tag at the manifest, subject to the repository's immutable tags and
+    /// mutability window.
+    async fn tag_manifest(
+        &self,
+        tx: &mut PostgresMetadataTx<'_>,
+        manifest_id: &Uuid,
+        tag: &str,
+    ) -> Result<()> {
+        // concurrent pushes to the same tag take turns moving it, so that each push sees the tag
+        // as left by the previous one and the last to commit wins
+        tx.lock_tag(&self.repository.id, tag).await?;
+
+        // an immutable tag is one whose mutability window closed as soon as it was pushed
+        let immutable = self
+            .repository
+            .immutable_tags
+            .iter()
+            .any(|pattern| glob_matches(pattern, tag));
+        let mutable_for = if immutable {
+            Some(0)
+        } else {
+            self.blobstore
+                .config
+                .tag_mutability_windows
+                .get(&self.repository.name)
+                .copied()
+        };
+        tx.upsert_tag(&self.repository.id, manifest_id, tag, mutable_for)
+            .await?;
+        Ok(())
+    }
+
+    /// Record the platforms and positions of the children of an index pushed before they were
+    /// recorded, from the index's stored content.
+    async fn backfill_index_platforms(&self, index: &Manifest) -> Result<()> {
+        let mut tx = self.blobstore.metadata.get_tx().await?;
+        // another resolution may have backfilled the index while this one waited for the lock
+        if !tx.lock_platform_backfill(&index.id).await? {
+            return Ok(());
+        }
+
+        let limit = self
+            .blobstore
+            .config
+            .max_manifest_read_bytes
+            .unwrap_or(DEFAULT_MAX_MANIFEST_READ_BYTES);
+        let bytes = read_manifest(self.blobstore.objects.as_ref(), index, limit).await?;
+        if let ManifestSpec::Index(ind) = ManifestSpec::try_from(&bytes)? {
+            let descriptors = child_descriptors(&ind);
+            let digests = descriptors.keys().copied().collect();
+            for child in tx.get_manifests(&self.repository.id, &digests).await? {
+                let digest: String = (&child.digest).into();
+                if let Some((position, platform)) = descriptors.get(digest.as_str()) {
+                    tx.set_index_child_platform(&index.id, &child.id, *platform, *position)
+                        .await?;
+                }
+            }
+        }
+        tx.clear_platform_backfill(&index.id).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Look up the blobs or manifests the manifest references, rejecting it if any are missing or,
+    /// for an image, if it exceeds [`StoreConfig::max_image_size`](super::StoreConfig).
+    async fn resolve_references<'a>(
+        &self,
+        tx: &mut PostgresMetadataTx<'_>,
+        spec: &'a ManifestSpec,
+    ) -> Result<References<'a>> {
+        match spec {
+            ManifestSpec::Image(img) => {
+                let layers = img.layers();
+
+                // first ensure all referenced layers exist as blobs
+                let digests = layers.iter().map(|desc| desc.digest().as_str()).collect();
+                let blobs = tx.get_blobs(&digests).await?;
+
+                let mut hs: HashSet<String> = HashSet::new();
+                for blob in &blobs {
+                    hs.insert((&blob.digest).into());
+                }
+                for digest in &digests {
+                    if !hs.contains(*digest) {
+                        let msg = format!("blob for layer {digest} not found in repository");
+                        tracing::warn!("{msg}");
+                        return Err(CoreError::ManifestBlobUnknown(Some(msg)));
+                    }
+                }
+
+                // a blob's metadata may outlive its content, so check the object store too
+                let keys: Vec<Key> = blobs.iter().map(|b| Key::from(&b.id)).collect();
+                let present = self
+                    .blobstore
+                    .objects
+                    .exists_many(&keys)
+                    .await
+                    .map_err(Error::from)?;
+                for (blob, present) in blobs.iter().zip(present) {
+                    if !present {
+                        let msg = format!(
+                            "blob for layer {} missing from storage",
+                            String::from(&blob.digest)
+                        );
+                        tracing::warn!("{msg}");
+                        return Err(CoreError::ManifestBlobUnknown(Some(msg)));
+                    }
+                }
+
+                let config = tx
+                    .get_blob(&img.config().digest().as_str().try_into()?)
+                    .await?;
+
+                if let Some(max_image_size) = self.blobstore.config.max_image_size {
+                    let config_size = config.as_ref().map(|b| b.bytes_on_disk).unwrap_or_default();
+                    let image_size: i64 =
+                        blobs.iter().map(|b| b.bytes_on_disk).sum::<i64>() + config_size;
+                    if image_size as u64 > max_image_size {
+                        let msg = format!(
+                            "image size {image_size} exceeds the maximum of {max_image_size} bytes"
+                        );
+                        tracing::warn!("{msg}");
+                        return Err(CoreError::Denied(Some(msg)));
+                    }
+                }
+
+                Ok(References::Image { blobs, config })
+            }
+            ManifestSpec::Index(ind) => {
+                // ensure all referenced manifests exist as blobs
+                let digests = ind
+                    .manifests()
+                    .iter()
+                    .map(|desc| desc.digest().as_str())
+                    .collect();
+                let manifests = tx.get_manifests(&self.repository.id, &digests).await?;
+
+                let mut hs: HashSet<String> = HashSet::new();
+                for manifest in &manifests {
+                    hs.insert((&manifest.digest).into());
+                }
+                for digest in &digests {
+                    if !hs.contains(*digest) {
+                        let msg = format!("blob for manifest {digest} not found in repository");
+                        tracing::warn!("{msg}");
+                        return Err(CoreError::ManifestUnknown(Some(msg)));
+                    }
+                }
+
+                let descriptors = child_descriptors(ind);
+                let children = manifests
+                    .iter()
+                    .filter_map(|m| {
+                        let digest: String = (&m.digest).into();
+                        let (position, platform) = descriptors.get(digest.as_str())?;
+                        Some((m.id, *platform, *position))
+                    })
+                    .collect();
+                Ok(References::Index(children))
+            }
+        }
+    }
+
+    /// Point the referrers tag schema tag of the manifest's subject, if it has one, at an index of
+    /// the subject's current referrers, or remove the tag if it has none; see
+    /// [`StoreConfig::referrers_fallback_tag`](super::StoreConfig).
+    async fn update_referrers_tag(
+        &self,
+        tx: &mut PostgresMetadataTx<'_>,
+        referrer: &Manifest,
+    ) -> Result<()> {
+        let subject = match &referrer.subject {
+            Some(s) if self.blobstore.config.referrers_fallback_tag => s,
+            _ => return Ok(()),
+        };
+        let tag = referrers_tag(subject);
+        // concurrent pushes of referrers to the same subject take turns rebuilding the index, so
+        // that the last to commit sees every referrer committed before it
+        tx.lock_tag(&self.repository.id, &tag).await?;
+
+        let referrers = tx
+            .get_referrers(&self.repository.id, subject, &None, None, None)
+            .await?;
+        if referrers.is_empty() {
+            tx.delete_tag(&self.repository.id, &tag).await?;
+            return Ok(());
+        }
+
+        // unlike the referrers API, descriptors are built from the metadata alone so that the
+        // index can be rebuilt without reading every referrer from the object store
+        let mut index = ImageIndex::default();
+        index.set_media_type(Some(MediaType::ImageIndex));
+        let descriptors = referrers
+            .into_iter()
+            .filter_map(stored_descriptor)
+            .collect();
+        index.set_manifests(descriptors);
+        let bytes = Bytes::from(serde_json::to_vec(&index).map_err(Error::from)?);
+        let digest = OciDigest::from(bytes.as_ref());
+        let byte_count = bytes.len();
+
+        tx.undelete_manifest(&self.repository.id, &digest).await?;
+        let manifest = match tx
+            .get_manifest(&self.repository.id, &ManifestRef::Digest(digest.clone()))
+            .await?
+        {
+            Some(m) => m,
+            None => {
+                let (blob_uuid, _) = self
+                    .blobstore
+                    .put_content(&digest, byte_count as u64, bytes.into())
+                    .await?;
+                let manifest = Manifest::from_spec_with_params(
+                    &ManifestSpec::Index(index),
+                    self.repository.id,
+                    blob_uuid,
+                    digest,
+                    byte_count as i64,
+                );
+                tx.insert_manifest(&manifest).await?;
+                manifest
+            }
+        };
+        tx.upsert_tag(&self.repository.id, &manifest.id, &tag, None)
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// What a pushed manifest references, as resolved by [`PgManifestStore::resolve_references`]: an
+/// image's layer and config blobs, or an index's child manifests along with the platform each is
+/// described as targeting and where the index lists it.
+enum References<'a> {
+    Image {
+        blobs: Vec<Blob>,
+        config: Option<Blob>,
+    },
+    Index(Vec<(Uuid, Option<&'a Platform>, i32)>),
+}
+
+/// The position and platform of each child manifest of the index by digest, as described by the
+/// first descriptor listing it.
+fn child_descriptors(index: &ImageIndex) -> HashMap<&str, (i32, Option<&Platform>)> {
+    let mut descriptors = HashMap::new();
+    for (position, desc) in index.manifests().iter().enumerate() {
+        descriptors
+            .entry(desc.digest().as_str())
+            .or_insert((position as i32, desc.platform().as_ref()));
+    }
+    descriptors
+}
+
+/// The tag the referrers tag schema uses for the referrers of `subject`, eg `sha256-<hex>`, with
+/// the algorithm and encoded digest truncated to 32 and 64 characters so that it is a valid tag.
+fn referrers_tag(subject: &OciDigest) -> String {
+    let digest = String::from(subject);
+    let (algorithm, encoded) = digest.split_once(':').unwrap_or(("", digest.as_str()));
+    let truncate = |s: &str, n: usize| s.chars().take(n).collect::<String>();
+    format!("{}-{}", truncate(algorithm, 32), truncate(encoded, 64))
+}
+
+/// Describe a manifest from its metadata alone, skipping (with a warning) manifests stored
+/// without a media type.
+fn stored_descriptor(m: Manifest) -> Option<Descriptor> {
+    let media_type = match m.media_type {
+        Some(mt) => mt,
+        None => {
+            tracing::warn!(
+                "manifest {} (digest {:?}) unexpectedly missing media type!",
+                m.id,
+                m.digest
+            );
+            return None;
+        }
+    };
+    let mut d = Descriptor::new(media_type, m.bytes_on_disk, &m.digest);
+    d.set_artifact_type(m.artifact_type);
+    d.set_annotations(m.annotations);
+    Some(d)
 }
 
 type TryBytes = std::result::Result<Bytes, Box<dyn std::error::Error + Send + Sync>>;
 
+/// Re-serialize manifest JSON with object keys sorted and no insignificant whitespace.
+///
+/// This deliberately discards the exact bytes sent by the client, so the resulting digest only
+/// matches the client's if the client itself sent canonical JSON; see
+/// [`StoreConfig::canonicalize_manifests`](super::StoreConfig).
+fn canonicalize_manifest(bytes: &[u8]) -> Result<Bytes> {
+    fn sort_keys(value: serde_json::Value) -> serde_json::Value {
+        match value {
+            serde_json::Value::Object(map) => {
+                let mut entries: Vec<(String, serde_json::Value)> = map.into_iter().collect();
+                entries.sort_by(|(left, _), (right, _)| left.cmp(right));
+                serde_json::Value::Object(
+                    entries
+                        .into_iter()
+                        .map(|(k, v)| (k, sort_keys(v)))
+                        .collect(),
+                )
+            }
+            serde_json::Value::Array(values) => {
+                serde_json::Value::Array(values.into_iter().map(sort_keys).collect())
+            }
+            v => v,
+        }
+    }
+
+    let value: serde_json::Value = serde_json::from_slice(bytes).map_err(|e| {
+        tracing::warn!("error canonicalizing manifest: {e:?}");
+        CoreError::ManifestInvalid(None)
+    })?;
+    Ok(serde_json::to_vec(&sort_keys(value))
+        .map_err(Error::from)?
+        .into())
+}
+
 #[async_trait]
 impl ManifestStore for PgManifestStore {
     async fn head(&self, key: &ManifestRef) -> Result<Option<BoxedManifest>> {
@@ -55,12 +584,29 @@ impl ManifestStore for PgManifestStore {
     ) -> Result<Option<(BoxedManifest, BoxStream<'static, TryBytes>)>> {
         let mut conn = self.blobstore.metadata.get_conn().await?;
         if let Some(manifest) = conn.get_manifest(&self.repository.id, key).await? {
-            let body = self
+            let body = match self
                 .blobstore
                 .objects
                 .get(&Key::from(&manifest.blob_id))
                 .await
-                .map_err(Error::from)?;
+            {
+                Ok(body) => body,
+                Err(ObjectStoreError::ObjectNotFound(_)) => {
+                    tracing::error!(
+                        "manifest {} exists in metadata but its content is missing from the object store",
+                        String::from(&manifest.digest),
+                    );
+                    if self.blobstore.config.heal_missing_manifests {
+                        // reporting the manifest as unknown prompts clients to push it again, at
+                        // which point PgBlobStore::put notices the missing object and re-uploads it
+                        return Ok(None);
+                    }
+                    return Err(CoreError::PortfolioSpecError(
+                        PortfolioErrorCode::ManifestContentMissing,
+                    ));
+                }
+                Err(e) => return Err(Error::from(e).into()),
+            };
             Ok(Some((
                 Box::new(manifest),
                 body.map_err(|e| e.into()).boxed(),
@@ -75,17 +621,49 @@ impl ManifestStore for PgManifestStore {
         key: &ManifestRef,
         spec: &ManifestSpec,
         bytes: Bytes,
+        content_type: Option<&str>,
     ) -> Result<OciDigest> {
-        let calculated_digest: OciDigest = bytes.as_ref().into();
+        if let Some(allowed) = &self.blobstore.config.allowed_reference_digest_algorithms {
+            check_reference_algorithms(spec, allowed)?;
+        }
+
+        let bytes = if self.blobstore.config.canonicalize_manifests {
+            canonicalize_manifest(&bytes)?
+        } else {
+            bytes
+        };
+
+        // manifests pushed by digest are stored under the algorithm the client chose
+        let calculated_digest: OciDigest = match key {
+            ManifestRef::Digest(d) => {
+                let mut digester = d.digester();
+                digester.update(&bytes);
+                digester
+                    .digest()
+                    .expect("a new digester always tracks its hash state")
+            }
+            ManifestRef::Tag(_) => bytes.as_ref().into(),
+        };
+
+        let mut tx = self.blobstore.metadata.get_tx().await?;
+
+        // every reason to reject the manifest is checked before its content is stored, so that a
+        // rejected push doesn't leave an unreferenced blob behind
+        let references = self.resolve_references(&mut tx, spec).await?;
+        let created = match spec {
+            ManifestSpec::Image(img) => self.image_created(&mut tx, img).await?,
+            ManifestSpec::Index(_) => None,
+        };
 
         let byte_count = bytes.len();
-        let blob_uuid = self
+        let (blob_uuid, _) = self
             .blobstore
-            .put(&calculated_digest, byte_count as u64, bytes.into())
+            .put_content(&calculated_digest, byte_count as u64, bytes.into())
             .await?;
 
-        let mut tx = self.blobstore.metadata.get_tx().await?;
-
+        // pushing a deleted manifest again restores it
+        tx.undelete_manifest(&self.repository.id, &calculated_digest)
+            .await?;
         if let Some(m) = tx
             .get_manifest(
                 &self.repository.id,
@@ -93,123 +671,199 @@ impl ManifestStore for PgManifestStore {
             )
             .await?
         {
+            // the manifest may have just been restored, making it a referrer again
+            self.update_referrers_tag(&mut tx, &m).await?;
+            if let ManifestRef::Tag(t) = key {
+                self.tag_manifest(&mut tx, &m.id, t).await?;
+            }
+            tx.commit().await?;
             return Ok(m.digest);
         }
 
-        let manifest = Manifest::from_spec_with_params(
+        let mut manifest = Manifest::from_spec_with_params(
             spec,
             self.repository.id,
             blob_uuid,
             calculated_digest.clone(),
             byte_count as i64,
         );
+        manifest.content_type = content_type.map(String::from);
+        // without a recorded subject the manifest never shows up in referrers listings
+        if !self.blobstore.config.referrers_enabled() {
+            manifest.subject = None;
+        }
+        manifest.created = created;
         tx.insert_manifest(&manifest).await?;
 
-        match spec {
-            ManifestSpec::Image(img) => {
-                let layers = img.layers();
-
-                // first ensure all referenced layers exist as blobs
-                let digests = layers.iter().map(|desc| desc.digest().as_str()).collect();
-                let blobs = tx.get_blobs(&digests).await?;
-
-                let mut hs: HashSet<String> = HashSet::new();
-                for blob in &blobs {
-                    hs.insert((&blob.digest).into());
-                }
-                for digest in &digests {
-                    if !hs.contains(*digest) {
-                        let msg = format!("blob for layer {digest} not found in repository");
-                        tracing::warn!("{msg}");
-                        return Err(CoreError::ManifestBlobUnknown(Some(msg)).into());
+        let batch_size = self
+            .blobstore
+            .config
+            .association_batch_size
+            .unwrap_or(DEFAULT_ASSOCIATION_BATCH_SIZE);
+        match references {
+            References::Image { blobs, config } => {
+                // associate all blobs with the manifest in the database, including the config so
+                // that it counts as referenced
+                let mut blob_uuids: Vec<&Uuid> = blobs.iter().map(|b| &b.id).collect();
+                if let Some(config) = &config {
+                    if !blob_uuids.contains(&&config.id) {
+                        blob_uuids.push(&config.id);
                     }
                 }
 
-                // then associate all blobs with the manifest in the database
-                let blob_uuids = blobs.iter().map(|b| &b.id).collect();
-
-                tx.associate_image_layers(&manifest.id, blob_uuids).await?;
+                tx.associate_image_layers(
+                    &manifest.id,
+                    blob_uuids,
+                    config.as_ref().map(|c| &c.id),
+                    batch_size,
+                )
+                .await?;
             }
-            ManifestSpec::Index(ind) => {
-                let manifests = ind.manifests();
-
-                // first ensure all referenced manifests exist as blobs
-                let digests = manifests
+            References::Index(children) => {
+                // associate all manifests with the index in the database, along with the platform
+                // each is described as targeting and where the index lists it
+                let children = children
                     .iter()
-                    .map(|desc| desc.digest().as_str())
+                    .map(|(id, platform, position)| (id, *platform, *position))
                     .collect();
-                let manifests = tx.get_manifests(&self.repository.id, &digests).await?;
-
-                let mut hs: HashSet<String> = HashSet::new();
-                for manifest in &manifests {
-                    hs.insert((&manifest.digest).into());
-                }
-                for digest in &digests {
-                    if !hs.contains(*digest) {
-                        let msg = format!("blob for manifest {digest} not found in repository");
-                        tracing::warn!("{msg}");
-                        return Err(CoreError::ManifestUnknown(Some(msg)).into());
-                    }
-                }
-
-                // then associate all blobs with the manifest in the database
-                let manifest_uuids = manifests.iter().map(|b| &b.id).collect();
 
-                tx.associate_index_manifests(&manifest.id, manifest_uuids)
+                tx.associate_index_manifests(&manifest.id, children, batch_size)
                     .await?;
             }
         }
 
         if let ManifestRef::Tag(t) = key {
-            // TODO: eventually we'll need to check the mutability of a tag before overwriting it
-            // but for now we overwrite it by default
-            tx.upsert_tag(&self.repository.id, &manifest.id, t.as_str())
-                .await?;
+            self.tag_manifest(&mut tx, &manifest.id, t).await?;
         }
 
+        self.update_referrers_tag(&mut tx, &manifest).await?;
+
         tx.commit().await?;
 
+        if let Some(audit) = &self.blobstore.audit {
+            audit.record_manifest(&calculated_digest);
+        }
+
+        if let Some(events) = &self.blobstore.events {
+            events.manifest_pushed(ContentEvent {
+                repository: self.repository.name.clone(),
+                digest: calculated_digest.clone(),
+                media_type: spec.media_type().map(|mt| mt.to_string()),
+            });
+        }
+
         Ok(calculated_digest)
     }
 
+    async fn missing_references(&self, spec: &ManifestSpec) -> Result<Vec<OciDigest>> {
+        let mut conn = self.blobstore.metadata.get_conn().await?;
+        let mut seen = HashSet::new();
+        let (digests, present): (Vec<&str>, HashSet<String>) = match spec {
+            ManifestSpec::Image(img) => {
+                let digests: Vec<&str> = std::iter::once(img.config())
+                    .chain(img.layers())
+                    .map(|desc| desc.digest().as_str())
+                    .filter(|digest| seen.insert(*digest))
+                    .collect();
+                let blobs = conn.get_blobs(&digests).await?;
+                let present = blobs.iter().map(|b| (&b.digest).into()).collect();
+                (digests, present)
+            }
+            ManifestSpec::Index(ind) => {
+                let digests: Vec<&str> = ind
+                    .manifests()
+                    .iter()
+                    .map(|desc| desc.digest().as_str())
+                    .filter(|digest| seen.insert(*digest))
+                    .collect();
+                let manifests = conn.get_manifests(&self.repository.id, &digests).await?;
+                let present = manifests.iter().map(|m| (&m.digest).into()).collect();
+                (digests, present)
+            }
+        };
+
+        digests
+            .into_iter()
+            .filter(|digest| !present.contains(*digest))
+            .map(OciDigest::try_from)
+            .collect()
+    }
+
+    async fn get_platform_manifest(
+        &self,
+        index: &ManifestRef,
+        platform: &Platform,
+    ) -> Result<Option<BoxedManifest>> {
+        let mut conn = self.blobstore.metadata.get_conn().await?;
+        let index = match conn.get_manifest(&self.repository.id, index).await? {
+            Some(m) => m,
+            None => return Ok(None),
+        };
+        if conn.platform_backfill_pending(&index.id).await? {
+            self.backfill_index_platforms(&index).await?;
+        }
+
+        Ok(conn
+            .get_index_child_by_platform(&index.id, platform)
+            .await?
+            .map(|m| -> BoxedManifest { Box::new(m) }))
+    }
+
     async fn delete(&self, key: &ManifestRef) -> Result<()> {
         let mut tx = self.blobstore.metadata.get_tx().await?;
 
+        // deleting by tag only untags the manifest, which remains retrievable by digest and by
+        // any other tags pointing to it
+        if let ManifestRef::Tag(tag) = key {
+            // like manifests, deleted tags can be restored until their retention window has passed
+            let deleted = if self.blobstore.config.manifest_retention_secs.is_some() {
+                tx.tombstone_tag(&self.repository.id, tag).await?
+            } else {
+                tx.delete_tag(&self.repository.id, tag).await?
+            };
+            if !deleted {
+                return Err(CoreError::ManifestUnknown(None).into());
+            }
+            tx.commit().await?;
+            return Ok(());
+        }
+
         let manifest = tx
             .get_manifest(&self.repository.id, key)
             .await?
             .ok_or(CoreError::ManifestUnknown(None))?;
 
-        // NOTE: it's possible (but how likely?) for a manifest to include both layers and
-        // manifests; we don't support creating both types of association for now, but we should
-        // support deleting them here just in case
-        tx.delete_image_layers(&manifest.id).await?;
-        tx.delete_index_manifests(&manifest.id).await?;
-        tx.delete_tags_by_manifest_id(&manifest.id).await?;
-        tx.delete_manifest(&manifest.id).await?;
-        tx.delete_blob(&manifest.blob_id).await?;
-
-        let manifest_blob_key = Key::from(&manifest.blob_id);
-
-        let mut count = 0;
-        while self
-            .blobstore
-            .objects
-            .exists(&manifest_blob_key)
-            .await
-            .map_err(Error::from)?
-            && count < 10
-        {
-            self.blobstore
-                .objects
-                .delete(&manifest_blob_key)
-                .await
-                .map_err(Error::from)?;
-            count += 1;
+        if self.blobstore.config.manifest_retention_secs.is_some() {
+            // purging the manifest later would fail for the same reason
+            if tx.manifest_referenced_by_index(&manifest.id).await? {
+                return Err(CoreError::PortfolioSpecError(
+                    PortfolioErrorCode::ContentReferenced,
+                ));
+            }
+            // associations and tags are kept so that restoring the manifest restores them too
+            tx.tombstone_manifest(&manifest.id).await?;
+        } else {
+            purge_manifest(
+                &mut tx,
+                &manifest,
+                self.blobstore.config.object_deletion,
+                self.blobstore.objects.as_ref(),
+            )
+            .await?;
         }
 
+        self.update_referrers_tag(&mut tx, &manifest).await?;
+
         tx.commit().await?;
 
+        if let Some(events) = &self.blobstore.events {
+            events.manifest_deleted(ContentEvent {
+                repository: self.repository.name.clone(),
+                digest: manifest.digest.clone(),
+                media_type: manifest.media_type.as_ref().map(|mt| mt.to_string()),
+            });
+        }
+
         Ok(())
     }
 
@@ -217,51 +871,57 @@ impl ManifestStore for PgManifestStore {
         &self,
         subject: &OciDigest,
         artifact_type: Option<String>,
+        n: Option<i64>,
+        last: Option<String>,
     ) -> Result<ImageIndex> {
+        if !self.blobstore.config.referrers_enabled() {
+            return Err(CoreError::Unsupported(Some(
+                "the referrers API is disabled".to_string(),
+            )));
+        }
+
         let mut index = ImageIndex::default();
         index.set_media_type(Some(MediaType::ImageIndex));
 
         let mut conn = self.blobstore.metadata.get_conn().await?;
 
         let manifests = conn
-            .get_referrers(&self.repository.id, subject, &artifact_type)
+            .get_referrers(&self.repository.id, subject, &artifact_type, n, last)
             .await?;
         let count = manifests.len();
 
+        let limit = self
+            .blobstore
+            .config
+            .max_manifest_read_bytes
+            .unwrap_or(DEFAULT_MAX_MANIFEST_READ_BYTES);
         let set = &mut tokio::task::JoinSet::new();
         for m in manifests.into_iter() {
             let objects = self.blobstore.objects.clone();
-            if m.media_type.is_none() {
-                tracing::warn!(
-                    "manifest {} (digest {:?}) unexpectedly missing media type!",
-                    m.id,
-                    m.digest
-                );
-                continue;
-            }
-            let db_media_type = m.media_type.unwrap();
-            set.spawn(async move {
-                let stream = objects
-                    .get(&Key::from(&m.blob_id))
-                    .await
-                    .map_err(Error::from)?;
-                let bs: Bytes = stream
-                    .try_collect::<Vec<Bytes>>()
-                    .await
-                    .map_err(Error::from)?
-                    .into_iter()
-                    .fold(BytesMut::new(), |mut acc, bs| {
-                        acc.extend_from_slice(&bs);
-                        acc
-                    })
-                    .into();
-                let spec = ManifestSpec::try_from(&bs)?;
-                let media_type = spec.media_type().unwrap_or(db_media_type);
-                let mut d = Descriptor::new(media_type, bs.len() as i64, &m.digest);
-                d.set_artifact_type(spec.artifact_type());
-                d.set_annotations(spec.annotations());
-                Ok(d)
-            });
+            let fan_out = self.blobstore.fan_out.clone();
+            // the query only returns manifests with a media type
+            let db_media_type = match m.media_type.clone() {
+                Some(mt) => mt,
+                None => continue,
+            };
+            // stay in the request's span so that object store logs can be correlated with it
+            set.spawn(
+                async move {
+                    // hold the permit until the manifest has been read in full
+                    let _permit = match &fan_out {
+                        Some(limiter) => Some(limiter.acquire().await),
+                        None => None,
+                    };
+                    let bs = read_manifest(objects.as_ref(), &m, limit).await?;
+                    let spec = ManifestSpec::try_from(&bs)?;
+                    let media_type = spec.media_type().unwrap_or(db_media_type);
+                    let mut d = Descriptor::new(media_type, bs.len() as i64, &m.digest);
+                    d.set_artifact_type(spec.artifact_type());
+                    d.set_annotations(spec.annotations());
+                    Ok(d)
+                }
+                .in_current_span(),
+            );
         }
 
         let mut ds: Vec<Descriptor> = Vec::with_capacity(count);
@@ -311,9 +971,145 @@ impl ManifestStore for PgManifestStore {
             .get_tags_by_manifest(&self.repository.id, key)
             .await?
             .into_iter()
-            .map(|t| -> BoxedTag { Box::new(t)})
+            .map(|t| -> BoxedTag { Box::new(t) })
             .collect::<Vec<BoxedTag>>();
 
         Ok(tags)
     }
+
+    async fn get_annotated(&self, key: &str, value: Option<&str>) -> Result<ImageIndex> {
+        let mut conn = self.blobstore.metadata.get_conn().await?;
+        let manifests = conn
+            .get_annotated_manifests(&self.repository.id, key, value)
+            .await?;
+
+        // annotations are stored alongside each manifest, so unlike referrers the descriptors can
+        // be built without reading manifests from the object store
+        let descriptors = manifests
+            .into_iter()
+            .filter_map(stored_descriptor)
+            .collect();
+
+        let mut index = ImageIndex::default();
+        index.set_media_type(Some(MediaType::ImageIndex));
+        index.set_manifests(descriptors);
+        Ok(index)
+    }
+
+    async fn get_tag_digests(
+        &self,
+        n: Option<i64>,
+        last: Option<String>,
+    ) -> Result<Vec<(String, OciDigest)>> {
+        let mut conn = self.blobstore.metadata.get_conn().await?;
+        let tags = conn
+            .get_tags(&self.repository.id, n, last)
+            .await?
+            .into_iter()
+            .map(|t| (t.name, t.digest))
+            .collect();
+
+        Ok(tags)
+    }
+}
+
+/// Ensure every descriptor the manifest refers to content by uses a digest algorithm in `allowed`;
+/// see [`StoreConfig::allowed_reference_digest_algorithms`](super::StoreConfig).
+fn check_reference_algorithms(spec: &ManifestSpec, allowed: &[String]) -> Result<()> {
+    let descriptors: Vec<&Descriptor> = match spec {
+        ManifestSpec::Image(img) => std::iter::once(img.config()).chain(img.layers()).collect(),
+        ManifestSpec::Index(ind) => ind.manifests().iter().collect(),
+    };
+    for desc in descriptors {
+        let digest = desc.digest().as_str();
+        let algorithm = digest.split_once(':').map(|(a, _)| a).unwrap_or(digest);
+        if !allowed.iter().any(|a| a == algorithm) {
+            let msg = format!("digest algorithm of {digest} is not allowed");
+            tracing::warn!("{msg}");
+            return Err(CoreError::ManifestInvalid(Some(msg)));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn canonicalized_manifests_digest_alike() {
+        let compact =
+            br#"{"schemaVersion":2,"config":{"size":2,"digest":"sha256:meow"},"layers":[]}"#;
+        let reformatted = br#"{
+            "config": {"digest": "sha256:meow", "size": 2},
+            "layers": [ ],
+            "schemaVersion": 2
+        }"#;
+
+        // strict digesting is over the raw bytes, so reformatting changes the digest
+        assert_ne!(
+            OciDigest::from(&compact[..]),
+            OciDigest::from(&reformatted[..])
+        );
+
+        let compact = canonicalize_manifest(compact).unwrap();
+        let reformatted = canonicalize_manifest(reformatted).unwrap();
+        assert_eq!(
+            compact.as_ref(),
+            br#"{"config":{"digest":"sha256:meow","size":2},"layers":[],"schemaVersion":2}"#
+        );
+        assert_eq!(
+            OciDigest::from(compact.as_ref()),
+            OciDigest::from(reformatted.as_ref())
+        );
+    }
+
+    #[test]
+    fn image_creation_read_from_config() {
+        let created =
+            config_created(br#"{"created":"2023-09-16T19:22:18.014+02:00","os":"linux"}"#);
+        assert_eq!(
+            created.map(|dt| dt.to_rfc3339()),
+            Some("2023-09-16T17:22:18.014+00:00".to_string())
+        );
+
+        assert_eq!(config_created(br#"{"os":"linux"}"#), None);
+        assert_eq!(config_created(br#"{"created":"yesterday"}"#), None);
+        assert_eq!(config_created(b"not json"), None);
+    }
+
+    #[test]
+    fn referrers_tags_follow_tag_schema() {
+        let digest = OciDigest::from("subject".as_bytes());
+        let hex = String::from(&digest)["sha256:".len()..].to_string();
+        assert_eq!(referrers_tag(&digest), format!("sha256-{hex}"));
+
+        // sha512 digests are too long to be tags in full
+        let digest = OciDigest::try_from(format!("sha512:{}", "a".repeat(128)).as_str()).unwrap();
+        assert_eq!(referrers_tag(&digest), format!("sha512-{}", "a".repeat(64)));
+    }
+
+    #[test]
+    fn reference_algorithms_checked_against_allowlist() {
+        let sha256 = format!("sha256:{}", "a".repeat(64));
+        let sha512 = format!("sha512:{}", "b".repeat(128));
+        let manifest = |layer: &str| {
+            let bytes = Bytes::from(format!(
+                r#"{{"schemaVersion":2,"mediaType":"application/vnd.oci.image.manifest.v1+json",
+                "config":{{"mediaType":"application/vnd.oci.image.config.v1+json","size":2,"digest":"{sha256}"}},
+                "layers":[{{"mediaType":"application/vnd.oci.image.layer.v1.tar","size":2,"digest":"{layer}"}}]}}"#
+            ));
+            ManifestSpec::try_from(&bytes).unwrap()
+        };
+
+        let allowed = vec!["sha256".to_string()];
+        assert!(check_reference_algorithms(&manifest(&sha256), &allowed).is_ok());
+        assert!(matches!(
+            check_reference_algorithms(&manifest(&sha512), &allowed),
+            Err(CoreError::ManifestInvalid(_))
+        ));
+
+        let allowed = vec!["sha256".to_string(), "sha512".to_string()];
+        assert!(check_reference_algorithms(&manifest(&sha512), &allowed).is_ok());
+    }
 }
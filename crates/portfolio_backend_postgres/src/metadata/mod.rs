@@ -3,6 +3,7 @@ pub use postgres::{PostgresConfig, PostgresMetadataPool, PostgresMetadataTx};
 
 mod types;
 pub use types::{
-    Blob, Blobs, Chunk, Chunks, IndexManifests, Layers, Manifest, Manifests, Repositories,
-    Repository, Tag, Tags, UploadSession, UploadSessions,
+    Blob, Blobs, Chunk, Chunks, DeletedTags, ImageAge, IndexManifests, Layers, Manifest, Manifests,
+    ObjectDeletions, Repositories, Repository, RepositoryBlobs, StorageStats, Tag, Tags,
+    UploadSession, UploadSessions,
 };
@@ -1,31 +1,75 @@
-use sea_query::{Alias, Expr, OnConflict, Order, PostgresQueryBuilder, Query, Value};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use sea_query::{
+    Alias, Cond, Expr, Iden, LikeExpr, LockType, OnConflict, Order, PostgresQueryBuilder, Query,
+    Value,
+};
 use sea_query_binder::SqlxBinder;
 use serde::Deserialize;
+use sqlx::migrate::Migrator;
 use sqlx::pool::PoolConnection;
 use sqlx::postgres::{PgPoolOptions, Postgres};
-use sqlx::types::Uuid;
+use sqlx::types::{Json, Uuid};
 use sqlx::{PgConnection, Pool, Row, Transaction};
 
-use portfolio_core::registry::ManifestRef;
+use oci_spec::image::Platform;
+use portfolio_core::registry::{ManifestRef, UploadProgress, Visibility};
 use portfolio_core::{DigestState, OciDigest};
 
 use super::super::errors::{Error, Result};
 use super::types::{
-    Blob, Blobs, IndexManifests, Layers, Manifest, Manifests, Repositories, Repository, Tag, Tags,
+    visibility_str, Blob, Blobs, DeletedTags, ImageAge, IndexManifests, Layers, Manifest,
+    Manifests, ObjectDeletions, Repositories, Repository, RepositoryBlobs, StorageStats, Tag, Tags,
 };
 use super::{Chunk, Chunks, UploadSession, UploadSessions};
 
+/// Settings for the Postgres connection pool. Pool settings that are left unset keep sqlx's
+/// defaults.
 #[derive(Clone, Deserialize)]
 pub struct PostgresConfig {
     connection_string: String,
+    /// Maximum number of connections the pool will open. Requests for a connection beyond this
+    /// wait for one to be returned to the pool, up to `acquire_timeout_secs`.
+    max_connections: Option<u32>,
+    /// Number of idle connections the pool tries to keep open.
+    min_connections: Option<u32>,
+    /// How long to wait for a connection before giving up with an error.
+    acquire_timeout_secs: Option<u64>,
+    /// How long a connection may sit idle in the pool before being closed.
+    idle_timeout_secs: Option<u64>,
+    /// Apply any pending schema migrations when connecting.
+    #[serde(default = "default_run_migrations")]
+    run_migrations: bool,
+}
+
+fn default_run_migrations() -> bool {
+    true
 }
 
+static MIGRATOR: Migrator = sqlx::migrate!();
+
 impl PostgresConfig {
     pub async fn new_metadata(&self) -> Result<PostgresMetadataPool> {
-        let pool = PgPoolOptions::new()
-            .connect(&self.connection_string)
-            .await?;
-        Ok(PostgresMetadataPool { pool })
+        let mut options = PgPoolOptions::new();
+        if let Some(max) = self.max_connections {
+            options = options.max_connections(max);
+        }
+        if let Some(min) = self.min_connections {
+            options = options.min_connections(min);
+        }
+        if let Some(secs) = self.acquire_timeout_secs {
+            options = options.acquire_timeout(Duration::from_secs(secs));
+        }
+        if let Some(secs) = self.idle_timeout_secs {
+            options = options.idle_timeout(Duration::from_secs(secs));
+        }
+        let pool = options.connect(&self.connection_string).await?;
+        let metadata = PostgresMetadataPool { pool };
+        if self.run_migrations {
+            metadata.migrate().await?;
+        }
+        Ok(metadata)
     }
 }
 
@@ -35,6 +79,21 @@ pub struct PostgresMetadataPool {
 }
 
 impl PostgresMetadataPool {
+    /// Apply the migrations embedded in this crate that haven't been applied to the database yet.
+    ///
+    /// The migrator holds a Postgres advisory lock while it runs, so instances starting up at the
+    /// same time wait for each other rather than racing to apply the same migrations.
+    pub async fn migrate(&self) -> Result<()> {
+        MIGRATOR.run(&self.pool).await?;
+        Ok(())
+    }
+
+    /// Run a trivial query to check that the database is reachable.
+    pub async fn ping(&self) -> Result<()> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+        Ok(())
+    }
+
     pub async fn get_conn(&self) -> Result<PostgresMetadataConn> {
         Ok(PostgresMetadataConn {
             conn: self.pool.acquire().await?,
@@ -57,17 +116,36 @@ pub struct PostgresMetadataConn {
 struct Queries {}
 
 impl Queries {
+    /// Insert a repository with the given name, or return the existing one if it already exists,
+    /// so that requests concurrently creating the same repository all succeed.
     pub async fn insert_repository(executor: &mut PgConnection, name: &str) -> Result<Repository> {
         let (sql, values) = Query::insert()
             .into_table(Repositories::Table)
             .columns([Repositories::Name])
             .values([Value::from(name).into()])?
-            .returning(Query::returning().columns([Repositories::Id, Repositories::Name]))
+            .on_conflict(
+                OnConflict::column(Repositories::Name)
+                    .do_nothing()
+                    .to_owned(),
+            )
+            .returning(Query::returning().columns([
+                Repositories::Id,
+                Repositories::Name,
+                Repositories::Visibility,
+                Repositories::ImmutableTags,
+            ]))
             .build_sqlx(PostgresQueryBuilder);
 
-        Ok(sqlx::query_as_with::<_, Repository, _>(&sql, values)
-            .fetch_one(executor)
-            .await?)
+        // nothing is returned when the repository already exists
+        if let Some(repository) = sqlx::query_as_with::<_, Repository, _>(&sql, values)
+            .fetch_optional(&mut *executor)
+            .await?
+        {
+            return Ok(repository);
+        }
+        Ok(Self::get_repository(executor, name)
+            .await?
+            .ok_or(sqlx::Error::RowNotFound)?)
     }
 
     pub async fn get_repository(
@@ -79,6 +157,8 @@ impl Queries {
             .columns([
                 (Repositories::Table, Repositories::Id),
                 (Repositories::Table, Repositories::Name),
+                (Repositories::Table, Repositories::Visibility),
+                (Repositories::Table, Repositories::ImmutableTags),
             ])
             .and_where(Expr::col((Repositories::Table, Repositories::Name)).eq(repository))
             .build_sqlx(PostgresQueryBuilder);
@@ -87,6 +167,120 @@ impl Queries {
             .await?)
     }
 
+    pub async fn list_repositories(
+        executor: &mut PgConnection,
+        n: Option<i64>,
+        last: Option<String>,
+    ) -> Result<Vec<Repository>> {
+        let mut builder = Query::select();
+        builder
+            .from(Repositories::Table)
+            .columns([
+                (Repositories::Table, Repositories::Id),
+                (Repositories::Table, Repositories::Name),
+                (Repositories::Table, Repositories::Visibility),
+                (Repositories::Table, Repositories::ImmutableTags),
+            ])
+            .order_by((Repositories::Table, Repositories::Name), Order::Asc);
+
+        if let Some(last) = last {
+            builder.and_where(Expr::col((Repositories::Table, Repositories::Name)).gt(last));
+        }
+        if let Some(n) = n {
+            builder.limit(n as u64);
+        }
+
+        let (sql, values) = builder.build_sqlx(PostgresQueryBuilder);
+        Ok(sqlx::query_as_with::<_, Repository, _>(&sql, values)
+            .fetch_all(executor)
+            .await?)
+    }
+
+    pub async fn search_repositories(
+        executor: &mut PgConnection,
+        prefix: &str,
+        n: Option<i64>,
+        last: Option<String>,
+    ) -> Result<Vec<Repository>> {
+        // wildcards in the prefix must only match themselves
+        let mut pattern = String::with_capacity(prefix.len() + 1);
+        for c in prefix.chars() {
+            if matches!(c, '%' | '_' | '\\') {
+                pattern.push('\\');
+            }
+            pattern.push(c);
+        }
+        pattern.push('%');
+
+        let mut builder = Query::select();
+        builder
+            .from(Repositories::Table)
+            .columns([
+                (Repositories::Table, Repositories::Id),
+                (Repositories::Table, Repositories::Name),
+                (Repositories::Table, Repositories::Visibility),
+                (Repositories::Table, Repositories::ImmutableTags),
+            ])
+            .and_where(
+                Expr::col((Repositories::Table, Repositories::Name))
+                    .like(LikeExpr::new(pattern).escape('\\')),
+            )
+            .order_by((Repositories::Table, Repositories::Name), Order::Asc);
+
+        match (n, last) {
+            (Some(n), Some(last)) => {
+                builder
+                    .and_where(Expr::col((Repositories::Table, Repositories::Name)).gt(last))
+                    .limit(n as u64);
+            }
+            (Some(n), None) => {
+                builder.limit(n as u64);
+            }
+            (None, Some(_)) => return Err(Error::MissingQueryParameter("n")),
+            (None, None) => {}
+        }
+
+        let (sql, values) = builder.build_sqlx(PostgresQueryBuilder);
+        Ok(sqlx::query_as_with::<_, Repository, _>(&sql, values)
+            .fetch_all(executor)
+            .await?)
+    }
+
+    /// Set the visibility of the repository with the given name, returning false if it doesn't
+    /// exist.
+    pub async fn set_repository_visibility(
+        executor: &mut PgConnection,
+        name: &str,
+        visibility: Visibility,
+    ) -> Result<bool> {
+        let (sql, values) = Query::update()
+            .table(Repositories::Table)
+            .value(Repositories::Visibility, visibility_str(visibility))
+            .and_where(Expr::col(Repositories::Name).eq(name))
+            .build_sqlx(PostgresQueryBuilder);
+        let result = sqlx::query_with(&sql, values).execute(executor).await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Set the glob patterns matching immutable tags in the repository with the given name,
+    /// returning false if it doesn't exist.
+    pub async fn set_repository_immutable_tags(
+        executor: &mut PgConnection,
+        name: &str,
+        patterns: &[String],
+    ) -> Result<bool> {
+        let (sql, values) = Query::update()
+            .table(Repositories::Table)
+            .value(
+                Repositories::ImmutableTags,
+                serde_json::value::to_value(patterns)?,
+            )
+            .and_where(Expr::col(Repositories::Name).eq(name))
+            .build_sqlx(PostgresQueryBuilder);
+        let result = sqlx::query_with(&sql, values).execute(executor).await?;
+        Ok(result.rows_affected() > 0)
+    }
+
     pub async fn repository_exists(executor: &mut PgConnection, name: &str) -> Result<bool> {
         let (sql, values) = Query::select()
             .expr_as(
@@ -104,6 +298,87 @@ impl Queries {
 
         Ok(row.try_get("exists")?)
     }
+
+    /// Delete the repository along with its tags, deleted tags, mounted blobs and manifests,
+    /// including the manifests' associations with their layers and with other manifests. The blobs
+    /// themselves are left for the caller to clean up since they may be shared with other
+    /// repositories.
+    pub async fn delete_repository(
+        executor: &mut PgConnection,
+        repository_id: &Uuid,
+    ) -> Result<()> {
+        let repository_manifests = Query::select()
+            .column(Manifests::Id)
+            .from(Manifests::Table)
+            .and_where(Expr::col(Manifests::RepositoryId).eq(*repository_id))
+            .to_owned();
+
+        let (sql, values) = Query::delete()
+            .from_table(Tags::Table)
+            .cond_where(Expr::col(Tags::RepositoryId).eq(*repository_id))
+            .build_sqlx(PostgresQueryBuilder);
+        sqlx::query_with(&sql, values)
+            .execute(&mut *executor)
+            .await?;
+
+        let (sql, values) = Query::delete()
+            .from_table(DeletedTags::Table)
+            .cond_where(Expr::col(DeletedTags::RepositoryId).eq(*repository_id))
+            .build_sqlx(PostgresQueryBuilder);
+        sqlx::query_with(&sql, values)
+            .execute(&mut *executor)
+            .await?;
+
+        let (sql, values) = Query::delete()
+            .from_table(RepositoryBlobs::Table)
+            .cond_where(Expr::col(RepositoryBlobs::RepositoryId).eq(*repository_id))
+            .build_sqlx(PostgresQueryBuilder);
+        sqlx::query_with(&sql, values)
+            .execute(&mut *executor)
+            .await?;
+
+        let (sql, values) = Query::delete()
+            .from_table(IndexManifests::Table)
+            .cond_where(
+                Cond::any()
+                    .add(
+                        Expr::col(IndexManifests::ParentManifest)
+                            .in_subquery(repository_manifests.clone()),
+                    )
+                    .add(
+                        Expr::col(IndexManifests::ChildManifest)
+                            .in_subquery(repository_manifests.clone()),
+                    ),
+            )
+            .build_sqlx(PostgresQueryBuilder);
+        sqlx::query_with(&sql, values)
+            .execute(&mut *executor)
+            .await?;
+
+        let (sql, values) = Query::delete()
+            .from_table(Layers::Table)
+            .cond_where(Expr::col(Layers::Manifest).in_subquery(repository_manifests))
+            .build_sqlx(PostgresQueryBuilder);
+        sqlx::query_with(&sql, values)
+            .execute(&mut *executor)
+            .await?;
+
+        let (sql, values) = Query::delete()
+            .from_table(Manifests::Table)
+            .cond_where(Expr::col(Manifests::RepositoryId).eq(*repository_id))
+            .build_sqlx(PostgresQueryBuilder);
+        sqlx::query_with(&sql, values)
+            .execute(&mut *executor)
+            .await?;
+
+        let (sql, values) = Query::delete()
+            .from_table(Repositories::Table)
+            .cond_where(Expr::col(Repositories::Id).eq(*repository_id))
+            .build_sqlx(PostgresQueryBuilder);
+        sqlx::query_with(&sql, values).execute(executor).await?;
+
+        Ok(())
+    }
     pub async fn insert_blob(
         executor: &mut PgConnection,
         digest: &OciDigest,
@@ -112,7 +387,7 @@ impl Queries {
         let (sql, values) = Query::insert()
             .into_table(Blobs::Table)
             .columns([Blobs::Digest, Blobs::BytesOnDisk])
-            .values([String::from(digest).into(), bytes_on_disk.into()])?
+            .values([digest.into(), bytes_on_disk.into()])?
             .returning_col(Blobs::Id)
             .build_sqlx(PostgresQueryBuilder);
 
@@ -124,8 +399,79 @@ impl Queries {
         let (sql, values) = Query::select()
             .from(Blobs::Table)
             .columns([Blobs::Id, Blobs::Digest, Blobs::BytesOnDisk])
-            // TODO: impl Value for OciDigest
-            .and_where(Expr::col(Blobs::Digest).eq(String::from(digest)))
+            .and_where(Expr::col(Blobs::Digest).eq(digest))
+            .build_sqlx(PostgresQueryBuilder);
+
+        Ok(sqlx::query_as_with::<_, Blob, _>(&sql, values)
+            .fetch_optional(executor)
+            .await?)
+    }
+
+    /// Return the blob with the given digest if it is referenced by a manifest in the named
+    /// repository, either as one of the manifest's layers or as the manifest content itself.
+    pub async fn get_repository_blob(
+        executor: &mut PgConnection,
+        repository: &str,
+        digest: &OciDigest,
+    ) -> Result<Option<Blob>> {
+        let as_layer = Query::select()
+            .expr(Expr::val(1))
+            .from(Layers::Table)
+            .inner_join(
+                Manifests::Table,
+                Expr::col((Layers::Table, Layers::Manifest))
+                    .equals((Manifests::Table, Manifests::Id)),
+            )
+            .inner_join(
+                Repositories::Table,
+                Expr::col((Manifests::Table, Manifests::RepositoryId))
+                    .equals((Repositories::Table, Repositories::Id)),
+            )
+            .and_where(Expr::col((Layers::Table, Layers::Blob)).equals((Blobs::Table, Blobs::Id)))
+            .and_where(Expr::col((Repositories::Table, Repositories::Name)).eq(repository))
+            .to_owned();
+        let as_manifest = Query::select()
+            .expr(Expr::val(1))
+            .from(Manifests::Table)
+            .inner_join(
+                Repositories::Table,
+                Expr::col((Manifests::Table, Manifests::RepositoryId))
+                    .equals((Repositories::Table, Repositories::Id)),
+            )
+            .and_where(
+                Expr::col((Manifests::Table, Manifests::BlobId)).equals((Blobs::Table, Blobs::Id)),
+            )
+            .and_where(Expr::col((Repositories::Table, Repositories::Name)).eq(repository))
+            .to_owned();
+        let as_mounted = Query::select()
+            .expr(Expr::val(1))
+            .from(RepositoryBlobs::Table)
+            .inner_join(
+                Repositories::Table,
+                Expr::col((RepositoryBlobs::Table, RepositoryBlobs::RepositoryId))
+                    .equals((Repositories::Table, Repositories::Id)),
+            )
+            .and_where(
+                Expr::col((RepositoryBlobs::Table, RepositoryBlobs::BlobId))
+                    .equals((Blobs::Table, Blobs::Id)),
+            )
+            .and_where(Expr::col((Repositories::Table, Repositories::Name)).eq(repository))
+            .to_owned();
+
+        let (sql, values) = Query::select()
+            .from(Blobs::Table)
+            .columns([
+                (Blobs::Table, Blobs::Id),
+                (Blobs::Table, Blobs::Digest),
+                (Blobs::Table, Blobs::BytesOnDisk),
+            ])
+            .and_where(Expr::col((Blobs::Table, Blobs::Digest)).eq(digest))
+            .cond_where(
+                Cond::any()
+                    .add(Expr::exists(as_layer))
+                    .add(Expr::exists(as_manifest))
+                    .add(Expr::exists(as_mounted)),
+            )
             .build_sqlx(PostgresQueryBuilder);
 
         Ok(sqlx::query_as_with::<_, Blob, _>(&sql, values)
@@ -147,7 +493,38 @@ impl Queries {
             .await?)
     }
 
+    /// Make the blob available in the given repository, eg when mounting it from another
+    /// repository.
+    pub async fn associate_repository_blob(
+        executor: &mut PgConnection,
+        repository_id: &Uuid,
+        blob_id: &Uuid,
+    ) -> Result<()> {
+        let (sql, values) = Query::insert()
+            .into_table(RepositoryBlobs::Table)
+            .columns([RepositoryBlobs::RepositoryId, RepositoryBlobs::BlobId])
+            .values([(*repository_id).into(), (*blob_id).into()])?
+            .on_conflict(
+                OnConflict::columns([RepositoryBlobs::RepositoryId, RepositoryBlobs::BlobId])
+                    .do_nothing()
+                    .to_owned(),
+            )
+            .build_sqlx(PostgresQueryBuilder);
+
+        sqlx::query_with(&sql, values).execute(executor).await?;
+        Ok(())
+    }
+
+    /// Delete the blob, along with the repositories it was made available in by mounting.
     pub async fn delete_blob(executor: &mut PgConnection, blob_id: &Uuid) -> Result<()> {
+        let (sql, values) = Query::delete()
+            .from_table(RepositoryBlobs::Table)
+            .cond_where(Expr::col(RepositoryBlobs::BlobId).eq(*blob_id))
+            .build_sqlx(PostgresQueryBuilder);
+        sqlx::query_with(&sql, values)
+            .execute(&mut *executor)
+            .await?;
+
         let (sql, values) = Query::delete()
             .from_table(Blobs::Table)
             .cond_where(Expr::col(Blobs::Id).eq(*blob_id))
@@ -167,12 +544,442 @@ impl Queries {
         }
     }
 
-    pub async fn get_manifests(
+    /// Count the manifests referring to the blob with the given digest, either as a layer (or
+    /// config) or as the manifest content itself.
+    pub async fn blob_reference_count(
+        executor: &mut PgConnection,
+        digest: &OciDigest,
+    ) -> Result<i64> {
+        let (sql, values) = Query::select()
+            .expr_as(
+                Expr::col((Layers::Table, Layers::Blob)).count(),
+                Alias::new("count"),
+            )
+            .from(Layers::Table)
+            .inner_join(
+                Blobs::Table,
+                Expr::col((Layers::Table, Layers::Blob)).equals((Blobs::Table, Blobs::Id)),
+            )
+            .and_where(Expr::col((Blobs::Table, Blobs::Digest)).eq(digest))
+            .build_sqlx(PostgresQueryBuilder);
+        let as_layer: i64 = sqlx::query_with(&sql, values)
+            .fetch_one(&mut *executor)
+            .await?
+            .try_get("count")?;
+
+        let (sql, values) = Query::select()
+            .expr_as(
+                Expr::col((Manifests::Table, Manifests::BlobId)).count(),
+                Alias::new("count"),
+            )
+            .from(Manifests::Table)
+            .inner_join(
+                Blobs::Table,
+                Expr::col((Manifests::Table, Manifests::BlobId)).equals((Blobs::Table, Blobs::Id)),
+            )
+            .and_where(Expr::col((Blobs::Table, Blobs::Digest)).eq(digest))
+            .build_sqlx(PostgresQueryBuilder);
+        let as_manifest: i64 = sqlx::query_with(&sql, values)
+            .fetch_one(executor)
+            .await?
+            .try_get("count")?;
+
+        Ok(as_layer + as_manifest)
+    }
+
+    /// Condition matching blobs referenced by a manifest in the given repository, either as one
+    /// of the manifest's layers or as the manifest content itself, or mounted into it.
+    fn repository_blobs_cond(repository_id: &Uuid) -> Cond {
+        Cond::any()
+            .add(
+                Expr::col(Blobs::Id).in_subquery(
+                    Query::select()
+                        .column(RepositoryBlobs::BlobId)
+                        .from(RepositoryBlobs::Table)
+                        .and_where(Expr::col(RepositoryBlobs::RepositoryId).eq(*repository_id))
+                        .to_owned(),
+                ),
+            )
+            .add(
+                Expr::col(Blobs::Id).in_subquery(
+                    Query::select()
+                        .column((Layers::Table, Layers::Blob))
+                        .from(Layers::Table)
+                        .inner_join(
+                            Manifests::Table,
+                            Expr::col((Layers::Table, Layers::Manifest))
+                                .equals((Manifests::Table, Manifests::Id)),
+                        )
+                        .and_where(
+                            Expr::col((Manifests::Table, Manifests::RepositoryId))
+                                .eq(*repository_id),
+                        )
+                        .to_owned(),
+                ),
+            )
+            .add(
+                Expr::col(Blobs::Id).in_subquery(
+                    Query::select()
+                        .column(Manifests::BlobId)
+                        .from(Manifests::Table)
+                        .and_where(Expr::col(Manifests::RepositoryId).eq(*repository_id))
+                        .to_owned(),
+                ),
+            )
+    }
+
+    /// Return all blobs referenced by a manifest in the given repository, either as one of the
+    /// manifest's layers or as the manifest content itself, or mounted into it.
+    pub async fn get_repository_blobs(
+        executor: &mut PgConnection,
+        repository_id: &Uuid,
+    ) -> Result<Vec<Blob>> {
+        let (sql, values) = Query::select()
+            .from(Blobs::Table)
+            .columns([Blobs::Id, Blobs::Digest, Blobs::BytesOnDisk])
+            .cond_where(Self::repository_blobs_cond(repository_id))
+            .build_sqlx(PostgresQueryBuilder);
+
+        Ok(sqlx::query_as_with::<_, Blob, _>(&sql, values)
+            .fetch_all(executor)
+            .await?)
+    }
+
+    /// Return up to `n` blobs ordered by id, starting after `last`, out of those referenced by the
+    /// given repository or out of every blob in the registry.
+    pub async fn get_blobs_page(
+        executor: &mut PgConnection,
+        repository_id: Option<&Uuid>,
+        last: Option<&Uuid>,
+        n: u64,
+    ) -> Result<Vec<Blob>> {
+        let mut builder = Query::select();
+        builder
+            .from(Blobs::Table)
+            .columns([Blobs::Id, Blobs::Digest, Blobs::BytesOnDisk])
+            .order_by(Blobs::Id, Order::Asc)
+            .limit(n);
+        if let Some(repository_id) = repository_id {
+            builder.cond_where(Self::repository_blobs_cond(repository_id));
+        }
+        if let Some(last) = last {
+            builder.and_where(Expr::col(Blobs::Id).gt(*last));
+        }
+
+        let (sql, values) = builder.build_sqlx(PostgresQueryBuilder);
+        Ok(sqlx::query_as_with::<_, Blob, _>(&sql, values)
+            .fetch_all(executor)
+            .await?)
+    }
+
+    /// Return all blobs that no manifest refers to.
+    pub async fn get_unreferenced_blobs(executor: &mut PgConnection) -> Result<Vec<Blob>> {
+        let (sql, values) = Query::select()
+            .from(Blobs::Table)
+            .columns([Blobs::Id, Blobs::Digest, Blobs::BytesOnDisk])
+            .and_where(
+                Expr::col(Blobs::Id).not_in_subquery(
+                    Query::select()
+                        .column(Layers::Blob)
+                        .from(Layers::Table)
+                        .to_owned(),
+                ),
+            )
+            .and_where(
+                Expr::col(Blobs::Id).not_in_subquery(
+                    Query::select()
+                        .column(Manifests::BlobId)
+                        .from(Manifests::Table)
+                        .to_owned(),
+                ),
+            )
+            .build_sqlx(PostgresQueryBuilder);
+
+        Ok(sqlx::query_as_with::<_, Blob, _>(&sql, values)
+            .fetch_all(executor)
+            .await?)
+    }
+
+    /// Record that the object store content with the given id should be removed by the next
+    /// sweep.
+    pub async fn mark_object_for_deletion(
+        executor: &mut PgConnection,
+        object_id: &Uuid,
+    ) -> Result<()> {
+        let (sql, values) = Query::insert()
+            .into_table(ObjectDeletions::Table)
+            .columns([ObjectDeletions::ObjectId])
+            .values([(*object_id).into()])?
+            .on_conflict(
+                OnConflict::column(ObjectDeletions::ObjectId)
+                    .do_nothing()
+                    .to_owned(),
+            )
+            .build_sqlx(PostgresQueryBuilder);
+
+        sqlx::query_with(&sql, values).execute(executor).await?;
+        Ok(())
+    }
+
+    /// Return the ids of up to `n` objects marked for deletion at least `grace_period_secs`
+    /// seconds ago, oldest first.
+    pub async fn get_object_deletions(
+        executor: &mut PgConnection,
+        grace_period_secs: u64,
+        n: u64,
+    ) -> Result<Vec<Uuid>> {
+        let (sql, values) = Query::select()
+            .from(ObjectDeletions::Table)
+            .column(ObjectDeletions::ObjectId)
+            .and_where(
+                Expr::col(ObjectDeletions::MarkedAt).lte(Expr::cust_with_values(
+                    "now() - make_interval(secs => $1)",
+                    [grace_period_secs as f64],
+                )),
+            )
+            .order_by(ObjectDeletions::MarkedAt, Order::Asc)
+            .limit(n)
+            .build_sqlx(PostgresQueryBuilder);
+
+        sqlx::query_with(&sql, values)
+            .fetch_all(executor)
+            .await?
+            .iter()
+            .map(|row| Ok(row.try_get("object_id")?))
+            .collect()
+    }
+
+    pub async fn delete_object_deletion(
+        executor: &mut PgConnection,
+        object_id: &Uuid,
+    ) -> Result<()> {
+        let (sql, values) = Query::delete()
+            .from_table(ObjectDeletions::Table)
+            .cond_where(Expr::col(ObjectDeletions::ObjectId).eq(*object_id))
+            .build_sqlx(PostgresQueryBuilder);
+
+        sqlx::query_with(&sql, values).execute(executor).await?;
+        Ok(())
+    }
+
+    pub async fn get_manifests(
+        executor: &mut PgConnection,
+        repository_id: &Uuid,
+        digests: &Vec<&str>,
+    ) -> Result<Vec<Manifest>> {
+        let digests = digests.iter().map(Clone::clone);
+        let (sql, values) = Query::select()
+            .from(Manifests::Table)
+            .columns([
+                (Manifests::Table, Manifests::Id),
+                (Manifests::Table, Manifests::RepositoryId),
+                (Manifests::Table, Manifests::BlobId),
+                (Manifests::Table, Manifests::MediaType),
+                (Manifests::Table, Manifests::ArtifactType),
+                (Manifests::Table, Manifests::Digest),
+                (Manifests::Table, Manifests::Subject),
+                (Manifests::Table, Manifests::Annotations),
+                (Manifests::Table, Manifests::ContentType),
+                (Manifests::Table, Manifests::Created),
+            ])
+            .column((Blobs::Table, Blobs::BytesOnDisk))
+            .left_join(
+                Blobs::Table,
+                Expr::col((Manifests::Table, Manifests::BlobId)).equals((Blobs::Table, Blobs::Id)),
+            )
+            .and_where(Expr::col((Manifests::Table, Manifests::RepositoryId)).eq(*repository_id))
+            .and_where(Expr::col((Manifests::Table, Manifests::Digest)).is_in(digests))
+            .and_where(Expr::col((Manifests::Table, Manifests::DeletedAt)).is_null())
+            .build_sqlx(PostgresQueryBuilder);
+
+        Ok(sqlx::query_as_with::<_, Manifest, _>(&sql, values)
+            .fetch_all(executor)
+            .await?)
+    }
+
+    pub async fn get_manifest(
+        executor: &mut PgConnection,
+        repository_id: &Uuid,
+        manifest_ref: &ManifestRef,
+    ) -> Result<Option<Manifest>> {
+        let mut builder = Query::select();
+        builder
+            .from(Manifests::Table)
+            .columns([
+                (Manifests::Table, Manifests::Id),
+                (Manifests::Table, Manifests::RepositoryId),
+                (Manifests::Table, Manifests::BlobId),
+                (Manifests::Table, Manifests::MediaType),
+                (Manifests::Table, Manifests::ArtifactType),
+                (Manifests::Table, Manifests::Digest),
+                (Manifests::Table, Manifests::Subject),
+                (Manifests::Table, Manifests::Annotations),
+                (Manifests::Table, Manifests::ContentType),
+                (Manifests::Table, Manifests::Created),
+            ])
+            .column((Blobs::Table, Blobs::BytesOnDisk))
+            .left_join(
+                Blobs::Table,
+                Expr::col((Manifests::Table, Manifests::BlobId)).equals((Blobs::Table, Blobs::Id)),
+            )
+            .and_where(Expr::col((Manifests::Table, Manifests::RepositoryId)).eq(*repository_id))
+            .and_where(Expr::col((Manifests::Table, Manifests::DeletedAt)).is_null());
+
+        match manifest_ref {
+            ManifestRef::Digest(d) => {
+                builder.and_where(Expr::col((Manifests::Table, Manifests::Digest)).eq(d));
+            }
+            ManifestRef::Tag(t) => {
+                builder
+                    .left_join(
+                        Tags::Table,
+                        Expr::col((Tags::Table, Tags::ManifestId))
+                            .equals((Manifests::Table, Manifests::Id)),
+                    )
+                    .and_where(Expr::col((Tags::Table, Tags::Name)).eq(t));
+            }
+        }
+
+        let (sql, values) = builder.build_sqlx(PostgresQueryBuilder);
+        Ok(sqlx::query_as_with::<_, Manifest, _>(&sql, values)
+            .fetch_optional(executor)
+            .await?)
+    }
+
+    pub async fn insert_manifest(executor: &mut PgConnection, manifest: &Manifest) -> Result<()> {
+        let (sql, values) = Query::insert()
+            .into_table(Manifests::Table)
+            .columns([
+                Manifests::Id,
+                Manifests::RepositoryId,
+                Manifests::BlobId,
+                Manifests::MediaType,
+                Manifests::ArtifactType,
+                Manifests::Digest,
+                Manifests::Subject,
+                Manifests::Annotations,
+                Manifests::ContentType,
+                Manifests::Created,
+            ])
+            .values([
+                Value::from(manifest.id).into(),
+                Value::from(manifest.repository_id).into(),
+                Value::from(manifest.blob_id).into(),
+                Value::from(manifest.media_type.clone().map(String::from)).into(),
+                Value::from(manifest.artifact_type.clone().map(String::from)).into(),
+                Value::from(&manifest.digest).into(),
+                Value::from(manifest.subject.clone()).into(),
+                Value::from(
+                    manifest
+                        .annotations
+                        .as_ref()
+                        .map(serde_json::value::to_value)
+                        .transpose()?,
+                )
+                .into(),
+                Value::from(manifest.content_type.clone()).into(),
+                Expr::cust_with_values(
+                    "$1::TIMESTAMPTZ",
+                    [manifest.created.as_ref().map(DateTime::to_rfc3339)],
+                ),
+            ])?
+            .build_sqlx(PostgresQueryBuilder);
+
+        sqlx::query_with(&sql, values).execute(executor).await?;
+        Ok(())
+    }
+
+    pub async fn delete_manifest(executor: &mut PgConnection, manifest_id: &Uuid) -> Result<()> {
+        let (sql, values) = Query::delete()
+            .from_table(Manifests::Table)
+            .cond_where(Expr::col(Manifests::Id).eq(*manifest_id))
+            .build_sqlx(PostgresQueryBuilder);
+
+        match sqlx::query_with(&sql, values).execute(executor).await {
+            Ok(_) => Ok(()),
+            Err(sqlx::Error::Database(dberr)) => match dberr.kind() {
+                sqlx::error::ErrorKind::ForeignKeyViolation => {
+                    tracing::warn!("foreign key violation error: {dberr}");
+                    Err(Error::PortfolioSpecError(
+                        portfolio_core::PortfolioErrorCode::ContentReferenced,
+                    ))
+                }
+                _ => Err(sqlx::Error::Database(dberr).into()),
+            },
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Mark the manifest as deleted without removing it, so that it is treated as absent until it
+    /// is either restored or purged.
+    pub async fn tombstone_manifest(executor: &mut PgConnection, manifest_id: &Uuid) -> Result<()> {
+        let (sql, values) = Query::update()
+            .table(Manifests::Table)
+            .value(Manifests::DeletedAt, Expr::cust("now()"))
+            .and_where(Expr::col(Manifests::Id).eq(*manifest_id))
+            .build_sqlx(PostgresQueryBuilder);
+
+        sqlx::query_with(&sql, values).execute(executor).await?;
+        Ok(())
+    }
+
+    /// Restore the tombstoned manifest with the given digest, returning false if there is none.
+    pub async fn undelete_manifest(
+        executor: &mut PgConnection,
+        repository_id: &Uuid,
+        digest: &OciDigest,
+    ) -> Result<bool> {
+        let (sql, values) = Query::update()
+            .table(Manifests::Table)
+            .value(Manifests::DeletedAt, Expr::cust("NULL"))
+            .and_where(Expr::col(Manifests::RepositoryId).eq(*repository_id))
+            .and_where(Expr::col(Manifests::Digest).eq(digest))
+            .and_where(Expr::col(Manifests::DeletedAt).is_not_null())
+            .build_sqlx(PostgresQueryBuilder);
+
+        let result = sqlx::query_with(&sql, values).execute(executor).await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Whether any index that hasn't been deleted refers to the manifest.
+    pub async fn manifest_referenced_by_index(
+        executor: &mut PgConnection,
+        manifest_id: &Uuid,
+    ) -> Result<bool> {
+        let (sql, values) = Query::select()
+            .expr_as(
+                Expr::exists(
+                    Query::select()
+                        .from(IndexManifests::Table)
+                        .column(IndexManifests::ParentManifest)
+                        .inner_join(
+                            Manifests::Table,
+                            Expr::col((IndexManifests::Table, IndexManifests::ParentManifest))
+                                .equals((Manifests::Table, Manifests::Id)),
+                        )
+                        .and_where(
+                            Expr::col((IndexManifests::Table, IndexManifests::ChildManifest))
+                                .eq(*manifest_id),
+                        )
+                        .and_where(Expr::col((Manifests::Table, Manifests::DeletedAt)).is_null())
+                        .to_owned(),
+                ),
+                Alias::new("exists"),
+            )
+            .build_sqlx(PostgresQueryBuilder);
+        let row = sqlx::query_with(&sql, values).fetch_one(executor).await?;
+
+        Ok(row.try_get("exists")?)
+    }
+
+    /// Return up to `n` manifests tombstoned at least `retention_secs` seconds ago, oldest first,
+    /// locking them so that they can't be restored while they are purged. Manifests still
+    /// referenced by an index are left out until the index itself has been purged.
+    pub async fn get_expired_manifest_tombstones(
         executor: &mut PgConnection,
-        repository_id: &Uuid,
-        digests: &Vec<&str>,
+        retention_secs: u64,
+        n: u64,
     ) -> Result<Vec<Manifest>> {
-        let digests = digests.iter().map(Clone::clone);
         let (sql, values) = Query::select()
             .from(Manifests::Table)
             .columns([
@@ -183,14 +990,37 @@ impl Queries {
                 (Manifests::Table, Manifests::ArtifactType),
                 (Manifests::Table, Manifests::Digest),
                 (Manifests::Table, Manifests::Subject),
+                (Manifests::Table, Manifests::Annotations),
+                (Manifests::Table, Manifests::ContentType),
+                (Manifests::Table, Manifests::Created),
             ])
             .column((Blobs::Table, Blobs::BytesOnDisk))
             .left_join(
                 Blobs::Table,
                 Expr::col((Manifests::Table, Manifests::BlobId)).equals((Blobs::Table, Blobs::Id)),
             )
-            .and_where(Expr::col((Manifests::Table, Manifests::RepositoryId)).eq(*repository_id))
-            .and_where(Expr::col((Manifests::Table, Manifests::Digest)).is_in(digests))
+            .and_where(Expr::col((Manifests::Table, Manifests::DeletedAt)).lte(
+                Expr::cust_with_values(
+                    "now() - make_interval(secs => $1)",
+                    [retention_secs as f64],
+                ),
+            ))
+            .and_where(
+                Expr::exists(
+                    Query::select()
+                        .from(IndexManifests::Table)
+                        .column(IndexManifests::ParentManifest)
+                        .and_where(
+                            Expr::col((IndexManifests::Table, IndexManifests::ChildManifest))
+                                .equals((Manifests::Table, Manifests::Id)),
+                        )
+                        .to_owned(),
+                )
+                .not(),
+            )
+            .order_by((Manifests::Table, Manifests::DeletedAt), Order::Asc)
+            .limit(n)
+            .lock_with_tables(LockType::Update, [Manifests::Table])
             .build_sqlx(PostgresQueryBuilder);
 
         Ok(sqlx::query_as_with::<_, Manifest, _>(&sql, values)
@@ -198,13 +1028,13 @@ impl Queries {
             .await?)
     }
 
-    pub async fn get_manifest(
+    /// Return up to `n` manifests whose config blob still has to be associated with them, locking
+    /// them so that concurrent backfills don't process them twice.
+    pub async fn get_manifests_pending_config_backfill(
         executor: &mut PgConnection,
-        repository_id: &Uuid,
-        manifest_ref: &ManifestRef,
-    ) -> Result<Option<Manifest>> {
-        let mut builder = Query::select();
-        builder
+        n: u64,
+    ) -> Result<Vec<Manifest>> {
+        let (sql, values) = Query::select()
             .from(Manifests::Table)
             .columns([
                 (Manifests::Table, Manifests::Id),
@@ -214,105 +1044,182 @@ impl Queries {
                 (Manifests::Table, Manifests::ArtifactType),
                 (Manifests::Table, Manifests::Digest),
                 (Manifests::Table, Manifests::Subject),
+                (Manifests::Table, Manifests::Annotations),
+                (Manifests::Table, Manifests::ContentType),
+                (Manifests::Table, Manifests::Created),
             ])
             .column((Blobs::Table, Blobs::BytesOnDisk))
             .left_join(
                 Blobs::Table,
                 Expr::col((Manifests::Table, Manifests::BlobId)).equals((Blobs::Table, Blobs::Id)),
             )
-            .and_where(Expr::col((Manifests::Table, Manifests::RepositoryId)).eq(*repository_id));
-
-        match manifest_ref {
-            ManifestRef::Digest(d) => {
-                builder.and_where(
-                    Expr::col((Manifests::Table, Manifests::Digest)).eq(String::from(d)),
-                );
-            }
-            ManifestRef::Tag(t) => {
-                builder
-                    .left_join(
-                        Tags::Table,
-                        Expr::col((Tags::Table, Tags::ManifestId))
-                            .equals((Manifests::Table, Manifests::Id)),
-                    )
-                    .and_where(Expr::col((Tags::Table, Tags::Name)).eq(t));
-            }
-        }
+            .and_where(Expr::col((Manifests::Table, Manifests::ConfigBackfillPending)).eq(true))
+            .limit(n)
+            .lock_with_tables(LockType::Update, [Manifests::Table])
+            .build_sqlx(PostgresQueryBuilder);
 
-        let (sql, values) = builder.build_sqlx(PostgresQueryBuilder);
         Ok(sqlx::query_as_with::<_, Manifest, _>(&sql, values)
-            .fetch_optional(executor)
+            .fetch_all(executor)
             .await?)
     }
 
-    pub async fn insert_manifest(executor: &mut PgConnection, manifest: &Manifest) -> Result<()> {
+    /// Associate the blob with the image manifest as its config, for manifests pushed before
+    /// configs were associated with them.
+    pub async fn associate_image_config(
+        executor: &mut PgConnection,
+        manifest_id: &Uuid,
+        blob_id: &Uuid,
+    ) -> Result<()> {
         let (sql, values) = Query::insert()
-            .into_table(Manifests::Table)
-            .columns([
-                Manifests::Id,
-                Manifests::RepositoryId,
-                Manifests::BlobId,
-                Manifests::MediaType,
-                Manifests::ArtifactType,
-                Manifests::Digest,
-                Manifests::Subject,
-            ])
+            .into_table(Layers::Table)
+            .columns([Layers::Manifest, Layers::Blob, Layers::Config])
+            .values([(*manifest_id).into(), (*blob_id).into(), true.into()])?
+            .on_conflict(
+                OnConflict::columns([Layers::Manifest, Layers::Blob])
+                    .update_column(Layers::Config)
+                    .to_owned(),
+            )
+            .build_sqlx(PostgresQueryBuilder);
+
+        sqlx::query_with(&sql, values).execute(executor).await?;
+        Ok(())
+    }
+
+    /// Record that the manifest's config blob, if it has one, has been associated with it.
+    pub async fn clear_config_backfill(
+        executor: &mut PgConnection,
+        manifest_id: &Uuid,
+    ) -> Result<()> {
+        let (sql, values) = Query::update()
+            .table(Manifests::Table)
+            .value(Manifests::ConfigBackfillPending, false)
+            .and_where(Expr::col(Manifests::Id).eq(*manifest_id))
+            .build_sqlx(PostgresQueryBuilder);
+
+        sqlx::query_with(&sql, values).execute(executor).await?;
+        Ok(())
+    }
+
+    /// Whether the platforms and positions of the given index's children still have to be
+    /// backfilled from its content.
+    pub async fn platform_backfill_pending(
+        executor: &mut PgConnection,
+        manifest_id: &Uuid,
+    ) -> Result<bool> {
+        let (sql, values) = Query::select()
+            .from(Manifests::Table)
+            .column(Manifests::PlatformBackfillPending)
+            .and_where(Expr::col(Manifests::Id).eq(*manifest_id))
+            .build_sqlx(PostgresQueryBuilder);
+        let row = sqlx::query_with(&sql, values)
+            .fetch_optional(executor)
+            .await?;
+        Ok(row
+            .map(|r| r.try_get("platform_backfill_pending"))
+            .transpose()?
+            .unwrap_or(false))
+    }
+
+    /// Like [`Self::platform_backfill_pending`], but locking the index so that concurrent
+    /// backfills of it take turns.
+    pub async fn lock_platform_backfill(
+        executor: &mut PgConnection,
+        manifest_id: &Uuid,
+    ) -> Result<bool> {
+        let (sql, values) = Query::select()
+            .from(Manifests::Table)
+            .column(Manifests::PlatformBackfillPending)
+            .and_where(Expr::col(Manifests::Id).eq(*manifest_id))
+            .lock(LockType::Update)
+            .build_sqlx(PostgresQueryBuilder);
+        let row = sqlx::query_with(&sql, values)
+            .fetch_optional(executor)
+            .await?;
+        Ok(row
+            .map(|r| r.try_get("platform_backfill_pending"))
+            .transpose()?
+            .unwrap_or(false))
+    }
+
+    /// Record the platform and position of a child of the given index, as described by the
+    /// index's descriptor for it.
+    pub async fn set_index_child_platform(
+        executor: &mut PgConnection,
+        parent: &Uuid,
+        child: &Uuid,
+        platform: Option<&Platform>,
+        position: i32,
+    ) -> Result<()> {
+        let (sql, values) = Query::update()
+            .table(IndexManifests::Table)
             .values([
-                Value::from(manifest.id).into(),
-                Value::from(manifest.repository_id).into(),
-                Value::from(manifest.blob_id).into(),
-                Value::from(manifest.media_type.clone().map(String::from)).into(),
-                Value::from(manifest.artifact_type.clone().map(String::from)).into(),
-                Value::from(String::from(&manifest.digest)).into(),
-                Value::from(manifest.subject.clone().map(String::from)).into(),
-            ])?
+                (
+                    IndexManifests::PlatformOs,
+                    platform.map(|p| p.os().to_string()).into(),
+                ),
+                (
+                    IndexManifests::PlatformArchitecture,
+                    platform.map(|p| p.architecture().to_string()).into(),
+                ),
+                (
+                    IndexManifests::PlatformVariant,
+                    platform.and_then(|p| p.variant().clone()).into(),
+                ),
+                (IndexManifests::Position, position.into()),
+            ])
+            .and_where(Expr::col(IndexManifests::ParentManifest).eq(*parent))
+            .and_where(Expr::col(IndexManifests::ChildManifest).eq(*child))
             .build_sqlx(PostgresQueryBuilder);
 
         sqlx::query_with(&sql, values).execute(executor).await?;
         Ok(())
     }
 
-    pub async fn delete_manifest(executor: &mut PgConnection, manifest_id: &Uuid) -> Result<()> {
-        let (sql, values) = Query::delete()
-            .from_table(Manifests::Table)
-            .cond_where(Expr::col(Manifests::Id).eq(*manifest_id))
+    pub async fn clear_platform_backfill(
+        executor: &mut PgConnection,
+        manifest_id: &Uuid,
+    ) -> Result<()> {
+        let (sql, values) = Query::update()
+            .table(Manifests::Table)
+            .value(Manifests::PlatformBackfillPending, false)
+            .and_where(Expr::col(Manifests::Id).eq(*manifest_id))
             .build_sqlx(PostgresQueryBuilder);
 
-        match sqlx::query_with(&sql, values).execute(executor).await {
-            Ok(_) => Ok(()),
-            Err(sqlx::Error::Database(dberr)) => match dberr.kind() {
-                sqlx::error::ErrorKind::ForeignKeyViolation => {
-                    tracing::warn!("foreign key violation error: {dberr}");
-                    Err(Error::PortfolioSpecError(
-                        portfolio_core::PortfolioErrorCode::ContentReferenced,
-                    ))
-                }
-                _ => Err(sqlx::Error::Database(dberr).into()),
-            },
-            Err(e) => Err(e.into()),
-        }
+        sqlx::query_with(&sql, values).execute(executor).await?;
+        Ok(())
     }
 
+    /// Associate the `children` blobs with the `parent` image manifest, marking `config` as its
+    /// config blob and inserting at most `batch_size` rows per statement.
     pub async fn associate_image_layers(
         executor: &mut PgConnection,
         parent: &Uuid,
         children: Vec<&Uuid>,
+        config: Option<&Uuid>,
+        batch_size: usize,
     ) -> Result<()> {
-        let mut builder = Query::insert();
-        builder
-            .into_table(Layers::Table)
-            .columns([Layers::Manifest, Layers::Blob]);
+        for batch in children.chunks(batch_size.max(1)) {
+            let mut builder = Query::insert();
+            builder.into_table(Layers::Table).columns([
+                Layers::Manifest,
+                Layers::Blob,
+                Layers::Config,
+            ]);
+
+            for child in batch.iter() {
+                builder.values([
+                    Value::from(parent.clone()).into(),
+                    Value::from((*child).clone()).into(),
+                    Value::from(config == Some(*child)).into(),
+                ])?;
+            }
 
-        for child in children.iter() {
-            builder.values([
-                Value::from(parent.clone()).into(),
-                Value::from((*child).clone()).into(),
-            ])?;
+            let (sql, values) = builder.build_sqlx(PostgresQueryBuilder);
+            sqlx::query_with(&sql, values)
+                .execute(&mut *executor)
+                .await?;
         }
 
-        let (sql, values) = builder.build_sqlx(PostgresQueryBuilder);
-        sqlx::query_with(&sql, values).execute(executor).await?;
-
         Ok(())
     }
 
@@ -337,27 +1244,106 @@ impl Queries {
         }
     }
 
+    /// Associate the `children` manifests with the `parent` index, along with the platform and
+    /// position of each, inserting at most `batch_size` rows per statement.
     pub async fn associate_index_manifests(
         executor: &mut PgConnection,
         parent: &Uuid,
-        children: Vec<&Uuid>,
+        children: Vec<(&Uuid, Option<&Platform>, i32)>,
+        batch_size: usize,
     ) -> Result<()> {
-        let mut builder = Query::insert();
-        builder.into_table(IndexManifests::Table).columns([
-            IndexManifests::ParentManifest,
-            IndexManifests::ChildManifest,
-        ]);
+        for batch in children.chunks(batch_size.max(1)) {
+            let mut builder = Query::insert();
+            builder.into_table(IndexManifests::Table).columns([
+                IndexManifests::ParentManifest,
+                IndexManifests::ChildManifest,
+                IndexManifests::PlatformOs,
+                IndexManifests::PlatformArchitecture,
+                IndexManifests::PlatformVariant,
+                IndexManifests::Position,
+            ]);
+
+            for (child, platform, position) in batch.iter() {
+                builder.values([
+                    Value::from(parent.clone()).into(),
+                    Value::from((*child).clone()).into(),
+                    platform.map(|p| p.os().to_string()).into(),
+                    platform.map(|p| p.architecture().to_string()).into(),
+                    platform.and_then(|p| p.variant().clone()).into(),
+                    (*position).into(),
+                ])?;
+            }
+
+            let (sql, values) = builder.build_sqlx(PostgresQueryBuilder);
+            sqlx::query_with(&sql, values)
+                .execute(&mut *executor)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Return the first child of the given index whose platform matches, in the order the index
+    /// lists them, without touching any of the index's other children.
+    pub async fn get_index_child_by_platform(
+        executor: &mut PgConnection,
+        parent: &Uuid,
+        platform: &Platform,
+    ) -> Result<Option<Manifest>> {
+        let mut builder = Query::select();
+        builder
+            .from(Manifests::Table)
+            .columns([
+                (Manifests::Table, Manifests::Id),
+                (Manifests::Table, Manifests::RepositoryId),
+                (Manifests::Table, Manifests::BlobId),
+                (Manifests::Table, Manifests::MediaType),
+                (Manifests::Table, Manifests::ArtifactType),
+                (Manifests::Table, Manifests::Digest),
+                (Manifests::Table, Manifests::Subject),
+                (Manifests::Table, Manifests::Annotations),
+                (Manifests::Table, Manifests::ContentType),
+                (Manifests::Table, Manifests::Created),
+            ])
+            .column((Blobs::Table, Blobs::BytesOnDisk))
+            .inner_join(
+                IndexManifests::Table,
+                Expr::col((IndexManifests::Table, IndexManifests::ChildManifest))
+                    .equals((Manifests::Table, Manifests::Id)),
+            )
+            .left_join(
+                Blobs::Table,
+                Expr::col((Manifests::Table, Manifests::BlobId)).equals((Blobs::Table, Blobs::Id)),
+            )
+            .and_where(
+                Expr::col((IndexManifests::Table, IndexManifests::ParentManifest)).eq(*parent),
+            )
+            .and_where(Expr::col((Manifests::Table, Manifests::DeletedAt)).is_null())
+            .and_where(
+                Expr::col((IndexManifests::Table, IndexManifests::PlatformOs))
+                    .eq(platform.os().to_string()),
+            )
+            .and_where(
+                Expr::col((IndexManifests::Table, IndexManifests::PlatformArchitecture))
+                    .eq(platform.architecture().to_string()),
+            )
+            .order_by(
+                (IndexManifests::Table, IndexManifests::Position),
+                Order::Asc,
+            )
+            .order_by((Manifests::Table, Manifests::Digest), Order::Asc)
+            .limit(1);
 
-        for child in children.iter() {
-            builder.values([
-                Value::from(parent.clone()).into(),
-                Value::from((*child).clone()).into(),
-            ])?;
+        if let Some(variant) = platform.variant() {
+            builder.and_where(
+                Expr::col((IndexManifests::Table, IndexManifests::PlatformVariant))
+                    .eq(variant.as_str()),
+            );
         }
 
         let (sql, values) = builder.build_sqlx(PostgresQueryBuilder);
-        sqlx::query_with(&sql, values).execute(executor).await?;
-        Ok(())
+        Ok(sqlx::query_as_with::<_, Manifest, _>(&sql, values)
+            .fetch_optional(executor)
+            .await?)
     }
 
     pub async fn delete_index_manifests(executor: &mut PgConnection, parent: &Uuid) -> Result<()> {
@@ -381,12 +1367,51 @@ impl Queries {
         }
     }
 
+    /// Take a transaction-scoped advisory lock on the tag with the given name in the given
+    /// repository, waiting for any other transaction holding it to finish first.
+    pub async fn lock_tag(
+        executor: &mut PgConnection,
+        repository_id: &Uuid,
+        tag: &str,
+    ) -> Result<()> {
+        sqlx::query("SELECT pg_advisory_xact_lock(hashtextextended($1, 0))")
+            .bind(format!("{repository_id}:{tag}"))
+            .execute(executor)
+            .await?;
+        Ok(())
+    }
+
+    /// Point the tag at the given manifest, creating it if necessary.
+    ///
+    /// When `mutable_for` is set, an existing tag can only be moved to a different manifest within
+    /// that many seconds of when it was last moved; after that it is immutable and the upsert
+    /// fails with [`portfolio_core::Error::Denied`].
     pub async fn upsert_tag(
         executor: &mut PgConnection,
         repository_id: &Uuid,
         manifest_id: &Uuid,
         tag: &str,
+        mutable_for: Option<u64>,
     ) -> Result<()> {
+        let unchanged = Expr::col((Tags::Table, Tags::ManifestId))
+            .equals((Alias::new("excluded"), Tags::ManifestId));
+
+        let mut on_conflict = OnConflict::columns([Tags::RepositoryId, Tags::Name]);
+        on_conflict.update_columns([Tags::ManifestId]).value(
+            Tags::UpdatedAt,
+            // re-pushing the manifest a tag already points to doesn't count as an update
+            Expr::case(unchanged.clone(), Expr::col((Tags::Table, Tags::UpdatedAt)))
+                .finally(Expr::current_timestamp()),
+        );
+        if let Some(secs) = mutable_for {
+            on_conflict.action_cond_where(Cond::any().add(unchanged).add(
+                Expr::col((Tags::Table, Tags::UpdatedAt)).gt(Expr::cust_with_values(
+                    "now() - make_interval(secs => $1)",
+                    [secs as f64],
+                )),
+            ));
+        }
+
         let (sql, values) = Query::insert()
             .into_table(Tags::Table)
             .columns([Tags::Name, Tags::RepositoryId, Tags::ManifestId])
@@ -395,14 +1420,15 @@ impl Queries {
                 Value::from(*repository_id).into(),
                 Value::from(*manifest_id).into(),
             ])?
-            .on_conflict(
-                OnConflict::columns([Tags::RepositoryId, Tags::Name])
-                    .update_columns([Tags::ManifestId])
-                    .to_owned(),
-            )
+            .on_conflict(on_conflict)
             .build_sqlx(PostgresQueryBuilder);
 
-        sqlx::query_with(&sql, values).execute(executor).await?;
+        let result = sqlx::query_with(&sql, values).execute(executor).await?;
+        if result.rows_affected() == 0 {
+            return Err(
+                portfolio_core::Error::Denied(Some(format!("tag {tag} is immutable"))).into(),
+            );
+        }
         Ok(())
     }
 
@@ -422,7 +1448,8 @@ impl Queries {
                     .equals((Manifests::Table, Manifests::Id)),
             )
             .from(Tags::Table)
-            .and_where(Expr::col((Tags::Table, Tags::RepositoryId)).eq(*repository_id));
+            .and_where(Expr::col((Tags::Table, Tags::RepositoryId)).eq(*repository_id))
+            .and_where(Expr::col((Manifests::Table, Manifests::DeletedAt)).is_null());
 
         match (n, last) {
             (Some(n), Some(last)) => {
@@ -471,13 +1498,16 @@ impl Queries {
                     .equals((Manifests::Table, Manifests::Id)),
             )
             .from(Tags::Table)
-            .and_where(Expr::col((Tags::Table, Tags::RepositoryId)).eq(*repository_id));
+            .and_where(Expr::col((Tags::Table, Tags::RepositoryId)).eq(*repository_id))
+            .and_where(Expr::col((Manifests::Table, Manifests::DeletedAt)).is_null());
 
         let builder = match manifest_ref {
-            ManifestRef::Digest(digest) => builder
-                .and_where(Expr::col((Manifests::Table, Manifests::Digest)).eq(String::from(digest))),
-            ManifestRef::Tag(tag) => builder
-                .and_where(Expr::col((Tags::Table, Tags::Name)).eq(tag)),
+            ManifestRef::Digest(digest) => {
+                builder.and_where(Expr::col((Manifests::Table, Manifests::Digest)).eq(digest))
+            }
+            ManifestRef::Tag(tag) => {
+                builder.and_where(Expr::col((Tags::Table, Tags::Name)).eq(tag))
+            }
         };
 
         let (sql, values) = builder.build_sqlx(PostgresQueryBuilder);
@@ -498,13 +1528,114 @@ impl Queries {
         Ok(())
     }
 
+    /// Delete the tag with the given name, returning false if it didn't exist.
+    pub async fn delete_tag(
+        executor: &mut PgConnection,
+        repository_id: &Uuid,
+        tag: &str,
+    ) -> Result<bool> {
+        let (sql, values) = Query::delete()
+            .from_table(Tags::Table)
+            .cond_where(
+                Cond::all()
+                    .add(Expr::col(Tags::RepositoryId).eq(*repository_id))
+                    .add(Expr::col(Tags::Name).eq(tag)),
+            )
+            .build_sqlx(PostgresQueryBuilder);
+        let result = sqlx::query_with(&sql, values).execute(executor).await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Delete the tag with the given name, recording the manifest it pointed to so that it can be
+    /// restored with [`Self::undelete_tag`]. Returns false if the tag didn't exist.
+    pub async fn tombstone_tag(
+        executor: &mut PgConnection,
+        repository_id: &Uuid,
+        tag: &str,
+    ) -> Result<bool> {
+        let result = sqlx::query(
+            "WITH deleted AS ( \
+                DELETE FROM tags WHERE repository_id = $1 AND name = $2 \
+                RETURNING repository_id, manifest_id, name \
+            ) \
+            INSERT INTO deleted_tags (repository_id, manifest_id, name) \
+            SELECT repository_id, manifest_id, name FROM deleted \
+            ON CONFLICT (repository_id, name) \
+            DO UPDATE SET manifest_id = excluded.manifest_id, deleted_at = now()",
+        )
+        .bind(*repository_id)
+        .bind(tag)
+        .execute(executor)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Remove the record of the deleted tag with the given name, returning the manifest it pointed
+    /// to, if any.
+    pub async fn undelete_tag(
+        executor: &mut PgConnection,
+        repository_id: &Uuid,
+        tag: &str,
+    ) -> Result<Option<Uuid>> {
+        let (sql, values) = Query::delete()
+            .from_table(DeletedTags::Table)
+            .cond_where(
+                Cond::all()
+                    .add(Expr::col(DeletedTags::RepositoryId).eq(*repository_id))
+                    .add(Expr::col(DeletedTags::Name).eq(tag)),
+            )
+            .returning_col(DeletedTags::ManifestId)
+            .build_sqlx(PostgresQueryBuilder);
+        let row = sqlx::query_with(&sql, values)
+            .fetch_optional(executor)
+            .await?;
+        Ok(row.map(|r| r.try_get("manifest_id")).transpose()?)
+    }
+
+    pub async fn delete_tag_tombstones_by_manifest_id(
+        executor: &mut PgConnection,
+        manifest_id: &Uuid,
+    ) -> Result<()> {
+        let (sql, values) = Query::delete()
+            .from_table(DeletedTags::Table)
+            .cond_where(Expr::col(DeletedTags::ManifestId).eq(*manifest_id))
+            .build_sqlx(PostgresQueryBuilder);
+        sqlx::query_with(&sql, values).execute(executor).await?;
+        Ok(())
+    }
+
+    /// Forget tags deleted at least `retention_secs` seconds ago, returning the number forgotten.
+    pub async fn delete_expired_tag_tombstones(
+        executor: &mut PgConnection,
+        retention_secs: u64,
+    ) -> Result<u64> {
+        let (sql, values) = Query::delete()
+            .from_table(DeletedTags::Table)
+            .cond_where(
+                Expr::col(DeletedTags::DeletedAt).lte(Expr::cust_with_values(
+                    "now() - make_interval(secs => $1)",
+                    [retention_secs as f64],
+                )),
+            )
+            .build_sqlx(PostgresQueryBuilder);
+        let result = sqlx::query_with(&sql, values).execute(executor).await?;
+        Ok(result.rows_affected())
+    }
+
     pub async fn get_chunks(
         executor: &mut PgConnection,
         session: &UploadSession,
     ) -> Result<Vec<Chunk>> {
         let (sql, values) = Query::select()
             .from(Chunks::Table)
-            .columns([Chunks::ETag, Chunks::ChunkNumber])
+            .columns([
+                Chunks::ETag,
+                Chunks::ChunkNumber,
+                Chunks::ChecksumCrc32,
+                Chunks::ChecksumCrc32C,
+                Chunks::ChecksumSha1,
+                Chunks::ChecksumSha256,
+            ])
             .and_where(Expr::col(Chunks::UploadSessionUuid).eq(session.uuid))
             .order_by(Chunks::ChunkNumber, Order::Asc)
             .build_sqlx(PostgresQueryBuilder);
@@ -513,18 +1644,34 @@ impl Queries {
             .await?)
     }
 
+    /// Record a chunk of `size` bytes written to the session.
     pub async fn insert_chunk(
         executor: &mut PgConnection,
         session: &UploadSession,
         chunk: &Chunk,
+        size: u64,
     ) -> Result<()> {
         let (sql, values) = Query::insert()
             .into_table(Chunks::Table)
-            .columns([Chunks::ChunkNumber, Chunks::UploadSessionUuid, Chunks::ETag])
+            .columns([
+                Chunks::ChunkNumber,
+                Chunks::UploadSessionUuid,
+                Chunks::ETag,
+                Chunks::Size,
+                Chunks::ChecksumCrc32,
+                Chunks::ChecksumCrc32C,
+                Chunks::ChecksumSha1,
+                Chunks::ChecksumSha256,
+            ])
             .values([
                 Value::from(chunk.chunk_number).into(),
                 Value::from(session.uuid).into(),
                 Value::from(chunk.e_tag.clone()).into(),
+                Value::from(size as i64).into(),
+                Value::from(chunk.checksum_crc32.clone()).into(),
+                Value::from(chunk.checksum_crc32_c.clone()).into(),
+                Value::from(chunk.checksum_sha1.clone()).into(),
+                Value::from(chunk.checksum_sha256.clone()).into(),
             ])?
             .build_sqlx(PostgresQueryBuilder);
 
@@ -532,6 +1679,33 @@ impl Queries {
         Ok(())
     }
 
+    /// Total size and number of the chunks written to the session with the given UUID.
+    pub async fn get_upload_progress(
+        executor: &mut PgConnection,
+        uuid: &Uuid,
+    ) -> Result<UploadProgress> {
+        let (sql, values) = Query::select()
+            .expr_as(
+                Expr::cust("COALESCE(SUM(size), 0)::BIGINT"),
+                Alias::new("bytes_received"),
+            )
+            .expr_as(
+                Expr::col(Chunks::ChunkNumber).count(),
+                Alias::new("chunk_count"),
+            )
+            .from(Chunks::Table)
+            .and_where(Expr::col(Chunks::UploadSessionUuid).eq(*uuid))
+            .build_sqlx(PostgresQueryBuilder);
+        let row = sqlx::query_with(&sql, values).fetch_one(executor).await?;
+        let bytes_received: i64 = row.try_get("bytes_received")?;
+        let chunk_count: i64 = row.try_get("chunk_count")?;
+
+        Ok(UploadProgress {
+            bytes_received: bytes_received as u64,
+            chunk_count: chunk_count as u64,
+        })
+    }
+
     pub async fn delete_chunks(executor: &mut PgConnection, uuid: &Uuid) -> Result<()> {
         let (sql, values) = Query::delete()
             .from_table(Chunks::Table)
@@ -542,13 +1716,19 @@ impl Queries {
         Ok(())
     }
 
-    pub async fn new_upload_session(executor: &mut PgConnection) -> Result<UploadSession> {
-        let state = DigestState::default();
+    pub async fn new_upload_session(
+        executor: &mut PgConnection,
+        declared_digest: Option<&OciDigest>,
+        state: DigestState,
+    ) -> Result<UploadSession> {
         let value = serde_json::value::to_value(state)?;
         let (sql, values) = Query::insert()
             .into_table(UploadSessions::Table)
-            .columns([UploadSessions::DigestState])
-            .values([Expr::value(value)])?
+            .columns([UploadSessions::DigestState, UploadSessions::DeclaredDigest])
+            .values([
+                Expr::value(value),
+                Value::from(declared_digest.map(String::from)).into(),
+            ])?
             .returning(Query::returning().columns([
                 UploadSessions::Uuid,
                 UploadSessions::StartDate,
@@ -556,6 +1736,8 @@ impl Queries {
                 UploadSessions::ChunkNumber,
                 UploadSessions::LastRangeEnd,
                 UploadSessions::DigestState,
+                UploadSessions::DigestCheckpoint,
+                UploadSessions::DeclaredDigest,
             ]))
             .build_sqlx(PostgresQueryBuilder);
         let session = sqlx::query_as_with::<_, UploadSession, _>(&sql, values)
@@ -575,6 +1757,8 @@ impl Queries {
                 UploadSessions::LastRangeEnd,
                 UploadSessions::UploadId,
                 UploadSessions::DigestState,
+                UploadSessions::DigestCheckpoint,
+                UploadSessions::DeclaredDigest,
             ])
             .and_where(Expr::col(UploadSessions::Uuid).eq(*uuid))
             .build_sqlx(PostgresQueryBuilder);
@@ -589,7 +1773,13 @@ impl Queries {
         executor: &mut PgConnection,
         session: &UploadSession,
     ) -> Result<()> {
-        let state = serde_json::value::to_value(&session.digest_state)?;
+        // sessions checkpointed to the object store have no digest state, which must be stored as
+        // NULL rather than JSON null
+        let state = session
+            .digest_state
+            .as_ref()
+            .map(serde_json::value::to_value)
+            .transpose()?;
         let (sql, values) = Query::update()
             .table(UploadSessions::Table)
             .and_where(Expr::col(UploadSessions::Uuid).eq(session.uuid))
@@ -597,12 +1787,51 @@ impl Queries {
             .value(UploadSessions::ChunkNumber, session.chunk_number)
             .value(UploadSessions::LastRangeEnd, session.last_range_end)
             .value(UploadSessions::DigestState, state)
+            .value(
+                UploadSessions::DigestCheckpoint,
+                session.digest_checkpoint.clone(),
+            )
             .build_sqlx(PostgresQueryBuilder);
 
         sqlx::query_with(&sql, values).execute(executor).await?;
         Ok(())
     }
 
+    /// Return up to `n` upload sessions started at least `max_age_secs` seconds ago, oldest first,
+    /// locking them so that they can't be written to while they are cleaned up.
+    pub async fn get_stale_sessions(
+        executor: &mut PgConnection,
+        max_age_secs: u64,
+        n: u64,
+    ) -> Result<Vec<UploadSession>> {
+        let (sql, values) = Query::select()
+            .from(UploadSessions::Table)
+            .columns([
+                UploadSessions::Uuid,
+                UploadSessions::StartDate,
+                UploadSessions::ChunkNumber,
+                UploadSessions::LastRangeEnd,
+                UploadSessions::UploadId,
+                UploadSessions::DigestState,
+                UploadSessions::DigestCheckpoint,
+                UploadSessions::DeclaredDigest,
+            ])
+            .and_where(
+                Expr::col(UploadSessions::StartDate).lte(Expr::cust_with_values(
+                    "now() - make_interval(secs => $1)",
+                    [max_age_secs as f64],
+                )),
+            )
+            .order_by(UploadSessions::StartDate, Order::Asc)
+            .limit(n)
+            .lock(LockType::Update)
+            .build_sqlx(PostgresQueryBuilder);
+
+        Ok(sqlx::query_as_with::<_, UploadSession, _>(&sql, values)
+            .fetch_all(executor)
+            .await?)
+    }
+
     pub async fn delete_session(executor: &mut PgConnection, session_uuid: &Uuid) -> Result<()> {
         let (sql, values) = Query::delete()
             .from_table(UploadSessions::Table)
@@ -618,6 +1847,8 @@ impl Queries {
         repository_id: &Uuid,
         subject: &OciDigest,
         artifact_type: &Option<String>,
+        n: Option<i64>,
+        last: Option<String>,
     ) -> Result<Vec<Manifest>> {
         let mut builder = Query::select();
         builder
@@ -630,6 +1861,9 @@ impl Queries {
                 (Manifests::Table, Manifests::ArtifactType),
                 (Manifests::Table, Manifests::Digest),
                 (Manifests::Table, Manifests::Subject),
+                (Manifests::Table, Manifests::Annotations),
+                (Manifests::Table, Manifests::ContentType),
+                (Manifests::Table, Manifests::Created),
             ])
             .column((Blobs::Table, Blobs::BytesOnDisk))
             .left_join(
@@ -638,7 +1872,10 @@ impl Queries {
             )
             .order_by(Manifests::Digest, Order::Asc)
             .and_where(Expr::col((Manifests::Table, Manifests::RepositoryId)).eq(*repository_id))
-            .and_where(Expr::col((Manifests::Table, Manifests::Subject)).eq(String::from(subject)));
+            .and_where(Expr::col((Manifests::Table, Manifests::Subject)).eq(subject))
+            .and_where(Expr::col((Manifests::Table, Manifests::DeletedAt)).is_null())
+            // manifests without a media type can't be listed, so they mustn't count towards `n`
+            .and_where(Expr::col((Manifests::Table, Manifests::MediaType)).is_not_null());
 
         if let Some(artifact_type) = artifact_type {
             builder.and_where(
@@ -646,11 +1883,159 @@ impl Queries {
             );
         }
 
+        // keyset pagination on digest rather than an offset so that referrers pushed between
+        // page requests can't shift already-returned results
+        if let Some(last) = last {
+            builder.and_where(Expr::col((Manifests::Table, Manifests::Digest)).gt(last));
+        }
+        if let Some(n) = n {
+            builder.limit(n as u64);
+        }
+
+        let (sql, values) = builder.build_sqlx(PostgresQueryBuilder);
+        Ok(sqlx::query_as_with::<_, Manifest, _>(&sql, values)
+            .fetch_all(executor)
+            .await?)
+    }
+
+    /// Manifests carrying the annotation `key`, restricted to those where it has the given `value`
+    /// if any, sorted by digest.
+    pub async fn get_annotated_manifests(
+        executor: &mut PgConnection,
+        repository_id: &Uuid,
+        key: &str,
+        value: Option<&str>,
+    ) -> Result<Vec<Manifest>> {
+        let mut builder = Query::select();
+        builder
+            .from(Manifests::Table)
+            .columns([
+                (Manifests::Table, Manifests::Id),
+                (Manifests::Table, Manifests::RepositoryId),
+                (Manifests::Table, Manifests::BlobId),
+                (Manifests::Table, Manifests::MediaType),
+                (Manifests::Table, Manifests::ArtifactType),
+                (Manifests::Table, Manifests::Digest),
+                (Manifests::Table, Manifests::Subject),
+                (Manifests::Table, Manifests::Annotations),
+                (Manifests::Table, Manifests::ContentType),
+                (Manifests::Table, Manifests::Created),
+            ])
+            .column((Blobs::Table, Blobs::BytesOnDisk))
+            .left_join(
+                Blobs::Table,
+                Expr::col((Manifests::Table, Manifests::BlobId)).equals((Blobs::Table, Blobs::Id)),
+            )
+            .order_by(Manifests::Digest, Order::Asc)
+            .and_where(Expr::col((Manifests::Table, Manifests::RepositoryId)).eq(*repository_id));
+
+        // containment rather than extracting the value so that the GIN index can be used
+        match value {
+            Some(value) => builder.and_where(Expr::cust_with_values(
+                "manifests.annotations @> $1",
+                [serde_json::json!({ key: value })],
+            )),
+            None => builder.and_where(Expr::cust_with_values(
+                "manifests.annotations -> $1 IS NOT NULL",
+                [key],
+            )),
+        };
+
         let (sql, values) = builder.build_sqlx(PostgresQueryBuilder);
         Ok(sqlx::query_as_with::<_, Manifest, _>(&sql, values)
             .fetch_all(executor)
             .await?)
     }
+
+    /// Total the bytes of stored content by kind, across every repository or only those referred
+    /// to by the given repository's manifests.
+    pub async fn get_storage_stats(
+        executor: &mut PgConnection,
+        repository_id: Option<&Uuid>,
+    ) -> Result<StorageStats> {
+        // blobs are selected by id so that content shared between manifests counts once
+        let sql = "
+            SELECT
+                (SELECT COALESCE(SUM(b.bytes_on_disk), 0)::BIGINT FROM blobs b
+                 WHERE b.id IN (
+                    SELECT m.blob_id FROM manifests m
+                    WHERE $1::UUID IS NULL OR m.repository_id = $1
+                 )) AS manifest_bytes,
+                (SELECT COALESCE(SUM(b.bytes_on_disk), 0)::BIGINT FROM blobs b
+                 WHERE b.id IN (
+                    SELECT l.blob FROM layers l JOIN manifests m ON m.id = l.manifest
+                    WHERE NOT l.config AND ($1::UUID IS NULL OR m.repository_id = $1)
+                 )) AS layer_bytes,
+                (SELECT COALESCE(SUM(b.bytes_on_disk), 0)::BIGINT FROM blobs b
+                 WHERE b.id IN (
+                    SELECT l.blob FROM layers l JOIN manifests m ON m.id = l.manifest
+                    WHERE l.config AND ($1::UUID IS NULL OR m.repository_id = $1)
+                 )) AS config_bytes";
+        Ok(sqlx::query_as::<_, StorageStats>(sql)
+            .bind(repository_id)
+            .fetch_one(executor)
+            .await?)
+    }
+
+    /// Return the images created before `before`, in the given repository or in all of them,
+    /// oldest first. Tombstoned manifests and those whose creation time isn't known are skipped.
+    pub async fn get_images_created_before(
+        executor: &mut PgConnection,
+        repository_id: Option<&Uuid>,
+        before: DateTime<Utc>,
+    ) -> Result<Vec<ImageAge>> {
+        let sql = "
+            SELECT r.name AS repository, m.digest, m.created
+            FROM manifests m JOIN repositories r ON r.id = m.repository_id
+            WHERE m.created < $1
+              AND m.deleted_at IS NULL
+              AND ($2::UUID IS NULL OR m.repository_id = $2)
+            ORDER BY m.created, r.name, m.digest";
+        Ok(sqlx::query_as::<_, ImageAge>(sql)
+            .bind(before)
+            .bind(repository_id)
+            .fetch_all(executor)
+            .await?)
+    }
+
+    /// Make the transaction read-only and have every statement in it see the database as of its
+    /// first statement. Must be called before any other statement in the transaction.
+    pub async fn set_snapshot_isolation(executor: &mut PgConnection) -> Result<()> {
+        sqlx::query("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ, READ ONLY")
+            .execute(executor)
+            .await?;
+        Ok(())
+    }
+
+    /// Return every row of the given table as a JSON object keyed by column name.
+    pub async fn export_table(
+        executor: &mut PgConnection,
+        table: impl Iden,
+    ) -> Result<Vec<serde_json::Value>> {
+        let sql = format!(
+            "SELECT COALESCE(json_agg(t), '[]'::json) FROM \"{}\" t",
+            table.to_string()
+        );
+        let rows: Json<Vec<serde_json::Value>> =
+            sqlx::query_scalar(&sql).fetch_one(executor).await?;
+        Ok(rows.0)
+    }
+
+    /// Insert rows exported by [`Self::export_table`] into the given table, skipping those that
+    /// conflict with existing rows, and return the number inserted.
+    pub async fn import_table(
+        executor: &mut PgConnection,
+        table: impl Iden,
+        rows: &[serde_json::Value],
+    ) -> Result<u64> {
+        let sql = format!(
+            "INSERT INTO \"{table}\" SELECT * FROM json_populate_recordset(NULL::\"{table}\", $1) \
+             ON CONFLICT DO NOTHING",
+            table = table.to_string()
+        );
+        let result = sqlx::query(&sql).bind(Json(rows)).execute(executor).await?;
+        Ok(result.rows_affected())
+    }
 }
 
 // PoolConnection<Postgres>-based metadata queries.
@@ -663,6 +2048,39 @@ impl PostgresMetadataConn {
         Queries::get_repository(&mut *self.conn, repository).await
     }
 
+    pub async fn set_repository_visibility(
+        &mut self,
+        name: &str,
+        visibility: Visibility,
+    ) -> Result<bool> {
+        Queries::set_repository_visibility(&mut *self.conn, name, visibility).await
+    }
+
+    pub async fn set_repository_immutable_tags(
+        &mut self,
+        name: &str,
+        patterns: &[String],
+    ) -> Result<bool> {
+        Queries::set_repository_immutable_tags(&mut *self.conn, name, patterns).await
+    }
+
+    pub async fn list_repositories(
+        &mut self,
+        n: Option<i64>,
+        last: Option<String>,
+    ) -> Result<Vec<Repository>> {
+        Queries::list_repositories(&mut *self.conn, n, last).await
+    }
+
+    pub async fn search_repositories(
+        &mut self,
+        prefix: &str,
+        n: Option<i64>,
+        last: Option<String>,
+    ) -> Result<Vec<Repository>> {
+        Queries::search_repositories(&mut *self.conn, prefix, n, last).await
+    }
+
     pub async fn repository_exists(&mut self, name: &str) -> Result<bool> {
         Queries::repository_exists(&mut *self.conn, name).await
     }
@@ -675,6 +2093,14 @@ impl PostgresMetadataConn {
         Queries::get_blob(&mut *self.conn, digest).await
     }
 
+    pub async fn get_blobs(&mut self, digests: &Vec<&str>) -> Result<Vec<Blob>> {
+        Queries::get_blobs(&mut *self.conn, digests).await
+    }
+
+    pub async fn blob_reference_count(&mut self, digest: &OciDigest) -> Result<i64> {
+        Queries::blob_reference_count(&mut *self.conn, digest).await
+    }
+
     pub async fn get_manifest(
         &mut self,
         repository_id: &Uuid,
@@ -683,6 +2109,26 @@ impl PostgresMetadataConn {
         Queries::get_manifest(&mut *self.conn, repository_id, manifest_ref).await
     }
 
+    pub async fn get_manifests(
+        &mut self,
+        repository_id: &Uuid,
+        digests: &Vec<&str>,
+    ) -> Result<Vec<Manifest>> {
+        Queries::get_manifests(&mut *self.conn, repository_id, digests).await
+    }
+
+    pub async fn get_index_child_by_platform(
+        &mut self,
+        parent: &Uuid,
+        platform: &Platform,
+    ) -> Result<Option<Manifest>> {
+        Queries::get_index_child_by_platform(&mut *self.conn, parent, platform).await
+    }
+
+    pub async fn platform_backfill_pending(&mut self, manifest_id: &Uuid) -> Result<bool> {
+        Queries::platform_backfill_pending(&mut *self.conn, manifest_id).await
+    }
+
     pub async fn get_tags(
         &mut self,
         repository_id: &Uuid,
@@ -692,8 +2138,24 @@ impl PostgresMetadataConn {
         Queries::get_tags(&mut *self.conn, repository_id, n, last).await
     }
 
-    pub async fn new_upload_session(&mut self) -> Result<UploadSession> {
-        Queries::new_upload_session(&mut *self.conn).await
+    pub async fn get_object_deletions(
+        &mut self,
+        grace_period_secs: u64,
+        n: u64,
+    ) -> Result<Vec<Uuid>> {
+        Queries::get_object_deletions(&mut *self.conn, grace_period_secs, n).await
+    }
+
+    pub async fn delete_object_deletion(&mut self, object_id: &Uuid) -> Result<()> {
+        Queries::delete_object_deletion(&mut *self.conn, object_id).await
+    }
+
+    pub async fn new_upload_session(
+        &mut self,
+        declared_digest: Option<&OciDigest>,
+        state: DigestState,
+    ) -> Result<UploadSession> {
+        Queries::new_upload_session(&mut *self.conn, declared_digest, state).await
     }
 
     pub async fn get_session(&mut self, uuid: &Uuid) -> Result<UploadSession> {
@@ -716,8 +2178,41 @@ impl PostgresMetadataConn {
         Queries::get_chunks(&mut *self.conn, session).await
     }
 
-    pub async fn insert_chunk(&mut self, session: &UploadSession, chunk: &Chunk) -> Result<()> {
-        Queries::insert_chunk(&mut *self.conn, session, chunk).await
+    pub async fn insert_chunk(
+        &mut self,
+        session: &UploadSession,
+        chunk: &Chunk,
+        size: u64,
+    ) -> Result<()> {
+        Queries::insert_chunk(&mut *self.conn, session, chunk, size).await
+    }
+
+    pub async fn get_upload_progress(&mut self, uuid: &Uuid) -> Result<UploadProgress> {
+        Queries::get_upload_progress(&mut *self.conn, uuid).await
+    }
+
+    pub async fn get_storage_stats(
+        &mut self,
+        repository_id: Option<&Uuid>,
+    ) -> Result<StorageStats> {
+        Queries::get_storage_stats(&mut *self.conn, repository_id).await
+    }
+
+    pub async fn get_blobs_page(
+        &mut self,
+        repository_id: Option<&Uuid>,
+        last: Option<&Uuid>,
+        n: u64,
+    ) -> Result<Vec<Blob>> {
+        Queries::get_blobs_page(&mut *self.conn, repository_id, last, n).await
+    }
+
+    pub async fn get_images_created_before(
+        &mut self,
+        repository_id: Option<&Uuid>,
+        before: DateTime<Utc>,
+    ) -> Result<Vec<ImageAge>> {
+        Queries::get_images_created_before(&mut *self.conn, repository_id, before).await
     }
 
     pub async fn get_referrers(
@@ -725,8 +2220,27 @@ impl PostgresMetadataConn {
         repository_id: &Uuid,
         subject: &OciDigest,
         artifact_type: &Option<String>,
+        n: Option<i64>,
+        last: Option<String>,
     ) -> Result<Vec<Manifest>> {
-        Queries::get_referrers(&mut *self.conn, repository_id, subject, artifact_type).await
+        Queries::get_referrers(
+            &mut *self.conn,
+            repository_id,
+            subject,
+            artifact_type,
+            n,
+            last,
+        )
+        .await
+    }
+
+    pub async fn get_annotated_manifests(
+        &mut self,
+        repository_id: &Uuid,
+        key: &str,
+        value: Option<&str>,
+    ) -> Result<Vec<Manifest>> {
+        Queries::get_annotated_manifests(&mut *self.conn, repository_id, key, value).await
     }
 
     pub async fn get_tags_by_manifest(
@@ -752,14 +2266,29 @@ impl<'a> PostgresMetadataTx<'a> {
         }
     }
 
+    pub async fn get_repository(&mut self, repository: &str) -> Result<Option<Repository>> {
+        let tx = self.tx.as_mut().ok_or(Error::PostgresMetadataTxInactive)?;
+        Queries::get_repository(&mut **tx, repository).await
+    }
+
+    pub async fn delete_repository(&mut self, repository_id: &Uuid) -> Result<()> {
+        let tx = self.tx.as_mut().ok_or(Error::PostgresMetadataTxInactive)?;
+        Queries::delete_repository(&mut **tx, repository_id).await
+    }
+
     pub async fn insert_blob(&mut self, digest: &OciDigest, bytes_on_disk: i64) -> Result<Uuid> {
         let tx = self.tx.as_mut().ok_or(Error::PostgresMetadataTxInactive)?;
         Queries::insert_blob(&mut **tx, digest, bytes_on_disk).await
     }
 
-    pub async fn insert_chunk(&mut self, session: &UploadSession, chunk: &Chunk) -> Result<()> {
+    pub async fn insert_chunk(
+        &mut self,
+        session: &UploadSession,
+        chunk: &Chunk,
+        size: u64,
+    ) -> Result<()> {
         let tx = self.tx.as_mut().ok_or(Error::PostgresMetadataTxInactive)?;
-        Queries::insert_chunk(&mut **tx, session, chunk).await
+        Queries::insert_chunk(&mut **tx, session, chunk, size).await
     }
 
     pub async fn get_chunks(&mut self, session: &UploadSession) -> Result<Vec<Chunk>> {
@@ -777,6 +2306,15 @@ impl<'a> PostgresMetadataTx<'a> {
         Queries::update_session(&mut **tx, session).await
     }
 
+    pub async fn get_stale_sessions(
+        &mut self,
+        max_age_secs: u64,
+        n: u64,
+    ) -> Result<Vec<UploadSession>> {
+        let tx = self.tx.as_mut().ok_or(Error::PostgresMetadataTxInactive)?;
+        Queries::get_stale_sessions(&mut **tx, max_age_secs, n).await
+    }
+
     pub async fn delete_session(&mut self, session_uuid: &Uuid) -> Result<()> {
         let tx = self.tx.as_mut().ok_or(Error::PostgresMetadataTxInactive)?;
         Queries::delete_session(&mut **tx, session_uuid).await
@@ -792,11 +2330,49 @@ impl<'a> PostgresMetadataTx<'a> {
         Queries::get_blobs(&mut **tx, digests).await
     }
 
+    pub async fn get_repository_blob(
+        &mut self,
+        repository: &str,
+        digest: &OciDigest,
+    ) -> Result<Option<Blob>> {
+        let tx = self.tx.as_mut().ok_or(Error::PostgresMetadataTxInactive)?;
+        Queries::get_repository_blob(&mut **tx, repository, digest).await
+    }
+
+    pub async fn associate_repository_blob(
+        &mut self,
+        repository_id: &Uuid,
+        blob_id: &Uuid,
+    ) -> Result<()> {
+        let tx = self.tx.as_mut().ok_or(Error::PostgresMetadataTxInactive)?;
+        Queries::associate_repository_blob(&mut **tx, repository_id, blob_id).await
+    }
+
     pub async fn delete_blob(&mut self, blob_id: &Uuid) -> Result<()> {
         let tx = self.tx.as_mut().ok_or(Error::PostgresMetadataTxInactive)?;
         Queries::delete_blob(&mut **tx, blob_id).await
     }
 
+    pub async fn blob_reference_count(&mut self, digest: &OciDigest) -> Result<i64> {
+        let tx = self.tx.as_mut().ok_or(Error::PostgresMetadataTxInactive)?;
+        Queries::blob_reference_count(&mut **tx, digest).await
+    }
+
+    pub async fn get_repository_blobs(&mut self, repository_id: &Uuid) -> Result<Vec<Blob>> {
+        let tx = self.tx.as_mut().ok_or(Error::PostgresMetadataTxInactive)?;
+        Queries::get_repository_blobs(&mut **tx, repository_id).await
+    }
+
+    pub async fn get_unreferenced_blobs(&mut self) -> Result<Vec<Blob>> {
+        let tx = self.tx.as_mut().ok_or(Error::PostgresMetadataTxInactive)?;
+        Queries::get_unreferenced_blobs(&mut **tx).await
+    }
+
+    pub async fn mark_object_for_deletion(&mut self, object_id: &Uuid) -> Result<()> {
+        let tx = self.tx.as_mut().ok_or(Error::PostgresMetadataTxInactive)?;
+        Queries::mark_object_for_deletion(&mut **tx, object_id).await
+    }
+
     pub async fn get_manifests(
         &mut self,
         repository_id: &Uuid,
@@ -825,13 +2401,83 @@ impl<'a> PostgresMetadataTx<'a> {
         Queries::delete_manifest(&mut **tx, manifest_id).await
     }
 
+    pub async fn tombstone_manifest(&mut self, manifest_id: &Uuid) -> Result<()> {
+        let tx = self.tx.as_mut().ok_or(Error::PostgresMetadataTxInactive)?;
+        Queries::tombstone_manifest(&mut **tx, manifest_id).await
+    }
+
+    pub async fn undelete_manifest(
+        &mut self,
+        repository_id: &Uuid,
+        digest: &OciDigest,
+    ) -> Result<bool> {
+        let tx = self.tx.as_mut().ok_or(Error::PostgresMetadataTxInactive)?;
+        Queries::undelete_manifest(&mut **tx, repository_id, digest).await
+    }
+
+    pub async fn manifest_referenced_by_index(&mut self, manifest_id: &Uuid) -> Result<bool> {
+        let tx = self.tx.as_mut().ok_or(Error::PostgresMetadataTxInactive)?;
+        Queries::manifest_referenced_by_index(&mut **tx, manifest_id).await
+    }
+
+    pub async fn get_expired_manifest_tombstones(
+        &mut self,
+        retention_secs: u64,
+        n: u64,
+    ) -> Result<Vec<Manifest>> {
+        let tx = self.tx.as_mut().ok_or(Error::PostgresMetadataTxInactive)?;
+        Queries::get_expired_manifest_tombstones(&mut **tx, retention_secs, n).await
+    }
+
+    pub async fn get_manifests_pending_config_backfill(&mut self, n: u64) -> Result<Vec<Manifest>> {
+        let tx = self.tx.as_mut().ok_or(Error::PostgresMetadataTxInactive)?;
+        Queries::get_manifests_pending_config_backfill(&mut **tx, n).await
+    }
+
+    pub async fn associate_image_config(
+        &mut self,
+        manifest_id: &Uuid,
+        blob_id: &Uuid,
+    ) -> Result<()> {
+        let tx = self.tx.as_mut().ok_or(Error::PostgresMetadataTxInactive)?;
+        Queries::associate_image_config(&mut **tx, manifest_id, blob_id).await
+    }
+
+    pub async fn clear_config_backfill(&mut self, manifest_id: &Uuid) -> Result<()> {
+        let tx = self.tx.as_mut().ok_or(Error::PostgresMetadataTxInactive)?;
+        Queries::clear_config_backfill(&mut **tx, manifest_id).await
+    }
+
+    pub async fn lock_platform_backfill(&mut self, manifest_id: &Uuid) -> Result<bool> {
+        let tx = self.tx.as_mut().ok_or(Error::PostgresMetadataTxInactive)?;
+        Queries::lock_platform_backfill(&mut **tx, manifest_id).await
+    }
+
+    pub async fn set_index_child_platform(
+        &mut self,
+        parent: &Uuid,
+        child: &Uuid,
+        platform: Option<&Platform>,
+        position: i32,
+    ) -> Result<()> {
+        let tx = self.tx.as_mut().ok_or(Error::PostgresMetadataTxInactive)?;
+        Queries::set_index_child_platform(&mut **tx, parent, child, platform, position).await
+    }
+
+    pub async fn clear_platform_backfill(&mut self, manifest_id: &Uuid) -> Result<()> {
+        let tx = self.tx.as_mut().ok_or(Error::PostgresMetadataTxInactive)?;
+        Queries::clear_platform_backfill(&mut **tx, manifest_id).await
+    }
+
     pub async fn associate_image_layers(
         &mut self,
         parent: &Uuid,
         children: Vec<&Uuid>,
+        config: Option<&Uuid>,
+        batch_size: usize,
     ) -> Result<()> {
         let tx = self.tx.as_mut().ok_or(Error::PostgresMetadataTxInactive)?;
-        Queries::associate_image_layers(&mut **tx, parent, children).await
+        Queries::associate_image_layers(&mut **tx, parent, children, config, batch_size).await
     }
 
     pub async fn delete_image_layers(&mut self, parent: &Uuid) -> Result<()> {
@@ -842,10 +2488,11 @@ impl<'a> PostgresMetadataTx<'a> {
     pub async fn associate_index_manifests(
         &mut self,
         parent: &Uuid,
-        children: Vec<&Uuid>,
+        children: Vec<(&Uuid, Option<&Platform>, i32)>,
+        batch_size: usize,
     ) -> Result<()> {
         let tx = self.tx.as_mut().ok_or(Error::PostgresMetadataTxInactive)?;
-        Queries::associate_index_manifests(&mut **tx, parent, children).await
+        Queries::associate_index_manifests(&mut **tx, parent, children, batch_size).await
     }
 
     pub async fn delete_index_manifests(&mut self, parent: &Uuid) -> Result<()> {
@@ -853,18 +2500,80 @@ impl<'a> PostgresMetadataTx<'a> {
         Queries::delete_index_manifests(&mut **tx, parent).await
     }
 
+    pub async fn lock_tag(&mut self, repository_id: &Uuid, tag: &str) -> Result<()> {
+        let tx = self.tx.as_mut().ok_or(Error::PostgresMetadataTxInactive)?;
+        Queries::lock_tag(&mut **tx, repository_id, tag).await
+    }
+
     pub async fn upsert_tag(
         &mut self,
         repository_id: &Uuid,
         manifest_id: &Uuid,
         tag: &str,
+        mutable_for: Option<u64>,
     ) -> Result<()> {
         let tx = self.tx.as_mut().ok_or(Error::PostgresMetadataTxInactive)?;
-        Queries::upsert_tag(&mut **tx, repository_id, manifest_id, tag).await
+        Queries::upsert_tag(&mut **tx, repository_id, manifest_id, tag, mutable_for).await
     }
 
     pub async fn delete_tags_by_manifest_id(&mut self, manifest_id: &Uuid) -> Result<()> {
         let tx = self.tx.as_mut().ok_or(Error::PostgresMetadataTxInactive)?;
         Queries::delete_tags_by_manifest_id(&mut **tx, manifest_id).await
     }
+
+    pub async fn delete_tag(&mut self, repository_id: &Uuid, tag: &str) -> Result<bool> {
+        let tx = self.tx.as_mut().ok_or(Error::PostgresMetadataTxInactive)?;
+        Queries::delete_tag(&mut **tx, repository_id, tag).await
+    }
+
+    pub async fn tombstone_tag(&mut self, repository_id: &Uuid, tag: &str) -> Result<bool> {
+        let tx = self.tx.as_mut().ok_or(Error::PostgresMetadataTxInactive)?;
+        Queries::tombstone_tag(&mut **tx, repository_id, tag).await
+    }
+
+    pub async fn undelete_tag(&mut self, repository_id: &Uuid, tag: &str) -> Result<Option<Uuid>> {
+        let tx = self.tx.as_mut().ok_or(Error::PostgresMetadataTxInactive)?;
+        Queries::undelete_tag(&mut **tx, repository_id, tag).await
+    }
+
+    pub async fn delete_tag_tombstones_by_manifest_id(&mut self, manifest_id: &Uuid) -> Result<()> {
+        let tx = self.tx.as_mut().ok_or(Error::PostgresMetadataTxInactive)?;
+        Queries::delete_tag_tombstones_by_manifest_id(&mut **tx, manifest_id).await
+    }
+
+    pub async fn delete_expired_tag_tombstones(&mut self, retention_secs: u64) -> Result<u64> {
+        let tx = self.tx.as_mut().ok_or(Error::PostgresMetadataTxInactive)?;
+        Queries::delete_expired_tag_tombstones(&mut **tx, retention_secs).await
+    }
+
+    pub async fn get_referrers(
+        &mut self,
+        repository_id: &Uuid,
+        subject: &OciDigest,
+        artifact_type: &Option<String>,
+        n: Option<i64>,
+        last: Option<String>,
+    ) -> Result<Vec<Manifest>> {
+        let tx = self.tx.as_mut().ok_or(Error::PostgresMetadataTxInactive)?;
+        Queries::get_referrers(&mut **tx, repository_id, subject, artifact_type, n, last).await
+    }
+
+    pub async fn set_snapshot_isolation(&mut self) -> Result<()> {
+        let tx = self.tx.as_mut().ok_or(Error::PostgresMetadataTxInactive)?;
+        Queries::set_snapshot_isolation(&mut **tx).await
+    }
+
+    pub async fn export_table(&mut self, table: impl Iden) -> Result<Vec<serde_json::Value>> {
+        let tx = self.tx.as_mut().ok_or(Error::PostgresMetadataTxInactive)?;
+        Queries::export_table(&mut **tx, table).await
+    }
+
+    pub async fn import_table(
+        &mut self,
+        table: impl Iden,
+        rows: &[serde_json::Value],
+    ) -> Result<u64> {
+        let tx = self.tx.as_mut().ok_or(Error::PostgresMetadataTxInactive)?;
+        Queries::import_table(&mut **tx, table, rows).await
+    }
 }
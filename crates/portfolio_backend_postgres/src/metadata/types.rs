@@ -1,20 +1,57 @@
-use chrono::NaiveDate;
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{DateTime, Utc};
 use oci_spec::image::MediaType;
 use sea_query::Iden;
+use serde::Serialize;
 use sqlx::types::Json;
 use sqlx::Row;
 use uuid::Uuid;
 
 use portfolio_core::registry;
 use portfolio_core::registry::ManifestSpec;
+use portfolio_core::registry::Visibility;
 use portfolio_core::DigestState;
+use portfolio_core::Digester;
+use portfolio_core::Error as CoreError;
 use portfolio_core::OciDigest;
 use portfolio_objectstore::Chunk as ObjectStoreChunk;
 
-#[derive(sqlx::FromRow, Clone)]
+#[derive(Clone)]
 pub struct Repository {
     pub(crate) id: Uuid,
     pub name: String,
+    pub visibility: Visibility,
+    /// Glob patterns matching tags that can't be moved to a different manifest once they exist.
+    pub immutable_tags: Vec<String>,
+}
+
+impl sqlx::FromRow<'_, sqlx_postgres::PgRow> for Repository {
+    fn from_row(row: &sqlx_postgres::PgRow) -> sqlx::Result<Self> {
+        Ok(Self {
+            id: row.try_get("id")?,
+            name: row.try_get("name")?,
+            visibility: match row.try_get::<String, _>("visibility")?.as_str() {
+                "public" => Visibility::Public,
+                "private" => Visibility::Private,
+                v => {
+                    return Err(sqlx::Error::ColumnDecode {
+                        index: "visibility".to_string(),
+                        source: format!("unknown visibility {v}").into(),
+                    })
+                }
+            },
+            immutable_tags: row.try_get::<Json<Vec<String>>, _>("immutable_tags")?.0,
+        })
+    }
+}
+
+/// Value stored in the `visibility` column of `repositories`.
+pub(crate) fn visibility_str(visibility: Visibility) -> &'static str {
+    match visibility {
+        Visibility::Public => "public",
+        Visibility::Private => "private",
+    }
 }
 
 #[derive(Iden)]
@@ -22,6 +59,8 @@ pub enum Repositories {
     Table,
     Id,
     Name,
+    Visibility,
+    ImmutableTags,
 }
 
 pub struct Blob {
@@ -34,15 +73,7 @@ impl sqlx::FromRow<'_, sqlx_postgres::PgRow> for Blob {
     fn from_row(row: &sqlx_postgres::PgRow) -> sqlx::Result<Self> {
         Ok(Self {
             id: row.try_get("id")?,
-            digest: match row.try_get::<String, &str>("digest")?.as_str().try_into() {
-                Ok(v) => v,
-                Err(e) => {
-                    return Err(sqlx::Error::ColumnDecode {
-                        index: "digest".to_string(),
-                        source: format!("{}", e).into(),
-                    })
-                }
-            },
+            digest: row.try_get("digest")?,
             bytes_on_disk: row.try_get("bytes_on_disk")?,
         })
     }
@@ -75,15 +106,7 @@ impl sqlx::FromRow<'_, sqlx_postgres::PgRow> for Tag {
         Ok(Self {
             manifest_id: row.try_get("manifest_id")?,
             name: row.try_get("name")?,
-            digest: match row.try_get::<String, &str>("digest")?.as_str().try_into() {
-                Ok(v) => v,
-                Err(e) => {
-                    return Err(sqlx::Error::ColumnDecode {
-                        index: "digest".to_string(),
-                        source: format!("{}", e).into(),
-                    })
-                }
-            },
+            digest: row.try_get("digest")?,
         })
     }
 }
@@ -104,6 +127,30 @@ pub enum Tags {
     RepositoryId,
     ManifestId,
     Name,
+    UpdatedAt,
+}
+
+#[derive(Iden)]
+pub enum RepositoryBlobs {
+    Table,
+    RepositoryId,
+    BlobId,
+}
+
+#[derive(Iden)]
+pub enum DeletedTags {
+    Table,
+    RepositoryId,
+    ManifestId,
+    Name,
+    DeletedAt,
+}
+
+#[derive(Iden)]
+pub enum ObjectDeletions {
+    Table,
+    ObjectId,
+    MarkedAt,
 }
 
 pub struct Manifest {
@@ -116,6 +163,12 @@ pub struct Manifest {
     pub subject: Option<OciDigest>,
     pub media_type: Option<oci_spec::image::MediaType>,
     pub artifact_type: Option<oci_spec::image::MediaType>,
+    pub annotations: Option<HashMap<String, String>>,
+    /// the Content-Type header the manifest was pushed with, if any
+    pub content_type: Option<String>,
+    /// when the image was created according to its `org.opencontainers.image.created` annotation
+    /// or its config, if recorded
+    pub created: Option<DateTime<Utc>>,
 }
 
 impl sqlx::FromRow<'_, sqlx_postgres::PgRow> for Manifest {
@@ -125,33 +178,19 @@ impl sqlx::FromRow<'_, sqlx_postgres::PgRow> for Manifest {
             repository_id: row.try_get("repository_id")?,
             blob_id: row.try_get("blob_id")?,
             bytes_on_disk: row.try_get("bytes_on_disk")?,
-            digest: match OciDigest::try_from(row.try_get::<String, _>("digest")?.as_str()) {
-                Ok(v) => v,
-                Err(e) => {
-                    return Err(sqlx::Error::ColumnDecode {
-                        index: "digest".to_string(),
-                        source: format!("{}", e).into(),
-                    })
-                }
-            },
-            subject: row
-                .try_get::<Option<String>, _>("subject")?
-                .map(|v| match OciDigest::try_from(v.as_str()) {
-                    Ok(v) => Ok(v),
-                    Err(e) => {
-                        return Err(sqlx::Error::ColumnDecode {
-                            index: "subject".to_string(),
-                            source: format!("{}", e).into(),
-                        })
-                    }
-                })
-                .transpose()?,
+            digest: row.try_get("digest")?,
+            subject: row.try_get("subject")?,
             media_type: row
                 .try_get::<Option<String>, _>("media_type")?
                 .map(|v| v.as_str().into()),
             artifact_type: row
-                .try_get::<Option<String>, _>("media_type")?
+                .try_get::<Option<String>, _>("artifact_type")?
                 .map(|v| v.as_str().into()),
+            annotations: row
+                .try_get::<Option<Json<HashMap<String, String>>>, _>("annotations")?
+                .map(|Json(annotations)| annotations),
+            content_type: row.try_get("content_type")?,
+            created: row.try_get("created")?,
         })
     }
 }
@@ -171,6 +210,11 @@ impl registry::Manifest for Manifest {
     fn media_type(&self) -> &Option<MediaType> {
         &self.media_type
     }
+
+    #[inline]
+    fn content_type(&self) -> Option<&str> {
+        self.content_type.as_deref()
+    }
 }
 
 impl Manifest {
@@ -196,6 +240,9 @@ impl Manifest {
                 }),
                 media_type: img.media_type().clone(),
                 artifact_type: img.artifact_type().clone(),
+                annotations: img.annotations().clone(),
+                content_type: None,
+                created: None,
             },
             ManifestSpec::Index(ind) => Manifest {
                 id: Uuid::new_v4(),
@@ -211,6 +258,9 @@ impl Manifest {
                 }),
                 media_type: ind.media_type().clone(),
                 artifact_type: ind.artifact_type().clone(),
+                annotations: ind.annotations().clone(),
+                content_type: None,
+                created: None,
             },
         }
     }
@@ -226,6 +276,12 @@ pub enum Manifests {
     RepositoryId,
     Digest,
     Subject,
+    Annotations,
+    DeletedAt,
+    ContentType,
+    Created,
+    ConfigBackfillPending,
+    PlatformBackfillPending,
 }
 
 #[derive(Iden)]
@@ -233,6 +289,53 @@ pub enum Layers {
     Table,
     Manifest,
     Blob,
+    Config,
+}
+
+/// Bytes of stored content by kind, counting each blob once however many manifests refer to it.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct StorageStats {
+    /// Bytes of manifest (and index) content.
+    pub manifest_bytes: u64,
+    /// Bytes of blobs referenced as image layers.
+    pub layer_bytes: u64,
+    /// Bytes of blobs referenced as image configs.
+    pub config_bytes: u64,
+    /// Bytes of stored objects by the storage class the object store keeps them under, when
+    /// requested; see
+    /// [`PgRepositoryFactory::storage_class_bytes`](crate::PgRepositoryFactory::storage_class_bytes).
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub storage_class_bytes: BTreeMap<String, u64>,
+}
+
+impl sqlx::FromRow<'_, sqlx_postgres::PgRow> for StorageStats {
+    fn from_row(row: &sqlx_postgres::PgRow) -> sqlx::Result<Self> {
+        Ok(Self {
+            manifest_bytes: row.try_get::<i64, _>("manifest_bytes")? as u64,
+            layer_bytes: row.try_get::<i64, _>("layer_bytes")? as u64,
+            config_bytes: row.try_get::<i64, _>("config_bytes")? as u64,
+            storage_class_bytes: BTreeMap::new(),
+        })
+    }
+}
+
+/// An image listed by when it was created, eg as a candidate for age-based retention.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ImageAge {
+    /// Name of the repository the image was pushed to.
+    pub repository: String,
+    pub digest: OciDigest,
+    pub created: DateTime<Utc>,
+}
+
+impl sqlx::FromRow<'_, sqlx_postgres::PgRow> for ImageAge {
+    fn from_row(row: &sqlx_postgres::PgRow) -> sqlx::Result<Self> {
+        Ok(Self {
+            repository: row.try_get("repository")?,
+            digest: row.try_get("digest")?,
+            created: row.try_get("created")?,
+        })
+    }
 }
 
 #[derive(Iden)]
@@ -240,27 +343,66 @@ pub enum IndexManifests {
     Table,
     ParentManifest,
     ChildManifest,
+    PlatformOs,
+    PlatformArchitecture,
+    PlatformVariant,
+    Position,
 }
 
 #[derive(Debug, sqlx::FromRow)]
 pub struct UploadSession {
     pub uuid: Uuid,
-    pub start_date: NaiveDate,
+    pub start_date: DateTime<Utc>,
     pub upload_id: Option<String>,
     pub chunk_number: i32,
     pub last_range_end: i64,
     pub digest_state: Option<Json<DigestState>>,
+    pub digest_checkpoint: Option<String>,
+    /// the digest the client declared the content would have when starting the session, if any
+    pub declared_digest: Option<OciDigest>,
 }
 
 impl UploadSession {
-    pub(crate) fn validate_range(&self, start: u64) -> bool {
-        if start == 0 && self.chunk_number == 1 {
-            return true;
-        }
-        if start as i64 != self.last_range_end + 1 {
-            return false;
-        }
-        return true;
+    /// Ensure a chunk starting at byte `start` immediately follows the content uploaded so far,
+    /// returning [`CoreError::ContentRangeInvalid`] describing the gap or overlap otherwise.
+    pub(crate) fn validate_range(&self, start: u64) -> portfolio_core::Result<()> {
+        // the first chunk must start at the beginning of the blob
+        let expected = if self.chunk_number == 1 {
+            0
+        } else {
+            self.last_range_end + 1
+        };
+        let start = start as i64;
+        let problem = match start.cmp(&expected) {
+            std::cmp::Ordering::Equal => return Ok(()),
+            std::cmp::Ordering::Greater => format!(
+                "chunk starting at byte {start} would leave a gap, expected it to start at byte \
+                 {expected}"
+            ),
+            std::cmp::Ordering::Less => format!(
+                "chunk starting at byte {start} overlaps content already uploaded, expected it to \
+                 start at byte {expected}"
+            ),
+        };
+        Err(CoreError::ContentRangeInvalid(
+            self.last_range_end,
+            Some(problem),
+        ))
+    }
+
+    /// Resume calculating the digest of the content uploaded so far in this session.
+    pub(crate) fn take_digester(&mut self) -> Digester {
+        self.digest_state
+            .take()
+            .map(|Json(state)| state)
+            .unwrap_or_default()
+            .into()
+    }
+
+    /// Record the digest calculation state so it can be resumed by the next chunk.
+    pub(crate) fn store_digester(&mut self, digester: Digester) {
+        self.digest_state = Some(Json(digester.into()));
+        self.digest_checkpoint = None;
     }
 }
 
@@ -279,6 +421,11 @@ impl registry::UploadSession for UploadSession {
     fn last_range_end(&self) -> i64 {
         self.last_range_end
     }
+
+    #[inline]
+    fn declared_digest(&self) -> Option<&OciDigest> {
+        self.declared_digest.as_ref()
+    }
 }
 
 #[derive(Iden)]
@@ -290,12 +437,18 @@ pub enum UploadSessions {
     ChunkNumber,
     LastRangeEnd,
     DigestState,
+    DigestCheckpoint,
+    DeclaredDigest,
 }
 
 #[derive(Default, sqlx::FromRow)]
 pub struct Chunk {
     pub e_tag: Option<String>,
     pub chunk_number: i32,
+    pub checksum_crc32: Option<String>,
+    pub checksum_crc32_c: Option<String>,
+    pub checksum_sha1: Option<String>,
+    pub checksum_sha256: Option<String>,
 }
 
 impl From<ObjectStoreChunk> for Chunk {
@@ -303,11 +456,19 @@ impl From<ObjectStoreChunk> for Chunk {
         ObjectStoreChunk {
             e_tag,
             chunk_number,
+            checksum_crc32,
+            checksum_crc32_c,
+            checksum_sha1,
+            checksum_sha256,
         }: ObjectStoreChunk,
     ) -> Self {
         Self {
             e_tag,
             chunk_number,
+            checksum_crc32,
+            checksum_crc32_c,
+            checksum_sha1,
+            checksum_sha256,
         }
     }
 }
@@ -317,11 +478,19 @@ impl From<Chunk> for ObjectStoreChunk {
         Chunk {
             e_tag,
             chunk_number,
+            checksum_crc32,
+            checksum_crc32_c,
+            checksum_sha1,
+            checksum_sha256,
         }: Chunk,
     ) -> Self {
         Self {
             e_tag,
             chunk_number,
+            checksum_crc32,
+            checksum_crc32_c,
+            checksum_sha1,
+            checksum_sha256,
         }
     }
 }
@@ -332,4 +501,60 @@ pub enum Chunks {
     ChunkNumber,
     UploadSessionUuid,
     ETag,
+    Size,
+    #[iden = "checksum_crc32"]
+    ChecksumCrc32,
+    #[iden = "checksum_crc32_c"]
+    ChecksumCrc32C,
+    ChecksumSha1,
+    ChecksumSha256,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn session(chunk_number: i32, last_range_end: i64) -> UploadSession {
+        UploadSession {
+            uuid: Uuid::nil(),
+            start_date: Utc::now(),
+            upload_id: None,
+            chunk_number,
+            last_range_end,
+            digest_state: None,
+            digest_checkpoint: None,
+            declared_digest: None,
+        }
+    }
+
+    #[test]
+    fn first_chunk_starts_at_zero() {
+        let fresh = session(1, 0);
+        assert!(fresh.validate_range(0).is_ok());
+        assert!(matches!(
+            fresh.validate_range(1),
+            Err(CoreError::ContentRangeInvalid(0, Some(_)))
+        ));
+    }
+
+    #[test]
+    fn contiguous_chunk_is_valid() {
+        assert!(session(2, 99).validate_range(100).is_ok());
+    }
+
+    #[test]
+    fn gap_is_invalid() {
+        match session(2, 99).validate_range(150) {
+            Err(CoreError::ContentRangeInvalid(99, Some(msg))) => assert!(msg.contains("gap")),
+            r => panic!("expected ContentRangeInvalid, got {r:?}"),
+        }
+    }
+
+    #[test]
+    fn overlap_is_invalid() {
+        match session(2, 99).validate_range(50) {
+            Err(CoreError::ContentRangeInvalid(99, Some(msg))) => assert!(msg.contains("overlaps")),
+            r => panic!("expected ContentRangeInvalid, got {r:?}"),
+        }
+    }
 }
@@ -1,22 +1,37 @@
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
+use std::time::Duration;
 
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use serde::Deserialize;
+use uuid::Uuid;
 
 use portfolio_core::errors::Result;
+use portfolio_core::events::{EventSink, WebhookEventSink};
 use portfolio_core::registry::BoxedBlobStore;
 use portfolio_core::registry::BoxedManifestStore;
 use portfolio_core::registry::BoxedRepositoryStore;
 use portfolio_core::registry::BoxedUploadSessionStore;
+use portfolio_core::registry::Features;
 use portfolio_core::registry::RepositoryStore as RepositoryStoreT;
 use portfolio_core::registry::RepositoryStoreManager;
-use portfolio_objectstore::{Config as ObjectStoreConfig, ObjectStore};
+use portfolio_core::registry::Visibility;
+use portfolio_core::Error as CoreError;
+use portfolio_core::{Digester, OciDigest};
+use portfolio_objectstore::{Config as ObjectStoreConfig, Key, ObjectStore};
 
+use super::audit::DigestAlgorithmAudit;
 use super::blobs::PgBlobStore;
+use super::deletion::{ManifestReaper, ObjectDeletion, ObjectSweeper, SessionReaper};
 use super::errors::Error;
-use super::manifests::PgManifestStore;
-use super::metadata::Repository;
+use super::fan_out::FanOutLimiter;
+use super::manifests::{
+    backfill_config_associations, ImageCreation, PgManifestStore, DEFAULT_MAX_MANIFEST_READ_BYTES,
+};
+use super::metadata::{ImageAge, Repository, StorageStats};
 use super::metadata::{PostgresConfig, PostgresMetadataPool};
+use super::snapshot::MetadataSnapshot;
 use super::upload_sessions::PgSessionStore;
 
 /// [`RepositoryStore`](portfolio_core::registry::RepositoryStore) implementation.
@@ -29,6 +44,10 @@ use super::upload_sessions::PgSessionStore;
 pub struct PgRepository {
     objects: Arc<dyn ObjectStore>,
     metadata: PostgresMetadataPool,
+    config: StoreConfig,
+    audit: Option<Arc<DigestAlgorithmAudit>>,
+    fan_out: Option<Arc<FanOutLimiter>>,
+    events: Option<Arc<dyn EventSink>>,
 
     repository: Repository,
 }
@@ -38,11 +57,19 @@ impl PgRepository {
         name: &str,
         metadata: PostgresMetadataPool,
         objects: Arc<dyn ObjectStore>,
+        config: StoreConfig,
+        audit: Option<Arc<DigestAlgorithmAudit>>,
+        fan_out: Option<Arc<FanOutLimiter>>,
+        events: Option<Arc<dyn EventSink>>,
     ) -> Result<Option<Self>> {
         if let Some(repository) = metadata.get_conn().await?.get_repository(name).await? {
             Ok(Some(Self {
                 objects,
                 metadata,
+                config,
+                audit,
+                fan_out,
+                events,
                 repository,
             }))
         } else {
@@ -54,6 +81,10 @@ impl PgRepository {
         name: &str,
         metadata: PostgresMetadataPool,
         objects: Arc<dyn ObjectStore>,
+        config: StoreConfig,
+        audit: Option<Arc<DigestAlgorithmAudit>>,
+        fan_out: Option<Arc<FanOutLimiter>>,
+        events: Option<Arc<dyn EventSink>>,
     ) -> Result<Self> {
         let mut conn = metadata.get_conn().await?;
 
@@ -65,6 +96,10 @@ impl PgRepository {
         Ok(Self {
             objects,
             metadata,
+            config,
+            audit,
+            fan_out,
+            events,
             repository,
         })
     }
@@ -77,7 +112,15 @@ impl RepositoryStoreT for PgRepository {
     }
 
     fn get_manifest_store(&self) -> BoxedManifestStore {
-        let blobstore = PgBlobStore::new(self.metadata.clone(), self.objects.clone());
+        let blobstore = PgBlobStore::new(
+            self.metadata.clone(),
+            self.objects.clone(),
+            self.config.clone(),
+            self.audit.clone(),
+            self.fan_out.clone(),
+            self.events.clone(),
+            self.repository.name.clone(),
+        );
         Box::new(PgManifestStore::new(blobstore, self.repository.clone()))
     }
 
@@ -85,11 +128,23 @@ impl RepositoryStoreT for PgRepository {
         Box::new(PgBlobStore::new(
             self.metadata.clone(),
             self.objects.clone(),
+            self.config.clone(),
+            self.audit.clone(),
+            self.fan_out.clone(),
+            self.events.clone(),
+            self.repository.name.clone(),
         ))
     }
 
     fn get_upload_session_store(&self) -> BoxedUploadSessionStore {
-        Box::new(PgSessionStore::new(self.metadata.clone()))
+        Box::new(PgSessionStore::new(
+            self.metadata.clone(),
+            self.config.clone(),
+        ))
+    }
+
+    fn visibility(&self) -> Visibility {
+        self.repository.visibility
     }
 }
 
@@ -100,13 +155,282 @@ impl RepositoryStoreT for PgRepository {
 pub struct PgRepositoryFactory {
     metadata: PostgresMetadataPool,
     objects: Arc<dyn ObjectStore>,
+    config: StoreConfig,
+    audit: Option<Arc<DigestAlgorithmAudit>>,
+    fan_out: Option<Arc<FanOutLimiter>>,
+    events: Option<Arc<dyn EventSink>>,
+}
+
+impl PgRepositoryFactory {
+    /// Number of manifests, across all repositories, referring to the blob with the given digest.
+    pub async fn reference_count(&self, digest: &OciDigest) -> Result<i64> {
+        Ok(self
+            .metadata
+            .get_conn()
+            .await?
+            .blob_reference_count(digest)
+            .await?)
+    }
+
+    /// The [`ObjectStore`] holding blob content for repositories handed out by this factory.
+    pub fn objects(&self) -> Arc<dyn ObjectStore> {
+        self.objects.clone()
+    }
+
+    /// Key under which the content of the blob with the given digest is stored in
+    /// [`Self::objects`], if the blob exists.
+    pub async fn object_key(&self, digest: &OciDigest) -> Result<Option<Key>> {
+        Ok(self
+            .metadata
+            .get_conn()
+            .await?
+            .get_blob(digest)
+            .await?
+            .map(|blob| Key::from(&blob.id)))
+    }
+
+    /// Delete blobs that no manifest refers to, along with their content in the object store,
+    /// returning the digests of the deleted blobs.
+    ///
+    /// Blobs are unreferenced in the window between being uploaded and the manifest that refers to
+    /// them being pushed, so this should only be run while no pushes are in progress.
+    pub async fn garbage_collect(&self) -> Result<Vec<OciDigest>> {
+        // configs of images pushed before configs counted as references would otherwise be
+        // collected out from under them
+        let limit = self
+            .config
+            .max_manifest_read_bytes
+            .unwrap_or(DEFAULT_MAX_MANIFEST_READ_BYTES);
+        backfill_config_associations(&self.metadata, self.objects.as_ref(), limit).await?;
+
+        let mut tx = self.metadata.get_tx().await?;
+
+        let blobs = tx.get_unreferenced_blobs().await?;
+        let mut orphaned = Vec::new();
+        for blob in &blobs {
+            tx.delete_blob(&blob.id).await?;
+            match self.config.object_deletion {
+                ObjectDeletion::Eager => orphaned.push(blob.id),
+                ObjectDeletion::Lazy => tx.mark_object_for_deletion(&blob.id).await?,
+            }
+        }
+
+        tx.commit().await?;
+
+        for id in orphaned {
+            self.objects
+                .delete(&Key::from(&id))
+                .await
+                .map_err(Error::from)?;
+        }
+
+        Ok(blobs.into_iter().map(|b| b.digest).collect())
+    }
+
+    /// Set the glob patterns matching tags in the repository with the given name that can't be
+    /// moved to a different manifest once pushed, eg `v*`. `*` matches any run of characters and
+    /// `?` any single character. Pushing the manifest an immutable tag already refers to again is
+    /// allowed, but pushing a different manifest to it is denied.
+    pub async fn set_immutable_tags(&self, name: &str, patterns: &[String]) -> Result<()> {
+        if self
+            .metadata
+            .get_conn()
+            .await?
+            .set_repository_immutable_tags(name, patterns)
+            .await?
+        {
+            Ok(())
+        } else {
+            Err(CoreError::NameUnknown(None))
+        }
+    }
+
+    /// Removes content marked for deletion when `object_deletion` is `lazy`. A sweeper already
+    /// runs in the background in that case, so this is only needed to sweep on demand.
+    pub fn object_sweeper(&self) -> ObjectSweeper {
+        ObjectSweeper::new(
+            self.metadata.clone(),
+            self.objects.clone(),
+            Duration::from_secs(self.config.object_deletion_grace_period_secs),
+        )
+    }
+
+    /// Purges deleted manifests whose retention window has passed when `manifest_retention_secs`
+    /// is set, or `None` otherwise. A reaper already runs in the background in that case, so this
+    /// is only needed to purge on demand.
+    pub fn manifest_reaper(&self) -> Option<ManifestReaper> {
+        self.config.manifest_retention_secs.map(|secs| {
+            ManifestReaper::new(
+                self.metadata.clone(),
+                self.objects.clone(),
+                self.config.object_deletion,
+                Duration::from_secs(secs),
+            )
+        })
+    }
+
+    /// Abort and delete upload sessions started longer ago than `max_age`, returning the number
+    /// cleaned up. Sessions are cleaned up in the background when `upload_session_max_age_secs`
+    /// is set, so this is only needed to clean up on demand or on a different schedule.
+    pub async fn cleanup_stale_sessions(&self, max_age: Duration) -> Result<usize> {
+        SessionReaper::new(self.metadata.clone(), self.objects.clone(), max_age)
+            .reap()
+            .await
+    }
+
+    /// Break down the bytes of stored content by kind, for the repository with the given name or
+    /// for the whole registry.
+    pub async fn storage_stats(&self, repository: Option<&str>) -> Result<StorageStats> {
+        let mut conn = self.metadata.get_conn().await?;
+        let repository = match repository {
+            Some(name) => Some(
+                conn.get_repository(name)
+                    .await?
+                    .ok_or(CoreError::NameUnknown(None))?,
+            ),
+            None => None,
+        };
+        Ok(conn
+            .get_storage_stats(repository.as_ref().map(|r| &r.id))
+            .await?)
+    }
+
+    /// Break down the bytes of stored objects, for the repository with the given name or for the
+    /// whole registry, by the storage class the object store keeps them under, eg `STANDARD` or
+    /// `GLACIER` on S3. Objects whose backend has no notion of storage classes are left out.
+    ///
+    /// This looks up every object in the object store, so it is much slower than
+    /// [`Self::storage_stats`].
+    pub async fn storage_class_bytes(
+        &self,
+        repository: Option<&str>,
+    ) -> Result<BTreeMap<String, u64>> {
+        let mut conn = self.metadata.get_conn().await?;
+        let repository = match repository {
+            Some(name) => Some(
+                conn.get_repository(name)
+                    .await?
+                    .ok_or(CoreError::NameUnknown(None))?,
+            ),
+            None => None,
+        };
+
+        let mut bytes = BTreeMap::new();
+        let mut last = None;
+        loop {
+            let blobs = conn
+                .get_blobs_page(
+                    repository.as_ref().map(|r| &r.id),
+                    last.as_ref(),
+                    STORAGE_CLASS_BATCH_SIZE,
+                )
+                .await?;
+            last = match blobs.last() {
+                Some(blob) => Some(blob.id),
+                None => return Ok(bytes),
+            };
+
+            for blob in &blobs {
+                match self.objects.storage_class(&Key::from(&blob.id)).await {
+                    Ok(Some(class)) => {
+                        *bytes.entry(class).or_default() += blob.bytes_on_disk as u64
+                    }
+                    Ok(None) => (),
+                    // blobs may be deleted while they are being counted
+                    Err(portfolio_objectstore::Error::ObjectNotFound(_)) => (),
+                    Err(e) => return Err(Error::from(e).into()),
+                }
+            }
+        }
+    }
+
+    /// List the images, in the repository with the given name or in the whole registry, that were
+    /// created before `before`, oldest first. Only images whose creation time was recorded on push
+    /// are listed; see [`StoreConfig::image_creation`].
+    pub async fn images_created_before(
+        &self,
+        repository: Option<&str>,
+        before: DateTime<Utc>,
+    ) -> Result<Vec<ImageAge>> {
+        let mut conn = self.metadata.get_conn().await?;
+        let repository = match repository {
+            Some(name) => Some(
+                conn.get_repository(name)
+                    .await?
+                    .ok_or(CoreError::NameUnknown(None))?,
+            ),
+            None => None,
+        };
+        Ok(conn
+            .get_images_created_before(repository.as_ref().map(|r| &r.id), before)
+            .await?)
+    }
+
+    /// Export a consistent snapshot of the registry's metadata.
+    pub async fn export_snapshot(&self) -> Result<MetadataSnapshot> {
+        MetadataSnapshot::export(&self.metadata).await
+    }
+
+    /// Restore metadata from a snapshot taken with [`Self::export_snapshot`], returning the
+    /// number of rows inserted.
+    pub async fn import_snapshot(&self, snapshot: &MetadataSnapshot) -> Result<u64> {
+        snapshot.import(&self.metadata).await
+    }
+
+    /// Restore the deleted manifest with the given digest in the repository with the given name,
+    /// provided it is still within its retention window and hasn't been purged.
+    pub async fn undelete_manifest(&self, name: &str, digest: &OciDigest) -> Result<()> {
+        let mut tx = self.metadata.get_tx().await?;
+        let repository = tx
+            .get_repository(name)
+            .await?
+            .ok_or(CoreError::NameUnknown(None))?;
+        if !tx.undelete_manifest(&repository.id, digest).await? {
+            return Err(CoreError::ManifestUnknown(None));
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Restore the deleted tag with the given name in the repository with the given name, pointing
+    /// it back at the manifest it pointed to when it was deleted, provided it is still within its
+    /// retention window.
+    pub async fn undelete_tag(&self, name: &str, tag: &str) -> Result<()> {
+        let mut tx = self.metadata.get_tx().await?;
+        let repository = tx
+            .get_repository(name)
+            .await?
+            .ok_or(CoreError::NameUnknown(None))?;
+        let manifest_id = tx
+            .undelete_tag(&repository.id, tag)
+            .await?
+            .ok_or(CoreError::ManifestUnknown(None))?;
+        tx.upsert_tag(&repository.id, &manifest_id, tag, None)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Digest algorithm usage recorded by repositories handed out by this factory, if
+    /// `audit_digest_algorithms` is enabled.
+    pub fn digest_algorithm_audit(&self) -> Option<Arc<DigestAlgorithmAudit>> {
+        self.audit.clone()
+    }
 }
 
 #[async_trait]
 impl RepositoryStoreManager for PgRepositoryFactory {
     async fn get(&self, name: &str) -> Result<Option<BoxedRepositoryStore>> {
-        if let Some(s) =
-            PgRepository::get(name, self.metadata.clone(), self.objects.clone()).await?
+        if let Some(s) = PgRepository::get(
+            name,
+            self.metadata.clone(),
+            self.objects.clone(),
+            self.config.clone(),
+            self.audit.clone(),
+            self.fan_out.clone(),
+            self.events.clone(),
+        )
+        .await?
         {
             Ok(Some(Box::new(s)))
         } else {
@@ -116,23 +440,344 @@ impl RepositoryStoreManager for PgRepositoryFactory {
 
     async fn create(&self, name: &str) -> Result<BoxedRepositoryStore> {
         Ok(Box::new(
-            PgRepository::get_or_insert(name, self.metadata.clone(), self.objects.clone()).await?,
+            PgRepository::get_or_insert(
+                name,
+                self.metadata.clone(),
+                self.objects.clone(),
+                self.config.clone(),
+                self.audit.clone(),
+                self.fan_out.clone(),
+                self.events.clone(),
+            )
+            .await?,
         ))
     }
+
+    async fn delete(&self, name: &str) -> Result<()> {
+        let mut tx = self.metadata.get_tx().await?;
+
+        let repository = tx
+            .get_repository(name)
+            .await?
+            .ok_or(CoreError::NameUnknown(None))?;
+        let blobs = tx.get_repository_blobs(&repository.id).await?;
+        tx.delete_repository(&repository.id).await?;
+
+        // only blobs that no other repository refers to can be deleted along with the repository
+        let mut orphaned = Vec::new();
+        for blob in blobs {
+            if tx.blob_reference_count(&blob.digest).await? == 0 {
+                tx.delete_blob(&blob.id).await?;
+                match self.config.object_deletion {
+                    ObjectDeletion::Eager => orphaned.push(blob),
+                    ObjectDeletion::Lazy => tx.mark_object_for_deletion(&blob.id).await?,
+                }
+            }
+        }
+
+        tx.commit().await?;
+
+        for blob in orphaned {
+            self.objects
+                .delete(&Key::from(&blob.id))
+                .await
+                .map_err(Error::from)?;
+        }
+
+        Ok(())
+    }
+
+    async fn list(&self, n: Option<i64>, last: Option<String>) -> Result<Vec<String>> {
+        Ok(self
+            .metadata
+            .get_conn()
+            .await?
+            .list_repositories(n, last)
+            .await?
+            .into_iter()
+            .map(|r| r.name)
+            .collect())
+    }
+
+    async fn search(
+        &self,
+        prefix: &str,
+        n: Option<i64>,
+        last: Option<String>,
+    ) -> Result<Vec<String>> {
+        Ok(self
+            .metadata
+            .get_conn()
+            .await?
+            .search_repositories(prefix, n, last)
+            .await?
+            .into_iter()
+            .map(|r| r.name)
+            .collect())
+    }
+
+    fn features(&self) -> Features {
+        Features {
+            referrers: self.config.referrers_enabled(),
+            delete: true,
+            mount: true,
+            chunk_min_length: Some(MIN_CHUNK_LENGTH),
+            max_image_size: self.config.max_image_size,
+        }
+    }
+
+    async fn set_visibility(&self, name: &str, visibility: Visibility) -> Result<()> {
+        if self
+            .metadata
+            .get_conn()
+            .await?
+            .set_repository_visibility(name, visibility)
+            .await?
+        {
+            Ok(())
+        } else {
+            Err(CoreError::NameUnknown(None))
+        }
+    }
+
+    async fn check_dependencies(&self) -> BTreeMap<String, String> {
+        let mut failed = BTreeMap::new();
+        if let Err(e) = self.metadata.ping().await {
+            tracing::warn!("database readiness check failed: {e:?}");
+            failed.insert("postgres".to_string(), e.to_string());
+        }
+        // objects are keyed by random uuids, so this never exists; only whether the store can be
+        // asked matters
+        if let Err(e) = self.objects.exists(&Key::from(&Uuid::nil())).await {
+            tracing::warn!("object store readiness check failed: {e:?}");
+            failed.insert("objects".to_string(), e.to_string());
+        }
+        failed
+    }
 }
 
+const DEFAULT_OBJECT_SWEEP_INTERVAL_SECS: u64 = 60;
+
+/// Smallest chunk S3 accepts for any but the last part of a multipart upload. Each PATCH carrying
+/// a Content-Length is stored as exactly one part and a streamed PATCH ends in a part of whatever
+/// was left over, so chunks aren't rechunked across PATCHes and clients have to respect this.
+const MIN_CHUNK_LENGTH: u64 = 5 * 1024 * 1024;
+
+/// Number of blobs [`PgRepositoryFactory::storage_class_bytes`] looks up per metadata query.
+const STORAGE_CLASS_BATCH_SIZE: u64 = 1000;
+
 /// Holds configuration necessary to initialize an instance of [`PgRepositoryFactory`].
 #[derive(Clone, Deserialize)]
 pub struct PgRepositoryConfig {
     postgres: PostgresConfig,
     objects: ObjectStoreConfig,
+    #[serde(flatten)]
+    store: StoreConfig,
 }
 
 impl PgRepositoryConfig {
     pub async fn get_manager(&self) -> Result<PgRepositoryFactory> {
-        Ok(PgRepositoryFactory {
+        if let Some(algorithms) = &self.store.upload_digest_algorithms {
+            Digester::for_algorithms(algorithms)?;
+        }
+
+        let factory = PgRepositoryFactory {
             metadata: self.postgres.new_metadata().await?,
             objects: self.objects.new_objects().await.map_err(Error::from)?,
-        })
+            config: self.store.clone(),
+            audit: self
+                .store
+                .audit_digest_algorithms
+                .then(|| Arc::new(DigestAlgorithmAudit::default())),
+            fan_out: self
+                .store
+                .max_manifest_fan_out
+                .map(|limit| Arc::new(FanOutLimiter::new(limit))),
+            events: match &self.store.webhook_url {
+                Some(url) => Some(Arc::new(WebhookEventSink::new(
+                    url.parse().map_err(Error::from)?,
+                ))),
+                None => None,
+            },
+        };
+
+        if self.store.object_deletion == ObjectDeletion::Lazy {
+            let interval = self
+                .store
+                .object_sweep_interval_secs
+                .unwrap_or(DEFAULT_OBJECT_SWEEP_INTERVAL_SECS);
+            factory
+                .object_sweeper()
+                .spawn(Duration::from_secs(interval));
+        }
+        if let Some(reaper) = factory.manifest_reaper() {
+            let interval = self
+                .store
+                .object_sweep_interval_secs
+                .unwrap_or(DEFAULT_OBJECT_SWEEP_INTERVAL_SECS);
+            reaper.spawn(Duration::from_secs(interval));
+        }
+        if let Some(secs) = self.store.upload_session_max_age_secs {
+            let interval = self
+                .store
+                .object_sweep_interval_secs
+                .unwrap_or(DEFAULT_OBJECT_SWEEP_INTERVAL_SECS);
+            SessionReaper::new(
+                factory.metadata.clone(),
+                factory.objects.clone(),
+                Duration::from_secs(secs),
+            )
+            .spawn(Duration::from_secs(interval));
+        }
+
+        Ok(factory)
+    }
+}
+
+/// Settings that control the behavior of the stores handed out by [`PgRepository`].
+#[derive(Clone, Default, Deserialize)]
+pub struct StoreConfig {
+    /// When a manifest's metadata exists but its content is missing from the object store,
+    /// report the manifest as unknown rather than failing so that clients push it again.
+    #[serde(default)]
+    pub(crate) heal_missing_manifests: bool,
+
+    /// Count stored blobs and manifests by digest algorithm; see [`DigestAlgorithmAudit`].
+    #[serde(default)]
+    pub(crate) audit_digest_algorithms: bool,
+
+    /// Reject image manifests whose config and layer blobs add up to more than this many bytes.
+    #[serde(default)]
+    pub(crate) max_image_size: Option<u64>,
+
+    /// Number of seconds, keyed by repository name, that tags in that repository may be moved to
+    /// a different manifest after they were last moved. Tags in repositories not listed here are
+    /// always mutable.
+    #[serde(default)]
+    pub(crate) tag_mutability_windows: HashMap<String, u64>,
+
+    /// Verify chunks written with a client-provided chunk digest before recording them in the
+    /// upload session. Chunk digests are ignored when this is disabled.
+    #[serde(default)]
+    pub(crate) verify_chunk_digests: bool,
+
+    /// Verify the digest of blob content as it is streamed back out of the object store, ending
+    /// the stream with an error rather than serving content that doesn't match its digest.
+    ///
+    /// Hashing everything that's pulled costs CPU, so this is off by default. It may also be set
+    /// as `verify_on_pull`.
+    #[serde(default, alias = "verify_on_pull")]
+    pub(crate) verify_on_read: bool,
+
+    /// Maximum number of object store reads that referrers listings may have in flight at once,
+    /// shared across all requests. Unlimited when unset.
+    #[serde(default)]
+    pub(crate) max_manifest_fan_out: Option<usize>,
+
+    /// Re-serialize manifest JSON with sorted keys and no insignificant whitespace before
+    /// digesting and storing it.
+    ///
+    /// **This violates the distribution spec**, which requires manifests to be stored and served
+    /// byte-for-byte as pushed. It is only meant as an escape hatch for registries behind proxies
+    /// that reformat JSON request bodies, and only helps clients that push canonical JSON in the
+    /// first place. Disabled by default.
+    #[serde(default)]
+    pub(crate) canonicalize_manifests: bool,
+
+    /// POST a JSON notification to this URL whenever a manifest is pushed or deleted or a blob is
+    /// pushed; see [`WebhookEventSink`].
+    #[serde(default)]
+    pub(crate) webhook_url: Option<String>,
+
+    /// Whether content is deleted from the object store immediately or marked for deletion and
+    /// swept in the background; see [`ObjectDeletion`]. Eager by default.
+    #[serde(default)]
+    pub(crate) object_deletion: ObjectDeletion,
+
+    /// Number of seconds between background sweeps when `object_deletion` is `lazy`, between
+    /// purges of expired manifest tombstones when `manifest_retention_secs` is set, and between
+    /// cleanups of abandoned upload sessions when `upload_session_max_age_secs` is set. Defaults
+    /// to 60.
+    #[serde(default)]
+    pub(crate) object_sweep_interval_secs: Option<u64>,
+
+    /// Number of seconds content marked for deletion is kept in the object store before being
+    /// swept when `object_deletion` is `lazy`, as a safety net against accidental deletes. Swept
+    /// at the next opportunity by default.
+    #[serde(default)]
+    pub(crate) object_deletion_grace_period_secs: u64,
+
+    /// Keep the digest state of upload sessions in the object store alongside the staged chunks
+    /// and record only its key in the database, rather than writing the full state to the
+    /// database with every chunk. Reduces database writes for uploads made of many chunks.
+    #[serde(default)]
+    pub(crate) checkpoint_digest_state: bool,
+
+    /// Largest stored manifest, in bytes, that is read into memory in full, eg to describe it in
+    /// a referrers listing. Requests that need to read a larger manifest fail with
+    /// `MANIFEST_INVALID`. Defaults to 4 MiB.
+    #[serde(default)]
+    pub(crate) max_manifest_read_bytes: Option<u64>,
+
+    /// Largest number of rows inserted per statement when associating an image's layers or an
+    /// index's manifests with it, keeping statements for manifests with thousands of children to
+    /// a reasonable size. Defaults to 1000.
+    #[serde(default)]
+    pub(crate) association_batch_size: Option<usize>,
+
+    /// Serve the referrers API and record the subjects of pushed manifests. When set to `false`,
+    /// referrers listings are reported as unsupported and subjects are ignored on push, so that
+    /// clients fall back to the referrers tag schema. Enabled unless set otherwise.
+    #[serde(default)]
+    pub(crate) enable_referrers: Option<bool>,
+
+    /// Number of seconds deleted manifests are kept as tombstones before being purged. Tombstoned
+    /// manifests are treated as absent but can be restored with
+    /// [`PgRepositoryFactory::undelete_manifest`] or by pushing them again until then. Deleted
+    /// tags are likewise remembered and can be restored with
+    /// [`PgRepositoryFactory::undelete_tag`]. Manifests are purged and tags forgotten as soon as
+    /// they are deleted when unset.
+    #[serde(default)]
+    pub(crate) manifest_retention_secs: Option<u64>,
+
+    /// Number of seconds after being started that upload sessions which haven't been completed are
+    /// considered abandoned. Abandoned sessions are cleaned up in the background, aborting the
+    /// multipart uploads staging their content. Sessions are kept until completed or cancelled
+    /// when unset.
+    #[serde(default)]
+    pub(crate) upload_session_max_age_secs: Option<u64>,
+
+    /// Digest algorithms, eg `sha256`, calculated while uploading blobs in chunks, since the one
+    /// the client used isn't known until the upload is completed. Each one costs a pass over the
+    /// uploaded content, so only `sha256` is calculated when unset. Sessions started with a digest
+    /// declared up front only calculate the algorithm of that digest.
+    #[serde(default)]
+    pub(crate) upload_digest_algorithms: Option<Vec<String>>,
+
+    /// Digest algorithms, eg `sha256`, that pushed manifests may use to refer to their config,
+    /// layers, or child manifests. Manifests referring to content by any other algorithm are
+    /// rejected with `MANIFEST_INVALID`. Any algorithm is allowed when unset.
+    #[serde(default)]
+    pub(crate) allowed_reference_digest_algorithms: Option<Vec<String>>,
+
+    /// Also maintain the tags of the referrers tag schema, eg `sha256-<hex>`, pointing each at an
+    /// index of the referrers of the subject with that digest, for tooling that discovers
+    /// referrers through the tag rather than the referrers API. Tags are updated in the same
+    /// transaction that pushes or deletes a referrer, and removed once a subject has none.
+    ///
+    /// Has no effect when the referrers API is disabled, since subjects aren't recorded then.
+    #[serde(default)]
+    pub(crate) referrers_fallback_tag: bool,
+
+    /// Whether the creation time of pushed images is recorded, for listing them with
+    /// [`PgRepositoryFactory::images_created_before`]; see [`ImageCreation`]. Not recorded by
+    /// default.
+    #[serde(default)]
+    pub(crate) image_creation: ImageCreation,
+}
+
+impl StoreConfig {
+    pub(crate) fn referrers_enabled(&self) -> bool {
+        self.enable_referrers.unwrap_or(true)
     }
 }
@@ -0,0 +1,70 @@
+//! # Metadata snapshots
+//!
+//! A [`MetadataSnapshot`] is a consistent copy of the registry's metadata -- its repositories,
+//! blobs, manifests, the relationships between them, and tags -- taken within a single
+//! transaction. Together with a backup of the object store it can be used to recover the
+//! registry, eg:
+//!
+//! ```sh
+//! portfolio --config-file config.yml snapshot --output snapshot.json
+//! portfolio --config-file config.yml restore snapshot.json
+//! ```
+//!
+//! Upload sessions are transient and aren't included.
+use serde::{Deserialize, Serialize};
+
+use portfolio_core::errors::Result;
+
+use super::metadata::{
+    Blobs, IndexManifests, Layers, Manifests, PostgresMetadataPool, Repositories, Tags,
+};
+
+/// The rows of each metadata table, as JSON objects keyed by column name.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct MetadataSnapshot {
+    pub repositories: Vec<serde_json::Value>,
+    pub blobs: Vec<serde_json::Value>,
+    pub manifests: Vec<serde_json::Value>,
+    pub index_manifests: Vec<serde_json::Value>,
+    pub layers: Vec<serde_json::Value>,
+    pub tags: Vec<serde_json::Value>,
+}
+
+impl MetadataSnapshot {
+    /// Export every table in one read-only transaction so that the snapshot reflects a single
+    /// point in time.
+    pub(crate) async fn export(metadata: &PostgresMetadataPool) -> Result<Self> {
+        let mut tx = metadata.get_tx().await?;
+        tx.set_snapshot_isolation().await?;
+        let snapshot = Self {
+            repositories: tx.export_table(Repositories::Table).await?,
+            blobs: tx.export_table(Blobs::Table).await?,
+            manifests: tx.export_table(Manifests::Table).await?,
+            index_manifests: tx.export_table(IndexManifests::Table).await?,
+            layers: tx.export_table(Layers::Table).await?,
+            tags: tx.export_table(Tags::Table).await?,
+        };
+        tx.commit().await?;
+        Ok(snapshot)
+    }
+
+    /// Import every table in one transaction, in an order satisfying their foreign keys,
+    /// returning the number of rows inserted. Rows conflicting with existing ones are skipped,
+    /// so a snapshot can be restored over a database that still holds some of its content.
+    pub(crate) async fn import(&self, metadata: &PostgresMetadataPool) -> Result<u64> {
+        let mut tx = metadata.get_tx().await?;
+        let mut inserted = 0;
+        inserted += tx
+            .import_table(Repositories::Table, &self.repositories)
+            .await?;
+        inserted += tx.import_table(Blobs::Table, &self.blobs).await?;
+        inserted += tx.import_table(Manifests::Table, &self.manifests).await?;
+        inserted += tx
+            .import_table(IndexManifests::Table, &self.index_manifests)
+            .await?;
+        inserted += tx.import_table(Layers::Table, &self.layers).await?;
+        inserted += tx.import_table(Tags::Table, &self.tags).await?;
+        tx.commit().await?;
+        Ok(inserted)
+    }
+}
@@ -1,19 +1,32 @@
 use async_trait::async_trait;
 use uuid::Uuid;
 
-use portfolio_core::registry::{BoxedUploadSession, UploadSessionStore};
+use portfolio_core::registry::{BoxedUploadSession, UploadProgress, UploadSessionStore};
 use portfolio_core::Result;
+use portfolio_core::{Digester, OciDigest};
 
 use super::metadata::PostgresMetadataPool;
+use super::repositories::StoreConfig;
 
 #[derive(Clone)]
 pub struct PgSessionStore {
     metadata: PostgresMetadataPool,
+    config: StoreConfig,
 }
 
 impl PgSessionStore {
-    pub fn new(metadata: PostgresMetadataPool) -> Self {
-        Self { metadata }
+    pub fn new(metadata: PostgresMetadataPool, config: StoreConfig) -> Self {
+        Self { metadata, config }
+    }
+
+    /// Digester for a new session; sessions started for a known digest only track its algorithm,
+    /// others track the configured `upload_digest_algorithms`.
+    fn new_digester(&self, declared_digest: Option<&OciDigest>) -> Result<Digester> {
+        match (declared_digest, &self.config.upload_digest_algorithms) {
+            (Some(digest), _) => Ok(digest.digester()),
+            (None, Some(algorithms)) => Digester::for_algorithms(algorithms),
+            (None, None) => Ok(Digester::default()),
+        }
     }
 }
 
@@ -21,7 +34,21 @@ impl PgSessionStore {
 impl UploadSessionStore for PgSessionStore {
     async fn new_upload_session(&self) -> Result<BoxedUploadSession> {
         Ok(Box::new(
-            self.metadata.get_conn().await?.new_upload_session().await?,
+            self.metadata
+                .get_conn()
+                .await?
+                .new_upload_session(None, self.new_digester(None)?.into())
+                .await?,
+        ))
+    }
+
+    async fn new_upload_session_for(&self, digest: &OciDigest) -> Result<BoxedUploadSession> {
+        Ok(Box::new(
+            self.metadata
+                .get_conn()
+                .await?
+                .new_upload_session(Some(digest), self.new_digester(Some(digest))?.into())
+                .await?,
         ))
     }
 
@@ -48,4 +75,12 @@ impl UploadSessionStore for PgSessionStore {
 
         Ok(())
     }
+    async fn get_upload_progress(&self, session_uuid: &Uuid) -> Result<UploadProgress> {
+        Ok(self
+            .metadata
+            .get_conn()
+            .await?
+            .get_upload_progress(session_uuid)
+            .await?)
+    }
 }
@@ -23,6 +23,9 @@ pub enum Error {
     #[error("blob writer already finished")]
     BlobWriterFinished,
 
+    #[error("stored content is corrupt: {0}")]
+    ContentCorrupt(String),
+
     // Distribution Errors
     #[error("blob unknown")]
     BlobUnknown(Option<String>),
@@ -30,6 +33,11 @@ pub enum Error {
     BlobUploadInvalid(Option<String>),
     #[error("blob upload unknown")]
     BlobUploadUnknown(Option<String>),
+    /// A chunk didn't start where the upload left off; carries the inclusive index of the last
+    /// byte the upload has received so that clients can resume from the right place, along with
+    /// a description of the gap or overlap.
+    #[error("content range invalid, upload has received bytes 0-{0}")]
+    ContentRangeInvalid(i64, Option<String>),
     #[error("digest invalid")]
     DigestInvalid(Option<String>),
     #[error("manifest blob unknown")]
@@ -56,5 +64,7 @@ pub enum Error {
 
 #[derive(Debug, Serialize)]
 pub enum PortfolioErrorCode {
-    ContentReferenced = 99, // content referenced elsewhere
+    ContentReferenced = 99,       // content referenced elsewhere
+    ManifestContentMissing = 100, // manifest metadata exists but its content doesn't
+    BlobContentMissing = 101,     // blob metadata exists but its content doesn't
 }
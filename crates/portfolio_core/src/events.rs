@@ -0,0 +1,201 @@
+//! # Content Events
+//!
+//! Notifications emitted by backends as content is pushed to and deleted from a registry, eg to
+//! trigger downstream vulnerability scanning when an image is pushed.
+use hyper::client::HttpConnector;
+use hyper::{header, Body, Client, Method, Request, Uri};
+use serde::Serialize;
+
+use super::OciDigest;
+
+/// Content that was pushed or deleted.
+#[derive(Clone, Debug)]
+pub struct ContentEvent {
+    /// Name of the repository the content was pushed to or deleted from.
+    pub repository: String,
+    pub digest: OciDigest,
+    /// Media type of the content, if known. Blobs are pushed without a media type.
+    pub media_type: Option<String>,
+}
+
+/// Receives [`ContentEvent`]s from a backend.
+///
+/// Callbacks are invoked once the change has been committed. Implementations must not block the
+/// caller since callbacks are invoked while handling Distribution API requests; any slow work,
+/// such as notifying another service, should be dispatched in the background.
+pub trait EventSink: Send + Sync + 'static {
+    fn manifest_pushed(&self, event: ContentEvent);
+
+    fn manifest_deleted(&self, event: ContentEvent);
+
+    fn blob_pushed(&self, event: ContentEvent);
+}
+
+/// JSON payload POSTed by [`WebhookEventSink`], eg:
+///
+/// ```json
+/// {
+///   "action": "manifest_pushed",
+///   "repository": "meow/woof",
+///   "digest": "sha256:...",
+///   "mediaType": "application/vnd.oci.image.manifest.v1+json"
+/// }
+/// ```
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WebhookPayload {
+    action: &'static str,
+    repository: String,
+    digest: String,
+    media_type: Option<String>,
+}
+
+/// [`EventSink`] that POSTs each event as JSON to a configured URL.
+///
+/// Each event is delivered on its own [`tokio`] task so that delivery never delays the API
+/// response that triggered it. Delivery is best-effort: failures are logged and not retried.
+pub struct WebhookEventSink {
+    url: Uri,
+    client: Client<HttpConnector>,
+}
+
+impl WebhookEventSink {
+    pub fn new(url: Uri) -> Self {
+        Self {
+            url,
+            client: Client::new(),
+        }
+    }
+
+    fn dispatch(&self, action: &'static str, event: ContentEvent) {
+        let payload = WebhookPayload {
+            action,
+            repository: event.repository,
+            digest: String::from(&event.digest),
+            media_type: event.media_type,
+        };
+        let url = self.url.clone();
+        let client = self.client.clone();
+
+        tokio::spawn(async move {
+            let body = match serde_json::to_vec(&payload) {
+                Ok(body) => body,
+                Err(e) => {
+                    tracing::warn!("failed to serialize {action} webhook payload: {e:?}");
+                    return;
+                }
+            };
+            let request = match Request::builder()
+                .method(Method::POST)
+                .uri(url)
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body))
+            {
+                Ok(request) => request,
+                Err(e) => {
+                    tracing::warn!("failed to build {action} webhook request: {e:?}");
+                    return;
+                }
+            };
+            match client.request(request).await {
+                Ok(response) if response.status().is_success() => (),
+                Ok(response) => {
+                    tracing::warn!(
+                        "{action} webhook for {} responded with {}",
+                        payload.digest,
+                        response.status()
+                    );
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        "failed to deliver {action} webhook for {}: {e:?}",
+                        payload.digest
+                    );
+                }
+            }
+        });
+    }
+}
+
+impl EventSink for WebhookEventSink {
+    fn manifest_pushed(&self, event: ContentEvent) {
+        self.dispatch("manifest_pushed", event);
+    }
+
+    fn manifest_deleted(&self, event: ContentEvent) {
+        self.dispatch("manifest_deleted", event);
+    }
+
+    fn blob_pushed(&self, event: ContentEvent) {
+        self.dispatch("blob_pushed", event);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::convert::Infallible;
+    use std::net::SocketAddr;
+
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Response, Server};
+    use tokio::sync::mpsc;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn webhook_posts_json_payload() {
+        // mock receiver that forwards each request it gets to the test
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let make_svc = make_service_fn(move |_| {
+            let tx = tx.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                    let tx = tx.clone();
+                    async move {
+                        let content_type = req.headers().get(header::CONTENT_TYPE).cloned();
+                        let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+                        tx.send((content_type, body)).unwrap();
+                        Ok::<_, Infallible>(Response::new(Body::empty()))
+                    }
+                }))
+            }
+        });
+        let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(make_svc);
+        let url: Uri = format!("http://{}/events", server.local_addr())
+            .parse()
+            .unwrap();
+        tokio::spawn(server);
+
+        let sink = WebhookEventSink::new(url);
+        let digest = OciDigest::from(&b"meow"[..]);
+        sink.manifest_pushed(ContentEvent {
+            repository: "meow/woof".to_string(),
+            digest: digest.clone(),
+            media_type: Some("application/vnd.oci.image.manifest.v1+json".to_string()),
+        });
+
+        let (content_type, body) = rx.recv().await.unwrap();
+        assert_eq!(content_type.unwrap(), "application/json");
+        let payload: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            payload,
+            serde_json::json!({
+                "action": "manifest_pushed",
+                "repository": "meow/woof",
+                "digest": String::from(&digest),
+                "mediaType": "application/vnd.oci.image.manifest.v1+json",
+            })
+        );
+
+        sink.blob_pushed(ContentEvent {
+            repository: "meow/woof".to_string(),
+            digest: digest.clone(),
+            media_type: None,
+        });
+
+        let (_, body) = rx.recv().await.unwrap();
+        let payload: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(payload["action"], "blob_pushed");
+        assert_eq!(payload["mediaType"], serde_json::Value::Null);
+    }
+}
@@ -0,0 +1,189 @@
+//! Export of images as [OCI Image
+//! Layout](https://github.com/opencontainers/image-spec/blob/main/image-layout.md) tarballs.
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+use bytes::Bytes;
+use futures::stream::{self, StreamExt, TryStreamExt};
+use oci_spec::image::{Descriptor, ImageIndex, MediaType};
+
+use crate::errors::{Error, Result};
+use crate::oci_digest::OciDigest;
+use crate::registry::{BoxedBlobStore, ManifestRef, ManifestSpec, RepositoryStore, StreamableBody};
+
+const BLOCK_SIZE: usize = 512;
+
+/// Assemble an OCI Image Layout tarball of the image manifest referenced by `reference`, along
+/// with its config and layers. Returns `None` if the manifest doesn't exist.
+///
+/// Only the manifest is read into memory; blobs are streamed from the [`BlobStore`] one at a
+/// time as the tarball is consumed.
+///
+/// [`BlobStore`]: crate::registry::BlobStore
+pub(crate) async fn export_image<R: RepositoryStore + ?Sized>(
+    repository: &R,
+    reference: &ManifestRef,
+) -> Result<Option<StreamableBody>> {
+    let (manifest, body) = match repository.get_manifest_store().get(reference).await? {
+        Some(m) => m,
+        None => return Ok(None),
+    };
+    let bytes: Bytes = body
+        .try_fold(Vec::new(), |mut acc, bs| async move {
+            acc.extend_from_slice(&bs);
+            Ok(acc)
+        })
+        .await
+        .map_err(|e| Error::BackendError(format!("error reading manifest: {e:?}")))?
+        .into();
+
+    let image = match ManifestSpec::try_from(&bytes)? {
+        ManifestSpec::Image(image) => image,
+        ManifestSpec::Index(_) => {
+            return Err(Error::Unsupported(Some(
+                "only image manifests can be exported".to_string(),
+            )))
+        }
+    };
+
+    let mut descriptor = Descriptor::new(
+        manifest
+            .media_type()
+            .clone()
+            .or_else(|| image.media_type().clone())
+            .unwrap_or(MediaType::ImageManifest),
+        bytes.len() as i64,
+        String::from(manifest.digest()),
+    );
+    if let ManifestRef::Tag(tag) = reference {
+        descriptor.set_annotations(Some(HashMap::from([(
+            "org.opencontainers.image.ref.name".to_string(),
+            tag.clone(),
+        )])));
+    }
+    let mut index = ImageIndex::default();
+    index.set_media_type(Some(MediaType::ImageIndex));
+    index.set_manifests(vec![descriptor]);
+    let index = serde_json::to_vec(&index)
+        .map_err(|e| Error::BackendError(format!("error serializing index: {e:?}")))?;
+
+    let entries = vec![
+        file(
+            "oci-layout",
+            Bytes::from_static(br#"{"imageLayoutVersion":"1.0.0"}"#),
+        )?,
+        file("index.json", Bytes::from(index))?,
+        file(&blob_path(manifest.digest()), bytes)?,
+    ];
+
+    // layers shared with the config or with each other are only stored once
+    let mut seen = HashSet::new();
+    let blobs = std::iter::once(image.config())
+        .chain(image.layers())
+        .filter(|d| seen.insert(d.digest().clone()))
+        .map(|d| OciDigest::try_from(d.digest().as_str()))
+        .collect::<Result<Vec<OciDigest>>>()?;
+
+    let blob_store = std::sync::Arc::new(repository.get_blob_store());
+    let blob_entries = stream::iter(blobs)
+        .then(move |digest| blob(blob_store.clone(), digest))
+        .try_flatten();
+    // archives end with two zeroed blocks
+    let end = stream::once(async { Ok(Bytes::from(vec![0; BLOCK_SIZE * 2])) });
+
+    Ok(Some(
+        stream::iter(entries)
+            .flatten()
+            .chain(blob_entries)
+            .chain(end)
+            .boxed(),
+    ))
+}
+
+/// Path of the given blob in the image layout, eg `blobs/sha256/<hex>`.
+fn blob_path(digest: &OciDigest) -> String {
+    format!("blobs/{}", String::from(digest).replacen(':', "/", 1))
+}
+
+/// Tar entry for a file with the given contents.
+fn file(path: &str, contents: Bytes) -> Result<StreamableBody> {
+    let header = header(path, contents.len() as u64)?;
+    let padding = padding(contents.len() as u64);
+    Ok(stream::iter([Ok(header), Ok(contents), Ok(padding)]).boxed())
+}
+
+/// Tar entry for the given blob, streamed from the [`BlobStore`].
+///
+/// [`BlobStore`]: crate::registry::BlobStore
+async fn blob(
+    blob_store: std::sync::Arc<BoxedBlobStore>,
+    digest: OciDigest,
+) -> std::result::Result<StreamableBody, Box<dyn std::error::Error + Send + Sync>> {
+    let (blob, body) = blob_store
+        .get(&digest)
+        .await?
+        .ok_or_else(|| Error::BlobUnknown(Some(String::from(&digest))))?;
+    let size = blob.bytes_on_disk();
+    let header = header(&blob_path(&digest), size)?;
+    Ok(stream::once(async { Ok(header) })
+        .chain(body)
+        .chain(stream::once(async move { Ok(padding(size)) }))
+        .boxed())
+}
+
+/// ustar header for a regular file.
+///
+/// Paths that don't fit in the header's 100 byte name field, such as those of sha512 blobs, are
+/// preceded by a pax extended header carrying the full path.
+fn header(path: &str, size: u64) -> Result<Bytes> {
+    // the size field holds 11 octal digits
+    if size > 0o77777777777 {
+        return Err(Error::BackendError(format!(
+            "{path} is too large to export: {size} bytes"
+        )));
+    }
+    if path.len() <= 100 {
+        return Ok(Bytes::copy_from_slice(&ustar_header(path, size, b'0')));
+    }
+
+    // each pax record is prefixed with its own length in bytes, including the length itself
+    let record_len = |digits: usize| digits + " path=\n".len() + path.len();
+    let mut digits = 1;
+    while record_len(digits).to_string().len() != digits {
+        digits += 1;
+    }
+    let record = format!("{} path={path}\n", record_len(digits));
+
+    let mut blocks = Vec::with_capacity(BLOCK_SIZE * 3 + record.len());
+    blocks.extend_from_slice(&ustar_header("PaxHeader", record.len() as u64, b'x'));
+    blocks.extend_from_slice(record.as_bytes());
+    blocks.extend_from_slice(&padding(record.len() as u64));
+    blocks.extend_from_slice(&ustar_header(&path[..100], size, b'0'));
+    Ok(Bytes::from(blocks))
+}
+
+/// Single ustar header block for an entry of the given type, with a name of at most 100 bytes.
+fn ustar_header(name: &str, size: u64, entry_type: u8) -> [u8; BLOCK_SIZE] {
+    let mut header = [0u8; BLOCK_SIZE];
+    header[..name.len()].copy_from_slice(name.as_bytes());
+    header[100..108].copy_from_slice(b"0000644\0");
+    header[108..116].copy_from_slice(b"0000000\0");
+    header[116..124].copy_from_slice(b"0000000\0");
+    header[124..136].copy_from_slice(format!("{size:011o}\0").as_bytes());
+    header[136..148].copy_from_slice(b"00000000000\0");
+    header[156] = entry_type;
+    header[257..265].copy_from_slice(b"ustar\x0000");
+
+    // the checksum is calculated with the checksum field itself filled with spaces
+    header[148..156].copy_from_slice(b"        ");
+    let checksum: u32 = header.iter().map(|b| *b as u32).sum();
+    header[148..156].copy_from_slice(format!("{checksum:06o}\0 ").as_bytes());
+
+    header
+}
+
+/// Zeroes padding a file of the given size out to a whole number of blocks.
+fn padding(size: u64) -> Bytes {
+    let remainder = size as usize % BLOCK_SIZE;
+    Bytes::from(vec![0; (BLOCK_SIZE - remainder) % BLOCK_SIZE])
+}
@@ -10,10 +10,21 @@ pub mod errors;
 pub use errors::{DistributionErrorCode, PortfolioErrorCode, Error, Result};
 
 mod oci_digest;
-pub use oci_digest::{DigestState, Digester, OciDigest};
+pub use oci_digest::{
+    register_digest_algorithm, DigestAlgorithm, DigestState, Digester, OciDigest, ResumableDigest,
+};
+
+pub mod events;
+
+mod export;
+
+#[cfg(feature = "postgres")]
+mod postgres;
 
 pub mod registry;
 
 mod stream;
 pub use stream::ChunkedBody;
 pub use stream::DigestBody;
+pub use stream::RechunkedBody;
+pub use stream::VerifiedBody;
@@ -1,7 +1,11 @@
-use digest::{Digest, DynDigest};
+use std::sync::{Arc, RwLock};
+
+use digest::generic_array::GenericArray;
+use digest::Digest;
+use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 use sha2::Sha256;
-use sha2::Sha512;
+use sha2::{compress256, compress512};
 
 use crate::{Error, Result};
 
@@ -9,10 +13,11 @@ use crate::{Error, Result};
 /// Digest](https://github.com/opencontainers/image-spec/blob/main/descriptor.md#digests).
 ///
 /// Used throughout [`portfolio_core`] and related crates to address various types of manifest and
-/// blob.
+/// blob. Only digests using an algorithm known to the [`DigestAlgorithm`] registry can be
+/// constructed.
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub struct OciDigest {
-    algorithm: RegisteredImageSpecAlgorithm,
+    algorithm: String,
     encoded: String,
 }
 
@@ -32,16 +37,12 @@ impl TryFrom<&str> for OciDigest {
             Some(_) => return Err(Error::InvalidDigest(s.to_string())),
             None => return Err(Error::InvalidDigest(s.to_string())),
         };
-        let algorithm = match algo {
-            "sha256" => RegisteredImageSpecAlgorithm::Sha256,
-            "sha512" => RegisteredImageSpecAlgorithm::Sha512,
-            _ => {
-                return Err(Error::InvalidDigest(s.to_string()));
-            }
-        };
+        if lookup_digest_algorithm(algo).is_none() {
+            return Err(Error::InvalidDigest(s.to_string()));
+        }
 
         Ok(Self {
-            algorithm,
+            algorithm: algo.to_string(),
             encoded: encoded.to_string(),
         })
     }
@@ -54,7 +55,7 @@ impl From<&[u8]> for OciDigest {
         let s = hasher.finalize();
 
         Self {
-            algorithm: RegisteredImageSpecAlgorithm::Sha256,
+            algorithm: String::from("sha256"),
             encoded: format!("{:x}", s),
         }
     }
@@ -62,50 +63,93 @@ impl From<&[u8]> for OciDigest {
 
 impl From<OciDigest> for String {
     fn from(d: OciDigest) -> String {
-        format!("{}:{}", String::from(&d.algorithm), d.encoded)
+        format!("{}:{}", d.algorithm, d.encoded)
     }
 }
 
 impl From<&OciDigest> for String {
     fn from(d: &OciDigest) -> String {
-        format!("{}:{}", String::from(&d.algorithm), d.encoded)
+        format!("{}:{}", d.algorithm, d.encoded)
     }
 }
 
 impl OciDigest {
+    /// Name of the algorithm used to calculate this digest, eg `sha256`.
+    pub fn algorithm(&self) -> String {
+        self.algorithm.clone()
+    }
+
     pub fn digester(&self) -> Digester {
-        match self.algorithm {
-            RegisteredImageSpecAlgorithm::Sha256 => Digester::new(Box::new(Sha256::new())),
-            RegisteredImageSpecAlgorithm::Sha512 => Digester::new(Box::new(Sha512::new())),
-        }
+        let algorithm = lookup_digest_algorithm(&self.algorithm)
+            .expect("digests are only constructed for registered algorithms");
+        Digester::new(&[algorithm])
     }
 }
 
-#[derive(Clone, Debug, Eq, Hash, PartialEq)]
-enum RegisteredImageSpecAlgorithm {
-    Sha256,
-    Sha512,
+/// A digest algorithm that can be used in [`OciDigest`]s.
+///
+/// `sha256` and `sha512` are always available; other algorithms can be made available with
+/// [`register_digest_algorithm`].
+pub trait DigestAlgorithm: Send + Sync {
+    /// Name of the algorithm as it appears in digests, eg `sha256`.
+    fn name(&self) -> &str;
+
+    /// Begin calculating a new digest.
+    fn new_digester(&self) -> Box<dyn ResumableDigest>;
+
+    /// Resume calculating a digest from state previously returned by [`ResumableDigest::save`],
+    /// or `None` if the state can't be restored.
+    fn resume_digester(&self, state: serde_json::Value) -> Option<Box<dyn ResumableDigest>>;
 }
 
-impl TryFrom<&str> for RegisteredImageSpecAlgorithm {
-    type Error = Error;
+/// In-progress digest calculation for a single [`DigestAlgorithm`] whose state can be saved
+/// between requests.
+pub trait ResumableDigest: Send + Sync {
+    fn update(&mut self, data: &[u8]);
 
-    fn try_from(a: &str) -> Result<Self> {
-        match a {
-            "sha512" => Ok(RegisteredImageSpecAlgorithm::Sha512),
-            "sha256" => Ok(RegisteredImageSpecAlgorithm::Sha256),
-            s => Err(Error::UnsupportedDigestAlgorithm(String::from(s))),
-        }
-    }
+    /// Encoded portion of the digest of all bytes consumed so far, eg the hex-encoded hash.
+    fn finalize(&self) -> String;
+
+    /// Serializable state from which [`DigestAlgorithm::resume_digester`] can resume the
+    /// calculation.
+    fn save(&self) -> serde_json::Value;
 }
 
-impl From<&RegisteredImageSpecAlgorithm> for String {
-    fn from(a: &RegisteredImageSpecAlgorithm) -> String {
-        match a {
-            RegisteredImageSpecAlgorithm::Sha512 => String::from("sha512"),
-            RegisteredImageSpecAlgorithm::Sha256 => String::from("sha256"),
-        }
+static DIGEST_ALGORITHMS: Lazy<RwLock<Vec<Arc<dyn DigestAlgorithm>>>> = Lazy::new(|| {
+    RwLock::new(vec![
+        Arc::new(Sha2Algorithm {
+            name: "sha256",
+            initial: HashState::sha256(),
+        }),
+        Arc::new(Sha2Algorithm {
+            name: "sha512",
+            initial: HashState::sha512(),
+        }),
+    ])
+});
+
+/// Make `algorithm` available for use in [`OciDigest`]s.
+///
+/// Returns false, leaving the registry unchanged, if an algorithm with the same name is already
+/// registered.
+pub fn register_digest_algorithm(algorithm: Arc<dyn DigestAlgorithm>) -> bool {
+    let mut algorithms = DIGEST_ALGORITHMS
+        .write()
+        .expect("digest algorithm registry lock should never be poisoned");
+    if algorithms.iter().any(|a| a.name() == algorithm.name()) {
+        return false;
     }
+    algorithms.push(algorithm);
+    true
+}
+
+fn lookup_digest_algorithm(name: &str) -> Option<Arc<dyn DigestAlgorithm>> {
+    DIGEST_ALGORITHMS
+        .read()
+        .expect("digest algorithm registry lock should never be poisoned")
+        .iter()
+        .find(|a| a.name() == name)
+        .cloned()
 }
 
 /// Wrapper type around resumable digest algorithms.
@@ -113,62 +157,361 @@ impl From<&RegisteredImageSpecAlgorithm> for String {
 /// Provides access to the underlying [`DigestState`] and number of bytes consumed so far.
 /// Primarily used by [`super::DigestBody`] to incrementally calculate blob digests across multiple
 /// upload chunks.
+///
+/// A digester may track more than one algorithm at a time, for chunked uploads that don't learn
+/// which one the client used until the upload is finalized; the [`Default`] digester only tracks
+/// `sha256`.
 pub struct Digester {
-    // TODO: once https://github.com/RustCrypto/traits/pull/1078 is merged we should be able to
-    // finish implementing chunked digest calculation
-    #[allow(dead_code)]
-    digester: Box<dyn DynDigest + 'static + Send>,
+    // empty when resuming from a DigestState recorded before hash state was tracked, in which case
+    // the digest of the content cannot be known.
+    hashes: Vec<(String, Box<dyn ResumableDigest>)>,
     bytes: u64,
 }
 
 impl Digester {
-    pub fn new(digester: Box<dyn DynDigest + 'static + Send>) -> Self {
-        Self { digester, bytes: 0 }
+    fn new(algorithms: &[Arc<dyn DigestAlgorithm>]) -> Self {
+        Self {
+            hashes: algorithms
+                .iter()
+                .map(|a| (a.name().to_string(), a.new_digester()))
+                .collect(),
+            bytes: 0,
+        }
+    }
+
+    pub fn sha256() -> Self {
+        let algorithm = lookup_digest_algorithm("sha256").expect("sha256 is always registered");
+        Self::new(&[algorithm])
+    }
+
+    pub fn sha512() -> Self {
+        let algorithm = lookup_digest_algorithm("sha512").expect("sha512 is always registered");
+        Self::new(&[algorithm])
+    }
+
+    /// Track each of the named algorithms, reporting the first of them from [`Digester::digest`],
+    /// or just `sha256` if none are named.
+    pub fn for_algorithms<S: AsRef<str>>(names: &[S]) -> Result<Self> {
+        if names.is_empty() {
+            return Ok(Self::default());
+        }
+        let algorithms = names
+            .iter()
+            .map(|name| {
+                lookup_digest_algorithm(name.as_ref())
+                    .ok_or_else(|| Error::UnsupportedDigestAlgorithm(name.as_ref().to_string()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self::new(&algorithms))
     }
 
     pub fn update(&mut self, data: &[u8]) {
         self.bytes += data.len() as u64;
+        for (_, hash) in &mut self.hashes {
+            hash.update(data);
+        }
     }
 
     #[inline]
     pub fn bytes(&self) -> u64 {
         self.bytes
     }
+
+    /// Calculate the digest of all bytes consumed so far using the first algorithm tracked by this
+    /// digester, or `None` if the hash state was lost.
+    pub fn digest(&self) -> Option<OciDigest> {
+        self.hashes.first().map(|(algorithm, hash)| OciDigest {
+            algorithm: algorithm.clone(),
+            encoded: hash.finalize(),
+        })
+    }
+
+    /// Calculate the digest of all bytes consumed so far using the same algorithm as `expected`,
+    /// or `None` if that algorithm isn't tracked by this digester.
+    pub fn digest_for(&self, expected: &OciDigest) -> Option<OciDigest> {
+        self.hashes
+            .iter()
+            .find(|(algorithm, _)| *algorithm == expected.algorithm)
+            .map(|(algorithm, hash)| OciDigest {
+                algorithm: algorithm.clone(),
+                encoded: hash.finalize(),
+            })
+    }
 }
 
 impl Default for Digester {
     fn default() -> Self {
-        Self::new(Box::new(Sha256::new()))
+        Self::sha256()
     }
 }
 
 impl From<Digester> for DigestState {
     fn from(d: Digester) -> DigestState {
-        DigestState { bytes: d.bytes }
+        DigestState {
+            bytes: d.bytes,
+            hashes: d
+                .hashes
+                .into_iter()
+                .map(|(algorithm, hash)| SavedHash::Tagged {
+                    algorithm,
+                    state: hash.save(),
+                })
+                .collect(),
+            hash: None,
+            buffer: Vec::new(),
+        }
+    }
+}
+
+impl From<DigestState> for Digester {
+    fn from(s: DigestState) -> Digester {
+        let bytes = s.bytes;
+        let mut hashes: Vec<(String, Box<dyn ResumableDigest>)> = Vec::new();
+        for saved in s.hashes {
+            match saved {
+                SavedHash::Tagged { algorithm, state } => {
+                    match lookup_digest_algorithm(&algorithm).and_then(|a| a.resume_digester(state))
+                    {
+                        Some(hash) => hashes.push((algorithm, hash)),
+                        None => tracing::warn!("unable to resume {algorithm} digest"),
+                    }
+                }
+                SavedHash::Untagged(partial) => hashes.push(partial.with_bytes(bytes)),
+            }
+        }
+
+        if hashes.is_empty() {
+            match s.hash {
+                // recorded before more than one algorithm could be tracked
+                Some(hash) => hashes.push(
+                    PartialHash {
+                        hash,
+                        buffer: s.buffer,
+                        bytes: 0,
+                    }
+                    .with_bytes(bytes),
+                ),
+                // nothing has been hashed yet, so there is nothing to lose by starting fresh
+                None if bytes == 0 => return Digester::default(),
+                None => (),
+            }
+        }
+
+        Digester { hashes, bytes }
     }
 }
 
 /// Serializable state of the underlying cryptographic digest algorithms managed by [`Digester`].
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct DigestState {
     bytes: u64,
+    #[serde(default)]
+    hashes: Vec<SavedHash>,
+
+    // single-algorithm state recorded by earlier versions; only read so that upload sessions
+    // started before an upgrade can still be resumed
+    #[serde(default, skip_serializing)]
+    hash: Option<HashState>,
+    #[serde(default, skip_serializing)]
+    buffer: Vec<u8>,
+}
+
+impl Default for DigestState {
+    fn default() -> Self {
+        Digester::default().into()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+enum SavedHash {
+    Tagged {
+        algorithm: String,
+        state: serde_json::Value,
+    },
+    // built-in hash state recorded before algorithms were pluggable
+    Untagged(PartialHash),
+}
+
+/// Built-in [`DigestAlgorithm`] for the sha2 family.
+///
+/// The hash state is driven directly through the block compression functions exposed by
+/// [`sha2`] rather than through [`sha2::Digest`] since the latter provides no way to serialize
+/// an in-progress hash between requests.
+struct Sha2Algorithm {
+    name: &'static str,
+    initial: HashState,
+}
+
+impl DigestAlgorithm for Sha2Algorithm {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn new_digester(&self) -> Box<dyn ResumableDigest> {
+        Box::new(PartialHash {
+            hash: self.initial.clone(),
+            buffer: Vec::new(),
+            bytes: 0,
+        })
+    }
+
+    fn resume_digester(&self, state: serde_json::Value) -> Option<Box<dyn ResumableDigest>> {
+        let partial: PartialHash = serde_json::from_value(state).ok()?;
+        if partial.hash.name() != self.name {
+            return None;
+        }
+        Some(Box::new(partial))
+    }
+}
+
+/// Hash state for a single sha2 algorithm along with any trailing bytes that don't yet fill a
+/// block.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct PartialHash {
+    hash: HashState,
+    #[serde(default)]
+    buffer: Vec<u8>,
+    #[serde(default)]
+    bytes: u64,
+}
+
+impl PartialHash {
+    /// Name and boxed state of a partial hash recorded without its own byte count.
+    fn with_bytes(mut self, bytes: u64) -> (String, Box<dyn ResumableDigest>) {
+        self.bytes = bytes;
+        (self.hash.name().to_string(), Box::new(self))
+    }
+}
+
+impl ResumableDigest for PartialHash {
+    fn update(&mut self, data: &[u8]) {
+        self.bytes += data.len() as u64;
+        self.buffer.extend_from_slice(data);
+        let consumed = self.hash.compress(&self.buffer);
+        self.buffer.drain(..consumed);
+    }
+
+    fn finalize(&self) -> String {
+        self.hash.finalize(&self.buffer, self.bytes)
+    }
+
+    fn save(&self) -> serde_json::Value {
+        serde_json::to_value(self).expect("hash state should always be serializable")
+    }
+}
+
+const SHA256_IV: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+const SHA512_IV: [u64; 8] = [
+    0x6a09e667f3bcc908,
+    0xbb67ae8584caa73b,
+    0x3c6ef372fe94f82b,
+    0xa54ff53a5f1d36f1,
+    0x510e527fade682d1,
+    0x9b05688c2b3e6c1f,
+    0x1f83d9abfb41bd6b,
+    0x5be0cd19137e2179,
+];
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum HashState {
+    Sha256([u32; 8]),
+    Sha512([u64; 8]),
+}
+
+impl HashState {
+    fn sha256() -> Self {
+        HashState::Sha256(SHA256_IV)
+    }
+
+    fn sha512() -> Self {
+        HashState::Sha512(SHA512_IV)
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            HashState::Sha256(_) => "sha256",
+            HashState::Sha512(_) => "sha512",
+        }
+    }
+
+    fn block_size(&self) -> usize {
+        match self {
+            HashState::Sha256(_) => 64,
+            HashState::Sha512(_) => 128,
+        }
+    }
+
+    /// Compress as many whole blocks from `data` as possible, returning the number of bytes
+    /// consumed.
+    fn compress(&mut self, data: &[u8]) -> usize {
+        let block_size = self.block_size();
+        let whole = data.len() - data.len() % block_size;
+        match self {
+            HashState::Sha256(state) => {
+                for block in data[..whole].chunks_exact(block_size) {
+                    compress256(state, &[GenericArray::clone_from_slice(block)]);
+                }
+            }
+            HashState::Sha512(state) => {
+                for block in data[..whole].chunks_exact(block_size) {
+                    compress512(state, &[GenericArray::clone_from_slice(block)]);
+                }
+            }
+        }
+        whole
+    }
+
+    /// Apply the final padding to a copy of the hash state and render the resulting hash.
+    fn finalize(&self, remainder: &[u8], bytes: u64) -> String {
+        let mut hash = self.clone();
+        let block_size = hash.block_size();
+        // the message length is stored in the last 8 bytes for sha256 and the last 16 for sha512
+        let length_size = block_size / 8;
+
+        let mut tail = remainder.to_vec();
+        tail.push(0x80);
+        while tail.len() % block_size != block_size - length_size {
+            tail.push(0);
+        }
+        let bits = (bytes as u128) * 8;
+        tail.extend_from_slice(&bits.to_be_bytes()[16 - length_size..]);
+        hash.compress(&tail);
+
+        match hash {
+            HashState::Sha256(state) => state.iter().map(|w| format!("{w:08x}")).collect(),
+            HashState::Sha512(state) => state.iter().map(|w| format!("{w:016x}")).collect(),
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
     use rstest::*;
+    use sha2::Sha512;
 
     use super::*;
 
     #[rstest]
     #[case::meow("sha256:meow", Ok(OciDigest {
-        algorithm: RegisteredImageSpecAlgorithm::Sha256,
+        algorithm: String::from("sha256"),
         encoded: String::from("meow"),
     }))]
     #[case::meow("sha512:meow", Ok(OciDigest {
-        algorithm: RegisteredImageSpecAlgorithm::Sha512,
+        algorithm: String::from("sha512"),
         encoded: String::from("meow"),
     }))]
+    #[case::sha512_hex(
+        "sha512:ee26b0dd4af7e749aa1a8ee3c10ae9923f618980772e473f8819a5d4940e0db27ac185f8a0e1d5f84f88bc887fd67b143732c304cc5fa9ad8e6f57f50028a8ff",
+        Ok(OciDigest {
+            algorithm: String::from("sha512"),
+            encoded: String::from("ee26b0dd4af7e749aa1a8ee3c10ae9923f618980772e473f8819a5d4940e0db27ac185f8a0e1d5f84f88bc887fd67b143732c304cc5fa9ad8e6f57f50028a8ff"),
+        }),
+    )]
     #[case::meow("sha666:meow", Err(Error::InvalidDigest(String::from("sha666:meow"))))]
     #[case::meow("sha256meow", Err(Error::InvalidDigest(String::from("sha256meow"))))]
     #[case::meow("sha256:", Err(Error::InvalidDigest(String::from("sha256:"))))]
@@ -192,4 +535,161 @@ mod test {
             }
         }
     }
+
+    #[rstest]
+    #[case::sha256("sha256:")]
+    #[case::sha512("sha512:")]
+    fn digester_resumes_across_chunks(#[case] prefix: &str) {
+        let data: Vec<u8> = (0..1000u32).map(|i| (i % 251) as u8).collect();
+        let expected = match prefix {
+            "sha256:" => format!("{prefix}{:x}", Sha256::digest(&data)),
+            _ => format!("{prefix}{:x}", Sha512::digest(&data)),
+        };
+        let expected: OciDigest = expected.as_str().try_into().unwrap();
+
+        // split the content at awkward offsets, serializing the state between each piece as the
+        // upload session would
+        let mut digester = expected.digester();
+        for piece in [&data[..1], &data[1..130], &data[130..131], &data[131..]] {
+            digester.update(piece);
+            let state = serde_json::to_value(DigestState::from(digester)).unwrap();
+            digester = serde_json::from_value::<DigestState>(state).unwrap().into();
+        }
+
+        assert_eq!(digester.bytes(), data.len() as u64);
+        assert_eq!(digester.digest(), Some(expected));
+    }
+
+    #[test]
+    fn digester_without_hash_state() {
+        let legacy: DigestState = serde_json::from_str(r#"{"bytes": 10}"#).unwrap();
+        assert_eq!(Digester::from(legacy).digest(), None);
+
+        let empty: DigestState = serde_json::from_str(r#"{"bytes": 0}"#).unwrap();
+        assert_eq!(
+            Digester::from(empty).digest(),
+            Some(OciDigest::from(&b""[..])),
+        );
+    }
+
+    #[rstest]
+    #[case::sha256("sha256:")]
+    #[case::sha512("sha512:")]
+    fn digester_tracks_requested_algorithms(#[case] prefix: &str) {
+        let data: Vec<u8> = (0..1000u32).map(|i| (i % 251) as u8).collect();
+        let expected = match prefix {
+            "sha256:" => format!("{prefix}{:x}", Sha256::digest(&data)),
+            _ => format!("{prefix}{:x}", Sha512::digest(&data)),
+        };
+        let expected: OciDigest = expected.as_str().try_into().unwrap();
+
+        let mut digester = Digester::for_algorithms(&["sha256", "sha512"]).unwrap();
+        for piece in [&data[..1], &data[1..130], &data[130..]] {
+            digester.update(piece);
+            let state = serde_json::to_value(DigestState::from(digester)).unwrap();
+            digester = serde_json::from_value::<DigestState>(state).unwrap().into();
+        }
+
+        assert_eq!(digester.digest_for(&expected), Some(expected));
+    }
+
+    #[test]
+    fn default_digester_tracks_only_sha256() {
+        let mut digester = Digester::default();
+        digester.update(b"abc");
+
+        assert_eq!(digester.digest(), Some(OciDigest::from(&b"abc"[..])));
+        let sha512: OciDigest = format!("sha512:{:x}", Sha512::digest(b"abc"))
+            .as_str()
+            .try_into()
+            .unwrap();
+        assert_eq!(digester.digest_for(&sha512), None);
+        assert!(matches!(
+            Digester::for_algorithms(&["sha256", "md5"]),
+            Err(Error::UnsupportedDigestAlgorithm(name)) if name == "md5"
+        ));
+    }
+
+    #[test]
+    fn digester_resumes_single_algorithm_state() {
+        // state as recorded before digesters could track more than one algorithm, after consuming
+        // b"a"
+        let legacy: DigestState = serde_json::from_value(serde_json::json!({
+            "bytes": 1,
+            "hash": { "sha256": SHA256_IV },
+            "buffer": [0x61],
+        }))
+        .unwrap();
+        let mut digester = Digester::from(legacy);
+        digester.update(b"bc");
+
+        let expected = OciDigest::from(&b"abc"[..]);
+        assert_eq!(digester.digest_for(&expected), Some(expected));
+        let sha512: OciDigest = format!("sha512:{:x}", Sha512::digest(b"abc"))
+            .as_str()
+            .try_into()
+            .unwrap();
+        assert_eq!(digester.digest_for(&sha512), None);
+    }
+
+    /// Sums the bytes of the content, which is just enough of a digest to check that the registry
+    /// wiring works.
+    struct ByteSum;
+
+    #[derive(Serialize, Deserialize)]
+    struct ByteSumState(u64);
+
+    impl DigestAlgorithm for ByteSum {
+        fn name(&self) -> &str {
+            "bytesum"
+        }
+
+        fn new_digester(&self) -> Box<dyn ResumableDigest> {
+            Box::new(ByteSumState(0))
+        }
+
+        fn resume_digester(&self, state: serde_json::Value) -> Option<Box<dyn ResumableDigest>> {
+            let state: ByteSumState = serde_json::from_value(state).ok()?;
+            Some(Box::new(state))
+        }
+    }
+
+    impl ResumableDigest for ByteSumState {
+        fn update(&mut self, data: &[u8]) {
+            self.0 += data.iter().map(|b| *b as u64).sum::<u64>();
+        }
+
+        fn finalize(&self) -> String {
+            format!("{:x}", self.0)
+        }
+
+        fn save(&self) -> serde_json::Value {
+            serde_json::to_value(self).unwrap()
+        }
+    }
+
+    #[test]
+    fn registered_algorithm_digests_content() {
+        assert!(OciDigest::try_from("bytesum:c6").is_err());
+        assert!(register_digest_algorithm(Arc::new(ByteSum)));
+        assert!(!register_digest_algorithm(Arc::new(ByteSum)));
+
+        // b"abc" sums to 0x61 + 0x62 + 0x63 = 0x126
+        let expected = OciDigest::try_from("bytesum:126").unwrap();
+        assert_eq!(expected.algorithm(), "bytesum");
+
+        let mut digester = expected.digester();
+        digester.update(b"abc");
+        assert_eq!(digester.digest(), Some(expected.clone()));
+
+        // upload sessions can track it alongside the built-in algorithms across chunks
+        let mut digester = Digester::for_algorithms(&["sha256", "bytesum"]).unwrap();
+        for piece in [&b"a"[..], &b"bc"[..]] {
+            digester.update(piece);
+            let state = serde_json::to_value(DigestState::from(digester)).unwrap();
+            digester = serde_json::from_value::<DigestState>(state).unwrap().into();
+        }
+        assert_eq!(digester.digest_for(&expected), Some(expected));
+        assert_eq!(digester.digest(), Some(OciDigest::from(&b"abc"[..])));
+    }
 }
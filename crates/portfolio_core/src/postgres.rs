@@ -0,0 +1,90 @@
+//! Conversions allowing [`OciDigest`] to be bound to and decoded from Postgres queries directly,
+//! both through [`sqlx`] and through [`sea_query`] query builders.
+//!
+//! Digests are stored in their string form, eg `sha256:<hex>`, in `VARCHAR` columns.
+use sea_query::{ArrayType, ColumnType, Nullable, Value, ValueType, ValueTypeErr};
+use sqlx::encode::IsNull;
+use sqlx::error::BoxDynError;
+use sqlx::postgres::{PgArgumentBuffer, PgTypeInfo, PgValueRef};
+use sqlx::{Decode, Encode, Postgres, Type};
+
+use crate::OciDigest;
+
+impl Type<Postgres> for OciDigest {
+    fn type_info() -> PgTypeInfo {
+        <String as Type<Postgres>>::type_info()
+    }
+
+    fn compatible(ty: &PgTypeInfo) -> bool {
+        <String as Type<Postgres>>::compatible(ty)
+    }
+}
+
+impl Encode<'_, Postgres> for OciDigest {
+    fn encode_by_ref(&self, buf: &mut PgArgumentBuffer) -> IsNull {
+        <String as Encode<Postgres>>::encode(String::from(self), buf)
+    }
+}
+
+impl<'r> Decode<'r, Postgres> for OciDigest {
+    fn decode(value: PgValueRef<'r>) -> Result<Self, BoxDynError> {
+        let s = <&str as Decode<Postgres>>::decode(value)?;
+        Ok(s.try_into()?)
+    }
+}
+
+impl From<&OciDigest> for Value {
+    fn from(digest: &OciDigest) -> Value {
+        Value::String(Some(Box::new(String::from(digest))))
+    }
+}
+
+impl From<OciDigest> for Value {
+    fn from(digest: OciDigest) -> Value {
+        Value::from(&digest)
+    }
+}
+
+impl Nullable for OciDigest {
+    fn null() -> Value {
+        Value::String(None)
+    }
+}
+
+impl ValueType for OciDigest {
+    fn try_from(v: Value) -> Result<Self, ValueTypeErr> {
+        match v {
+            Value::String(Some(s)) => s.as_str().try_into().map_err(|_| ValueTypeErr),
+            _ => Err(ValueTypeErr),
+        }
+    }
+
+    fn type_name() -> String {
+        String::from("OciDigest")
+    }
+
+    fn array_type() -> ArrayType {
+        ArrayType::String
+    }
+
+    fn column_type() -> ColumnType {
+        ColumnType::String(None)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn value_round_trip() {
+        let digest = OciDigest::from(b"meow".as_slice());
+
+        let value = Value::from(&digest);
+        assert_eq!(value, Value::from(String::from(&digest)));
+        assert_eq!(value.unwrap::<OciDigest>(), digest);
+
+        assert_eq!(Value::from(None::<OciDigest>), Value::String(None));
+        assert!(<OciDigest as ValueType>::try_from(Value::from("meow")).is_err());
+    }
+}
@@ -27,23 +27,24 @@
 //! Bulk data storage is handled via the [`postgres_objectstore`] crate, which itself abstracts
 //! over different kinds of bulk data store via its
 //! [`ObjectStore`](postgres_objectstore::ObjectStore) trait.
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use async_trait::async_trait;
 use bytes::Bytes;
 use futures::stream::BoxStream;
 use hyper::body::Body;
 use oci_spec::distribution::TagList;
-use oci_spec::image::{Descriptor, ImageIndex, ImageManifest, MediaType};
+use oci_spec::image::{Descriptor, ImageIndex, ImageManifest, MediaType, Platform};
 use once_cell::sync::Lazy;
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use crate::errors::{Error, Result};
 use crate::oci_digest::OciDigest;
 
 /// Alias to simplify method signatures on traits and implementations.
-type StreamableBody =
+pub type StreamableBody =
     BoxStream<'static, std::result::Result<Bytes, Box<dyn std::error::Error + Send + Sync>>>;
 
 /// Alias to simplify method signatures on traits and implementations.
@@ -86,6 +87,61 @@ pub trait RepositoryStoreManager: Send + Sync + 'static {
     /// Create new [`RepositoryStore`] with the given name. This name corresponds to the
     /// `<name>` in distribution-spec API endpoints like `/v2/<name>/blobs/<digest>`.
     async fn create(&self, name: &str) -> Result<BoxedRepositoryStore>;
+
+    /// Delete the [`RepositoryStore`] with the given name along with all of its tags and
+    /// manifests. Blobs that are no longer referenced by any repository are deleted as well.
+    async fn delete(&self, name: &str) -> Result<()>;
+
+    /// List the names of repositories in lexical order. `n` limits the number of names returned
+    /// and `last` is a cursor such that only names that sort after it are returned.
+    async fn list(&self, n: Option<i64>, last: Option<String>) -> Result<Vec<String>>;
+
+    /// List the names of repositories starting with `prefix` in lexical order, paginated like
+    /// [`RepositoryStoreManager::list`]. `prefix` is matched literally, without wildcards.
+    async fn search(
+        &self,
+        prefix: &str,
+        n: Option<i64>,
+        last: Option<String>,
+    ) -> Result<Vec<String>>;
+
+    /// Optional behaviors supported by repositories handed out by this manager.
+    fn features(&self) -> Features;
+
+    /// Set the [`Visibility`] of the repository with the given name.
+    async fn set_visibility(&self, name: &str, visibility: Visibility) -> Result<()>;
+
+    /// Check that the services repositories depend on, such as databases and object stores, are
+    /// reachable. Returns a description of the problem keyed by the name of each one that isn't.
+    async fn check_dependencies(&self) -> BTreeMap<String, String>;
+}
+
+/// Optional registry behaviors, reported to clients so that they can adapt to the registry's
+/// configuration.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Features {
+    /// Whether the referrers API is available.
+    pub referrers: bool,
+    /// Whether manifests, blobs, and repositories can be deleted.
+    pub delete: bool,
+    /// Whether blobs can be mounted from other repositories.
+    pub mount: bool,
+    /// Minimum size in bytes of all but the last chunk of a chunked upload, if any.
+    pub chunk_min_length: Option<u64>,
+    /// Maximum combined size in bytes of an image manifest's config and layers, if limited.
+    pub max_image_size: Option<u64>,
+}
+
+/// Whether a repository's content can be pulled without authenticating.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Visibility {
+    /// Anyone may pull from the repository; pushing still requires authentication.
+    Public,
+    /// Only authenticated clients may access the repository.
+    #[default]
+    Private,
 }
 
 /// Provides access to a [`ManifestStore`] and [`BlobStore`] instances for a repository.
@@ -105,6 +161,17 @@ pub trait RepositoryStore: Send + Sync + 'static {
 
     /// Return a [`UploadSessionStore`] to provide access to blobs in this repository.
     fn get_upload_session_store(&self) -> BoxedUploadSessionStore;
+
+    /// Whether this repository can be pulled from without authenticating.
+    fn visibility(&self) -> Visibility;
+
+    /// Stream the image manifest referenced by `reference`, along with its config and layers, as
+    /// an [OCI Image Layout](https://github.com/opencontainers/image-spec/blob/main/image-layout.md)
+    /// tarball. Returns `None` if the manifest doesn't exist and [`Error::Unsupported`] if it is
+    /// an index.
+    async fn export_image(&self, reference: &ManifestRef) -> Result<Option<StreamableBody>> {
+        crate::export::export_image(self, reference).await
+    }
 }
 
 /// Provides access to upload sessions.
@@ -113,11 +180,33 @@ pub trait UploadSessionStore: Send + Sync + 'static {
     /// Initiate a new blob upload session.
     async fn new_upload_session(&self) -> Result<BoxedUploadSession>;
 
+    /// Initiate a new blob upload session for content the client has declared to have the given
+    /// digest, eg when a cross-repository mount falls back to an upload. The declared digest is
+    /// reported by [`UploadSession::declared_digest`] so that completing the session with a
+    /// different digest can be rejected.
+    ///
+    /// Backends that don't record declared digests start a regular session.
+    async fn new_upload_session_for(&self, _digest: &OciDigest) -> Result<BoxedUploadSession> {
+        self.new_upload_session().await
+    }
+
     /// Get an existing blob upload session.
     async fn get_upload_session(&self, session_uuid: &Uuid) -> Result<BoxedUploadSession>;
 
     /// Delete an existing blob upload session.
     async fn delete_session(&self, session_uuid: &Uuid) -> Result<()>;
+
+    /// Get how much content has been written to an existing blob upload session so far.
+    async fn get_upload_progress(&self, session_uuid: &Uuid) -> Result<UploadProgress>;
+}
+
+/// How much content has been written to a blob upload session so far.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct UploadProgress {
+    /// Total number of bytes across all chunks written to the session.
+    pub bytes_received: u64,
+    /// Number of chunks written to the session.
+    pub chunk_count: u64,
 }
 
 /// Provides access to registry manifests.
@@ -127,22 +216,57 @@ pub trait ManifestStore: Send + Sync + 'static {
 
     async fn get(&self, key: &ManifestRef) -> Result<Option<(BoxedManifest, StreamableBody)>>;
 
+    /// Store the manifest, returning its digest. `content_type` is the Content-Type header the
+    /// manifest was pushed with, if any, to be reported verbatim by [`Manifest::content_type`].
     async fn put(
         &self,
         key: &ManifestRef,
         spec: &ManifestSpec,
         bytes: Bytes,
+        content_type: Option<&str>,
     ) -> Result<OciDigest>;
 
+    /// Return the digests of content referenced by `spec` that isn't in this repository, without
+    /// storing anything: the config and layer blobs of an image manifest, or the manifests of an
+    /// index.
+    async fn missing_references(&self, spec: &ManifestSpec) -> Result<Vec<OciDigest>>;
+
+    /// Return the manifest referenced by the given index that targets the given platform, if
+    /// any.
+    ///
+    /// Implementations should resolve the platform without retrieving every manifest referenced
+    /// by the index; the returned manifest can then be retrieved by digest.
+    async fn get_platform_manifest(
+        &self,
+        index: &ManifestRef,
+        platform: &Platform,
+    ) -> Result<Option<BoxedManifest>>;
+
+    /// Delete the manifest referred to by `key`.
+    ///
+    /// Deleting by [`ManifestRef::Tag`] only removes the tag, leaving the manifest and any other
+    /// tags pointing to it in place. Deleting by [`ManifestRef::Digest`] removes the manifest
+    /// along with all of its tags.
     async fn delete(&self, key: &ManifestRef) -> Result<()>;
 
     /// Return an ImageIndex containing a list of manifests that reference the given OciDigest.
+    ///
+    /// Referrers are sorted by digest; `n` limits the number returned and `last` is a keyset
+    /// cursor such that only referrers whose digest sorts after it are returned. Referrers pushed
+    /// while a client is paginating therefore never cause already-returned entries to be skipped
+    /// or repeated.
     async fn get_referrers(
         &self,
         subject: &OciDigest,
         artifact_type: Option<String>,
+        n: Option<i64>,
+        last: Option<String>,
     ) -> Result<ImageIndex>;
 
+    /// Return an ImageIndex listing manifests in this repository annotated with `key`, restricted
+    /// to those where the annotation has the given `value` if any. Manifests are sorted by digest.
+    async fn get_annotated(&self, key: &str, value: Option<&str>) -> Result<ImageIndex>;
+
     /// Return an OCI TagList of tags in this repository.
     async fn get_tags_list(&self, n: Option<i64>, last: Option<String>) -> Result<TagList>;
 
@@ -150,6 +274,14 @@ pub trait ManifestStore: Send + Sync + 'static {
     /// [`Error::ManifestUnknown`] if the manifest doesn't exist and an empty Vec if there are no
     /// tags for the manifest.
     async fn get_tags(&self, key: &ManifestRef) -> Result<Vec<BoxedTag>>;
+
+    /// Return the tags in this repository, sorted by name, each paired with the digest of the
+    /// manifest it references. Paginated the same way as [`ManifestStore::get_tags_list`].
+    async fn get_tag_digests(
+        &self,
+        n: Option<i64>,
+        last: Option<String>,
+    ) -> Result<Vec<(String, OciDigest)>>;
 }
 
 /// Provides access to registry blobs.
@@ -163,6 +295,16 @@ pub trait BlobStore: Send + Sync + 'static {
 
     async fn delete(&self, digest: &OciDigest) -> Result<()>;
 
+    /// Make the blob with the given digest from the `from` repository available in this
+    /// repository without re-uploading it, returning `None` if `from` doesn't contain the blob.
+    async fn mount(&self, digest: &OciDigest, from: &str) -> Result<Option<BoxedBlob>>;
+
+    /// Resume the upload session with the given UUID, returning a [`BlobWriter`] that provides
+    /// access to the session as loaded so that callers don't need to retrieve it separately.
+    ///
+    /// Should return [`Error::BlobUploadUnknown`] if the session doesn't exist and
+    /// [`Error::ContentRangeInvalid`] if `start` doesn't immediately follow the content uploaded
+    /// so far.
     async fn resume(
         &self,
         session_uuid: &Uuid,
@@ -173,11 +315,25 @@ pub trait BlobStore: Send + Sync + 'static {
 /// Implements chunked blob uploads.
 #[async_trait]
 pub trait BlobWriter: Send + Sync + 'static {
-    async fn write(&mut self, content_length: u64, body: Body) -> Result<BoxedUploadSession>;
+    /// Write a single chunk of `content_length` bytes.
+    ///
+    /// If `chunk_digest` is given, implementations that support per-chunk verification should
+    /// reject the chunk with [`Error::BlobUploadInvalid`] when its content doesn't match, without
+    /// advancing the upload session.
+    async fn write(
+        &mut self,
+        content_length: u64,
+        body: Body,
+        chunk_digest: Option<&OciDigest>,
+    ) -> Result<BoxedUploadSession>;
 
     async fn write_chunked(&mut self, body: Body) -> Result<BoxedUploadSession>;
 
     async fn finalize(&mut self, digest: &OciDigest) -> Result<BoxedUploadSession>;
+
+    /// The upload session this writer was resumed from, or `None` once the writer has written a
+    /// chunk or been finalized.
+    fn session(&self) -> Option<&dyn UploadSession>;
 }
 
 /// Provides access to blob metadata.
@@ -190,6 +346,8 @@ pub trait Manifest {
     fn bytes_on_disk(&self) -> u64;
     fn digest(&self) -> &OciDigest;
     fn media_type(&self) -> &Option<MediaType>;
+    /// The exact Content-Type header the manifest was pushed with, if it was pushed with one.
+    fn content_type(&self) -> Option<&str>;
 }
 
 // Provides access to tag metadata.
@@ -203,12 +361,20 @@ pub trait UploadSession {
     fn uuid(&self) -> &Uuid;
     fn upload_id(&self) -> &Option<String>;
     fn last_range_end(&self) -> i64;
+
+    /// The digest the client declared the content would have when starting the session, if any;
+    /// see [`UploadSessionStore::new_upload_session_for`].
+    fn declared_digest(&self) -> Option<&OciDigest> {
+        None
+    }
 }
 
 /// Abstraction over [`oci_spec::image::ImageManifest`] and [`oci_spec::image::ImageIndex`].
 ///
 /// Provides methods to access metadata relevant for implementing Distribution HTTP API and
 /// Portfolio backends.
+#[derive(Clone, Serialize)]
+#[serde(untagged)]
 pub enum ManifestSpec {
     Image(ImageManifest),
     Index(ImageIndex),
@@ -291,18 +457,20 @@ impl ManifestSpec {
                 // manifest. When this is done, the config.mediaType value MUST be set to a value
                 // specific to the artifact type or the empty value. If the config.mediaType is set
                 // to the empty value, the artifactType MUST be defined.
-                if let Some(_artifact_type) = im.artifact_type() {
-                    im.set_media_type(Some(MediaType::ImageManifest));
-                } else if im.config().media_type() == &MediaType::EmptyJSON {
-                    return Err(Error::ManifestInvalid(None));
+                let valid = match (im.config().media_type(), im.artifact_type()) {
+                    // an artifact with no config of its own is only identified by its artifactType
+                    (MediaType::EmptyJSON, None) => false,
+                    // container images, and artifacts whose config media type is specific to
+                    // them, may omit artifactType
+                    _ => true,
+                };
+                if !valid {
+                    return Err(Error::ManifestInvalid(Some(
+                        "manifests with an empty config must set artifactType".to_string(),
+                    )));
                 }
-
-                if im.config().media_type() == &MediaType::ImageConfig {
-                    im.set_media_type(Some(MediaType::ImageManifest));
-                    return Ok(());
-                }
-
-                Err(Error::ManifestInvalid(None))
+                im.set_media_type(Some(MediaType::ImageManifest));
+                Ok(())
             }
             ManifestSpec::Index(ii) => {
                 ii.set_media_type(Some(MediaType::ImageIndex));
@@ -310,6 +478,66 @@ impl ManifestSpec {
             }
         }
     }
+
+    /// Rewrite a [Docker Image Manifest V2 Schema
+    /// 2](https://docs.docker.com/registry/spec/manifest-v2-2/) manifest or manifest list in place
+    /// as its OCI equivalent, converting the media types of the config, layer, and manifest
+    /// descriptors along with that of the manifest itself.
+    ///
+    /// Returns false, leaving the manifest unchanged, if it isn't a Docker schema 2 manifest. Only
+    /// the deserialized manifest is changed, so its serialization won't match the original digest.
+    pub fn convert_docker_to_oci(&mut self) -> bool {
+        fn convert_descriptor(d: &Descriptor) -> Descriptor {
+            let mut d = d.clone();
+            if let Some(mt) = docker_to_oci_media_type(d.media_type()) {
+                d.set_media_type(mt);
+            }
+            d
+        }
+
+        match self {
+            ManifestSpec::Image(im) => {
+                match im.media_type().as_ref().and_then(docker_to_oci_media_type) {
+                    Some(mt @ MediaType::ImageManifest) => im.set_media_type(Some(mt)),
+                    _ => return false,
+                };
+                let config = convert_descriptor(im.config());
+                im.set_config(config);
+                let layers = im.layers().iter().map(convert_descriptor).collect();
+                im.set_layers(layers);
+            }
+            ManifestSpec::Index(ii) => {
+                match ii.media_type().as_ref().and_then(docker_to_oci_media_type) {
+                    Some(mt @ MediaType::ImageIndex) => ii.set_media_type(Some(mt)),
+                    _ => return false,
+                };
+                let manifests = ii.manifests().iter().map(convert_descriptor).collect();
+                ii.set_manifests(manifests);
+            }
+        }
+        true
+    }
+}
+
+/// OCI equivalent of a Docker Image Manifest V2 Schema 2 media type.
+pub fn docker_to_oci_media_type(media_type: &MediaType) -> Option<MediaType> {
+    match media_type {
+        MediaType::Other(mt) => match mt.as_str() {
+            "application/vnd.docker.distribution.manifest.v2+json" => {
+                Some(MediaType::ImageManifest)
+            }
+            "application/vnd.docker.distribution.manifest.list.v2+json" => {
+                Some(MediaType::ImageIndex)
+            }
+            "application/vnd.docker.container.image.v1+json" => Some(MediaType::ImageConfig),
+            "application/vnd.docker.image.rootfs.diff.tar.gzip" => Some(MediaType::ImageLayerGzip),
+            "application/vnd.docker.image.rootfs.foreign.diff.tar.gzip" => {
+                Some(MediaType::ImageLayerNonDistributableGzip)
+            }
+            _ => None,
+        },
+        _ => None,
+    }
 }
 
 /// Reference to an [OCI
@@ -354,3 +582,211 @@ impl std::str::FromStr for ManifestRef {
         Err(Error::ManifestInvalid(None))
     }
 }
+
+/// Check that `name` is a valid repository name according to the [Distribution
+/// Spec](https://github.com/opencontainers/distribution-spec/blob/main/spec.md#pulling-manifests):
+///
+/// > `<name>` MUST match the following regular expression:
+/// >
+/// > `[a-z0-9]+((\.|_|__|-+)[a-z0-9]+)*(\/[a-z0-9]+((\.|_|__|-+)[a-z0-9]+)*)*`
+pub fn validate_repository_name(name: &str) -> Result<()> {
+    static RE: Lazy<Regex> = Lazy::new(|| {
+        Regex::new(r"^[a-z0-9]+((\.|_|__|-+)[a-z0-9]+)*(/[a-z0-9]+((\.|_|__|-+)[a-z0-9]+)*)*$")
+            .unwrap()
+    });
+
+    if RE.is_match(name) {
+        return Ok(());
+    }
+
+    Err(Error::NameInvalid(Some(format!(
+        "invalid repository name: {name}"
+    ))))
+}
+
+/// Return true if `name` matches the glob `pattern`, where `*` matches any run of characters,
+/// including `/`, and `?` matches any single character. Used to match tags and repository names.
+pub fn glob_matches(pattern: &str, name: &str) -> bool {
+    let (pattern, name): (Vec<char>, Vec<char>) =
+        (pattern.chars().collect(), name.chars().collect());
+    // position in the pattern of the last `*` seen and the position in the name it was tried at,
+    // so that a failed match can backtrack to let the `*` consume one more character
+    let mut star: Option<(usize, usize)> = None;
+    let (mut p, mut t) = (0, 0);
+    while t < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    star = Some((star_p, star_t + 1));
+                    p = star_p + 1;
+                    t = star_t + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::*;
+
+    use super::*;
+
+    #[rstest]
+    #[case::single_segment("ubuntu", true)]
+    #[case::multi_segment("library/ubuntu", true)]
+    #[case::nested("a/b/c/d", true)]
+    #[case::period("my.repo", true)]
+    #[case::underscore("my_repo", true)]
+    #[case::double_underscore("my__repo", true)]
+    #[case::dashes("my--repo", true)]
+    #[case::digits("repo9/2023", true)]
+    #[case::empty("", false)]
+    #[case::uppercase("Ubuntu", false)]
+    #[case::uppercase_segment("library/Ubuntu", false)]
+    #[case::leading_slash("/ubuntu", false)]
+    #[case::trailing_slash("ubuntu/", false)]
+    #[case::double_slash("library//ubuntu", false)]
+    #[case::double_dot("my..repo", false)]
+    #[case::parent_dir("library/../ubuntu", false)]
+    #[case::triple_underscore("my___repo", false)]
+    #[case::leading_separator("-repo", false)]
+    #[case::trailing_separator("repo.", false)]
+    #[case::mixed_separators("my._repo", false)]
+    #[case::colon("repo:latest", false)]
+    fn validate_repository_names(#[case] name: &str, #[case] valid: bool) {
+        match validate_repository_name(name) {
+            Ok(()) => assert!(valid, "expected {name:?} to be rejected"),
+            Err(Error::NameInvalid(_)) => assert!(!valid, "expected {name:?} to be accepted"),
+            Err(e) => panic!("unexpected error for {name:?}: {e:?}"),
+        }
+    }
+
+    #[test]
+    fn globs() {
+        assert!(glob_matches("v*", "v1.0"));
+        assert!(glob_matches("v*", "v"));
+        assert!(!glob_matches("v*", "latest"));
+        assert!(glob_matches("latest", "latest"));
+        assert!(!glob_matches("latest", "latest-1"));
+        assert!(glob_matches("v?.*-rc*", "v1.2-rc3"));
+        assert!(glob_matches("*-final", "v1-final-final"));
+        assert!(!glob_matches("v?", "v10"));
+        assert!(glob_matches("team/*", "team/a/b"));
+    }
+
+    #[test]
+    fn docker_manifests_convert_to_oci() {
+        let docker = Bytes::from_static(
+            br#"{
+                "schemaVersion": 2,
+                "mediaType": "application/vnd.docker.distribution.manifest.v2+json",
+                "config": {
+                    "mediaType": "application/vnd.docker.container.image.v1+json",
+                    "size": 2,
+                    "digest": "sha256:44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a"
+                },
+                "layers": [{
+                    "mediaType": "application/vnd.docker.image.rootfs.diff.tar.gzip",
+                    "size": 3,
+                    "digest": "sha256:e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+                }]
+            }"#,
+        );
+        let mut spec = ManifestSpec::try_from(&docker).unwrap();
+        assert!(spec.convert_docker_to_oci());
+
+        let converted: ImageManifest =
+            serde_json::from_slice(&serde_json::to_vec(&spec).unwrap()).unwrap();
+        assert_eq!(converted.media_type(), &Some(MediaType::ImageManifest));
+        assert_eq!(converted.config().media_type(), &MediaType::ImageConfig);
+        assert_eq!(
+            converted.layers()[0].media_type(),
+            &MediaType::ImageLayerGzip
+        );
+        assert_eq!(converted.layers()[0].size(), 3);
+
+        // OCI manifests are left alone
+        assert!(!spec.convert_docker_to_oci());
+    }
+
+    /// An image manifest without a media type, with the given config media type and artifact
+    /// type.
+    fn untyped_manifest(config_media_type: &str, artifact_type: Option<&str>) -> ManifestSpec {
+        let mut manifest = serde_json::json!({
+            "schemaVersion": 2,
+            "config": {
+                "mediaType": config_media_type,
+                "size": 2,
+                "digest": "sha256:44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a"
+            },
+            "layers": []
+        });
+        if let Some(artifact_type) = artifact_type {
+            manifest["artifactType"] = artifact_type.into();
+        }
+        ManifestSpec::try_from(&Bytes::from(serde_json::to_vec(&manifest).unwrap())).unwrap()
+    }
+
+    // examples from https://github.com/opencontainers/image-spec/blob/main/manifest.md#guidelines-for-artifact-usage
+    #[rstest]
+    #[case::image("application/vnd.oci.image.config.v1+json", None, true)]
+    #[case::image_with_artifact_type(
+        "application/vnd.oci.image.config.v1+json",
+        Some("application/vnd.example+type"),
+        true
+    )]
+    #[case::artifact_without_config(
+        "application/vnd.oci.empty.v1+json",
+        Some("application/vnd.example+type"),
+        true
+    )]
+    #[case::sbom_without_config(
+        "application/vnd.oci.empty.v1+json",
+        Some("application/vnd.example.sbom.v1"),
+        true
+    )]
+    #[case::artifact_with_config("application/vnd.example.config.v1+json", None, true)]
+    #[case::artifact_with_config_and_artifact_type(
+        "application/vnd.example.config.v1+json",
+        Some("application/vnd.example+type"),
+        true
+    )]
+    #[case::empty_config_without_artifact_type("application/vnd.oci.empty.v1+json", None, false)]
+    fn image_manifest_media_types_inferred(
+        #[case] config_media_type: &str,
+        #[case] artifact_type: Option<&str>,
+        #[case] valid: bool,
+    ) {
+        let mut spec = untyped_manifest(config_media_type, artifact_type);
+        match spec.infer_media_type() {
+            Ok(()) => {
+                assert!(valid, "expected manifest to be rejected");
+                assert_eq!(spec.media_type(), Some(MediaType::ImageManifest));
+            }
+            Err(Error::ManifestInvalid(Some(_))) => {
+                assert!(!valid, "expected manifest to be accepted");
+                assert_eq!(spec.media_type(), None);
+            }
+            Err(e) => panic!("unexpected error: {e:?}"),
+        }
+    }
+
+    #[test]
+    fn index_media_type_inferred() {
+        let index = Bytes::from_static(br#"{"schemaVersion": 2, "manifests": []}"#);
+        let mut spec = ManifestSpec::try_from(&index).unwrap();
+        spec.infer_media_type().unwrap();
+        assert_eq!(spec.media_type(), Some(MediaType::ImageIndex));
+    }
+}
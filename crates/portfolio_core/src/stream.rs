@@ -7,7 +7,7 @@ use futures_core::stream::Stream;
 use hyper::body::Body;
 use pin_project::pin_project;
 
-use crate::Digester;
+use crate::{Digester, Error, OciDigest};
 
 pub type StreamableBody = Box<
     (dyn futures_core::stream::Stream<
@@ -55,6 +55,120 @@ impl Stream for DigestBody {
     }
 }
 
+/// Wrapper around a stream of bytes that verifies the digest of the contents as they are read.
+///
+/// Once the underlying stream is exhausted the digest calculated over its contents is compared to
+/// the expected digest. On mismatch the stream ends with [`Error::ContentCorrupt`] rather than
+/// ending normally, so that consumers never mistake corrupted content for the real thing.
+#[pin_project]
+pub struct VerifiedBody<S> {
+    #[pin]
+    body: S,
+    digester: Digester,
+    expected: OciDigest,
+    finished: bool,
+}
+
+impl<S> VerifiedBody<S> {
+    pub fn new(body: S, expected: OciDigest) -> Self {
+        Self {
+            body,
+            digester: expected.digester(),
+            expected,
+            finished: false,
+        }
+    }
+}
+
+impl<S> Stream for VerifiedBody<S>
+where
+    S: Stream<
+        Item = std::result::Result<Bytes, Box<dyn std::error::Error + Send + Sync + 'static>>,
+    >,
+{
+    type Item = std::result::Result<Bytes, Box<dyn std::error::Error + Send + Sync + 'static>>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        if *this.finished {
+            return Poll::Ready(None);
+        }
+        match this.body.poll_next(cx) {
+            Poll::Ready(Some(Ok(bytes))) => {
+                this.digester.update(bytes.as_ref());
+                Poll::Ready(Some(Ok(bytes)))
+            }
+            Poll::Ready(Some(Err(e))) => Poll::Ready(Some(Err(e))),
+            Poll::Ready(None) => {
+                *this.finished = true;
+                let calculated = this.digester.digest();
+                if calculated.as_ref() == Some(&*this.expected) {
+                    return Poll::Ready(None);
+                }
+                Poll::Ready(Some(Err(Box::new(Error::ContentCorrupt(format!(
+                    "expected {} but content digest is {:?}",
+                    String::from(&*this.expected),
+                    calculated.as_ref().map(String::from),
+                ))))))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// Wrapper around a stream of bytes that re-chunks its contents into frames of a fixed size.
+///
+/// Every frame but the last is exactly `frame_size` bytes long; the last holds whatever remains.
+/// Unlike [`ChunkedBody`], the underlying stream can be any stream of bytes, eg one read from an
+/// object store.
+#[pin_project]
+pub struct RechunkedBody<S> {
+    #[pin]
+    body: S,
+    buffer: BytesMut,
+    frame_size: usize,
+    finished: bool,
+}
+
+impl<S> RechunkedBody<S> {
+    /// Re-chunk `body` into frames of `frame_size` bytes, which must be greater than zero.
+    pub fn new(body: S, frame_size: usize) -> Self {
+        assert!(frame_size > 0, "frames must hold at least one byte");
+        Self {
+            body,
+            buffer: BytesMut::new(),
+            frame_size,
+            finished: false,
+        }
+    }
+}
+
+impl<S> Stream for RechunkedBody<S>
+where
+    S: Stream<
+        Item = std::result::Result<Bytes, Box<dyn std::error::Error + Send + Sync + 'static>>,
+    >,
+{
+    type Item = std::result::Result<Bytes, Box<dyn std::error::Error + Send + Sync + 'static>>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        while !*this.finished && this.buffer.len() < *this.frame_size {
+            match this.body.as_mut().poll_next(cx) {
+                Poll::Ready(Some(Ok(bytes))) => this.buffer.extend_from_slice(&bytes),
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+                Poll::Ready(None) => *this.finished = true,
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        if this.buffer.is_empty() {
+            return Poll::Ready(None);
+        }
+        let size = std::cmp::min(*this.frame_size, this.buffer.len());
+        Poll::Ready(Some(Ok(this.buffer.split_to(size).freeze())))
+    }
+}
+
 const CHUNK_SIZE: usize = 6 * 1024 * 1024; // 6 MB
 
 /// Turn a [`hyper::body::Body`] into a stream of fixed-size [`bytes::Bytes`].
@@ -112,3 +226,55 @@ impl Stream for ChunkedBody {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use futures::executor::block_on;
+    use futures::stream::{self, StreamExt};
+
+    use super::*;
+
+    type TryBytes = std::result::Result<Bytes, Box<dyn std::error::Error + Send + Sync + 'static>>;
+
+    fn verify(chunks: &[&'static str], expected: &str) -> Vec<TryBytes> {
+        let body = stream::iter(chunks.iter().map(|c| Ok(Bytes::from_static(c.as_bytes()))));
+        let verified = VerifiedBody::new(body, OciDigest::from(expected.as_bytes()));
+        block_on(verified.collect())
+    }
+
+    #[test]
+    fn verified_body_passes_matching_content() {
+        let items = verify(&["some ", "content"], "some content");
+        assert_eq!(items.len(), 2);
+        assert!(items.iter().all(|i| i.is_ok()));
+    }
+
+    #[test]
+    fn verified_body_errors_after_corrupted_content() {
+        let items = verify(&["some ", "c0ntent"], "some content");
+        assert_eq!(items.len(), 3);
+        assert!(items[..2].iter().all(|i| i.is_ok()));
+        let err = items[2]
+            .as_ref()
+            .expect_err("stream should end with an error");
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::ContentCorrupt(_))
+        ));
+    }
+
+    #[test]
+    fn rechunked_body_yields_fixed_size_frames() {
+        let chunks = ["a", "bcdefgh", "", "ij", "klmnopqrstu"];
+        let body = stream::iter(chunks.iter().map(|c| Ok(Bytes::from_static(c.as_bytes()))));
+        let frames: Vec<TryBytes> = block_on(RechunkedBody::new(body, 4).collect());
+        let frames: Vec<Bytes> = frames.into_iter().map(|f| f.unwrap()).collect();
+        assert_eq!(
+            frames,
+            ["abcd", "efgh", "ijkl", "mnop", "qrst", "u"]
+                .iter()
+                .map(|f| Bytes::from_static(f.as_bytes()))
+                .collect::<Vec<Bytes>>()
+        );
+    }
+}
@@ -0,0 +1,42 @@
+use axum::extract::{Extension, Query};
+use axum::http::header::{self, HeaderMap, HeaderValue};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use http::StatusCode;
+use oci_spec::image::MediaType;
+use serde::Deserialize;
+
+use super::empty_string_as_none;
+use super::errors::{Error, Result};
+use super::ArcRepositoryStore;
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct GetParams {
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    key: Option<String>,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    value: Option<String>,
+}
+
+/// List manifests in the repository carrying the annotation named by the `key` query parameter,
+/// optionally restricted to those where it has the given `value`, as an image index in the same
+/// form as the referrers API.
+pub(crate) async fn get_annotated(
+    Extension(repository): Extension<ArcRepositoryStore>,
+    Query(params): Query<GetParams>,
+) -> Result<Response> {
+    let key = params
+        .key
+        .ok_or_else(|| Error::MissingQueryParameter("key"))?;
+
+    let mstore = repository.get_manifest_store();
+    let image_index = mstore.get_annotated(&key, params.value.as_deref()).await?;
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_str(MediaType::ImageIndex.to_string().as_str())?,
+    );
+
+    Ok((StatusCode::OK, headers, Json(image_index)).into_response())
+}
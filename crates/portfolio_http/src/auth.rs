@@ -0,0 +1,264 @@
+//! # HTTP Basic Authentication
+//!
+//! [`BasicAuthenticator`] checks usernames and passwords against bcrypt hashes loaded from an
+//! `htpasswd`-style file, eg one generated with `htpasswd -B`. [`basic_auth`] enforces it as
+//! middleware:
+//!
+//! ```rust,ignore
+//! let authenticator = Arc::new(BasicAuthenticator::from_file("./htpasswd")?);
+//! let router = router.layer(middleware::from_fn_with_state(authenticator, basic_auth));
+//! ```
+//!
+//! Anonymous pulls from repositories whose [`Visibility`] is public can be allowed with
+//! [`BasicAuthenticator::allow_anonymous_pulls`].
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use axum::extract::{State, TypedHeader};
+use axum::headers::authorization::{Authorization, Basic};
+use axum::http::header::{self, HeaderValue};
+use axum::http::{Method, Request};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use once_cell::sync::Lazy;
+
+use portfolio_core::registry::Visibility;
+use portfolio_core::Error as CoreError;
+
+use super::errors::{Error, Result};
+use super::Portfolio;
+
+/// Verified in place of the stored hash for unknown users so that the time taken to reject a
+/// request doesn't reveal which usernames exist.
+static UNKNOWN_USER_HASH: Lazy<String> = Lazy::new(|| {
+    bcrypt::hash("", bcrypt::DEFAULT_COST).expect("hashing with the default cost can't fail")
+});
+
+/// Username and bcrypt password hash pairs that requests are authenticated against.
+#[derive(Clone, Default)]
+pub struct BasicAuthenticator {
+    credentials: HashMap<String, String>,
+    anonymous_pulls: Option<Portfolio>,
+}
+
+impl BasicAuthenticator {
+    /// Parse `htpasswd`-style `username:hash` lines. Blank lines and lines starting with `#` are
+    /// ignored.
+    pub fn from_htpasswd(contents: &str) -> Result<Self> {
+        let mut credentials = HashMap::new();
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line.split_once(':') {
+                Some((username, hash)) if !username.is_empty() && !hash.is_empty() => {
+                    credentials.insert(username.to_string(), hash.to_string());
+                }
+                _ => return Err(Error::InvalidHtpasswdEntry(i + 1)),
+            }
+        }
+        Ok(Self {
+            credentials,
+            anonymous_pulls: None,
+        })
+    }
+
+    /// Load credentials from the `htpasswd`-style file at `path`.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_htpasswd(&std::fs::read_to_string(path)?)
+    }
+
+    /// Return true if `password` is correct for `username`.
+    ///
+    /// Hashes are compared in constant time, and unknown users are checked against a dummy hash,
+    /// so the time taken doesn't depend on how much of the password is correct or on whether the
+    /// user exists.
+    pub fn verify(&self, username: &str, password: &str) -> bool {
+        let (hash, known) = match self.credentials.get(username) {
+            Some(hash) => (hash.as_str(), true),
+            None => (UNKNOWN_USER_HASH.as_str(), false),
+        };
+        bcrypt::verify(password, hash).unwrap_or(false) && known
+    }
+
+    /// Allow requests without credentials to pull from repositories in `portfolio` whose
+    /// [`Visibility`] is public.
+    pub fn allow_anonymous_pulls(mut self, portfolio: Portfolio) -> Self {
+        self.anonymous_pulls = Some(portfolio);
+        self
+    }
+
+    /// Return true if the request is a pull from a public repository and anonymous pulls are
+    /// allowed.
+    async fn allows_anonymous<B>(&self, req: &Request<B>) -> bool {
+        let portfolio = match &self.anonymous_pulls {
+            Some(portfolio) => portfolio,
+            None => return false,
+        };
+        if req.method() != Method::GET && req.method() != Method::HEAD {
+            return false;
+        }
+        let name = match pulled_repository(req.uri().path()) {
+            Some(name) => name,
+            None => return false,
+        };
+        match portfolio.get_repository(name).await {
+            Ok(Some(repository)) => repository.visibility() == Visibility::Public,
+            Ok(None) => false,
+            Err(e) => {
+                tracing::warn!("error retrieving repository for anonymous pull: {e:?}");
+                false
+            }
+        }
+    }
+}
+
+/// Name of the repository pulled from by a request for `path`, if it's a pull.
+///
+/// Only content can be pulled anonymously; upload sessions and registry-wide endpoints such as
+/// the catalog always require credentials.
+fn pulled_repository(path: &str) -> Option<&str> {
+    let (name, rest) = path.strip_prefix("/v2/")?.split_once('/')?;
+    let pull = [
+        "manifests/",
+        "tags/",
+        "referrers/",
+        "annotations",
+        "images/",
+    ]
+    .iter()
+    .any(|prefix| rest.starts_with(prefix))
+        || (rest.starts_with("blobs/") && !rest.starts_with("blobs/uploads"));
+    (!name.starts_with('_') && pull).then_some(name)
+}
+
+/// Paths of the health probes, which orchestrators poll without credentials.
+const PROBE_PATHS: [&str; 2] = ["/healthz", "/readyz"];
+
+/// Middleware rejecting requests without valid Basic credentials with `401 Unauthorized`, apart
+/// from health probes and anonymous pulls if allowed. Requests with invalid credentials are always
+/// rejected.
+pub async fn basic_auth<B>(
+    State(authenticator): State<Arc<BasicAuthenticator>>,
+    authorization: Option<TypedHeader<Authorization<Basic>>>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    if PROBE_PATHS.contains(&req.uri().path()) {
+        return next.run(req).await;
+    }
+    match authorization {
+        Some(TypedHeader(Authorization(credentials)))
+            if authenticator.verify(credentials.username(), credentials.password()) =>
+        {
+            next.run(req).await
+        }
+        None if authenticator.allows_anonymous(&req).await => next.run(req).await,
+        _ => {
+            let mut response = Error::from(CoreError::Unauthorized(None)).into_response();
+            response.headers_mut().insert(
+                header::WWW_AUTHENTICATE,
+                HeaderValue::from_static("Basic realm=\"portfolio\""),
+            );
+            response
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use axum::body::Body;
+    use axum::http::StatusCode;
+    use axum::middleware;
+    use axum::routing::get;
+    use axum::Router;
+    use headers::HeaderMapExt;
+    use tower::ServiceExt;
+
+    use super::*;
+
+    fn router() -> Router {
+        // the minimum cost keeps the tests fast
+        let hash = bcrypt::hash("hunter2", 4).unwrap();
+        let authenticator =
+            BasicAuthenticator::from_htpasswd(&format!("# registry users\n\nmeow:{hash}\n"))
+                .unwrap();
+        Router::new()
+            .route("/v2/", get(|| async { "{}" }))
+            .route("/healthz", get(|| async { "" }))
+            .layer(middleware::from_fn_with_state(
+                Arc::new(authenticator),
+                basic_auth,
+            ))
+    }
+
+    async fn get_with(credentials: Option<(&str, &str)>) -> Response {
+        let mut req = Request::get("/v2/").body(Body::empty()).unwrap();
+        if let Some((username, password)) = credentials {
+            req.headers_mut()
+                .typed_insert(Authorization::basic(username, password));
+        }
+        router().oneshot(req).await.unwrap()
+    }
+
+    fn assert_challenged(response: &Response) {
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            response.headers()[header::WWW_AUTHENTICATE],
+            "Basic realm=\"portfolio\""
+        );
+    }
+
+    #[tokio::test]
+    async fn valid_credentials_are_accepted() {
+        let response = get_with(Some(("meow", "hunter2"))).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn wrong_password_is_rejected() {
+        assert_challenged(&get_with(Some(("meow", "hunter3"))).await);
+    }
+
+    #[tokio::test]
+    async fn unknown_user_is_rejected() {
+        assert_challenged(&get_with(Some(("woof", "hunter2"))).await);
+    }
+
+    #[tokio::test]
+    async fn missing_credentials_are_rejected() {
+        assert_challenged(&get_with(None).await);
+    }
+
+    #[tokio::test]
+    async fn probes_need_no_credentials() {
+        let req = Request::get("/healthz").body(Body::empty()).unwrap();
+        let response = router().oneshot(req).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn only_content_is_pulled_anonymously() {
+        assert_eq!(pulled_repository("/v2/meow/manifests/latest"), Some("meow"));
+        assert_eq!(pulled_repository("/v2/meow/blobs/sha256:abc"), Some("meow"));
+        assert_eq!(pulled_repository("/v2/meow/tags/list"), Some("meow"));
+        assert_eq!(
+            pulled_repository("/v2/meow/images/latest/export"),
+            Some("meow")
+        );
+        assert_eq!(pulled_repository("/v2/meow/blobs/uploads/"), None);
+        assert_eq!(pulled_repository("/v2/_catalog/features"), None);
+        assert_eq!(pulled_repository("/v2/meow"), None);
+        assert_eq!(pulled_repository("/v2/"), None);
+    }
+
+    #[test]
+    fn malformed_htpasswd_is_rejected() {
+        assert!(matches!(
+            BasicAuthenticator::from_htpasswd("meow:hash\nwoof\n"),
+            Err(Error::InvalidHtpasswdEntry(2))
+        ));
+    }
+}
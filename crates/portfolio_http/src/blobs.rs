@@ -1,23 +1,28 @@
 use std::collections::HashMap;
-use std::str::FromStr;
+use std::sync::Arc;
 
 use ::http::StatusCode;
 use axum::body::StreamBody;
 use axum::extract::{Extension, Path, Query, TypedHeader};
 use axum::headers::{ContentLength, ContentType};
-use axum::http::header::{self, HeaderMap, HeaderName, HeaderValue};
+use axum::http::header::{self, HeaderMap, HeaderValue};
 use axum::http::Request;
 use axum::response::{IntoResponse, Response};
 use axum::routing::{get, patch, post};
 use axum::Router;
-use headers::Header;
+use headers::{Header, HeaderMapExt};
 use hyper::body::Body;
 use uuid::Uuid;
 
-use portfolio_core::{Error as CoreError, OciDigest};
+use portfolio_core::{Error as CoreError, OciDigest, RechunkedBody};
 
+use super::config::HttpConfig;
 use super::errors::{Error, Result};
-use super::headers::{ContentRange, Range};
+use super::headers::{
+    ChunkDigest, ContentRange, Range, DOCKER_CONTENT_DIGEST, DOCKER_UPLOAD_UUID,
+    X_UPLOAD_BYTES_RECEIVED, X_UPLOAD_CHUNK_COUNT,
+};
+use super::metrics;
 use super::ArcRepositoryStore;
 
 pub fn router() -> Router {
@@ -35,6 +40,7 @@ pub fn router() -> Router {
 
 async fn get_blob(
     Extension(repository): Extension<ArcRepositoryStore>,
+    Extension(config): Extension<Arc<HttpConfig>>,
     Path(path_params): Path<HashMap<String, String>>,
 ) -> Result<Response> {
     let digest: &str = path_params
@@ -46,15 +52,21 @@ async fn get_blob(
 
     if let Some((blob, body)) = blob_store.get(&oci_digest).await? {
         let mut headers = HeaderMap::new();
-        headers.insert(
-            HeaderName::from_lowercase(b"docker-content-digest")?,
-            HeaderValue::from_str(digest)?,
-        );
+        headers.insert(&DOCKER_CONTENT_DIGEST, HeaderValue::from_str(digest)?);
         headers.insert(
             header::CONTENT_LENGTH,
             HeaderValue::from_str(blob.bytes_on_disk().to_string().as_str())?,
         );
-        Ok((StatusCode::OK, headers, StreamBody::new(body)).into_response())
+        let body = match config.blob_read_frame_bytes.filter(|size| *size > 0) {
+            Some(size) => Box::pin(RechunkedBody::new(body, size)),
+            None => body,
+        };
+        Ok((
+            StatusCode::OK,
+            headers,
+            StreamBody::new(metrics::count_downloaded_bytes(body)),
+        )
+            .into_response())
     } else {
         Err(CoreError::BlobUnknown(None).into())
     }
@@ -73,10 +85,7 @@ async fn head_blob(
 
     if let Some(blob) = blob_store.head(&oci_digest).await? {
         let mut headers = HeaderMap::new();
-        headers.insert(
-            HeaderName::from_lowercase(b"docker-content-digest")?,
-            HeaderValue::from_str(digest)?,
-        );
+        headers.insert(&DOCKER_CONTENT_DIGEST, HeaderValue::from_str(digest)?);
         headers.insert(
             header::CONTENT_LENGTH,
             HeaderValue::from_str(blob.bytes_on_disk().to_string().as_str())?,
@@ -96,7 +105,9 @@ async fn head_blob(
 // * initiate upload session for POST-PUT or POST-PATCH-PUT sequence
 async fn uploads_post(
     Extension(repository): Extension<ArcRepositoryStore>,
+    Extension(config): Extension<Arc<HttpConfig>>,
     content_length: Option<TypedHeader<ContentLength>>,
+    content_type: Option<TypedHeader<ContentType>>,
     Query(query_params): Query<HashMap<String, String>>,
     request: Request<Body>,
 ) -> Result<Response> {
@@ -105,22 +116,22 @@ async fn uploads_post(
     let mount = query_params.get("mount");
     let from = query_params.get("from");
     match (mount, from) {
-        (Some(digest), Some(_dontcare)) => {
+        (Some(digest), Some(from)) => {
             let mut headers = HeaderMap::new();
             let oci_digest: OciDigest = digest.as_str().try_into()?;
 
             let store = repository.get_blob_store();
-            if !store.head(&oci_digest).await?.is_some() {
-                let session = session_store.new_upload_session().await?;
+            if store.mount(&oci_digest, from).await?.is_none() {
+                // the spec requires falling back to a regular upload session when the blob can't
+                // be mounted; the session is for the blob that was to be mounted, so completing it
+                // as any other blob is rejected
+                let session = session_store.new_upload_session_for(&oci_digest).await?;
 
                 let location =
                     format!("/v2/{}/blobs/uploads/{}", repository.name(), session.uuid(),);
                 let mut headers = HeaderMap::new();
                 headers.insert(header::LOCATION, HeaderValue::from_str(&location)?);
-                headers.insert(
-                    HeaderName::from_str("docker-upload-uuid")?,
-                    HeaderValue::from_str(session.uuid().to_string().as_str())?,
-                );
+                insert_upload_uuid(&mut headers, session.uuid())?;
                 return Ok((StatusCode::ACCEPTED, headers, "").into_response());
             }
 
@@ -161,23 +172,33 @@ async fn uploads_post(
             let location = format!("/v2/{}/blobs/uploads/{}", repository.name(), session.uuid(),);
             let mut headers = HeaderMap::new();
             headers.insert(header::LOCATION, HeaderValue::from_str(&location)?);
-            headers.insert(
-                HeaderName::from_str("docker-upload-uuid")?,
-                HeaderValue::from_str(session.uuid().to_string().as_str())?,
-            );
+            insert_upload_uuid(&mut headers, session.uuid())?;
             Ok((StatusCode::ACCEPTED, headers, "").into_response())
         }
         Some(dgst) => {
             if let Some(TypedHeader(length)) = content_length {
+                if length.0 > 0 {
+                    check_content_type(&config, &content_type)?;
+                }
                 let oci_digest: OciDigest = dgst.as_str().try_into()?;
                 let mut store = repository.get_blob_store();
+                // the store rejects content that doesn't match the digest, so by the time this
+                // returns the digest is known to be that of the stored content
                 store
-                    .put(&oci_digest, length.0, request.into_body())
+                    .put(
+                        &oci_digest,
+                        length.0,
+                        metrics::count_uploaded_bytes(request.into_body()),
+                    )
                     .await?;
 
                 let location = format!("/v2/{}/blobs/{}", repository.name(), dgst);
                 let mut headers = HeaderMap::new();
                 headers.insert(header::LOCATION, HeaderValue::from_str(&location)?);
+                headers.insert(
+                    &DOCKER_CONTENT_DIGEST,
+                    HeaderValue::from_str(String::from(&oci_digest).as_str())?,
+                );
                 Ok((StatusCode::CREATED, headers, "").into_response())
             } else {
                 Err(Error::MissingHeader("ContentLength"))
@@ -202,6 +223,7 @@ async fn uploads_post(
 //
 async fn uploads_put(
     Extension(repository): Extension<ArcRepositoryStore>,
+    Extension(config): Extension<Arc<HttpConfig>>,
     Path(path_params): Path<HashMap<String, String>>,
     content_length: Option<TypedHeader<ContentLength>>,
     content_type: Option<TypedHeader<ContentType>>,
@@ -214,46 +236,80 @@ async fn uploads_put(
         .ok_or_else(|| Error::MissingQueryParameter("digest"))?;
     let oci_digest: OciDigest = digest.try_into()?;
 
-    let session_uuid_str = path_params
-        .get("session_uuid")
-        .ok_or_else(|| Error::MissingPathParameter("session_uuid"))?;
-    let session_uuid = Uuid::parse_str(session_uuid_str).map_err(CoreError::from)?;
+    let session_uuid = session_uuid(&path_params)?;
+
+    // a PUT without a Content-Length carries no body, so its Content-Type is irrelevant
+    if matches!(content_length, Some(TypedHeader(ContentLength(length))) if length > 0) {
+        check_content_type(&config, &content_type)?;
+    }
 
     let start = content_range.map(|TypedHeader(content_range)| content_range.start);
 
-    // retrieve the session or fail if it doesn't exist
-    // TODO: refactor this method to avoid retrieving the upload session twice (once here, once in
-    // the resume method)
-    let session_store = repository.get_upload_session_store();
-    let session = session_store
-        .get_upload_session(&session_uuid)
+    // retrieve the session or fail if it doesn't exist; the writer holds on to the session it
+    // loaded so that it doesn't have to be retrieved again here
+    let store = repository.get_blob_store();
+    let mut writer = store
+        .resume(&session_uuid, start)
         .await
-        .map_err(|_| CoreError::BlobUploadUnknown(None))?;
+        .map_err(|e| range_error(&config, e))?;
+    let session = writer.session().ok_or(CoreError::BlobWriterFinished)?;
+    if let Some(declared) = session.declared_digest() {
+        if declared != &oci_digest {
+            return Err(CoreError::DigestInvalid(Some(format!(
+                "upload session was started for {} but completed as {digest}",
+                String::from(declared)
+            )))
+            .into());
+        }
+    }
+    let upload_id = session.upload_id().clone();
+
+    // the length of the content this request carries, if any
+    let content_length = match (content_type, content_length) {
+        (Some(TypedHeader(_content_type)), Some(TypedHeader(content_length))) => {
+            Some(content_length.0)
+        }
+        // TODO: what if there is a body but none of the content headers are set? technically this
+        // would be a client bug, but it could also result in data corruption and as such should
+        // probably be handled here. this should probably result in a 400 bad request error if we
+        // can detect it
+        _ => None,
+    };
 
     // determine if this is a monolithic POST-PUT or the final request in a chunked POST-PATCH-PUT
-    // sequence
-    let response = match session.upload_id() {
+    // sequence. the object store upload backing a chunked upload is only started once content is
+    // written to the session, so a session without one hasn't received any content yet and a PUT
+    // carrying content must be a monolithic upload of all of it; a PUT without content completes
+    // whatever was written so far, which may be nothing at all if every PATCH was empty
+    let response = match (upload_id, content_length) {
+        // POST-PUT
+        (None, Some(content_length)) => {
+            store
+                .put(
+                    &oci_digest,
+                    content_length,
+                    metrics::count_uploaded_bytes(request.into_body()),
+                )
+                .await?;
+
+            let location = format!("/v2/{}/blobs/{}", repository.name(), digest);
+            let mut headers = HeaderMap::new();
+            headers.insert(header::LOCATION, HeaderValue::from_str(&location)?);
+            insert_upload_uuid(&mut headers, &session_uuid)?;
+            (StatusCode::CREATED, headers, "").into_response()
+        }
         // POST-PATCH-PUT
-        Some(_) => {
-            let store = repository.get_blob_store();
-            let session = if let (
-                // TODO: what if there is a body but none of the content headers are set? technically
-                // this would be a client bug, but it could also result in data corruption and as such
-                // should probably be handled here. this should probably result in a 400 bad request
-                // error if we can detect it
-                // TODO: what should we do with ContentType?
-                Some(TypedHeader(_content_type)),
-                Some(TypedHeader(content_length)),
-            ) = (content_type, content_length)
-            {
-                let mut writer = store.resume(&session_uuid, start).await?;
-                let session = writer.write(content_length.0, request.into_body()).await?;
-
-                // TODO: validate content length of chunk
-                // TODO: update incremental digest state on session
-                session
+        (_, content_length) => {
+            let session = if let Some(content_length) = content_length {
+                let chunk_digest = chunk_digest(request.headers())?;
+                writer
+                    .write(
+                        content_length,
+                        metrics::count_uploaded_bytes(request.into_body()),
+                        chunk_digest.as_ref(),
+                    )
+                    .await?
             } else {
-                let mut writer = store.resume(&session_uuid, start).await?;
                 writer.finalize(&oci_digest).await?
             };
 
@@ -268,31 +324,9 @@ async fn uploads_put(
             let location = format!("/v2/{}/blobs/{}", repository.name(), digest);
             let mut headers = HeaderMap::new();
             headers.insert(header::LOCATION, HeaderValue::from_str(&location)?);
-            headers.insert(
-                HeaderName::from_str("docker-upload-uuid")?,
-                HeaderValue::from_str(&session_uuid_str)?,
-            );
+            insert_upload_uuid(&mut headers, &session_uuid)?;
             (StatusCode::CREATED, headers, "").into_response()
         }
-        // POST-PUT
-        None => match (content_type, content_length) {
-            (Some(TypedHeader(_content_type)), Some(TypedHeader(content_length))) => {
-                let mut store = repository.get_blob_store();
-                store
-                    .put(&oci_digest, content_length.0, request.into_body())
-                    .await?;
-
-                let location = format!("/v2/{}/blobs/{}", repository.name(), digest);
-                let mut headers = HeaderMap::new();
-                headers.insert(header::LOCATION, HeaderValue::from_str(&location)?);
-                headers.insert(
-                    HeaderName::from_str("docker-upload-uuid")?,
-                    HeaderValue::from_str(session_uuid_str)?,
-                );
-                (StatusCode::CREATED, headers, "").into_response()
-            }
-            _ => return Err(CoreError::SizeInvalid(None).into()),
-        },
     };
 
     Ok(response)
@@ -300,43 +334,46 @@ async fn uploads_put(
 
 async fn uploads_patch(
     Extension(repository): Extension<ArcRepositoryStore>,
+    Extension(config): Extension<Arc<HttpConfig>>,
     Path(path_params): Path<HashMap<String, String>>,
     content_length: Option<TypedHeader<ContentLength>>,
+    content_type: Option<TypedHeader<ContentType>>,
     content_range: Option<TypedHeader<ContentRange>>,
     request: Request<Body>,
 ) -> Result<Response> {
-    let session_uuid_str = path_params
-        .get("session_uuid")
-        .ok_or_else(|| Error::MissingPathParameter("session_uuid"))?;
-    let session_uuid = Uuid::parse_str(session_uuid_str).map_err(CoreError::from)?;
+    let session_uuid = session_uuid(&path_params)?;
+
+    check_content_type(&config, &content_type)?;
 
     let start = content_range.map(|TypedHeader(content_range)| content_range.start);
 
     let store = repository.get_blob_store();
-    let mut writer = store.resume(&session_uuid, start).await?;
+    let chunk_digest = chunk_digest(request.headers())?;
+    let mut writer = store
+        .resume(&session_uuid, start)
+        .await
+        .map_err(|e| range_error(&config, e))?;
     let session = if let Some(TypedHeader(content_length)) = content_length {
-        writer.write(content_length.0, request.into_body()).await?
+        writer
+            .write(
+                content_length.0,
+                metrics::count_uploaded_bytes(request.into_body()),
+                chunk_digest.as_ref(),
+            )
+            .await?
     } else {
-        writer.write_chunked(request.into_body()).await?
+        writer
+            .write_chunked(metrics::count_uploaded_bytes(request.into_body()))
+            .await?
     };
 
-    // TODO: validate content length of chunk
-    // TODO: update incremental digest state on session
-
     let mut headers = HeaderMap::new();
 
     let location = format!("/v2/{}/blobs/uploads/{}", repository.name(), session_uuid);
     headers.insert(header::LOCATION, HeaderValue::from_str(&location)?);
-    headers.insert(
-        HeaderName::from_str("docker-upload-uuid")?,
-        HeaderValue::from_str(session_uuid_str)?,
-    );
+    insert_upload_uuid(&mut headers, &session_uuid)?;
 
-    let range = Range {
-        start: 0,
-        end: session.last_range_end() as u64,
-    };
-    let range: String = (&range).into();
+    let range: String = (&config.upload_range_format.range(session.last_range_end())).into();
     headers.insert(Range::name(), HeaderValue::from_str(&range).expect("meow"));
 
     Ok((StatusCode::ACCEPTED, headers, "").into_response())
@@ -344,12 +381,10 @@ async fn uploads_patch(
 
 async fn uploads_get(
     Extension(repository): Extension<ArcRepositoryStore>,
+    Extension(config): Extension<Arc<HttpConfig>>,
     Path(path_params): Path<HashMap<String, String>>,
 ) -> Result<Response> {
-    let session_uuid_str = path_params
-        .get("session_uuid")
-        .ok_or_else(|| Error::MissingPathParameter("session_uuid"))?;
-    let session_uuid = Uuid::parse_str(session_uuid_str).map_err(CoreError::from)?;
+    let session_uuid = session_uuid(&path_params)?;
 
     // retrieve the session or fail if it doesn't exist
     let session_store = repository.get_upload_session_store();
@@ -357,22 +392,24 @@ async fn uploads_get(
         .get_upload_session(&session_uuid)
         .await
         .map_err(|_| CoreError::BlobUploadUnknown(None))?;
+    let progress = session_store.get_upload_progress(&session_uuid).await?;
 
     let mut headers = HeaderMap::new();
 
     let location = format!("/v2/{}/blobs/uploads/{}", repository.name(), session_uuid);
     headers.insert(header::LOCATION, HeaderValue::from_str(&location)?);
-    headers.insert(
-        HeaderName::from_str("docker-upload-uuid")?,
-        HeaderValue::from_str(session_uuid_str)?,
-    );
+    insert_upload_uuid(&mut headers, &session_uuid)?;
 
-    let range = Range {
-        start: 0,
-        end: session.last_range_end() as u64,
-    };
-    let range: String = (&range).into();
+    let range: String = (&config.upload_range_format.range(session.last_range_end())).into();
     headers.insert(Range::name(), HeaderValue::from_str(&range).expect("meow"));
+    headers.insert(
+        &X_UPLOAD_BYTES_RECEIVED,
+        HeaderValue::from(progress.bytes_received),
+    );
+    headers.insert(
+        &X_UPLOAD_CHUNK_COUNT,
+        HeaderValue::from(progress.chunk_count),
+    );
 
     Ok((StatusCode::NO_CONTENT, headers, "").into_response())
 }
@@ -392,3 +429,541 @@ async fn delete_blob(
 
     Ok((StatusCode::ACCEPTED, "").into_response())
 }
+
+/// Parse the upload session UUID from the request path. Session IDs that aren't UUIDs can't
+/// refer to any session, so they are reported as unknown uploads rather than server errors.
+fn session_uuid(path_params: &HashMap<String, String>) -> Result<Uuid> {
+    let session_uuid = path_params
+        .get("session_uuid")
+        .ok_or_else(|| Error::MissingPathParameter("session_uuid"))?;
+    Uuid::parse_str(session_uuid).map_err(|e| {
+        CoreError::BlobUploadUnknown(Some(format!(
+            "malformed upload session id {session_uuid:?}: {e}"
+        )))
+        .into()
+    })
+}
+
+/// Identify the upload session a response belongs to. The UUID is written in its canonical form,
+/// rather than however the client spelled it in the request path, so that every response for the
+/// same session carries the same value.
+fn insert_upload_uuid(headers: &mut HeaderMap, session_uuid: &Uuid) -> Result<()> {
+    headers.insert(
+        &DOCKER_UPLOAD_UUID,
+        HeaderValue::from_str(&session_uuid.to_string())?,
+    );
+    Ok(())
+}
+
+/// Report a chunk that doesn't follow the content already uploaded with a `Range` header in the
+/// configured [`HttpConfig::upload_range_format`].
+fn range_error(config: &HttpConfig, e: CoreError) -> Error {
+    match e {
+        CoreError::ContentRangeInvalid(last_range_end, ref msg) => Error::UploadRangeInvalid(
+            config.upload_range_format.range(last_range_end),
+            msg.clone().unwrap_or_else(|| e.to_string()),
+        ),
+        e => e.into(),
+    }
+}
+
+/// Extract the optional per-chunk digest, rejecting malformed values rather than ignoring them
+/// since the client expects the chunk to be verified.
+fn chunk_digest(headers: &HeaderMap) -> Result<Option<OciDigest>> {
+    match headers.typed_try_get::<ChunkDigest>() {
+        Ok(chunk_digest) => Ok(chunk_digest.map(|ChunkDigest(d)| d)),
+        Err(_) => Err(CoreError::DigestInvalid(Some("malformed chunk digest".to_string())).into()),
+    }
+}
+
+/// Reject upload bodies whose Content-Type isn't one of [`HttpConfig::blob_content_types`], if
+/// set. Parameters such as `charset` are ignored.
+fn check_content_type(
+    config: &HttpConfig,
+    content_type: &Option<TypedHeader<ContentType>>,
+) -> Result<()> {
+    let allowed = match &config.blob_content_types {
+        Some(allowed) => allowed,
+        None => return Ok(()),
+    };
+    let content_type = content_type
+        .as_ref()
+        .map(|TypedHeader(content_type)| content_type.to_string())
+        .unwrap_or_default();
+    let essence = content_type.split(';').next().unwrap_or_default().trim();
+    if allowed.iter().any(|a| a.eq_ignore_ascii_case(essence)) {
+        Ok(())
+    } else {
+        Err(Error::UnsupportedContentType(content_type))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use async_trait::async_trait;
+    use axum::body::Bytes;
+    use futures::stream;
+    use hyper::body::HttpBody;
+
+    use portfolio_core::registry::{
+        Blob, BlobStore, BlobWriter, BoxedBlob, BoxedBlobStore, BoxedBlobWriter,
+        BoxedManifestStore, BoxedUploadSession, BoxedUploadSessionStore, RepositoryStore,
+        StreamableBody, UploadProgress, UploadSession, UploadSessionStore, Visibility,
+    };
+    use portfolio_core::Result as CoreResult;
+
+    use super::*;
+    use crate::config::RangeFormat;
+
+    /// UUID given to sessions started by [`CountingRepository`].
+    const NEW_SESSION_UUID: Uuid = Uuid::from_u128(0x8c1f_4d2e_0b7a_4e39_9f5d_63a1_c2e4_7b90);
+
+    /// Repository whose upload session is loaded from nowhere, counting each time it is loaded.
+    #[derive(Clone)]
+    struct CountingRepository {
+        session_loads: Arc<AtomicUsize>,
+        upload_id: Option<String>,
+        declared_digest: Option<OciDigest>,
+    }
+
+    impl CountingRepository {
+        fn load_session(&self, uuid: &Uuid) -> MockSession {
+            self.session_loads.fetch_add(1, Ordering::SeqCst);
+            MockSession {
+                uuid: *uuid,
+                upload_id: self.upload_id.clone(),
+                declared_digest: self.declared_digest.clone(),
+            }
+        }
+    }
+
+    struct MockSession {
+        uuid: Uuid,
+        upload_id: Option<String>,
+        declared_digest: Option<OciDigest>,
+    }
+
+    impl UploadSession for MockSession {
+        fn uuid(&self) -> &Uuid {
+            &self.uuid
+        }
+
+        fn upload_id(&self) -> &Option<String> {
+            &self.upload_id
+        }
+
+        fn last_range_end(&self) -> i64 {
+            0
+        }
+
+        fn declared_digest(&self) -> Option<&OciDigest> {
+            self.declared_digest.as_ref()
+        }
+    }
+
+    struct MockBlob;
+
+    impl Blob for MockBlob {
+        fn bytes_on_disk(&self) -> u64 {
+            21
+        }
+    }
+
+    struct MockWriter {
+        session: Option<MockSession>,
+    }
+
+    impl RepositoryStore for CountingRepository {
+        fn name(&self) -> &str {
+            "counting"
+        }
+
+        fn get_manifest_store(&self) -> BoxedManifestStore {
+            unimplemented!()
+        }
+
+        fn get_blob_store(&self) -> BoxedBlobStore {
+            Box::new(self.clone())
+        }
+
+        fn get_upload_session_store(&self) -> BoxedUploadSessionStore {
+            Box::new(self.clone())
+        }
+
+        fn visibility(&self) -> Visibility {
+            Visibility::Private
+        }
+    }
+
+    #[async_trait]
+    impl UploadSessionStore for CountingRepository {
+        async fn new_upload_session(&self) -> CoreResult<BoxedUploadSession> {
+            Ok(Box::new(self.load_session(&NEW_SESSION_UUID)))
+        }
+
+        async fn get_upload_session(&self, session_uuid: &Uuid) -> CoreResult<BoxedUploadSession> {
+            Ok(Box::new(self.load_session(session_uuid)))
+        }
+
+        async fn delete_session(&self, _session_uuid: &Uuid) -> CoreResult<()> {
+            Ok(())
+        }
+
+        async fn get_upload_progress(&self, _session_uuid: &Uuid) -> CoreResult<UploadProgress> {
+            Ok(UploadProgress::default())
+        }
+    }
+
+    #[async_trait]
+    impl BlobStore for CountingRepository {
+        async fn head(&self, _key: &OciDigest) -> CoreResult<Option<BoxedBlob>> {
+            Ok(Some(Box::new(MockBlob)))
+        }
+
+        async fn get(&self, _key: &OciDigest) -> CoreResult<Option<(BoxedBlob, StreamableBody)>> {
+            // the blob arrives in frames of uneven sizes, as object stores are apt to yield them
+            let chunks = ["a", "bcdefgh", "ij", "klmnopqrstu"];
+            let body = stream::iter(chunks.map(|c| Ok(Bytes::from_static(c.as_bytes()))));
+            Ok(Some((Box::new(MockBlob), Box::pin(body))))
+        }
+
+        async fn put(&self, _digest: &OciDigest, _len: u64, _body: Body) -> CoreResult<Uuid> {
+            Ok(Uuid::new_v4())
+        }
+
+        async fn delete(&self, _digest: &OciDigest) -> CoreResult<()> {
+            Ok(())
+        }
+
+        async fn mount(&self, _digest: &OciDigest, _from: &str) -> CoreResult<Option<BoxedBlob>> {
+            Ok(None)
+        }
+
+        async fn resume(
+            &self,
+            session_uuid: &Uuid,
+            _start: Option<u64>,
+        ) -> CoreResult<BoxedBlobWriter> {
+            Ok(Box::new(MockWriter {
+                session: Some(self.load_session(session_uuid)),
+            }))
+        }
+    }
+
+    #[async_trait]
+    impl BlobWriter for MockWriter {
+        async fn write(
+            &mut self,
+            _content_length: u64,
+            _body: Body,
+            _chunk_digest: Option<&OciDigest>,
+        ) -> CoreResult<BoxedUploadSession> {
+            let session = self.session.take().ok_or(CoreError::BlobWriterFinished)?;
+            Ok(Box::new(session))
+        }
+
+        async fn write_chunked(&mut self, _body: Body) -> CoreResult<BoxedUploadSession> {
+            let session = self.session.take().ok_or(CoreError::BlobWriterFinished)?;
+            Ok(Box::new(session))
+        }
+
+        async fn finalize(&mut self, _digest: &OciDigest) -> CoreResult<BoxedUploadSession> {
+            let session = self.session.take().ok_or(CoreError::BlobWriterFinished)?;
+            Ok(Box::new(session))
+        }
+
+        fn session(&self) -> Option<&dyn UploadSession> {
+            self.session.as_ref().map(|s| s as &dyn UploadSession)
+        }
+    }
+
+    async fn put_upload(upload_id: Option<String>, body: Option<&'static [u8]>) -> usize {
+        let session_loads = Arc::new(AtomicUsize::new(0));
+        let repository: ArcRepositoryStore = Arc::new(CountingRepository {
+            session_loads: session_loads.clone(),
+            upload_id,
+            declared_digest: None,
+        });
+        let session_uuid = Uuid::new_v4();
+        let digest = String::from(OciDigest::from(body.unwrap_or_default()));
+
+        let response = uploads_put(
+            Extension(repository),
+            Extension(Arc::new(HttpConfig::default())),
+            Path(HashMap::from([(
+                "session_uuid".to_string(),
+                session_uuid.to_string(),
+            )])),
+            body.map(|b| TypedHeader(ContentLength(b.len() as u64))),
+            body.map(|_| TypedHeader(ContentType::octet_stream())),
+            None,
+            Query(HashMap::from([("digest".to_string(), digest)])),
+            Request::new(body.map(Body::from).unwrap_or_else(Body::empty)),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        session_loads.load(Ordering::SeqCst)
+    }
+
+    #[tokio::test]
+    async fn chunked_put_loads_session_once() {
+        assert_eq!(put_upload(Some("upload".to_string()), None).await, 1);
+    }
+
+    #[tokio::test]
+    async fn monolithic_put_loads_session_once() {
+        assert_eq!(put_upload(None, Some(b"meow")).await, 1);
+    }
+
+    #[tokio::test]
+    async fn strict_put_rejects_unexpected_content_type() {
+        let session_loads = Arc::new(AtomicUsize::new(0));
+        let repository: ArcRepositoryStore = Arc::new(CountingRepository {
+            session_loads: session_loads.clone(),
+            upload_id: None,
+            declared_digest: None,
+        });
+        let config = HttpConfig {
+            blob_content_types: Some(vec!["application/octet-stream".to_string()]),
+            ..Default::default()
+        };
+        let body: &[u8] = b"{}";
+
+        let result = uploads_put(
+            Extension(repository),
+            Extension(Arc::new(config)),
+            Path(HashMap::from([(
+                "session_uuid".to_string(),
+                Uuid::new_v4().to_string(),
+            )])),
+            Some(TypedHeader(ContentLength(body.len() as u64))),
+            Some(TypedHeader(ContentType::json())),
+            None,
+            Query(HashMap::from([(
+                "digest".to_string(),
+                String::from(OciDigest::from(body)),
+            )])),
+            Request::new(Body::from(body)),
+        )
+        .await;
+
+        assert!(matches!(result, Err(Error::UnsupportedContentType(_))));
+        assert_eq!(session_loads.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn put_rejects_digest_conflicting_with_declared_digest() {
+        let declared = OciDigest::from(b"meow".as_slice());
+        let put = |digest: &OciDigest| {
+            let repository: ArcRepositoryStore = Arc::new(CountingRepository {
+                session_loads: Arc::new(AtomicUsize::new(0)),
+                upload_id: Some("upload".to_string()),
+                declared_digest: Some(declared.clone()),
+            });
+            uploads_put(
+                Extension(repository),
+                Extension(Arc::new(HttpConfig::default())),
+                Path(HashMap::from([(
+                    "session_uuid".to_string(),
+                    Uuid::new_v4().to_string(),
+                )])),
+                None,
+                None,
+                None,
+                Query(HashMap::from([(
+                    "digest".to_string(),
+                    String::from(digest),
+                )])),
+                Request::new(Body::empty()),
+            )
+        };
+
+        let result = put(&OciDigest::from(b"woof".as_slice())).await;
+        assert!(matches!(
+            result,
+            Err(Error::PortfolioCoreError(CoreError::DigestInvalid(_)))
+        ));
+
+        let response = put(&declared).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+    }
+
+    #[tokio::test]
+    async fn upload_uuid_header_matches_across_handlers() {
+        let repository: ArcRepositoryStore = Arc::new(CountingRepository {
+            session_loads: Arc::new(AtomicUsize::new(0)),
+            upload_id: Some("upload".to_string()),
+            declared_digest: None,
+        });
+        let config = Extension(Arc::new(HttpConfig::default()));
+        // clients may spell the session's UUID differently than it was handed to them
+        let path = || {
+            Path(HashMap::from([(
+                "session_uuid".to_string(),
+                NEW_SESSION_UUID.to_string().to_uppercase(),
+            )]))
+        };
+
+        let post = uploads_post(
+            Extension(repository.clone()),
+            config.clone(),
+            None,
+            None,
+            Query(HashMap::new()),
+            Request::new(Body::empty()),
+        )
+        .await
+        .unwrap();
+        let patch = uploads_patch(
+            Extension(repository.clone()),
+            config.clone(),
+            path(),
+            None,
+            None,
+            None,
+            Request::new(Body::from("meow")),
+        )
+        .await
+        .unwrap();
+        let get = uploads_get(Extension(repository.clone()), config.clone(), path())
+            .await
+            .unwrap();
+        let put = uploads_put(
+            Extension(repository),
+            config,
+            path(),
+            None,
+            None,
+            None,
+            Query(HashMap::from([(
+                "digest".to_string(),
+                String::from(OciDigest::from(b"meow".as_slice())),
+            )])),
+            Request::new(Body::empty()),
+        )
+        .await
+        .unwrap();
+
+        let expected = NEW_SESSION_UUID.to_string();
+        for response in [post, patch, get, put] {
+            assert_eq!(response.headers()[&DOCKER_UPLOAD_UUID], expected.as_str());
+        }
+    }
+
+    #[tokio::test]
+    async fn blob_downloads_use_configured_frame_size() {
+        let repository: ArcRepositoryStore = Arc::new(CountingRepository {
+            session_loads: Arc::new(AtomicUsize::new(0)),
+            upload_id: None,
+            declared_digest: None,
+        });
+        let config = HttpConfig {
+            blob_read_frame_bytes: Some(4),
+            ..Default::default()
+        };
+
+        let response = get_blob(
+            Extension(repository),
+            Extension(Arc::new(config)),
+            Path(HashMap::from([(
+                "digest".to_string(),
+                String::from(OciDigest::from(b"meow".as_slice())),
+            )])),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let mut body = response.into_body();
+        let mut frames = Vec::new();
+        while let Some(frame) = body.data().await {
+            frames.push(frame.unwrap());
+        }
+        assert_eq!(frames, ["abcd", "efgh", "ijkl", "mnop", "qrst", "u"]);
+    }
+
+    #[tokio::test]
+    async fn malformed_session_uuid_is_unknown_upload() {
+        let repository: ArcRepositoryStore = Arc::new(CountingRepository {
+            session_loads: Arc::new(AtomicUsize::new(0)),
+            upload_id: None,
+            declared_digest: None,
+        });
+
+        let response = uploads_patch(
+            Extension(repository),
+            Extension(Arc::new(HttpConfig::default())),
+            Path(HashMap::from([(
+                "session_uuid".to_string(),
+                "not-a-uuid".to_string(),
+            )])),
+            None,
+            None,
+            None,
+            Request::new(Body::from("meow")),
+        )
+        .await
+        .into_response();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["errors"][0]["code"], "BLOB_UPLOAD_UNKNOWN");
+        assert!(body["errors"][0]["message"]
+            .as_str()
+            .unwrap()
+            .contains("not-a-uuid"));
+    }
+
+    /// The `Range` header reported by a GET for a session that has received 1 byte and by the
+    /// rejection of a chunk sent to a session that has received 100 bytes.
+    async fn upload_ranges(format: RangeFormat) -> (String, String) {
+        let repository: ArcRepositoryStore = Arc::new(CountingRepository {
+            session_loads: Arc::new(AtomicUsize::new(0)),
+            upload_id: None,
+            declared_digest: None,
+        });
+        let config = HttpConfig {
+            upload_range_format: format,
+            ..Default::default()
+        };
+
+        let get = uploads_get(
+            Extension(repository),
+            Extension(Arc::new(config.clone())),
+            Path(HashMap::from([(
+                "session_uuid".to_string(),
+                Uuid::new_v4().to_string(),
+            )])),
+        )
+        .await
+        .unwrap();
+        let rejected =
+            range_error(&config, CoreError::ContentRangeInvalid(99, None)).into_response();
+        assert_eq!(rejected.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+
+        let range = |response: &Response| response.headers()["range"].to_str().unwrap().to_string();
+        (range(&get), range(&rejected))
+    }
+
+    #[tokio::test]
+    async fn inclusive_upload_range() {
+        assert_eq!(
+            upload_ranges(RangeFormat::Inclusive).await,
+            ("0-0".to_string(), "0-99".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn count_upload_range() {
+        assert_eq!(
+            upload_ranges(RangeFormat::Count).await,
+            ("0-1".to_string(), "0-100".to_string())
+        );
+    }
+}
@@ -0,0 +1,63 @@
+use axum::extract::{Query, State};
+use axum::http::header::HeaderMap;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use headers::HeaderMapExt;
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use super::empty_string_as_none;
+use super::errors::Result;
+use super::headers::NextLink;
+use super::Portfolio;
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct GetParams {
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    n: Option<i64>,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    last: Option<String>,
+    /// Only list repositories whose names start with this prefix.
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    prefix: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct Catalog {
+    repositories: Vec<String>,
+}
+
+/// https://github.com/opencontainers/distribution-spec/blob/main/extensions/_oci.md
+pub(crate) async fn get_catalog(
+    State(portfolio): State<Portfolio>,
+    Query(params): Query<GetParams>,
+) -> Result<Response> {
+    let repositories = match &params.prefix {
+        Some(prefix) => {
+            portfolio
+                .manager
+                .search(prefix, params.n, params.last)
+                .await?
+        }
+        None => portfolio.manager.list(params.n, params.last).await?,
+    };
+
+    let mut headers = HeaderMap::new();
+    // a full page means there may be more results; let the client know where to find them
+    if let (Some(n), Some(last)) = (params.n, repositories.last()) {
+        if repositories.len() as i64 == n {
+            headers.typed_insert(NextLink {
+                path: "/v2/_catalog".to_string(),
+                filters: params
+                    .prefix
+                    .into_iter()
+                    .map(|p| ("prefix".to_string(), p))
+                    .collect(),
+                n,
+                last: last.clone(),
+            });
+        }
+    }
+
+    Ok((StatusCode::OK, headers, Json(Catalog { repositories })).into_response())
+}
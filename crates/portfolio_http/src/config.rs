@@ -0,0 +1,140 @@
+use serde::Deserialize;
+
+use portfolio_core::registry::glob_matches;
+
+use super::headers::Range;
+
+/// Largest manifest, in bytes, that clients may push when `max_manifest_bytes` is unset.
+pub const DEFAULT_MAX_MANIFEST_BYTES: u64 = 4 * 1024 * 1024;
+
+/// Settings controlling how the Distribution API handlers treat requests, independent of the
+/// backend serving them.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct HttpConfig {
+    /// Content-Types that blob upload bodies must be sent with, eg `application/octet-stream`.
+    ///
+    /// Uploads carrying a body with any other Content-Type, or none at all, are rejected with
+    /// `415 Unsupported Media Type`. This catches clients that mistakenly send eg manifests to the
+    /// blob endpoints. Any Content-Type is accepted when unset.
+    pub blob_content_types: Option<Vec<String>>,
+
+    /// Serve Docker Image Manifest V2 Schema 2 manifests pulled by tag as their OCI equivalents
+    /// to clients whose `Accept` headers prefer the OCI media type.
+    ///
+    /// The conversion only rewrites media types in the served response; stored manifests are
+    /// left untouched and pulls by digest always return the original bytes.
+    pub convert_docker_manifests: bool,
+
+    /// Send response header names in title case, eg `Docker-Content-Digest` rather than
+    /// `docker-content-digest`, for HTTP/1 clients that mistakenly compare header names
+    /// case-sensitively. HTTP/2 requires lowercase header names so this has no effect there.
+    ///
+    /// This is applied by the server the router is served with rather than by the router itself.
+    pub title_case_headers: bool,
+
+    /// Number of tags returned by tag listings that don't specify `n`, with a `Link` header
+    /// pointing at the next page when there may be more. The distribution spec allows returning
+    /// every tag in that case, which is what happens when this is unset, but that response can
+    /// get very large for repositories with many tags.
+    pub default_tags_page_size: Option<i64>,
+
+    /// Largest manifest, in bytes, that clients may push. Larger manifests are rejected with
+    /// `413 Payload Too Large` before being parsed. Defaults to [`DEFAULT_MAX_MANIFEST_BYTES`].
+    pub max_manifest_bytes: Option<u64>,
+
+    /// Number of referrers requests handled at once, separately from any other requests. Further
+    /// referrers requests are rejected with `429 Too Many Requests` until one completes.
+    /// Unlimited when unset.
+    pub max_concurrent_referrers_requests: Option<usize>,
+
+    /// Size, in bytes, of the frames blob downloads are sent to clients in. Content read from the
+    /// backend is buffered and re-chunked into frames of this size, which can improve throughput
+    /// on some networks. Frames are sent as the backend yields them when unset or zero.
+    pub blob_read_frame_bytes: Option<usize>,
+
+    /// Whether repositories that don't exist yet are created when first accessed. Defaults to
+    /// [`RepositoryCreationPolicy::AutoCreate`].
+    pub repository_creation: RepositoryCreationPolicy,
+
+    /// Number of repositories that may be created on first access per minute, across all
+    /// clients. Requests that would create further repositories are rejected with `429 Too Many
+    /// Requests` until the minute is up, which keeps clients from flooding the metadata store with
+    /// empty repositories. Unlimited when unset.
+    pub max_repository_creations_per_minute: Option<u32>,
+
+    /// How the `Range` header reporting the content an upload session has received is written.
+    /// Defaults to [`RangeFormat::Inclusive`], which is what the distribution spec requires.
+    pub upload_range_format: RangeFormat,
+}
+
+/// Controls which repositories [`crate::add_basic_repository_extensions`] creates on first
+/// access. Repositories it doesn't create are reported as unknown, eg:
+///
+/// ```yaml
+/// repository_creation:
+///   allow_list:
+///     - "team-a/*"
+///     - "sandbox"
+/// ```
+///
+/// Statically-defined repositories are always created regardless of the policy.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RepositoryCreationPolicy {
+    /// Create any repository.
+    #[default]
+    AutoCreate,
+    /// Never create repositories.
+    Deny,
+    /// Create repositories whose names match any of the given globs, where `*` matches any run of
+    /// characters, including `/`, and `?` matches any single character.
+    AllowList(Vec<String>),
+}
+
+impl RepositoryCreationPolicy {
+    /// Return true if the repository with the given name may be created.
+    pub fn allows(&self, name: &str) -> bool {
+        match self {
+            Self::AutoCreate => true,
+            Self::Deny => false,
+            Self::AllowList(patterns) => patterns.iter().any(|p| glob_matches(p, name)),
+        }
+    }
+}
+
+/// Format of the `Range` header in upload session responses.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RangeFormat {
+    /// `0-<last>`, where `<last>` is the index of the last byte received, eg `0-99` after 100
+    /// bytes have been received.
+    #[default]
+    Inclusive,
+    /// `0-<count>`, where `<count>` is the number of bytes received, eg `0-100` after 100 bytes
+    /// have been received, for clients that read the end of the range as a byte count.
+    Count,
+}
+
+impl RangeFormat {
+    /// The range to report for an upload session whose last received byte is at
+    /// `last_range_end`.
+    pub(crate) fn range(&self, last_range_end: i64) -> Range {
+        let end = match self {
+            Self::Inclusive => last_range_end,
+            Self::Count => last_range_end + 1,
+        };
+        Range {
+            start: 0,
+            end: end as u64,
+        }
+    }
+}
+
+impl HttpConfig {
+    /// The effective maximum manifest size, in bytes.
+    pub fn max_manifest_bytes(&self) -> u64 {
+        self.max_manifest_bytes
+            .unwrap_or(DEFAULT_MAX_MANIFEST_BYTES)
+    }
+}
@@ -1,4 +1,6 @@
 use axum::response::{IntoResponse, Response};
+use headers::Header;
+use http::header::HeaderValue;
 use http::StatusCode;
 use serde::Serialize;
 use thiserror;
@@ -9,6 +11,9 @@ use oci_spec::distribution::ErrorResponseBuilder;
 use portfolio_core::Error as CoreError;
 use portfolio_core::PortfolioErrorCode;
 
+use super::headers::Range;
+use super::metrics;
+
 pub type Result<T> = std::result::Result<T, Error>;
 
 #[derive(thiserror::Error, Debug)]
@@ -24,6 +29,16 @@ pub enum Error {
     MissingHeader(&'static str),
     #[error("missing path parameter: {0}")]
     MissingPathParameter(&'static str),
+    #[error("unsupported content type: {0}")]
+    UnsupportedContentType(String),
+    #[error("manifest exceeds the maximum of {0} bytes")]
+    ManifestTooLarge(u64),
+    #[error("the referrers API is disabled")]
+    ReferrersDisabled,
+    /// A chunk didn't start where the upload left off; carries the range the upload has received
+    /// as it should be reported to the client.
+    #[error("{1}")]
+    UploadRangeInvalid(Range, String),
 
     #[error("portfolio spec error")]
     PortfolioSpecError(PortfolioErrorCode),
@@ -33,6 +48,11 @@ pub enum Error {
 
     #[error("internal server error")]
     InternalServerError(String),
+
+    #[error("io error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("invalid htpasswd entry on line {0}")]
+    InvalidHtpasswdEntry(usize),
 }
 
 impl IntoResponse for Error {
@@ -49,6 +69,28 @@ impl IntoResponse for Error {
             Error::MissingPathParameter(_) => {
                 (StatusCode::BAD_REQUEST, format!("{}", self)).into_response()
             }
+            Error::UnsupportedContentType(_) => {
+                (StatusCode::UNSUPPORTED_MEDIA_TYPE, format!("{}", self)).into_response()
+            }
+            Error::ManifestTooLarge(_) => {
+                let mut response = into_error_response(
+                    DistributionErrorCode::ManifestInvalid,
+                    Some(format!("{}", self)),
+                );
+                *response.status_mut() = StatusCode::PAYLOAD_TOO_LARGE;
+                response
+            }
+            // registries without the referrers API respond with 404 so that clients fall back to
+            // the referrers tag schema
+            Error::ReferrersDisabled => {
+                let mut response = into_error_response(
+                    DistributionErrorCode::Unsupported,
+                    Some(format!("{}", self)),
+                );
+                *response.status_mut() = StatusCode::NOT_FOUND;
+                response
+            }
+            Error::UploadRangeInvalid(range, msg) => range_not_satisfiable(&range, msg),
             Error::HTTPInvalidHeaderName(_) => {
                 (StatusCode::BAD_REQUEST, format!("{}", self)).into_response()
             }
@@ -63,6 +105,14 @@ impl IntoResponse for Error {
                 )
                     .into_response()
             }
+            Error::IoError(_) | Error::InvalidHtpasswdEntry(_) => {
+                tracing::warn!("{:?}", self);
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    String::from("internal server error"),
+                )
+                    .into_response()
+            }
         }
     }
 }
@@ -85,6 +135,18 @@ fn into_error_response(code: DistributionErrorCode, msg: Option<String>) -> Resp
     (status_code, axum::Json(error_response)).into_response()
 }
 
+/// Reject a chunk that doesn't follow the content already uploaded, reporting the range received
+/// so far so that the client can resume from the right place.
+fn range_not_satisfiable(range: &Range, msg: String) -> Response {
+    let mut response = into_error_response(DistributionErrorCode::BlobUploadInvalid, Some(msg));
+    let range: String = range.into();
+    response.headers_mut().insert(
+        Range::name(),
+        HeaderValue::from_str(&range).expect("range should always be a valid header value"),
+    );
+    response
+}
+
 #[inline]
 fn core_error_to_response(e: CoreError) -> Response {
     match e {
@@ -110,6 +172,14 @@ fn core_error_to_response(e: CoreError) -> Response {
             )
                 .into_response()
         }
+        CoreError::ContentCorrupt(s) => {
+            tracing::error!("{s}");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                String::from("internal server error"),
+            )
+                .into_response()
+        }
         CoreError::UuidError(e) => {
             into_error_response(DistributionErrorCode::DigestInvalid, Some(format!("{}", e)))
         }
@@ -121,6 +191,13 @@ fn core_error_to_response(e: CoreError) -> Response {
         CoreError::BlobUploadUnknown(s) => {
             into_error_response(DistributionErrorCode::BlobUploadUnknown, s)
         }
+        CoreError::ContentRangeInvalid(last_range_end, ref s) => range_not_satisfiable(
+            &Range {
+                start: 0,
+                end: last_range_end as u64,
+            },
+            s.clone().unwrap_or_else(|| format!("{e}")),
+        ),
         CoreError::DigestInvalid(s) => into_error_response(DistributionErrorCode::DigestInvalid, s),
         CoreError::ManifestBlobUnknown(s) => {
             into_error_response(DistributionErrorCode::ManifestBlobUnknown, s)
@@ -163,6 +240,11 @@ struct NonStandardErrorResponse {
 
 #[inline]
 fn into_nonstandard_error_response(code: PortfolioErrorCode, msg: Option<String>) -> Response {
+    match code {
+        PortfolioErrorCode::ManifestContentMissing => metrics::content_missing("manifest"),
+        PortfolioErrorCode::BlobContentMissing => metrics::content_missing("blob"),
+        PortfolioErrorCode::ContentReferenced => {}
+    }
     let msg = msg.or(Some(nonstandard_default_message(&code).to_string()));
     let status_code = nonstandard_status_code(&code);
     let response = NonStandardErrorResponse {
@@ -174,12 +256,16 @@ fn into_nonstandard_error_response(code: PortfolioErrorCode, msg: Option<String>
 fn nonstandard_default_message(c: &PortfolioErrorCode) -> &str {
     match c {
         PortfolioErrorCode::ContentReferenced => "content referenced",
+        PortfolioErrorCode::ManifestContentMissing => "manifest content missing from storage",
+        PortfolioErrorCode::BlobContentMissing => "blob content missing from storage",
     }
 }
 
 fn nonstandard_status_code(c: &PortfolioErrorCode) -> StatusCode {
     match c {
         PortfolioErrorCode::ContentReferenced => StatusCode::CONFLICT,
+        PortfolioErrorCode::ManifestContentMissing => StatusCode::INTERNAL_SERVER_ERROR,
+        PortfolioErrorCode::BlobContentMissing => StatusCode::INTERNAL_SERVER_ERROR,
     }
 }
 
@@ -0,0 +1,40 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use axum::body::StreamBody;
+use axum::extract::{Extension, Path};
+use axum::http::header::{self, HeaderMap, HeaderValue};
+use axum::response::{IntoResponse, Response};
+use http::StatusCode;
+
+use portfolio_core::registry::ManifestRef;
+use portfolio_core::Error as CoreError;
+
+use super::errors::{Error, Result};
+use super::ArcRepositoryStore;
+
+/// Stream the image manifest named by the `reference` path parameter, along with its config and
+/// layers, as an OCI Image Layout tarball for tooling that wants the image as a single file.
+pub(crate) async fn export_image(
+    Extension(repository): Extension<ArcRepositoryStore>,
+    Path(path_params): Path<HashMap<String, String>>,
+) -> Result<Response> {
+    let reference = ManifestRef::from_str(
+        path_params
+            .get("reference")
+            .ok_or_else(|| Error::MissingPathParameter("reference"))?,
+    )?;
+
+    let body = repository
+        .export_image(&reference)
+        .await?
+        .ok_or(CoreError::ManifestUnknown(None))?;
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/x-tar"),
+    );
+
+    Ok((StatusCode::OK, headers, StreamBody::new(body)).into_response())
+}
@@ -0,0 +1,39 @@
+use axum::extract::State;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use http::StatusCode;
+use serde::Serialize;
+
+use portfolio_core::registry::Features;
+
+use super::errors::Result;
+use super::Portfolio;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FeaturesResponse {
+    #[serde(flatten)]
+    features: Features,
+    max_manifest_size: u64,
+}
+
+/// Describe the optional behaviors supported by this registry, eg:
+///
+/// ```json
+/// {
+///   "referrers": true,
+///   "delete": true,
+///   "mount": true,
+///   "chunkMinLength": 5242880,
+///   "maxImageSize": 1073741824,
+///   "maxManifestSize": 4194304
+/// }
+/// ```
+pub(crate) async fn get_features(State(portfolio): State<Portfolio>) -> Result<Response> {
+    let response = FeaturesResponse {
+        features: portfolio.manager.features(),
+        max_manifest_size: portfolio.config.max_manifest_bytes(),
+    };
+
+    Ok((StatusCode::OK, Json(response)).into_response())
+}
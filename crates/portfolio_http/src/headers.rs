@@ -1,5 +1,27 @@
 use headers::{Header, HeaderName, HeaderValue};
 
+use portfolio_core::OciDigest;
+
+/// Name of the header carrying the digest of blob and manifest content, which responses for the
+/// same content must set identically whether they answer `GET` or `HEAD` requests.
+///
+/// [`HeaderName`]s are always lowercase, so it is sent as `docker-content-digest` unless the
+/// server is configured to title case header names; see [`crate::HttpConfig`].
+pub(crate) static DOCKER_CONTENT_DIGEST: HeaderName =
+    HeaderName::from_static("docker-content-digest");
+
+/// Name of the header identifying the upload session a blob upload response belongs to. It must
+/// be set identically by every upload handler so that clients see one value per session.
+pub(crate) static DOCKER_UPLOAD_UUID: HeaderName = HeaderName::from_static("docker-upload-uuid");
+
+/// Name of the header reporting the total number of bytes written to an upload session so far.
+pub(crate) static X_UPLOAD_BYTES_RECEIVED: HeaderName =
+    HeaderName::from_static("x-upload-bytes-received");
+
+/// Name of the header reporting the number of chunks written to an upload session so far.
+pub(crate) static X_UPLOAD_CHUNK_COUNT: HeaderName =
+    HeaderName::from_static("x-upload-chunk-count");
+
 #[derive(Debug)]
 pub struct ContentRange {
     pub start: u64,
@@ -108,3 +130,179 @@ impl Header for Range {
         values.extend(std::iter::once(value))
     }
 }
+
+/// Digest of the content of a single upload chunk, which the backend may verify before accepting
+/// the chunk, eg:
+///
+/// ```text
+/// Portfolio-Chunk-Digest: sha256:<hex>
+/// ```
+#[derive(Debug)]
+pub struct ChunkDigest(pub OciDigest);
+
+static CHUNK_DIGEST_NAME: HeaderName = HeaderName::from_static("portfolio-chunk-digest");
+
+impl Header for ChunkDigest {
+    fn name() -> &'static HeaderName {
+        &CHUNK_DIGEST_NAME
+    }
+
+    fn decode<'i, I>(values: &mut I) -> Result<Self, headers::Error>
+    where
+        I: Iterator<Item = &'i HeaderValue>,
+    {
+        let value = values.next().ok_or_else(headers::Error::invalid)?;
+        let s = value.to_str().map_err(|_| headers::Error::invalid())?;
+        let digest = OciDigest::try_from(s).map_err(|_| headers::Error::invalid())?;
+
+        Ok(ChunkDigest(digest))
+    }
+
+    fn encode<E>(&self, values: &mut E)
+    where
+        E: Extend<HeaderValue>,
+    {
+        let value = HeaderValue::from_str(&String::from(&self.0)).expect("this should always work");
+        values.extend(std::iter::once(value))
+    }
+}
+
+/// `Link` header pointing at the next page of a paginated listing as described by [RFC
+/// 5988](https://www.rfc-editor.org/rfc/rfc5988), eg:
+///
+/// ```text
+/// Link: </v2/<name>/tags/list?n=<n>&last=<last>>; rel="next"
+/// ```
+///
+/// The `last` cursor is URL-encoded. Any `filters` the listing was requested with, such as the
+/// referrers API's `artifactType`, are carried over to the next page ahead of `n` and `last`.
+#[derive(Debug, PartialEq)]
+pub struct NextLink {
+    pub path: String,
+    pub filters: Vec<(String, String)>,
+    pub n: i64,
+    pub last: String,
+}
+
+static LINK_NAME: HeaderName = HeaderName::from_static("link");
+
+impl Header for NextLink {
+    fn name() -> &'static HeaderName {
+        &LINK_NAME
+    }
+
+    fn decode<'i, I>(values: &mut I) -> Result<Self, headers::Error>
+    where
+        I: Iterator<Item = &'i HeaderValue>,
+    {
+        let value = values.next().ok_or_else(headers::Error::invalid)?;
+        let s = value.to_str().map_err(|_| headers::Error::invalid())?;
+        let uri = s
+            .strip_prefix('<')
+            .and_then(|s| s.strip_suffix(r#">; rel="next""#))
+            .ok_or_else(headers::Error::invalid)?;
+        let (path, query) = uri.split_once('?').ok_or_else(headers::Error::invalid)?;
+
+        let mut filters = Vec::new();
+        let mut n = None;
+        let mut last = None;
+        for (k, v) in form_urlencoded::parse(query.as_bytes()) {
+            match k.as_ref() {
+                "n" => n = Some(v.parse::<i64>().map_err(|_| headers::Error::invalid())?),
+                "last" => last = Some(v.into_owned()),
+                _ => filters.push((k.into_owned(), v.into_owned())),
+            }
+        }
+
+        Ok(NextLink {
+            path: path.to_string(),
+            filters,
+            n: n.ok_or_else(headers::Error::invalid)?,
+            last: last.ok_or_else(headers::Error::invalid)?,
+        })
+    }
+
+    fn encode<E>(&self, values: &mut E)
+    where
+        E: Extend<HeaderValue>,
+    {
+        let query = form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(&self.filters)
+            .append_pair("n", &self.n.to_string())
+            .append_pair("last", &self.last)
+            .finish();
+        let value = HeaderValue::from_str(&format!(r#"<{}?{}>; rel="next""#, self.path, query))
+            .expect("url-encoded link should always be a valid header value");
+        values.extend(std::iter::once(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use headers::HeaderMapExt;
+    use http::HeaderMap;
+
+    use super::*;
+
+    #[test]
+    fn next_link_round_trip() {
+        let link = NextLink {
+            path: "/v2/meow/woof/tags/list".to_string(),
+            filters: Vec::new(),
+            n: 2,
+            last: "v1.0+build&1".to_string(),
+        };
+
+        let mut headers = HeaderMap::new();
+        headers.typed_insert(link);
+        assert_eq!(
+            headers.get("link").unwrap(),
+            r#"</v2/meow/woof/tags/list?n=2&last=v1.0%2Bbuild%261>; rel="next""#
+        );
+
+        let decoded: NextLink = headers.typed_get().unwrap();
+        assert_eq!(decoded.n, 2);
+        assert_eq!(decoded.last, "v1.0+build&1");
+        assert_eq!(decoded.path, "/v2/meow/woof/tags/list");
+    }
+
+    #[test]
+    fn next_link_keeps_filters() {
+        let link = NextLink {
+            path: "/v2/meow/referrers/sha256:woof".to_string(),
+            filters: vec![(
+                "artifactType".to_string(),
+                "application/vnd.meow+json".to_string(),
+            )],
+            n: 2,
+            last: "sha256:bark".to_string(),
+        };
+
+        let mut headers = HeaderMap::new();
+        headers.typed_insert(link);
+        assert_eq!(
+            headers.get("link").unwrap(),
+            r#"</v2/meow/referrers/sha256:woof?artifactType=application%2Fvnd.meow%2Bjson&n=2&last=sha256%3Abark>; rel="next""#
+        );
+
+        let decoded: NextLink = headers.typed_get().unwrap();
+        assert_eq!(
+            decoded.filters,
+            vec![(
+                "artifactType".to_string(),
+                "application/vnd.meow+json".to_string()
+            )]
+        );
+        assert_eq!(decoded.last, "sha256:bark");
+    }
+
+    #[test]
+    fn range_format() {
+        let range = Range { start: 0, end: 34 };
+        assert_eq!(Into::<String>::into(&range), "0-34");
+
+        let mut headers = HeaderMap::new();
+        headers.typed_insert(range);
+        assert_eq!(headers.get("range").unwrap(), "0-34");
+    }
+}
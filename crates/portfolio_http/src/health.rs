@@ -0,0 +1,83 @@
+use std::collections::BTreeMap;
+
+use axum::extract::State;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use http::StatusCode;
+use serde::Serialize;
+
+use super::Portfolio;
+
+#[derive(Debug, Serialize)]
+struct ReadinessResponse {
+    ready: bool,
+    failed: BTreeMap<String, String>,
+}
+
+/// Liveness probe; responds with `200 OK` as long as the process is serving requests.
+pub(crate) async fn healthz() -> StatusCode {
+    StatusCode::OK
+}
+
+/// Readiness probe; responds with `200 OK` if the services the registry depends on are reachable
+/// and `503 Service Unavailable` otherwise, eg:
+///
+/// ```json
+/// {
+///   "ready": false,
+///   "failed": {
+///     "postgres": "pool timed out while waiting for an open connection"
+///   }
+/// }
+/// ```
+pub(crate) async fn readyz(State(portfolio): State<Portfolio>) -> Response {
+    readiness(portfolio.manager.check_dependencies().await)
+}
+
+fn readiness(failed: BTreeMap<String, String>) -> Response {
+    let status = if failed.is_empty() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    let response = ReadinessResponse {
+        ready: failed.is_empty(),
+        failed,
+    };
+
+    (status, Json(response)).into_response()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    async fn get(response: Response) -> (StatusCode, serde_json::Value) {
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn healthz_always_ok() {
+        assert_eq!(healthz().await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn readyz_ok_when_dependencies_reachable() {
+        let (status, body) = get(readiness(BTreeMap::new())).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, serde_json::json!({"ready": true, "failed": {}}));
+    }
+
+    #[tokio::test]
+    async fn readyz_unavailable_when_database_unreachable() {
+        let failed = BTreeMap::from([("postgres".to_string(), "unreachable".to_string())]);
+        let (status, body) = get(readiness(failed)).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            body,
+            serde_json::json!({"ready": false, "failed": {"postgres": "unreachable"}})
+        );
+    }
+}
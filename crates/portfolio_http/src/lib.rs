@@ -81,45 +81,72 @@
 use std::collections::HashMap;
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 
-use axum::extract::{Path, State};
+use axum::extract::{Extension, Path, State};
 use axum::http::header::{self, HeaderMap, HeaderName, HeaderValue};
-use axum::http::{Request, StatusCode};
+use axum::http::{Method, Request, StatusCode};
 use axum::middleware::Next;
 use axum::response::{IntoResponse, Response};
-use axum::routing::get;
+use axum::routing::{delete, get};
 use axum::Router;
 use http::Response as HttpResponse;
 use http_body::Body;
 use serde::{de, Deserialize, Deserializer};
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
 use tower_http::set_header::SetResponseHeaderLayer;
 use tower_http::trace::{self, TraceLayer};
 
 mod errors;
-pub(crate) use errors::Error;
+pub use errors::Error;
 pub(crate) use errors::Result;
 
+mod auth;
+pub use auth::{basic_auth, BasicAuthenticator};
+
+mod config;
+pub use config::{HttpConfig, RangeFormat, RepositoryCreationPolicy};
+
+mod annotations;
 pub(crate) mod blobs;
+mod catalog;
+mod export;
+mod features;
 pub(crate) mod headers;
+mod health;
 mod manifests;
+mod metrics;
+mod rate_limit;
 mod referrers;
+mod repositories;
+mod request_id;
 mod tags;
 
+use portfolio_core::registry::validate_repository_name;
 use portfolio_core::registry::RepositoryStore;
 use portfolio_core::registry::RepositoryStoreManager;
+use portfolio_core::registry::Visibility;
 use portfolio_core::Error as CoreError;
 
+use rate_limit::RateLimiter;
+
 /// Configuration struct defining parameters for statically-defined repositories initialized at
 /// program startup if they don't already exist.
 #[derive(Clone, Deserialize)]
 pub struct RepositoryDefinition {
     /// Name of repository to initialize.
     pub name: String,
+    /// Visibility to give the repository. Left unchanged for existing repositories if unset.
+    #[serde(default)]
+    pub visibility: Option<Visibility>,
 }
 
 /// Adds a [`axum::Extension`] containing a [`RepositoryStore`] for use in HTTP handlers. This is
 /// not included in the default [`axum::Router`] returned by [`self::Portfolio`] to enable users
 /// to add their own logic to determin how repositories are created or accessed.
+///
+/// Repositories that don't exist yet are created if [`HttpConfig::repository_creation`] allows
+/// it and reported as unknown otherwise.
 pub async fn add_basic_repository_extensions<B>(
     State(portfolio): State<Portfolio>,
     Path(path_params): Path<HashMap<String, String>>,
@@ -128,16 +155,32 @@ pub async fn add_basic_repository_extensions<B>(
 ) -> Result<Response> {
     let repo_name = match path_params.get("repository") {
         Some(s) => s,
-        None => return Err(Error::MissingPathParameter("repository")),
+        // routes that aren't scoped to a repository (eg `/v2/` and `/v2/_catalog`)
+        None => return Ok(next.run(req).await),
     };
 
+    validate_repository_name(repo_name)?;
+
     let repository = match portfolio.get_repository(repo_name).await {
         Err(e) => {
             tracing::warn!("error retrieving repository: {e:?}");
             return Err(CoreError::NameUnknown(None).into());
         }
         Ok(Some(r)) => r,
-        Ok(None) => portfolio.insert_repository(repo_name).await?,
+        // deleting content (or the repository itself) shouldn't bring a repository into existence
+        Ok(None) if req.method() == Method::DELETE => {
+            return Err(CoreError::NameUnknown(None).into())
+        }
+        Ok(None) if portfolio.config.repository_creation.allows(repo_name) => {
+            if !portfolio.repository_creations_allowed() {
+                return Err(CoreError::TooManyRequests(Some(
+                    "repository creation rate exceeded".to_string(),
+                ))
+                .into());
+            }
+            portfolio.insert_repository(repo_name).await?
+        }
+        Ok(None) => return Err(CoreError::NameUnknown(None).into()),
     };
 
     req.extensions_mut().insert(repository);
@@ -184,13 +227,29 @@ async fn version() -> Result<Response> {
 #[derive(Clone)]
 pub struct Portfolio {
     manager: Arc<dyn RepositoryStoreManager>,
+    config: Arc<HttpConfig>,
+    repository_creations: Option<Arc<RateLimiter>>,
 }
 
 pub(crate) type ArcRepositoryStore = Arc<dyn RepositoryStore + Send + Sync>;
 
 impl Portfolio {
     pub fn new(manager: Arc<dyn RepositoryStoreManager>) -> Self {
-        Self { manager }
+        Self {
+            manager,
+            config: Arc::new(HttpConfig::default()),
+            repository_creations: None,
+        }
+    }
+
+    /// Use the given [`HttpConfig`] in place of the default for the handlers in the
+    /// [`axum::Router`] returned by [`Self::router`].
+    pub fn with_config(mut self, config: HttpConfig) -> Self {
+        self.repository_creations = config
+            .max_repository_creations_per_minute
+            .map(|limit| Arc::new(RateLimiter::new(limit, Duration::from_secs(60))));
+        self.config = Arc::new(config);
+        self
     }
 
     pub async fn initialize_static_repositories(
@@ -209,6 +268,11 @@ impl Portfolio {
                 }
                 Err(e) => return Err(e),
             };
+            if let Some(visibility) = repository_config.visibility {
+                self.manager
+                    .set_visibility(&repository_config.name, visibility)
+                    .await?;
+            }
         }
         Ok(())
     }
@@ -224,6 +288,15 @@ impl Portfolio {
         }
     }
 
+    /// Count a repository creation against `max_repository_creations_per_minute`, returning false
+    /// if the limit has been reached.
+    fn repository_creations_allowed(&self) -> bool {
+        match &self.repository_creations {
+            Some(limiter) => limiter.try_acquire(),
+            None => true,
+        }
+    }
+
     async fn insert_repository(
         &self,
         name: &str,
@@ -235,24 +308,53 @@ impl Portfolio {
     pub fn router(&self) -> Result<axum::Router> {
         let blobs = blobs::router();
         let manifests = manifests::router();
-        let referrers = referrers::router();
+        let referrers = referrers::router(&self.config);
         let tags = tags::router();
 
         let repository = Router::new()
             .nest("/blobs", blobs)
             .nest("/manifests", manifests)
             .nest("/referrers", referrers)
-            .nest("/tags", tags);
+            .nest("/tags", tags)
+            .route("/annotations", get(annotations::get_annotated))
+            .route("/images/:reference/export", get(export::export_image))
+            .layer(Extension(self.config.clone()))
+            .layer(Extension(self.manager.features()));
 
+        // probes live outside `/v2` so that they're unaffected by registry API changes
         let app = Router::new()
             .route("/v2/", get(version))
-            .nest("/v2/:repository", repository)
+            .route("/healthz", get(health::healthz))
+            .route("/readyz", get(health::readyz).with_state(self.clone()));
+        #[cfg(feature = "metrics")]
+        let app = app.route("/metrics", get(metrics::get_metrics));
+        let app = app
+            .route(
+                "/v2/_catalog",
+                get(catalog::get_catalog).with_state(self.clone()),
+            )
+            // `_catalog` can't be a repository name, so this can't shadow repository routes
+            .route(
+                "/v2/_catalog/features",
+                get(features::get_features).with_state(self.clone()),
+            )
+            .route(
+                "/v2/:repository",
+                delete(repositories::delete_repository).with_state(self.clone()),
+            )
+            .nest("/v2/:repository", repository);
+        #[cfg(feature = "metrics")]
+        let app = app.layer(axum::middleware::from_fn(metrics::track_requests));
+        let app = app
             .layer(
                 TraceLayer::new_for_http()
-                    .make_span_with(trace::DefaultMakeSpan::new().include_headers(true))
+                    .make_span_with(request_id::RequestSpan)
                     .on_response(trace::DefaultOnResponse::new())
                     .on_request(trace::DefaultOnRequest::new()),
             )
+            // the request ID is set outside of the trace layer so that its span can record it
+            .layer(PropagateRequestIdLayer::x_request_id())
+            .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
             .layer(SetResponseHeaderLayer::if_not_present(
                 HeaderName::from_str("docker-distribution-api-version")?,
                 HeaderValue::from_str("registry/2.0")?,
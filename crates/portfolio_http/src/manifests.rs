@@ -1,36 +1,48 @@
 use std::collections::HashMap;
 use std::str::FromStr;
+use std::sync::Arc;
 
 use axum::body::{Bytes, StreamBody};
-use axum::extract::{DefaultBodyLimit, Extension, Path};
+use axum::extract::{Extension, Path, RawBody};
 use axum::http::header::{self, HeaderMap, HeaderName, HeaderValue};
 use axum::response::{IntoResponse, Response};
 use axum::routing::get;
-use axum::{Router, TypedHeader};
+use axum::{Json, Router, TypedHeader};
 use headers::{ContentLength, ContentType};
 use http::StatusCode;
+use hyper::body::HttpBody;
+use hyper::Body;
+use oci_spec::image::MediaType;
+use serde::Serialize;
 
-use portfolio_core::registry::{ManifestRef, ManifestSpec};
-use portfolio_core::Error as CoreError;
+use portfolio_core::registry::{
+    docker_to_oci_media_type, Features, ManifestRef, ManifestSpec, StreamableBody,
+};
+use portfolio_core::{Error as CoreError, OciDigest};
 
 use super::errors::{Error, Result};
+use super::headers::DOCKER_CONTENT_DIGEST;
+use super::metrics;
 use super::ArcRepositoryStore;
+use super::HttpConfig;
 
 pub fn router() -> Router {
-    Router::new()
-        .route(
-            "/:reference",
-            get(get_manifest)
-                .delete(delete_manifest)
-                .put(put_manifest)
-                .head(head_manifest),
-        )
-        .layer(DefaultBodyLimit::max(6 * 1024 * 1024))
+    Router::new().route(
+        "/:reference",
+        get(get_manifest)
+            .delete(delete_manifest)
+            .put(put_manifest)
+            .head(head_manifest)
+            // only `validate` accepts POST; it's routed here so that a tag of the same name can
+            // still be pulled and pushed
+            .post(validate_manifest),
+    )
 }
 
 async fn head_manifest(
     Extension(repository): Extension<ArcRepositoryStore>,
     Path(path_params): Path<HashMap<String, String>>,
+    request_headers: HeaderMap,
 ) -> Result<Response> {
     let manifest_ref = ManifestRef::from_str(
         path_params
@@ -42,25 +54,38 @@ async fn head_manifest(
     let manifest = mstore.head(&manifest_ref).await?;
 
     if let Some(manifest) = manifest {
+        check_acceptable(&request_headers, manifest.media_type().as_ref())?;
+
         let mut headers = HeaderMap::new();
         let dgst: String = manifest.digest().into();
         headers.insert(
-            HeaderName::from_lowercase(b"docker-content-digest")?,
+            &DOCKER_CONTENT_DIGEST,
             HeaderValue::from_str(dgst.as_str())?,
         );
         headers.insert(
             header::CONTENT_LENGTH,
             HeaderValue::from_str(manifest.bytes_on_disk().to_string().as_str())?,
         );
+        // reported the same way as by `get_manifest`, except that manifests stored without a
+        // media type are left with the default since inferring one requires reading the body
+        match (manifest.content_type(), manifest.media_type()) {
+            (Some(ct), _) => {
+                headers.insert(header::CONTENT_TYPE, HeaderValue::from_str(ct)?);
+            }
+            (None, Some(mt)) => insert_content_type(&mut headers, mt)?,
+            (None, None) => {}
+        }
         return Ok((StatusCode::OK, headers, "").into_response());
     }
 
-    Err(CoreError::ManifestBlobUnknown(None).into())
+    Err(CoreError::ManifestUnknown(None).into())
 }
 
 async fn get_manifest(
     Extension(repository): Extension<ArcRepositoryStore>,
+    Extension(config): Extension<Arc<HttpConfig>>,
     Path(path_params): Path<HashMap<String, String>>,
+    request_headers: HeaderMap,
 ) -> Result<Response> {
     let manifest_ref = ManifestRef::from_str(
         path_params
@@ -78,36 +103,228 @@ async fn get_manifest(
     let mut headers = HeaderMap::new();
     let dgst: String = manifest.digest().into();
     headers.insert(
-        HeaderName::from_lowercase(b"docker-content-digest")?,
+        &DOCKER_CONTENT_DIGEST,
         HeaderValue::from_str(dgst.as_str())?,
     );
     headers.insert(
         header::CONTENT_LENGTH,
         HeaderValue::from_str(manifest.bytes_on_disk().to_string().as_str())?,
     );
+
+    // the router defaults Content-Type to `application/json` when handlers don't set it, which
+    // would leave clients unable to tell image manifests and indexes (or OCI and Docker
+    // manifests) apart
     if let Some(mt) = manifest.media_type() {
-        let content_type: String = mt.clone().into();
-        headers.insert(
-            http::header::CONTENT_TYPE,
-            HeaderValue::from_str(content_type.as_str())?,
-        );
+        // digests identify the exact stored bytes, so only pulls by tag are converted
+        if let (true, ManifestRef::Tag(_), Some(oci_mt)) = (
+            config.convert_docker_manifests,
+            &manifest_ref,
+            docker_to_oci_media_type(mt),
+        ) {
+            if prefers(&request_headers, &oci_mt, mt) {
+                return docker_to_oci_response(headers, body, &config).await;
+            }
+        }
+
+        check_acceptable(&request_headers, Some(mt))?;
+        match manifest.content_type() {
+            // serve the Content-Type the manifest was pushed with verbatim, parameters and all
+            Some(ct) => {
+                headers.insert(header::CONTENT_TYPE, HeaderValue::from_str(ct)?);
+            }
+            None => insert_content_type(&mut headers, mt)?,
+        }
+        return Ok((StatusCode::OK, headers, StreamBody::new(body)).into_response());
+    }
+
+    // manifests pushed without a media type have one inferred at push time, but fall back to
+    // inferring it here in case an older manifest was stored without one
+    let bytes = read_stored_manifest(body, &config).await?;
+    let media_type = match ManifestSpec::try_from(&bytes).and_then(|mut spec| {
+        spec.infer_media_type()?;
+        Ok(spec.media_type())
+    }) {
+        Ok(mt) => mt,
+        Err(e) => {
+            tracing::warn!("unable to infer media type of stored manifest: {e:?}");
+            None
+        }
+    };
+    check_acceptable(&request_headers, media_type.as_ref())?;
+    if let Some(mt) = media_type {
+        insert_content_type(&mut headers, &mt)?;
+    }
+    Ok((StatusCode::OK, headers, bytes).into_response())
+}
+
+/// Buffer a stored manifest that needs to be parsed, failing with `MANIFEST_INVALID` rather than
+/// buffering more than the configured maximum manifest size.
+async fn read_stored_manifest(body: StreamableBody, config: &HttpConfig) -> Result<Bytes> {
+    let limit = config.max_manifest_bytes();
+    read_manifest(Body::wrap_stream(body), limit)
+        .await?
+        .ok_or_else(|| {
+            CoreError::ManifestInvalid(Some(format!(
+                "manifest exceeds the maximum of {limit} bytes"
+            )))
+            .into()
+        })
+}
+
+/// Buffer a manifest body, returning `None` as soon as it turns out to be larger than `limit`
+/// bytes rather than buffering all of it.
+async fn read_manifest(mut body: Body, limit: u64) -> Result<Option<Bytes>> {
+    let mut buf = Vec::new();
+    while let Some(bs) = body.data().await {
+        let bs =
+            bs.map_err(|e| Error::InternalServerError(format!("error reading manifest: {e:?}")))?;
+        if (buf.len() + bs.len()) as u64 > limit {
+            return Ok(None);
+        }
+        buf.extend_from_slice(&bs);
     }
-    Ok((StatusCode::OK, headers, StreamBody::new(body)).into_response())
+    Ok(Some(Bytes::from(buf)))
+}
+
+/// Reject requests whose `Accept` headers don't list `media_type`, eg a client that only
+/// understands indexes resolving a tag that points to an image manifest.
+///
+/// Requests without an `Accept` header accept any media type, as do `*/*` and `type/*` entries.
+/// Manifests whose media type can't be determined are always considered acceptable.
+fn check_acceptable(request_headers: &HeaderMap, media_type: Option<&MediaType>) -> Result<()> {
+    let media_type: String = match media_type {
+        Some(mt) => mt.clone().into(),
+        None => return Ok(()),
+    };
+    let (top_level, _) = media_type
+        .split_once('/')
+        .unwrap_or((media_type.as_str(), ""));
+
+    let mut accepted = request_headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|entry| entry.split(';').next().unwrap_or_default().trim())
+        .filter(|entry| !entry.is_empty())
+        .peekable();
+
+    // an empty Accept header list means the client will take whatever it's given
+    if accepted.peek().is_none() {
+        return Ok(());
+    }
+
+    if accepted.any(|entry| match entry.split_once('/') {
+        Some(("*", "*")) => true,
+        Some((t, "*")) => t.eq_ignore_ascii_case(top_level),
+        _ => entry.eq_ignore_ascii_case(&media_type),
+    }) {
+        return Ok(());
+    }
+
+    Err(CoreError::ManifestUnknown(Some(format!(
+        "manifest media type {media_type} is not acceptable"
+    )))
+    .into())
+}
+
+/// Whether the `Accept` headers explicitly list `preferred`, either without `other` or ahead of it.
+fn prefers(request_headers: &HeaderMap, preferred: &MediaType, other: &MediaType) -> bool {
+    let preferred: String = preferred.clone().into();
+    let other: String = other.clone().into();
+    for entry in request_headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|entry| entry.split(';').next().unwrap_or_default().trim())
+    {
+        if entry.eq_ignore_ascii_case(&preferred) {
+            return true;
+        }
+        if entry.eq_ignore_ascii_case(&other) {
+            return false;
+        }
+    }
+    false
+}
+
+/// Serve the stored Docker schema 2 manifest in `body` rewritten as its OCI equivalent.
+async fn docker_to_oci_response(
+    mut headers: HeaderMap,
+    body: StreamableBody,
+    config: &HttpConfig,
+) -> Result<Response> {
+    let bytes = read_stored_manifest(body, config).await?;
+    let mut spec = ManifestSpec::try_from(&bytes)?;
+    if !spec.convert_docker_to_oci() {
+        return Err(Error::InternalServerError(String::from(
+            "stored manifest is not a docker schema 2 manifest",
+        )));
+    }
+    let converted =
+        Bytes::from(serde_json::to_vec(&spec).map_err(|e| {
+            Error::InternalServerError(format!("error converting manifest: {e:?}"))
+        })?);
+
+    // the converted manifest no longer matches the stored one's digest
+    let dgst: String = OciDigest::from(converted.as_ref()).into();
+    headers.insert(
+        &DOCKER_CONTENT_DIGEST,
+        HeaderValue::from_str(dgst.as_str())?,
+    );
+    headers.insert(
+        header::CONTENT_LENGTH,
+        HeaderValue::from_str(converted.len().to_string().as_str())?,
+    );
+    if let Some(mt) = spec.media_type() {
+        insert_content_type(&mut headers, &mt)?;
+    }
+    Ok((StatusCode::OK, headers, converted).into_response())
+}
+
+fn insert_content_type(headers: &mut HeaderMap, media_type: &MediaType) -> Result<()> {
+    let content_type: String = media_type.clone().into();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_str(content_type.as_str())?,
+    );
+    Ok(())
 }
 
 /// https://github.com/opencontainers/distribution-spec/blob/main/spec.md#pushing-manifests
 async fn put_manifest(
     Extension(repository): Extension<ArcRepositoryStore>,
+    Extension(config): Extension<Arc<HttpConfig>>,
+    Extension(features): Extension<Features>,
     content_type: Option<TypedHeader<ContentType>>,
     content_length: Option<TypedHeader<ContentLength>>,
     Path(path_params): Path<HashMap<String, String>>,
-    bytes: Bytes,
+    request_headers: HeaderMap,
+    RawBody(body): RawBody,
 ) -> Result<Response> {
     let mref = path_params
         .get("reference")
         .ok_or_else(|| Error::MissingPathParameter("reference"))?;
     let manifest_ref = ManifestRef::from_str(mref)?;
 
+    // oversized manifests are rejected before being buffered or parsed, by their Content-Length
+    // when it's given and otherwise as soon as the body grows past the limit
+    let limit = config.max_manifest_bytes();
+    if let Some(TypedHeader(content_length)) = content_length {
+        if content_length.0 > limit {
+            return Err(Error::ManifestTooLarge(limit));
+        }
+    }
+    let bytes = read_manifest(body, limit)
+        .await?
+        .ok_or(Error::ManifestTooLarge(limit))?;
+
+    // kept exactly as sent so that pulls can return it byte-for-byte
+    let raw_content_type = request_headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok());
+
     // we need to deserialize the request body into a type we can use to determine how to represent
     // it in the database, but according to distribution spec we also need to store the exact byte
     // representation provided by the client. because there is a good chance of information loss
@@ -129,13 +346,12 @@ async fn put_manifest(
             tracing::warn!("client neglected to include content type in header");
         }
         (Some(mt), Some(TypedHeader(ct))) => {
-            if mt != ct.to_string().as_str().into() {
+            if mt != essence(&ct.to_string()).into() {
                 return Err(CoreError::ManifestInvalid(None).into());
             }
         }
         (None, Some(TypedHeader(ct))) => {
-            let s = ct.to_string();
-            manifest.set_media_type(s.as_str());
+            manifest.set_media_type(essence(&ct.to_string()));
         }
         (None, None) => {
             tracing::warn!(
@@ -149,24 +365,23 @@ async fn put_manifest(
         }
     }
 
-    if let Some(TypedHeader(content_length)) = content_length {
-        if content_length.0 > 4 * 1024 * 1024 {
-            return Err(CoreError::SizeInvalid(None).into());
-        }
-    }
-
     let mut mstore = repository.get_manifest_store();
-    let calculated_digest = mstore.put(&manifest_ref, &manifest, bytes).await?;
+    let calculated_digest = mstore
+        .put(&manifest_ref, &manifest, bytes, raw_content_type)
+        .await?;
+    metrics::manifest_pushed();
 
     let location = format!("/v2/{}/manifests/{}", repository.name(), mref);
     let mut headers = HeaderMap::new();
     headers.insert(header::LOCATION, HeaderValue::from_str(&location)?);
     headers.insert(
-        HeaderName::from_lowercase(b"docker-content-digest")?,
+        &DOCKER_CONTENT_DIGEST,
         HeaderValue::from_str(String::from(calculated_digest).as_ref())?,
     );
 
-    if let Some(subject) = manifest.subject() {
+    // the subject is only processed when the referrers API is available; without this header
+    // clients know to update the referrers tag schema themselves
+    if let Some(subject) = manifest.subject().filter(|_| features.referrers) {
         headers.insert(
             HeaderName::from_lowercase(b"oci-subject")?,
             HeaderValue::from_str(subject.digest().as_str())?,
@@ -176,6 +391,66 @@ async fn put_manifest(
     Ok((StatusCode::CREATED, headers, "").into_response())
 }
 
+#[derive(Debug, Serialize)]
+struct ValidationResponse {
+    missing: Vec<String>,
+}
+
+/// Report which of the blobs (or, for an index, manifests) referenced by the manifest in the
+/// request body haven't been pushed to the repository yet, without storing anything, eg:
+///
+/// ```json
+/// {
+///   "missing": ["sha256:<hex>"]
+/// }
+/// ```
+///
+/// This is an extension to the Distribution Spec served at `POST /v2/<name>/manifests/validate`,
+/// allowing clients to check that a manifest can be pushed before pushing it.
+async fn validate_manifest(
+    Extension(repository): Extension<ArcRepositoryStore>,
+    Extension(config): Extension<Arc<HttpConfig>>,
+    content_length: Option<TypedHeader<ContentLength>>,
+    Path(path_params): Path<HashMap<String, String>>,
+    RawBody(body): RawBody,
+) -> Result<Response> {
+    if path_params.get("reference").map(String::as_str) != Some("validate") {
+        return Ok(StatusCode::METHOD_NOT_ALLOWED.into_response());
+    }
+
+    let limit = config.max_manifest_bytes();
+    if let Some(TypedHeader(content_length)) = content_length {
+        if content_length.0 > limit {
+            return Err(Error::ManifestTooLarge(limit));
+        }
+    }
+    let bytes = read_manifest(body, limit)
+        .await?
+        .ok_or(Error::ManifestTooLarge(limit))?;
+    let manifest = ManifestSpec::try_from(&bytes).map_err(|e| {
+        tracing::warn!("error deserializing manifest: {e:?}");
+        CoreError::ManifestInvalid(None)
+    })?;
+
+    let missing = repository
+        .get_manifest_store()
+        .missing_references(&manifest)
+        .await?;
+    let response = ValidationResponse {
+        missing: missing.iter().map(String::from).collect(),
+    };
+
+    Ok((StatusCode::OK, Json(response)).into_response())
+}
+
+/// The media type named by a Content-Type header value, without parameters such as `charset`.
+fn essence(content_type: &str) -> &str {
+    content_type
+        .split_once(';')
+        .map_or(content_type, |(essence, _)| essence)
+        .trim()
+}
+
 async fn delete_manifest(
     Extension(repository): Extension<ArcRepositoryStore>,
     Path(path_params): Path<HashMap<String, String>>,
@@ -0,0 +1,187 @@
+//! Prometheus instrumentation for the Distribution API.
+//!
+//! Metrics are recorded in the default [`prometheus`] registry, which is shared with other
+//! portfolio crates (eg object store operation timings), and served in the text exposition format
+//! at `/metrics`.
+//!
+//! When the `metrics` feature is disabled the helpers in this module are no-ops so that handlers
+//! don't need to care whether metrics are being collected.
+use hyper::body::Body;
+use portfolio_core::registry::StreamableBody;
+
+#[cfg(feature = "metrics")]
+use axum::extract::MatchedPath;
+#[cfg(feature = "metrics")]
+use axum::http::header::{self, HeaderMap, HeaderValue};
+#[cfg(feature = "metrics")]
+use axum::http::{Request, StatusCode};
+#[cfg(feature = "metrics")]
+use axum::middleware::Next;
+#[cfg(feature = "metrics")]
+use axum::response::{IntoResponse, Response};
+#[cfg(feature = "metrics")]
+use futures::StreamExt;
+#[cfg(feature = "metrics")]
+use once_cell::sync::Lazy;
+#[cfg(feature = "metrics")]
+use prometheus::{
+    register_histogram_vec, register_int_counter, register_int_counter_vec, Encoder, HistogramVec,
+    IntCounter, IntCounterVec, TextEncoder,
+};
+
+#[cfg(feature = "metrics")]
+static HTTP_REQUESTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "portfolio_http_requests_total",
+        "Number of HTTP requests handled, by route and response status.",
+        &["method", "route", "status"]
+    )
+    .expect("http metrics are registered once")
+});
+
+#[cfg(feature = "metrics")]
+static HTTP_REQUEST_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "portfolio_http_request_duration_seconds",
+        "Time taken to handle HTTP requests, by route.",
+        &["method", "route"]
+    )
+    .expect("http metrics are registered once")
+});
+
+#[cfg(feature = "metrics")]
+static BLOB_BYTES_UPLOADED: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "portfolio_blob_bytes_uploaded_total",
+        "Number of blob bytes received from clients."
+    )
+    .expect("http metrics are registered once")
+});
+
+#[cfg(feature = "metrics")]
+static BLOB_BYTES_DOWNLOADED: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "portfolio_blob_bytes_downloaded_total",
+        "Number of blob bytes sent to clients."
+    )
+    .expect("http metrics are registered once")
+});
+
+#[cfg(feature = "metrics")]
+static MANIFEST_PUTS: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "portfolio_manifest_puts_total",
+        "Number of manifests successfully pushed."
+    )
+    .expect("http metrics are registered once")
+});
+
+#[cfg(feature = "metrics")]
+static CONTENT_MISSING: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "portfolio_content_missing_total",
+        "Number of requests for content whose metadata exists but is missing from the object store.",
+        &["kind"]
+    )
+    .expect("http metrics are registered once")
+});
+
+/// Middleware recording the count, status, and latency of each request.
+///
+/// Requests are labelled by their matched route (eg `/v2/:repository/blobs/:digest`) rather than
+/// their literal path to keep label cardinality bounded.
+#[cfg(feature = "metrics")]
+pub(crate) async fn track_requests<B>(req: Request<B>, next: Next<B>) -> Response {
+    let method = req.method().to_string();
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+
+    let timer = HTTP_REQUEST_DURATION
+        .with_label_values(&[&method, &route])
+        .start_timer();
+    let response = next.run(req).await;
+    timer.observe_duration();
+
+    HTTP_REQUESTS
+        .with_label_values(&[&method, &route, response.status().as_str()])
+        .inc();
+
+    response
+}
+
+/// Serve the contents of the default registry in the Prometheus text exposition format.
+#[cfg(feature = "metrics")]
+pub(crate) async fn get_metrics() -> Response {
+    // metrics are registered on first use; make sure they're all present from the first scrape
+    Lazy::force(&BLOB_BYTES_UPLOADED);
+    Lazy::force(&BLOB_BYTES_DOWNLOADED);
+    Lazy::force(&MANIFEST_PUTS);
+    Lazy::force(&CONTENT_MISSING);
+
+    let encoder = TextEncoder::new();
+    let mut buf = Vec::new();
+    if let Err(e) = encoder.encode(&prometheus::gather(), &mut buf) {
+        tracing::warn!("error encoding metrics: {e:?}");
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static(prometheus::TEXT_FORMAT),
+    );
+    (StatusCode::OK, headers, buf).into_response()
+}
+
+/// Count blob bytes as they are read from an upload request body.
+#[cfg(feature = "metrics")]
+pub(crate) fn count_uploaded_bytes(body: Body) -> Body {
+    Body::wrap_stream(body.inspect(|chunk| {
+        if let Ok(chunk) = chunk {
+            BLOB_BYTES_UPLOADED.inc_by(chunk.len() as u64);
+        }
+    }))
+}
+
+/// Count blob bytes as they are streamed to the client.
+#[cfg(feature = "metrics")]
+pub(crate) fn count_downloaded_bytes(body: StreamableBody) -> StreamableBody {
+    body.inspect(|chunk| {
+        if let Ok(chunk) = chunk {
+            BLOB_BYTES_DOWNLOADED.inc_by(chunk.len() as u64);
+        }
+    })
+    .boxed()
+}
+
+/// Record a successful manifest push.
+#[cfg(feature = "metrics")]
+pub(crate) fn manifest_pushed() {
+    MANIFEST_PUTS.inc();
+}
+
+/// Record a request for content that the metadata and object store disagree about, eg `blob` or
+/// `manifest`, so that operators can alert on it.
+#[cfg(feature = "metrics")]
+pub(crate) fn content_missing(kind: &str) {
+    CONTENT_MISSING.with_label_values(&[kind]).inc();
+}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn count_uploaded_bytes(body: Body) -> Body {
+    body
+}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn count_downloaded_bytes(body: StreamableBody) -> StreamableBody {
+    body
+}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn manifest_pushed() {}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn content_missing(_kind: &str) {}
@@ -0,0 +1,67 @@
+//! # Rate limits
+//!
+//! [`RateLimiter`] caps how many times an action may be taken within a fixed window, eg how many
+//! repositories [`crate::add_basic_repository_extensions`] may create per minute.
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Allows an action to be taken up to `limit` times per `window`. The count resets once a window
+/// has elapsed since the first action counted in it.
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    limit: u32,
+    window: Duration,
+    state: Mutex<(Instant, u32)>,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(limit: u32, window: Duration) -> Self {
+        Self {
+            limit,
+            window,
+            state: Mutex::new((Instant::now(), 0)),
+        }
+    }
+
+    /// Count an action against the limit, returning false without counting it if the limit has
+    /// already been reached in the current window.
+    pub(crate) fn try_acquire(&self) -> bool {
+        self.try_acquire_at(Instant::now())
+    }
+
+    fn try_acquire_at(&self, now: Instant) -> bool {
+        let mut state = self.state.lock().expect("rate limiter lock poisoned");
+        let (window_start, count) = &mut *state;
+        if now.saturating_duration_since(*window_start) >= self.window {
+            *window_start = now;
+            *count = 0;
+        }
+        if *count >= self.limit {
+            return false;
+        }
+        *count += 1;
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn limit_resets_after_window() {
+        let start = Instant::now();
+        let limiter = RateLimiter {
+            limit: 2,
+            window: Duration::from_secs(60),
+            state: Mutex::new((start, 0)),
+        };
+
+        assert!(limiter.try_acquire_at(start));
+        assert!(limiter.try_acquire_at(start + Duration::from_secs(1)));
+        assert!(!limiter.try_acquire_at(start + Duration::from_secs(59)));
+        assert!(limiter.try_acquire_at(start + Duration::from_secs(60)));
+        assert!(limiter.try_acquire_at(start + Duration::from_secs(61)));
+        assert!(!limiter.try_acquire_at(start + Duration::from_secs(62)));
+    }
+}
@@ -1,35 +1,104 @@
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 
 use axum::extract::{Extension, Path, Query};
 use axum::http::header::{self, HeaderMap, HeaderName, HeaderValue};
 use axum::response::{IntoResponse, Response};
 use axum::routing::get;
 use axum::{Json, Router};
+use headers::HeaderMapExt;
 use http::StatusCode;
 use oci_spec::image::MediaType;
 use serde::Deserialize;
 
-use portfolio_core::OciDigest;
+use portfolio_core::registry::Features;
+use portfolio_core::{Error as CoreError, OciDigest};
 
 use super::empty_string_as_none;
 use super::errors::{Error, Result};
+use super::headers::NextLink;
 use super::ArcRepositoryStore;
+use super::HttpConfig;
 
-pub fn router() -> Router {
-    Router::new().route("/:digest", get(get_referrers))
+pub fn router(config: &HttpConfig) -> Router {
+    Router::new()
+        .route("/:digest", get(get_referrers))
+        .layer(Extension(
+            config
+                .max_concurrent_referrers_requests
+                .map(ReferrersLimiter::new),
+        ))
+}
+
+/// Caps the number of referrers requests handled at once, independently of any other requests,
+/// so that a burst of referrers listings (each of which fans out to many object store reads)
+/// can't starve pushes and pulls.
+#[derive(Clone, Debug)]
+struct ReferrersLimiter {
+    in_flight: Arc<AtomicUsize>,
+    limit: usize,
+}
+
+impl ReferrersLimiter {
+    fn new(limit: usize) -> Self {
+        Self {
+            in_flight: Arc::new(AtomicUsize::new(0)),
+            limit,
+        }
+    }
+
+    /// Admit a request if fewer than `limit` are already being handled; it counts as in flight
+    /// until the returned permit is dropped.
+    fn try_acquire(&self) -> Option<ReferrersPermit> {
+        self.in_flight
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                (n < self.limit).then_some(n + 1)
+            })
+            .ok()?;
+        Some(ReferrersPermit(self.in_flight.clone()))
+    }
+}
+
+struct ReferrersPermit(Arc<AtomicUsize>);
+
+impl Drop for ReferrersPermit {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
 }
 
 #[derive(Debug, Deserialize)]
 struct GetParams {
-    #[serde(default, deserialize_with = "empty_string_as_none")]
+    #[serde(
+        default,
+        rename = "artifactType",
+        deserialize_with = "empty_string_as_none"
+    )]
     artifact_type: Option<String>,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    n: Option<i64>,
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    last: Option<String>,
 }
 
 async fn get_referrers(
     Extension(repository): Extension<ArcRepositoryStore>,
+    Extension(features): Extension<Features>,
+    Extension(limiter): Extension<Option<ReferrersLimiter>>,
     Path(path_params): Path<HashMap<String, String>>,
     Query(params): Query<GetParams>,
 ) -> Result<Response> {
+    if !features.referrers {
+        return Err(Error::ReferrersDisabled);
+    }
+    let _permit = match &limiter {
+        Some(limiter) => Some(limiter.try_acquire().ok_or_else(|| {
+            CoreError::TooManyRequests(Some("too many concurrent referrers requests".to_string()))
+        })?),
+        None => None,
+    };
+
     let digest: &str = path_params
         .get("digest")
         .ok_or_else(|| Error::MissingQueryParameter("digest"))?;
@@ -37,7 +106,12 @@ async fn get_referrers(
 
     let mstore = repository.get_manifest_store();
     let image_index = mstore
-        .get_referrers(&oci_digest, params.artifact_type.clone())
+        .get_referrers(
+            &oci_digest,
+            params.artifact_type.clone(),
+            params.n,
+            params.last.clone(),
+        )
         .await?;
 
     let mut headers = HeaderMap::new();
@@ -46,12 +120,63 @@ async fn get_referrers(
         HeaderValue::from_str(MediaType::ImageIndex.to_string().as_str())?,
     );
 
-    if let Some(artifact_type) = &params.artifact_type {
+    // the header names the filters that were applied rather than their values
+    if params.artifact_type.is_some() {
         headers.insert(
             HeaderName::from_lowercase(b"oci-filters-applied")?,
-            HeaderValue::from_str(artifact_type.as_str())?,
+            HeaderValue::from_static("artifactType"),
         );
     }
 
+    // a full page means there may be more results; let the client know where to find them
+    if let (Some(n), Some(last)) = (params.n, image_index.manifests().last()) {
+        if image_index.manifests().len() as i64 == n {
+            headers.typed_insert(NextLink {
+                path: format!("/v2/{}/referrers/{}", repository.name(), digest),
+                filters: params
+                    .artifact_type
+                    .iter()
+                    .map(|a| ("artifactType".to_string(), a.clone()))
+                    .collect(),
+                n,
+                last: last.digest().clone(),
+            });
+        }
+    }
+
     Ok((StatusCode::OK, headers, Json(image_index)).into_response())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn limiter_rejects_requests_beyond_limit() {
+        let limiter = ReferrersLimiter::new(2);
+
+        let first = limiter.try_acquire();
+        let second = limiter.try_acquire();
+        assert!(first.is_some());
+        assert!(second.is_some());
+        assert!(limiter.try_acquire().is_none());
+
+        // a slot frees up once a request completes
+        drop(first);
+        let third = limiter.try_acquire();
+        assert!(third.is_some());
+        assert!(limiter.try_acquire().is_none());
+
+        drop(second);
+        drop(third);
+        assert_eq!(limiter.in_flight.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn limiter_shared_between_clones() {
+        // the router hands each request a clone of the limiter, so they must share one count
+        let limiter = ReferrersLimiter::new(1);
+        let _permit = limiter.clone().try_acquire().unwrap();
+        assert!(limiter.clone().try_acquire().is_none());
+    }
+}
@@ -0,0 +1,14 @@
+use axum::extract::{Path, State};
+use axum::response::{IntoResponse, Response};
+use http::StatusCode;
+
+use super::errors::Result;
+use super::Portfolio;
+
+pub(crate) async fn delete_repository(
+    State(portfolio): State<Portfolio>,
+    Path(repository): Path<String>,
+) -> Result<Response> {
+    portfolio.manager.delete(&repository).await?;
+    Ok(StatusCode::ACCEPTED.into_response())
+}
@@ -0,0 +1,34 @@
+//! # Request IDs
+//!
+//! Every request is identified by the `X-Request-Id` header, which is generated if the client
+//! didn't send one and echoed on the response. The ID is recorded on the `request` [`Span`] that
+//! the rest of the request is handled in, so log lines emitted by the metadata and object store
+//! layers while serving it, including the S3 client's, can be correlated with it.
+use axum::http::Request;
+use tower_http::request_id::RequestId;
+use tower_http::trace::MakeSpan;
+use tracing::Span;
+
+/// Creates the span each request is handled in, recording its method, URI, version and ID. It is
+/// created at `INFO` rather than `DEBUG` like [`tower_http::trace::DefaultMakeSpan`] so that the
+/// ID is attached to log lines at the levels typically enabled in production, which is also why
+/// headers, including any credentials, aren't recorded.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct RequestSpan;
+
+impl<B> MakeSpan<B> for RequestSpan {
+    fn make_span(&mut self, request: &Request<B>) -> Span {
+        let request_id = request
+            .extensions()
+            .get::<RequestId>()
+            .and_then(|id| id.header_value().to_str().ok())
+            .unwrap_or_default();
+        tracing::info_span!(
+            "request",
+            request_id,
+            method = %request.method(),
+            uri = %request.uri(),
+            version = ?request.version(),
+        )
+    }
+}
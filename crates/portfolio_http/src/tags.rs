@@ -1,13 +1,19 @@
+use std::sync::Arc;
+
 use axum::extract::{Extension, Query};
+use axum::http::header::HeaderMap;
 use axum::response::{IntoResponse, Response};
 use axum::routing::get;
 use axum::{Json, Router};
+use headers::HeaderMapExt;
 use http::StatusCode;
 use serde::Deserialize;
 
 use super::empty_string_as_none;
 use super::errors::Result;
+use super::headers::NextLink;
 use super::ArcRepositoryStore;
+use super::HttpConfig;
 
 pub fn router() -> Router {
     Router::new().route("/list", get(get_tags))
@@ -23,10 +29,25 @@ struct GetListParams {
 
 async fn get_tags(
     Extension(repository): Extension<ArcRepositoryStore>,
+    Extension(config): Extension<Arc<HttpConfig>>,
     Query(params): Query<GetListParams>,
 ) -> Result<Response> {
+    let n = params.n.or(config.default_tags_page_size);
     let mstore = repository.get_manifest_store();
-    let tags_list = mstore.get_tags_list(params.n, params.last).await?;
+    let tags_list = mstore.get_tags_list(n, params.last).await?;
+
+    let mut headers = HeaderMap::new();
+    // a full page means there may be more results; let the client know where to find them
+    if let (Some(n), Some(last)) = (n, tags_list.tags().last()) {
+        if tags_list.tags().len() as i64 == n {
+            headers.typed_insert(NextLink {
+                path: format!("/v2/{}/tags/list", repository.name()),
+                filters: Vec::new(),
+                n,
+                last: last.clone(),
+            });
+        }
+    }
 
-    Ok((StatusCode::OK, Json(tags_list)).into_response())
+    Ok((StatusCode::OK, headers, Json(tags_list)).into_response())
 }
@@ -0,0 +1,123 @@
+//! Object bodies that stop reading from the backend once cancelled.
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use futures::future::FutureExt;
+use futures::stream::Stream;
+use tokio_util::sync::{CancellationToken, WaitForCancellationFutureOwned};
+
+use super::errors::{Error, Result};
+use super::ObjectBody;
+
+/// Wraps an [`ObjectBody`] so that it is dropped, closing whatever it reads from, as soon as a
+/// [`CancellationToken`] is cancelled rather than once the body itself is dropped. The body then
+/// yields [`Error::Cancelled`] so that consumers can't mistake it for complete content.
+pub struct CancellableBody {
+    inner: Option<ObjectBody>,
+    cancelled: Pin<Box<WaitForCancellationFutureOwned>>,
+}
+
+impl CancellableBody {
+    pub fn new(inner: ObjectBody, token: CancellationToken) -> Self {
+        Self {
+            inner: Some(inner),
+            cancelled: Box::pin(token.cancelled_owned()),
+        }
+    }
+}
+
+impl Stream for CancellableBody {
+    type Item = Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        let Some(inner) = this.inner.as_mut() else {
+            return Poll::Ready(None);
+        };
+        // polling the cancellation first registers interest in it, so that consumers waiting on
+        // a slow backend are woken up to drop it when cancelled
+        if this.cancelled.poll_unpin(cx).is_ready() {
+            this.inner = None;
+            return Poll::Ready(Some(Err(Error::Cancelled)));
+        }
+        let next = inner.as_mut().poll_next(cx);
+        if let Poll::Ready(None) = next {
+            this.inner = None;
+        }
+        next
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    use futures::stream::StreamExt;
+
+    use super::*;
+
+    /// Yields one chunk and then waits forever, recording when it is dropped.
+    struct Reader {
+        sent: bool,
+        closed: Arc<AtomicBool>,
+    }
+
+    impl Stream for Reader {
+        type Item = Result<Bytes>;
+
+        fn poll_next(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            if self.sent {
+                return Poll::Pending;
+            }
+            self.sent = true;
+            Poll::Ready(Some(Ok(Bytes::from_static(b"chunk"))))
+        }
+    }
+
+    impl Drop for Reader {
+        fn drop(&mut self) {
+            self.closed.store(true, Ordering::SeqCst);
+        }
+    }
+
+    fn reader() -> (ObjectBody, Arc<AtomicBool>) {
+        let closed = Arc::new(AtomicBool::new(false));
+        let reader = Reader {
+            sent: false,
+            closed: closed.clone(),
+        };
+        (reader.boxed(), closed)
+    }
+
+    #[tokio::test]
+    async fn dropping_body_closes_reader() {
+        let (inner, closed) = reader();
+        let mut body = CancellableBody::new(inner, CancellationToken::new());
+        assert_eq!(body.next().await.unwrap().unwrap(), "chunk");
+
+        drop(body);
+        assert!(closed.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn cancelling_closes_reader_while_waiting() {
+        let (inner, closed) = reader();
+        let token = CancellationToken::new();
+        let mut body = CancellableBody::new(inner, token.clone());
+        assert_eq!(body.next().await.unwrap().unwrap(), "chunk");
+
+        // the reader never yields again, so only cancellation can end the wait
+        let waiting = tokio::spawn(async move {
+            let next = body.next().await;
+            (next, body)
+        });
+        tokio::task::yield_now().await;
+        token.cancel();
+        let (next, mut body) = waiting.await.unwrap();
+        assert!(matches!(next, Some(Err(Error::Cancelled))));
+        assert!(closed.load(Ordering::SeqCst));
+        assert!(body.next().await.is_none());
+    }
+}
@@ -19,8 +19,12 @@ impl Config {
     /// Constructs an instance of [`Arc<dyn ObjectStore>`] whose concrete type depends
     /// on which variant is present.
     pub async fn new_objects(&self) -> Result<Arc<dyn ObjectStore>> {
-        match self {
-            Self::S3(cfg) => Ok(Arc::new(cfg.new_objects().await?)),
-        }
+        let objects: Arc<dyn ObjectStore> = match self {
+            Self::S3(cfg) => Arc::new(cfg.new_objects().await?),
+        };
+        #[cfg(feature = "metrics")]
+        let objects: Arc<dyn ObjectStore> =
+            Arc::new(super::metrics::InstrumentedObjectStore::new(objects));
+        Ok(objects)
     }
 }
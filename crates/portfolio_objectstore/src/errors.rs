@@ -62,11 +62,21 @@ pub enum Error {
     #[error("aws sdk credentials error")]
     AWSSDKCredentialsError(#[from] aws_credential_types::provider::error::CredentialsError),
 
+    #[error("object not found: {0}")]
+    ObjectNotFound(String),
+    #[error("object not readable after being written: {0}")]
+    ObjectNotReadableAfterWrite(String),
+    #[error("object read cancelled")]
+    Cancelled,
+
     #[error("failed to initiate chunked upload: {0}")]
     ObjectsFailedToInitiateChunkedUpload(&'static str),
     #[error("missing upload id for session: {0}")]
     ObjectsMissingUploadID(uuid::Uuid),
 
+    #[error("invalid config: {0}")]
+    InvalidConfig(&'static str),
+
     #[error("missing query parameter: {0}")]
     MissingQueryParameter(&'static str),
 
@@ -2,29 +2,45 @@
 //!
 //! Primarily intended for use in backend implementations of the traits in [`portfolio_core`].
 //!
+use std::future::Future;
 use std::path::Component;
 use std::path::PathBuf;
 
 use async_trait::async_trait;
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
 use futures::stream::BoxStream;
+use futures::stream::TryStreamExt;
 use hyper::body::Body;
 use once_cell::sync::Lazy;
 use regex::Regex;
+use tokio::sync::Semaphore;
+use tokio_util::sync::CancellationToken;
 
+mod cancel;
 pub mod config;
 pub mod errors;
+#[cfg(feature = "metrics")]
+pub(crate) mod metrics;
 pub(crate) mod s3;
 
+pub use cancel::CancellableBody;
 #[doc(hidden)]
 pub use config::Config;
 #[doc(hidden)]
 pub use errors::{Error, KeyError, Result};
 
 /// Used to communicate multi-part upload information between [`ObjectStore`] user and backends.
+///
+/// The `checksum_*` fields hold the integrity checksum the backend calculated for the chunk, when
+/// one was requested; backends that verify checksums need them again to finalize the upload.
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct Chunk {
     pub e_tag: Option<String>,
     pub chunk_number: i32,
+    pub checksum_crc32: Option<String>,
+    pub checksum_crc32_c: Option<String>,
+    pub checksum_sha1: Option<String>,
+    pub checksum_sha256: Option<String>,
 }
 
 /// Wrapper around [`std::path::PathBuf`] that can reject unsavory key names.
@@ -115,6 +131,32 @@ fn validate_component(mut pb: PathBuf, c: Component<'_>) -> std::result::Result<
 #[doc(hidden)]
 pub type ObjectBody = BoxStream<'static, Result<Bytes>>;
 
+/// Run `check` on each of `keys` with at most `limit` checks in flight at once, returning the
+/// results in the same order as `keys`. Gives up as soon as any check fails.
+pub async fn check_concurrently<'a, F, Fut>(
+    keys: &'a [Key],
+    limit: usize,
+    check: F,
+) -> Result<Vec<bool>>
+where
+    F: Fn(&'a Key) -> Fut,
+    Fut: Future<Output = Result<bool>>,
+{
+    let permits = Semaphore::new(limit.max(1));
+    futures::future::try_join_all(keys.iter().map(|key| {
+        let permits = &permits;
+        let check = &check;
+        async move {
+            let _permit = permits
+                .acquire()
+                .await
+                .expect("the semaphore is never closed");
+            check(key).await
+        }
+    }))
+    .await
+}
+
 /// Provides a common interface for interacting with different kinds of backend object stores.
 ///
 /// Object retrieval methods return [`futures::stream::Stream`] over [`bytes::Bytes`] and object
@@ -125,17 +167,76 @@ pub type ObjectBody = BoxStream<'static, Result<Bytes>>;
 #[async_trait]
 pub trait ObjectStore: Send + Sync + 'static {
     /// Get the contents of the referenced [`Key`].
+    ///
+    /// Returns [`Error::ObjectNotFound`] if the [`Key`] doesn't exist.
     async fn get(&self, key: &Key) -> Result<ObjectBody>;
 
+    /// Get the contents of the referenced [`Key`] as a [`CancellableBody`], which stops reading
+    /// from the backend as soon as `token` is cancelled, eg when the client it is being sent to
+    /// disconnects.
+    ///
+    /// Returns [`Error::ObjectNotFound`] if the [`Key`] doesn't exist.
+    async fn get_cancellable(&self, key: &Key, token: CancellationToken) -> Result<ObjectBody> {
+        Ok(Box::pin(CancellableBody::new(self.get(key).await?, token)))
+    }
+
     /// Return true if referenced [`Key`] exists.
     async fn exists(&self, key: &Key) -> Result<bool>;
 
+    /// Return whether each of the referenced [`Key`]s exists, in the same order as `keys`.
+    ///
+    /// The default implementation checks each key in turn with [`ObjectStore::exists`]. Backends
+    /// whose existence checks are network round trips should override it to check keys
+    /// concurrently, eg with [`check_concurrently`].
+    async fn exists_many(&self, keys: &[Key]) -> Result<Vec<bool>> {
+        let mut found = Vec::with_capacity(keys.len());
+        for key in keys {
+            found.push(self.exists(key).await?);
+        }
+        Ok(found)
+    }
+
     /// Upload the given contents as [`Key`].
     async fn put(&self, key: &Key, body: Body, content_length: u64) -> Result<()>;
 
+    /// Upload the contents of the given stream as [`Key`], for callers producing content
+    /// in-process rather than relaying a request body. The stream is adapted into a
+    /// [`hyper::body::Body`] and passed to [`ObjectStore::put`].
+    async fn put_stream(&self, key: &Key, body: ObjectBody, content_length: u64) -> Result<()> {
+        self.put(key, Body::wrap_stream(body), content_length).await
+    }
+
+    /// Return the backend-specific storage class the referenced [`Key`] is stored under, or `None`
+    /// if the backend has no notion of storage classes.
+    ///
+    /// Returns [`Error::ObjectNotFound`] if the [`Key`] doesn't exist.
+    async fn storage_class(&self, key: &Key) -> Result<Option<String>>;
+
     /// Delete the [`Key`] from the backend.
     async fn delete(&self, key: &Key) -> Result<()>;
 
+    /// Copy the contents of the `from` [`Key`] to the `to` [`Key`], leaving `from` intact.
+    ///
+    /// The default implementation reads the whole object through this process and uploads it
+    /// again, buffering it in memory since [`ObjectStore::put`] needs to know its length up
+    /// front. Backends that can copy objects without transferring their contents should override
+    /// it.
+    ///
+    /// Returns [`Error::ObjectNotFound`] if `from` doesn't exist.
+    async fn copy(&self, from: &Key, to: &Key) -> Result<()> {
+        let bytes = self
+            .get(from)
+            .await?
+            .try_fold(BytesMut::new(), |mut acc, bs| async move {
+                acc.extend_from_slice(&bs);
+                Ok(acc)
+            })
+            .await?
+            .freeze();
+        let content_length = bytes.len() as u64;
+        self.put(to, Body::from(bytes), content_length).await
+    }
+
     /// Initiated a chunked upload session and return an upload id as a String.
     async fn initiate_chunked_upload(&self, session_key: &Key) -> Result<String>;
 
@@ -149,6 +250,26 @@ pub trait ObjectStore: Send + Sync + 'static {
         body: Body,
     ) -> Result<Chunk>;
 
+    /// Upload a chunk read from the given stream for the given upload id and session key; see
+    /// [`ObjectStore::put_stream`].
+    async fn upload_chunk_stream(
+        &self,
+        upload_id: &str,
+        session_key: &Key,
+        chunk_number: i32,
+        content_length: u64,
+        body: ObjectBody,
+    ) -> Result<Chunk> {
+        self.upload_chunk(
+            upload_id,
+            session_key,
+            chunk_number,
+            content_length,
+            Body::wrap_stream(body),
+        )
+        .await
+    }
+
     /// Finalize the chunked upload and make the concatenated contents available under the given
     /// [`Key`].
     async fn finalize_chunked_upload(
@@ -165,10 +286,226 @@ pub trait ObjectStore: Send + Sync + 'static {
 
 #[cfg(test)]
 mod tests {
+    use std::collections::{BTreeMap, HashMap};
+    use std::sync::Mutex;
+
+    use futures::stream::StreamExt;
+
     use super::*;
 
     // validate object safety
     struct Whatever {
         objectstore: Box<dyn ObjectStore>,
     }
+
+    /// In-memory [`ObjectStore`] for exercising the trait's default methods.
+    #[derive(Default)]
+    struct MemoryObjectStore {
+        objects: Mutex<HashMap<String, Bytes>>,
+        // chunks of in-progress chunked uploads by upload id and chunk number
+        uploads: Mutex<HashMap<String, BTreeMap<i32, Bytes>>>,
+    }
+
+    #[async_trait]
+    impl ObjectStore for MemoryObjectStore {
+        async fn get(&self, key: &Key) -> Result<ObjectBody> {
+            let bytes = self
+                .objects
+                .lock()
+                .unwrap()
+                .get(&String::from(key))
+                .cloned()
+                .ok_or_else(|| Error::ObjectNotFound(key.to_string()))?;
+            Ok(futures::stream::iter([Ok(bytes)]).boxed())
+        }
+
+        async fn exists(&self, key: &Key) -> Result<bool> {
+            Ok(self
+                .objects
+                .lock()
+                .unwrap()
+                .contains_key(&String::from(key)))
+        }
+
+        async fn put(&self, key: &Key, body: Body, content_length: u64) -> Result<()> {
+            let bytes = hyper::body::to_bytes(body).await.unwrap();
+            assert_eq!(bytes.len() as u64, content_length);
+            self.objects
+                .lock()
+                .unwrap()
+                .insert(String::from(key), bytes);
+            Ok(())
+        }
+
+        async fn storage_class(&self, _key: &Key) -> Result<Option<String>> {
+            Ok(None)
+        }
+
+        async fn delete(&self, key: &Key) -> Result<()> {
+            self.objects.lock().unwrap().remove(&String::from(key));
+            Ok(())
+        }
+
+        async fn initiate_chunked_upload(&self, _session_key: &Key) -> Result<String> {
+            let upload_id = uuid::Uuid::new_v4().to_string();
+            self.uploads
+                .lock()
+                .unwrap()
+                .insert(upload_id.clone(), BTreeMap::new());
+            Ok(upload_id)
+        }
+
+        async fn upload_chunk(
+            &self,
+            upload_id: &str,
+            _session_key: &Key,
+            chunk_number: i32,
+            content_length: u64,
+            body: Body,
+        ) -> Result<Chunk> {
+            let bytes = hyper::body::to_bytes(body).await.unwrap();
+            assert_eq!(bytes.len() as u64, content_length);
+            self.uploads
+                .lock()
+                .unwrap()
+                .get_mut(upload_id)
+                .ok_or_else(|| Error::ObjectNotFound(upload_id.to_string()))?
+                .insert(chunk_number, bytes);
+            Ok(Chunk {
+                chunk_number,
+                ..Default::default()
+            })
+        }
+
+        async fn finalize_chunked_upload(
+            &self,
+            upload_id: &str,
+            _session_key: &Key,
+            chunks: Vec<Chunk>,
+            key: &Key,
+        ) -> Result<()> {
+            let mut uploaded = self
+                .uploads
+                .lock()
+                .unwrap()
+                .remove(upload_id)
+                .ok_or_else(|| Error::ObjectNotFound(upload_id.to_string()))?;
+            let mut bytes = BytesMut::new();
+            for chunk in chunks {
+                bytes.extend_from_slice(
+                    &uploaded
+                        .remove(&chunk.chunk_number)
+                        .ok_or_else(|| Error::ObjectNotFound(upload_id.to_string()))?,
+                );
+            }
+            self.objects
+                .lock()
+                .unwrap()
+                .insert(String::from(key), bytes.freeze());
+            Ok(())
+        }
+
+        async fn abort_chunked_upload(&self, upload_id: &str, _session_key: &Key) -> Result<()> {
+            self.uploads.lock().unwrap().remove(upload_id);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn copy_duplicates_object() -> Result<()> {
+        let store = MemoryObjectStore::default();
+        let from = Key::from_pathbuf(PathBuf::from("from"))?;
+        let to = Key::from_pathbuf(PathBuf::from("to"))?;
+        store.put(&from, Body::from("meow"), 4).await?;
+
+        store.copy(&from, &to).await?;
+
+        for key in [&from, &to] {
+            let bytes: Vec<Bytes> = store.get(key).await?.try_collect().await?;
+            assert_eq!(bytes.concat(), b"meow");
+        }
+
+        let missing = Key::from_pathbuf(PathBuf::from("missing"))?;
+        assert!(matches!(
+            store.copy(&missing, &to).await,
+            Err(Error::ObjectNotFound(_))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn chunked_upload_assembles_chunks_in_order() -> Result<()> {
+        let store = MemoryObjectStore::default();
+        let session_key = Key::from_pathbuf(PathBuf::from("session"))?;
+        let key = Key::from_pathbuf(PathBuf::from("assembled"))?;
+        let upload_id = store.initiate_chunked_upload(&session_key).await?;
+
+        let mut chunks = Vec::new();
+        for (chunk_number, part) in [(1, "me"), (2, "ow")] {
+            chunks.push(
+                store
+                    .upload_chunk(&upload_id, &session_key, chunk_number, 2, Body::from(part))
+                    .await?,
+            );
+        }
+        store
+            .finalize_chunked_upload(&upload_id, &session_key, chunks, &key)
+            .await?;
+
+        let bytes: Vec<Bytes> = store.get(&key).await?.try_collect().await?;
+        assert_eq!(bytes.concat(), b"meow");
+
+        // an aborted upload can't be finalized
+        let upload_id = store.initiate_chunked_upload(&session_key).await?;
+        store.abort_chunked_upload(&upload_id, &session_key).await?;
+        assert!(matches!(
+            store
+                .finalize_chunked_upload(&upload_id, &session_key, Vec::new(), &key)
+                .await,
+            Err(Error::ObjectNotFound(_))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn put_stream_uploads_stream_contents() -> Result<()> {
+        let store = MemoryObjectStore::default();
+        let key = Key::from_pathbuf(PathBuf::from("streamed"))?;
+        let body =
+            futures::stream::iter(["me", "ow"].map(|s| Ok(Bytes::from_static(s.as_bytes()))));
+
+        store.put_stream(&key, body.boxed(), 4).await?;
+
+        let bytes: Vec<Bytes> = store.get(&key).await?.try_collect().await?;
+        assert_eq!(bytes.concat(), b"meow");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn concurrent_checks_bounded_and_ordered() -> Result<()> {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let keys = (0..20)
+            .map(|i| Key::from_pathbuf(PathBuf::from(i.to_string())))
+            .collect::<Result<Vec<Key>>>()?;
+        let in_flight = AtomicUsize::new(0);
+        let max_in_flight = AtomicUsize::new(0);
+
+        let found = check_concurrently(&keys, 3, |key| {
+            let (in_flight, max_in_flight) = (&in_flight, &max_in_flight);
+            async move {
+                let n = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                max_in_flight.fetch_max(n, Ordering::SeqCst);
+                tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+                // only even keys exist
+                Ok(key.to_string().parse::<u32>().unwrap() % 2 == 0)
+            }
+        })
+        .await?;
+
+        assert_eq!(max_in_flight.load(Ordering::SeqCst), 3);
+        assert_eq!(found, (0..20).map(|i| i % 2 == 0).collect::<Vec<bool>>());
+        Ok(())
+    }
 }
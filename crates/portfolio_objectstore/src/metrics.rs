@@ -0,0 +1,115 @@
+//! Prometheus instrumentation for [`ObjectStore`] implementations.
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use hyper::body::Body;
+use once_cell::sync::Lazy;
+use prometheus::{register_histogram_vec, HistogramVec};
+
+use super::{Chunk, Key, ObjectBody, ObjectStore, Result};
+
+static OPERATION_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "portfolio_objectstore_operation_duration_seconds",
+        "Time taken by object store operations.",
+        &["operation"]
+    )
+    .expect("object store metrics are registered once")
+});
+
+/// [`ObjectStore`] wrapper that records the duration of each operation in the default
+/// [`prometheus`] registry.
+pub(crate) struct InstrumentedObjectStore {
+    inner: Arc<dyn ObjectStore>,
+}
+
+impl InstrumentedObjectStore {
+    pub(crate) fn new(inner: Arc<dyn ObjectStore>) -> Self {
+        Self { inner }
+    }
+}
+
+fn timer(operation: &str) -> prometheus::HistogramTimer {
+    OPERATION_DURATION
+        .with_label_values(&[operation])
+        .start_timer()
+}
+
+#[async_trait]
+impl ObjectStore for InstrumentedObjectStore {
+    /// Only the time taken to start the download is recorded, not the time taken to stream the
+    /// object's contents.
+    async fn get(&self, key: &Key) -> Result<ObjectBody> {
+        let _timer = timer("get");
+        self.inner.get(key).await
+    }
+
+    async fn exists(&self, key: &Key) -> Result<bool> {
+        let _timer = timer("exists");
+        self.inner.exists(key).await
+    }
+
+    async fn exists_many(&self, keys: &[Key]) -> Result<Vec<bool>> {
+        let _timer = timer("exists_many");
+        self.inner.exists_many(keys).await
+    }
+
+    async fn put(&self, key: &Key, body: Body, content_length: u64) -> Result<()> {
+        let _timer = timer("put");
+        self.inner.put(key, body, content_length).await
+    }
+
+    async fn storage_class(&self, key: &Key) -> Result<Option<String>> {
+        let _timer = timer("storage_class");
+        self.inner.storage_class(key).await
+    }
+
+    async fn delete(&self, key: &Key) -> Result<()> {
+        let _timer = timer("delete");
+        self.inner.delete(key).await
+    }
+
+    async fn copy(&self, from: &Key, to: &Key) -> Result<()> {
+        let _timer = timer("copy");
+        self.inner.copy(from, to).await
+    }
+
+    async fn initiate_chunked_upload(&self, session_key: &Key) -> Result<String> {
+        let _timer = timer("initiate_chunked_upload");
+        self.inner.initiate_chunked_upload(session_key).await
+    }
+
+    async fn upload_chunk(
+        &self,
+        upload_id: &str,
+        session_key: &Key,
+        chunk_number: i32,
+        content_length: u64,
+        body: Body,
+    ) -> Result<Chunk> {
+        let _timer = timer("upload_chunk");
+        self.inner
+            .upload_chunk(upload_id, session_key, chunk_number, content_length, body)
+            .await
+    }
+
+    async fn finalize_chunked_upload(
+        &self,
+        upload_id: &str,
+        session_key: &Key,
+        chunks: Vec<Chunk>,
+        key: &Key,
+    ) -> Result<()> {
+        let _timer = timer("finalize_chunked_upload");
+        self.inner
+            .finalize_chunked_upload(upload_id, session_key, chunks, key)
+            .await
+    }
+
+    async fn abort_chunked_upload(&self, upload_id: &str, session_key: &Key) -> Result<()> {
+        let _timer = timer("abort_chunked_upload");
+        self.inner
+            .abort_chunked_upload(upload_id, session_key)
+            .await
+    }
+}
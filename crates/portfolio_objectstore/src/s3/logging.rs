@@ -1,3 +1,7 @@
+/// Logs S3 requests and responses at `TRACE`.
+///
+/// Lines are emitted within the span of whichever operation made the request, so those made while
+/// serving an HTTP request carry its `request_id`.
 #[derive(Debug)]
 pub(crate) struct LoggingInterceptor;
 
@@ -1,9 +1,23 @@
+use std::future::Future;
+use std::time::Duration;
+
 use async_trait::async_trait;
-use aws_credential_types::provider::{ProvideCredentials, SharedCredentialsProvider};
+use aws_config::{ConfigLoader, SdkConfig};
+use aws_credential_types::provider::SharedCredentialsProvider;
 use aws_credential_types::Credentials;
+use aws_sdk_s3::config::retry::RetryConfig;
 use aws_sdk_s3::config::Region;
 use aws_sdk_s3::error::SdkError;
-use aws_sdk_s3::types::{CompletedMultipartUpload, CompletedPart};
+use aws_sdk_s3::operation::complete_multipart_upload::builders::CompleteMultipartUploadFluentBuilder;
+use aws_sdk_s3::operation::copy_object::builders::CopyObjectFluentBuilder;
+use aws_sdk_s3::operation::create_multipart_upload::builders::CreateMultipartUploadFluentBuilder;
+use aws_sdk_s3::operation::head_object::HeadObjectOutput;
+use aws_sdk_s3::operation::put_object::builders::PutObjectFluentBuilder;
+use aws_sdk_s3::operation::upload_part::builders::UploadPartFluentBuilder;
+use aws_sdk_s3::types::{
+    ChecksumAlgorithm as S3ChecksumAlgorithm, CompletedMultipartUpload, CompletedPart,
+    ServerSideEncryption as S3ServerSideEncryption,
+};
 use aws_sdk_s3::Client;
 use futures::stream::StreamExt;
 use futures::stream::TryStreamExt;
@@ -21,48 +35,309 @@ use super::ObjectStore;
 
 #[derive(Clone, Deserialize)]
 pub struct S3Config {
-    secret_key: String,
-    access_key: String,
-    hostname: String,
+    /// Static credentials, set together. When both are omitted, credentials are resolved by the
+    /// SDK's default provider chain, eg from the environment, a web identity token such as IRSA's
+    /// or the ECS or EC2 instance metadata service.
+    #[serde(default)]
+    secret_key: Option<String>,
+    #[serde(default)]
+    access_key: Option<String>,
+    /// Custom S3 API endpoint. When unset the SDK resolves the AWS endpoint for `region`.
+    #[serde(default)]
+    hostname: Option<String>,
     bucket_name: String,
     region: String,
+    /// Resolve FIPS 140-2 validated endpoints.
+    #[serde(default)]
+    use_fips: bool,
+    /// Resolve dualstack (IPv4 and IPv6) endpoints.
+    #[serde(default)]
+    use_dualstack: bool,
+    /// Optional integrity checksum sent along with object and chunk uploads so the backend can
+    /// reject objects corrupted in transit.
+    #[serde(default)]
+    checksum_algorithm: Option<ChecksumAlgorithm>,
+    /// Number of times idempotent operations are retried after transient errors. The SDK's own
+    /// retries are disabled so that they don't multiply with these, which means uploads of
+    /// streamed content, eg `PutObject` and `UploadPart`, and `CompleteMultipartUpload` aren't
+    /// retried.
+    #[serde(default = "default_max_retries")]
+    max_retries: u32,
+    /// Base delay used to calculate the jittered exponential backoff between retries.
+    #[serde(default = "default_base_delay_ms")]
+    base_delay_ms: u64,
+    /// Number of times to check that an object is readable after writing it before reporting the
+    /// write as failed, for S3-compatible stores that don't provide read-after-write consistency.
+    /// Writes aren't checked when 0, the default.
+    #[serde(default)]
+    read_after_write_checks: u32,
+    /// Base delay used to calculate the jittered exponential backoff between read-after-write
+    /// checks.
+    #[serde(default = "default_base_delay_ms")]
+    read_after_write_delay_ms: u64,
+    /// Number of `HeadObject` requests made at once when checking whether many objects exist.
+    #[serde(default = "default_max_concurrent_exists_checks")]
+    max_concurrent_exists_checks: usize,
+    /// Address buckets by path, eg `https://hostname/bucket/key`, rather than by virtual host, eg
+    /// `https://bucket.hostname/key`. Needed by S3-compatible stores such as MinIO that don't
+    /// serve buckets on their own hostnames; AWS prefers virtual-hosted addressing, the default.
+    #[serde(default)]
+    force_path_style: bool,
+    /// Server-side encryption requested for every object written, including those assembled
+    /// from chunked uploads and copies. The bucket's default encryption applies when unset.
+    #[serde(default)]
+    sse: Option<ServerSideEncryption>,
+}
+
+fn default_max_retries() -> u32 {
+    3
+}
+
+fn default_base_delay_ms() -> u64 {
+    100
+}
+
+fn default_max_concurrent_exists_checks() -> usize {
+    16
+}
+
+/// Integrity checksum algorithms supported by S3 for uploads.
+///
+/// When configured, the SDK calculates the checksum while the request body is streamed and S3
+/// rejects the upload if the checksum it calculates on receipt doesn't match. This is independent
+/// of the OCI digest, which is only verified once the upload is complete.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum ChecksumAlgorithm {
+    Crc32,
+    Crc32C,
+    Sha1,
+    Sha256,
+}
+
+impl From<ChecksumAlgorithm> for S3ChecksumAlgorithm {
+    fn from(a: ChecksumAlgorithm) -> Self {
+        match a {
+            ChecksumAlgorithm::Crc32 => S3ChecksumAlgorithm::Crc32,
+            ChecksumAlgorithm::Crc32C => S3ChecksumAlgorithm::Crc32C,
+            ChecksumAlgorithm::Sha1 => S3ChecksumAlgorithm::Sha1,
+            ChecksumAlgorithm::Sha256 => S3ChecksumAlgorithm::Sha256,
+        }
+    }
+}
+
+/// Server-side encryption S3 applies to objects as they are written.
+///
+/// Configured as eg:
+///
+/// ```yaml
+/// sse:
+///   type: aws:kms
+///   kms_key_id: arn:aws:kms:us-east-1:111122223333:key/1234abcd-12ab-34cd-56ef-1234567890ab
+/// ```
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(tag = "type")]
+pub enum ServerSideEncryption {
+    /// Encrypt with keys managed by S3 (SSE-S3).
+    #[serde(rename = "aes256")]
+    Aes256,
+    /// Encrypt with a KMS key (SSE-KMS), the bucket's or the account's default KMS key for S3
+    /// unless `kms_key_id` is set.
+    #[serde(rename = "aws:kms")]
+    AwsKms {
+        #[serde(default)]
+        kms_key_id: Option<String>,
+    },
+}
+
+impl ServerSideEncryption {
+    fn algorithm(&self) -> S3ServerSideEncryption {
+        match self {
+            ServerSideEncryption::Aes256 => S3ServerSideEncryption::Aes256,
+            ServerSideEncryption::AwsKms { .. } => S3ServerSideEncryption::AwsKms,
+        }
+    }
+
+    fn kms_key_id(&self) -> Option<String> {
+        match self {
+            ServerSideEncryption::Aes256 => None,
+            ServerSideEncryption::AwsKms { kms_key_id } => kms_key_id.clone(),
+        }
+    }
 }
 
 impl S3Config {
     pub async fn new_objects(&self) -> Result<S3> {
-        let scp = SharedCredentialsProvider::new(
-            Credentials::new(
-                self.access_key.clone(),
-                self.secret_key.clone(),
-                None,
-                None,
-                "portfolio",
-            )
-            .provide_credentials()
-            .await?,
-        );
+        let sdk_config = self.config_loader()?.load().await;
+
+        let config = self.client_config(&sdk_config).build();
+
+        let s3_client = aws_sdk_s3::Client::from_conf(config);
+
+        Ok(S3 {
+            bucket_name: self.bucket_name.clone(),
+            client: s3_client,
+            checksum_algorithm: self.checksum_algorithm.map(Into::into),
+            sse: self.sse.clone(),
+            retry_policy: RetryPolicy {
+                max_retries: self.max_retries,
+                base_delay: Duration::from_millis(self.base_delay_ms),
+            },
+            read_after_write: RetryPolicy {
+                max_retries: self.read_after_write_checks,
+                base_delay: Duration::from_millis(self.read_after_write_delay_ms),
+            },
+            max_concurrent_exists_checks: self.max_concurrent_exists_checks,
+        })
+    }
+
+    /// Builds S3 client config from shared SDK config, applying the settings specific to S3.
+    ///
+    /// Transient errors are retried by [`RetryPolicy`] according to `max_retries`, so the SDK is
+    /// limited to a single attempt per request.
+    fn client_config(&self, sdk_config: &SdkConfig) -> aws_sdk_s3::config::Builder {
+        aws_sdk_s3::config::Builder::from(sdk_config)
+            .retry_config(RetryConfig::disabled())
+            .force_path_style(self.force_path_style)
+            .interceptor(LoggingInterceptor)
+    }
+
+    /// Loads shared SDK config from the environment, overridden by this config's region, endpoint
+    /// and credential settings.
+    ///
+    /// FIPS and dualstack endpoints are left to the SDK's endpoint resolution since they can't be
+    /// combined with a custom `hostname`.
+    fn config_loader(&self) -> Result<ConfigLoader> {
+        self.config_loader_with(None)
+    }
+
+    /// Like [`S3Config::config_loader`], but resolving credentials with `default_credentials`
+    /// rather than the SDK's default provider chain when no static keys are configured.
+    fn config_loader_with(
+        &self,
+        default_credentials: Option<SharedCredentialsProvider>,
+    ) -> Result<ConfigLoader> {
+        let loader = aws_config::from_env()
+            .region(Region::new(self.region.clone()))
+            .use_fips(self.use_fips)
+            .use_dual_stack(self.use_dualstack);
+        let loader = match (&self.access_key, &self.secret_key, default_credentials) {
+            (Some(access_key), Some(secret_key), _) => {
+                loader.credentials_provider(Credentials::new(
+                    access_key.clone(),
+                    secret_key.clone(),
+                    None,
+                    None,
+                    "portfolio",
+                ))
+            }
+            (None, None, Some(provider)) => loader.credentials_provider(provider),
+            (None, None, None) => loader,
+            _ => {
+                return Err(Error::InvalidConfig(
+                    "access_key and secret_key must be set together",
+                ))
+            }
+        };
+
+        let Some(hostname) = &self.hostname else {
+            return Ok(loader);
+        };
+        if self.use_fips || self.use_dualstack {
+            return Err(Error::InvalidConfig(
+                "hostname can't be combined with use_fips or use_dualstack",
+            ));
+        }
 
         let uri = Uri::builder()
             .scheme("https")
-            .authority(self.hostname.as_str())
+            .authority(hostname.as_str())
             .path_and_query("/")
             .build()?;
 
-        let sdk_config = aws_config::load_from_env().await;
+        Ok(loader.endpoint_url(uri.to_string()))
+    }
+}
 
-        let config = aws_sdk_s3::config::Builder::from(&sdk_config)
-            .region(Region::new(self.region.clone()))
-            .credentials_provider(scp)
-            .endpoint_url(uri.to_string())
-            .interceptor(LoggingInterceptor)
-            .build();
+/// Errors that may be transient and so are worth retrying.
+trait Retryable {
+    fn is_retryable(&self) -> bool;
+}
 
-        let s3_client = aws_sdk_s3::Client::from_conf(config);
+impl<E> Retryable for SdkError<E> {
+    fn is_retryable(&self) -> bool {
+        match self {
+            SdkError::TimeoutError(_)
+            | SdkError::DispatchFailure(_)
+            | SdkError::ResponseError(_) => true,
+            SdkError::ServiceError(e) => matches!(
+                e.raw().status(),
+                StatusCode::TOO_MANY_REQUESTS
+                    | StatusCode::INTERNAL_SERVER_ERROR
+                    | StatusCode::BAD_GATEWAY
+                    | StatusCode::SERVICE_UNAVAILABLE
+                    | StatusCode::GATEWAY_TIMEOUT
+            ),
+            _ => false,
+        }
+    }
+}
 
-        Ok(S3 {
-            bucket_name: self.bucket_name.clone(),
-            client: s3_client,
-        })
+/// Retries operations that fail with [`Retryable`] errors using exponential backoff with full
+/// jitter.
+///
+/// Only use this for idempotent operations.
+#[derive(Clone, Debug)]
+struct RetryPolicy {
+    max_retries: u32,
+    base_delay: Duration,
+}
+
+impl RetryPolicy {
+    async fn retry<T, E, F, Fut>(&self, mut op: F) -> std::result::Result<T, E>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = std::result::Result<T, E>>,
+        E: Retryable,
+    {
+        let mut attempt = 0;
+        loop {
+            match op().await {
+                Err(e) if attempt < self.max_retries && e.is_retryable() => {
+                    let delay = self.backoff(attempt);
+                    attempt += 1;
+                    tracing::warn!(
+                        "transient S3 error, retrying in {delay:?} (attempt {attempt} of {})",
+                        self.max_retries
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Poll `check` until it reports true, up to `max_retries` times in total with backoff
+    /// between attempts, returning whether it ever did.
+    async fn poll<F, Fut>(&self, mut check: F) -> Result<bool>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<bool>>,
+    {
+        for attempt in 0..self.max_retries {
+            if check().await? {
+                return Ok(true);
+            }
+            if attempt + 1 < self.max_retries {
+                tokio::time::sleep(self.backoff(attempt)).await;
+            }
+        }
+        Ok(false)
+    }
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        let ceiling = self.base_delay.saturating_mul(2u32.saturating_pow(attempt));
+        ceiling.mul_f64(rand::random::<f64>())
     }
 }
 
@@ -70,29 +345,171 @@ impl S3Config {
 pub struct S3 {
     bucket_name: String,
     client: Client,
+    checksum_algorithm: Option<S3ChecksumAlgorithm>,
+    /// See [`S3Config::sse`].
+    sse: Option<ServerSideEncryption>,
+    retry_policy: RetryPolicy,
+    /// Checks made after writes; see [`S3Config::read_after_write_checks`].
+    read_after_write: RetryPolicy,
+    /// See [`S3Config::max_concurrent_exists_checks`].
+    max_concurrent_exists_checks: usize,
+}
+
+impl S3 {
+    /// Wait until the object just written as `key` is visible to reads, when read-after-write
+    /// checks are enabled.
+    async fn confirm_written(&self, key: &Key) -> Result<()> {
+        if self.read_after_write.max_retries == 0 {
+            return Ok(());
+        }
+        if self.read_after_write.poll(|| self.exists(key)).await? {
+            return Ok(());
+        }
+        tracing::warn!(
+            "object {key} still not readable after {} checks",
+            self.read_after_write.max_retries
+        );
+        Err(Error::ObjectNotReadableAfterWrite(key.to_string()))
+    }
+
+    fn put_object_request(
+        &self,
+        key: &Key,
+        body: Body,
+        content_length: u64,
+    ) -> PutObjectFluentBuilder {
+        self.client
+            .put_object()
+            .key(key)
+            .body(body.into())
+            .content_length(content_length as i64)
+            .set_checksum_algorithm(self.checksum_algorithm.clone())
+            .set_server_side_encryption(self.sse.as_ref().map(ServerSideEncryption::algorithm))
+            .set_ssekms_key_id(self.sse.as_ref().and_then(ServerSideEncryption::kms_key_id))
+            .bucket(&self.bucket_name)
+    }
+
+    /// Parts of a chunked upload are encrypted as requested when the upload is created, so parts
+    /// themselves carry no encryption settings.
+    fn create_multipart_upload_request(
+        &self,
+        session_key: &Key,
+    ) -> CreateMultipartUploadFluentBuilder {
+        self.client
+            .create_multipart_upload()
+            .key(session_key)
+            .set_checksum_algorithm(self.checksum_algorithm.clone())
+            .set_server_side_encryption(self.sse.as_ref().map(ServerSideEncryption::algorithm))
+            .set_ssekms_key_id(self.sse.as_ref().and_then(ServerSideEncryption::kms_key_id))
+            .bucket(&self.bucket_name)
+    }
+
+    /// Copies don't inherit the encryption of their source, so it is requested again.
+    fn copy_object_request(&self, from: &Key, to: &Key) -> CopyObjectFluentBuilder {
+        self.client
+            .copy_object()
+            .copy_source(format!("{}/{}", self.bucket_name, from))
+            .key(to)
+            .set_server_side_encryption(self.sse.as_ref().map(ServerSideEncryption::algorithm))
+            .set_ssekms_key_id(self.sse.as_ref().and_then(ServerSideEncryption::kms_key_id))
+            .bucket(&self.bucket_name)
+    }
+
+    fn upload_part_request(
+        &self,
+        upload_id: &str,
+        session_key: &Key,
+        chunk_number: i32,
+        content_length: u64,
+        body: Body,
+    ) -> UploadPartFluentBuilder {
+        self.client
+            .upload_part()
+            .upload_id(upload_id)
+            .part_number(chunk_number)
+            .key(session_key)
+            .body(body.into())
+            .content_length(content_length as i64)
+            .set_checksum_algorithm(self.checksum_algorithm.clone())
+            .bucket(&self.bucket_name)
+    }
+
+    /// S3 rejects completing an upload whose parts were checksummed unless each part's checksum
+    /// is passed back along with its e-tag.
+    fn complete_multipart_upload_request(
+        &self,
+        upload_id: &str,
+        session_key: &Key,
+        chunks: Vec<Chunk>,
+    ) -> CompleteMultipartUploadFluentBuilder {
+        let parts = chunks
+            .into_iter()
+            .map(|chunk| {
+                CompletedPart::builder()
+                    .set_e_tag(chunk.e_tag)
+                    .part_number(chunk.chunk_number)
+                    .set_checksum_crc32(chunk.checksum_crc32)
+                    .set_checksum_crc32_c(chunk.checksum_crc32_c)
+                    .set_checksum_sha1(chunk.checksum_sha1)
+                    .set_checksum_sha256(chunk.checksum_sha256)
+                    .build()
+            })
+            .collect();
+        self.client
+            .complete_multipart_upload()
+            .multipart_upload(
+                CompletedMultipartUpload::builder()
+                    .set_parts(Some(parts))
+                    .build(),
+            )
+            .upload_id(upload_id)
+            .key(session_key)
+            .bucket(&self.bucket_name)
+    }
+}
+
+/// S3 omits the storage class from `HeadObject` responses for objects in the default class.
+fn storage_class_name(output: &HeadObjectOutput) -> String {
+    output
+        .storage_class()
+        .map(|class| class.as_str())
+        .unwrap_or("STANDARD")
+        .to_string()
 }
 
 #[async_trait]
 impl ObjectStore for S3 {
     async fn get(&self, key: &Key) -> Result<super::ObjectBody> {
-        let get_object_output = self
-            .client
-            .get_object()
-            .key(key)
-            .bucket(&self.bucket_name)
-            .send()
-            .await?;
+        let get_object_output = match self
+            .retry_policy
+            .retry(|| {
+                self.client
+                    .get_object()
+                    .key(key)
+                    .bucket(&self.bucket_name)
+                    .send()
+            })
+            .await
+        {
+            Err(SdkError::ServiceError(e)) if e.raw().status() == StatusCode::NOT_FOUND => {
+                return Err(Error::ObjectNotFound(key.to_string()))
+            }
+            result => result?,
+        };
 
         Ok(get_object_output.body.map_err(|e| e.into()).boxed())
     }
 
     async fn exists(&self, key: &Key) -> Result<bool> {
         match self
-            .client
-            .head_object()
-            .key(key)
-            .bucket(&self.bucket_name)
-            .send()
+            .retry_policy
+            .retry(|| {
+                self.client
+                    .head_object()
+                    .key(key)
+                    .bucket(&self.bucket_name)
+                    .send()
+            })
             .await
         {
             Err(SdkError::ServiceError(e)) => {
@@ -107,36 +524,76 @@ impl ObjectStore for S3 {
         }
     }
 
+    async fn exists_many(&self, keys: &[Key]) -> Result<Vec<bool>> {
+        super::check_concurrently(keys, self.max_concurrent_exists_checks, |key| {
+            self.exists(key)
+        })
+        .await
+    }
+
+    async fn storage_class(&self, key: &Key) -> Result<Option<String>> {
+        match self
+            .retry_policy
+            .retry(|| {
+                self.client
+                    .head_object()
+                    .key(key)
+                    .bucket(&self.bucket_name)
+                    .send()
+            })
+            .await
+        {
+            Err(SdkError::ServiceError(e)) if e.raw().status() == StatusCode::NOT_FOUND => {
+                Err(Error::ObjectNotFound(key.to_string()))
+            }
+            Err(e) => Err(Error::AWSSDKHeadObjectError(e)),
+            Ok(output) => Ok(Some(storage_class_name(&output))),
+        }
+    }
+
     async fn put(&self, key: &Key, body: Body, content_length: u64) -> Result<()> {
         let _put_object_output = self
-            .client
-            .put_object()
-            .key(key)
-            .body(body.into())
-            .content_length(content_length as i64)
-            .bucket(&self.bucket_name)
+            .put_object_request(key, body, content_length)
             .send()
             .await?;
-        Ok(())
+        self.confirm_written(key).await
     }
 
     async fn delete(&self, key: &Key) -> Result<()> {
-        self.client
-            .delete_object()
-            .key(key)
-            .bucket(&self.bucket_name)
-            .send()
+        self.retry_policy
+            .retry(|| {
+                self.client
+                    .delete_object()
+                    .key(key)
+                    .bucket(&self.bucket_name)
+                    .send()
+            })
             .await?;
         Ok(())
     }
 
+    /// Copies the object server-side with `CopyObject`, so its contents never pass through this
+    /// process. S3 limits `CopyObject` to objects of up to 5 GiB.
+    async fn copy(&self, from: &Key, to: &Key) -> Result<()> {
+        match self
+            .retry_policy
+            .retry(|| self.copy_object_request(from, to).send())
+            .await
+        {
+            Err(SdkError::ServiceError(e)) if e.raw().status() == StatusCode::NOT_FOUND => {
+                return Err(Error::ObjectNotFound(from.to_string()))
+            }
+            result => result?,
+        };
+        self.confirm_written(to).await
+    }
+
     async fn initiate_chunked_upload(&self, session_key: &Key) -> Result<String> {
+        // a retried request may leave behind an extra multipart upload with no parts, which is
+        // harmless since nothing refers to it
         let create_multipart_upload_output = self
-            .client
-            .create_multipart_upload()
-            .key(session_key)
-            .bucket(&self.bucket_name)
-            .send()
+            .retry_policy
+            .retry(|| self.create_multipart_upload_request(session_key).send())
             .await?;
 
         let upload_id = create_multipart_upload_output.upload_id.ok_or(
@@ -155,20 +612,17 @@ impl ObjectStore for S3 {
         body: Body,
     ) -> Result<Chunk> {
         let upload_part_output = self
-            .client
-            .upload_part()
-            .upload_id(upload_id)
-            .part_number(chunk_number)
-            .key(session_key)
-            .body(body.into())
-            .content_length(content_length as i64)
-            .bucket(&self.bucket_name)
+            .upload_part_request(upload_id, session_key, chunk_number, content_length, body)
             .send()
             .await?;
 
         let chunk = Chunk {
             e_tag: upload_part_output.e_tag,
             chunk_number,
+            checksum_crc32: upload_part_output.checksum_crc32,
+            checksum_crc32_c: upload_part_output.checksum_crc32_c,
+            checksum_sha1: upload_part_output.checksum_sha1,
+            checksum_sha256: upload_part_output.checksum_sha256,
         };
 
         Ok(chunk)
@@ -181,52 +635,42 @@ impl ObjectStore for S3 {
         chunks: Vec<Chunk>,
         key: &Key,
     ) -> Result<()> {
-        let mut mpu = CompletedMultipartUpload::builder();
-        for chunk in chunks {
-            let mut pb = CompletedPart::builder();
-            if let Some(e_tag) = &chunk.e_tag {
-                pb = pb.e_tag(e_tag);
-            }
-            mpu = mpu.parts(pb.part_number(chunk.chunk_number).build());
-        }
+        // completing an upload isn't idempotent: a retry after a response was lost fails with
+        // NoSuchUpload even though the object was assembled, so it's attempted only once
         let _complete_multipart_upload_output = self
-            .client
-            .complete_multipart_upload()
-            .multipart_upload(mpu.build())
-            .upload_id(upload_id)
-            .key(session_key)
-            .bucket(&self.bucket_name)
+            .complete_multipart_upload_request(upload_id, session_key, chunks)
             .send()
             .await?;
 
-        let copy_source = format!("{}/{}", &self.bucket_name, session_key);
         let _copy_object_output = self
-            .client
-            .copy_object()
-            .copy_source(copy_source)
-            .key(key)
-            .bucket(&self.bucket_name)
-            .send()
+            .retry_policy
+            .retry(|| self.copy_object_request(session_key, key).send())
             .await?;
 
         let _delete_object_output = self
-            .client
-            .delete_object()
-            .key(session_key)
-            .bucket(&self.bucket_name)
-            .send()
+            .retry_policy
+            .retry(|| {
+                self.client
+                    .delete_object()
+                    .key(session_key)
+                    .bucket(&self.bucket_name)
+                    .send()
+            })
             .await?;
-        Ok(())
+        self.confirm_written(key).await
     }
 
     async fn abort_chunked_upload(&self, upload_id: &str, session_key: &Key) -> Result<()> {
         let _complete_multipart_upload_output = self
-            .client
-            .abort_multipart_upload()
-            .upload_id(upload_id)
-            .key(session_key)
-            .bucket(&self.bucket_name)
-            .send()
+            .retry_policy
+            .retry(|| {
+                self.client
+                    .abort_multipart_upload()
+                    .upload_id(upload_id)
+                    .key(session_key)
+                    .bucket(&self.bucket_name)
+                    .send()
+            })
             .await?;
         // TODO: list parts to identify any lingering parts that may have been uploading during the
         // abort? the SDK docs suggest doing this, but i don't think it should be possible for a
@@ -236,3 +680,481 @@ impl ObjectStore for S3 {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn s3(checksum_algorithm: Option<ChecksumAlgorithm>) -> S3 {
+        let config = aws_sdk_s3::config::Builder::new()
+            .region(Region::new("us-east-1"))
+            .build();
+        S3 {
+            bucket_name: "portfolio".to_string(),
+            client: Client::from_conf(config),
+            checksum_algorithm: checksum_algorithm.map(Into::into),
+            sse: None,
+            retry_policy: RetryPolicy {
+                max_retries: 0,
+                base_delay: Duration::ZERO,
+            },
+            read_after_write: RetryPolicy {
+                max_retries: 0,
+                base_delay: Duration::ZERO,
+            },
+            max_concurrent_exists_checks: 1,
+        }
+    }
+
+    #[test]
+    fn checksum_algorithm_sent_with_uploads() {
+        let key = Key::from(&uuid::Uuid::new_v4());
+        let s3 = s3(Some(ChecksumAlgorithm::Crc32C));
+
+        let put = s3.put_object_request(&key, Body::empty(), 0);
+        assert_eq!(
+            put.as_input().get_checksum_algorithm(),
+            &Some(S3ChecksumAlgorithm::Crc32C)
+        );
+
+        let part = s3.upload_part_request("upload-id", &key, 1, 0, Body::empty());
+        assert_eq!(
+            part.as_input().get_checksum_algorithm(),
+            &Some(S3ChecksumAlgorithm::Crc32C)
+        );
+    }
+
+    #[test]
+    fn part_checksums_sent_when_finalizing() {
+        let key = Key::from(&uuid::Uuid::new_v4());
+        let s3 = s3(Some(ChecksumAlgorithm::Crc32C));
+        let chunks = vec![
+            Chunk {
+                e_tag: Some("etag-1".to_string()),
+                chunk_number: 1,
+                checksum_crc32_c: Some("crc-1".to_string()),
+                ..Default::default()
+            },
+            Chunk {
+                e_tag: Some("etag-2".to_string()),
+                chunk_number: 2,
+                checksum_crc32_c: Some("crc-2".to_string()),
+                ..Default::default()
+            },
+        ];
+
+        let complete = s3.complete_multipart_upload_request("upload-id", &key, chunks);
+        let parts: Vec<(Option<&str>, i32, Option<&str>)> = complete
+            .as_input()
+            .get_multipart_upload()
+            .as_ref()
+            .and_then(|mpu| mpu.parts())
+            .expect("parts should be set")
+            .iter()
+            .map(|part| (part.e_tag(), part.part_number(), part.checksum_crc32_c()))
+            .collect();
+        assert_eq!(
+            parts,
+            vec![
+                (Some("etag-1"), 1, Some("crc-1")),
+                (Some("etag-2"), 2, Some("crc-2")),
+            ]
+        );
+    }
+
+    #[test]
+    fn checksum_algorithm_omitted_by_default() {
+        let key = Key::from(&uuid::Uuid::new_v4());
+        let s3 = s3(None);
+
+        let put = s3.put_object_request(&key, Body::empty(), 0);
+        assert_eq!(put.as_input().get_checksum_algorithm(), &None);
+    }
+
+    /// Returns the encryption algorithm and KMS key id requested by uploads, multipart uploads
+    /// and copies, in that order.
+    fn requested_encryption(s3: &S3) -> Vec<(Option<S3ServerSideEncryption>, Option<String>)> {
+        let key = Key::from(&uuid::Uuid::new_v4());
+        let put = s3.put_object_request(&key, Body::empty(), 0);
+        let create = s3.create_multipart_upload_request(&key);
+        let copy = s3.copy_object_request(&key, &Key::from(&uuid::Uuid::new_v4()));
+        vec![
+            (
+                put.as_input().get_server_side_encryption().clone(),
+                put.as_input().get_ssekms_key_id().clone(),
+            ),
+            (
+                create.as_input().get_server_side_encryption().clone(),
+                create.as_input().get_ssekms_key_id().clone(),
+            ),
+            (
+                copy.as_input().get_server_side_encryption().clone(),
+                copy.as_input().get_ssekms_key_id().clone(),
+            ),
+        ]
+    }
+
+    #[test]
+    fn kms_encryption_requested_on_writes() {
+        let s3 = S3 {
+            sse: Some(ServerSideEncryption::AwsKms {
+                kms_key_id: Some("meow-key".to_string()),
+            }),
+            ..s3(None)
+        };
+        let expected = (
+            Some(S3ServerSideEncryption::AwsKms),
+            Some("meow-key".to_string()),
+        );
+        assert_eq!(requested_encryption(&s3), vec![expected; 3]);
+    }
+
+    #[test]
+    fn aes256_encryption_requested_on_writes() {
+        let s3 = S3 {
+            sse: Some(ServerSideEncryption::Aes256),
+            ..s3(None)
+        };
+        let expected = (Some(S3ServerSideEncryption::Aes256), None);
+        assert_eq!(requested_encryption(&s3), vec![expected; 3]);
+    }
+
+    #[test]
+    fn encryption_omitted_by_default() {
+        assert_eq!(requested_encryption(&s3(None)), vec![(None, None); 3]);
+    }
+
+    #[test]
+    fn storage_class_reported_from_head_object() {
+        let output = HeadObjectOutput::builder()
+            .storage_class(aws_sdk_s3::types::StorageClass::StandardIa)
+            .build();
+        assert_eq!(storage_class_name(&output), "STANDARD_IA");
+
+        let output = HeadObjectOutput::builder().build();
+        assert_eq!(storage_class_name(&output), "STANDARD");
+    }
+
+    fn parse_config(yaml: &str) -> S3Config {
+        serde_yaml::from_str(yaml).expect("config should parse")
+    }
+
+    const BASE_CONFIG: &str = "
+secret_key: secret
+access_key: access
+bucket_name: portfolio
+region: us-gov-west-1
+";
+
+    #[test]
+    fn encryption_parsed_from_config() {
+        assert_eq!(parse_config(BASE_CONFIG).sse, None);
+
+        let config = parse_config(&format!("{BASE_CONFIG}sse:\n  type: aes256\n"));
+        assert_eq!(config.sse, Some(ServerSideEncryption::Aes256));
+
+        let config = parse_config(&format!(
+            "{BASE_CONFIG}sse:\n  type: aws:kms\n  kms_key_id: meow-key\n"
+        ));
+        assert_eq!(
+            config.sse,
+            Some(ServerSideEncryption::AwsKms {
+                kms_key_id: Some("meow-key".to_string())
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn endpoint_flags_reflected_in_sdk_config() {
+        let config = parse_config(&format!(
+            "{BASE_CONFIG}use_fips: true\nuse_dualstack: true\n"
+        ));
+        assert!(config.use_fips);
+        assert!(config.use_dualstack);
+        assert_eq!(config.hostname, None);
+
+        let sdk_config = config.config_loader().unwrap().load().await;
+        assert_eq!(sdk_config.use_fips(), Some(true));
+        assert_eq!(sdk_config.use_dual_stack(), Some(true));
+        assert_eq!(sdk_config.endpoint_url(), None);
+        assert_eq!(sdk_config.region(), Some(&Region::new("us-gov-west-1")));
+    }
+
+    #[tokio::test]
+    async fn hostname_sets_endpoint_url() {
+        let config = parse_config(&format!("{BASE_CONFIG}hostname: localhost:9000\n"));
+        assert!(!config.use_fips);
+        assert!(!config.use_dualstack);
+
+        let sdk_config = config.config_loader().unwrap().load().await;
+        assert_eq!(sdk_config.use_fips(), Some(false));
+        assert_eq!(sdk_config.use_dual_stack(), Some(false));
+        assert_eq!(sdk_config.endpoint_url(), Some("https://localhost:9000/"));
+    }
+
+    #[test]
+    fn hostname_conflicts_with_endpoint_flags() {
+        let config = parse_config(&format!(
+            "{BASE_CONFIG}hostname: localhost:9000\nuse_fips: true\n"
+        ));
+        assert!(matches!(
+            config.config_loader(),
+            Err(Error::InvalidConfig(_))
+        ));
+    }
+
+    /// Records the URI of each request, then fails it before it is signed and sent.
+    #[derive(Debug)]
+    struct CaptureUri(std::sync::Arc<std::sync::Mutex<Option<String>>>);
+
+    impl aws_sdk_s3::config::Interceptor for CaptureUri {
+        fn name(&self) -> &'static str {
+            "CaptureUri"
+        }
+
+        fn read_before_signing(
+            &self,
+            context: &aws_sdk_s3::config::interceptors::BeforeTransmitInterceptorContextRef<'_>,
+            _runtime_components: &aws_sdk_s3::config::RuntimeComponents,
+            _cfg: &mut aws_sdk_s3::config::ConfigBag,
+        ) -> std::result::Result<(), aws_sdk_s3::error::BoxError> {
+            *self.0.lock().unwrap() = Some(context.request().uri().to_string());
+            Err("request captured".into())
+        }
+    }
+
+    /// Returns the URI that a client built from the config requests an object at.
+    async fn object_uri(config: &S3Config) -> String {
+        let sdk_config = config.config_loader().unwrap().load().await;
+        let uri = std::sync::Arc::default();
+        let client_config = config
+            .client_config(&sdk_config)
+            .credentials_provider(Credentials::new("access", "secret", None, None, "test"))
+            .interceptor(CaptureUri(std::sync::Arc::clone(&uri)))
+            .build();
+        let _ = Client::from_conf(client_config)
+            .head_object()
+            .bucket("portfolio")
+            .key("meow")
+            .send()
+            .await;
+        let uri = uri.lock().unwrap().take();
+        uri.expect("request should have been captured")
+    }
+
+    #[tokio::test]
+    async fn virtual_hosted_addressing_by_default() {
+        let config = parse_config(BASE_CONFIG);
+        assert!(!config.force_path_style);
+        assert_eq!(
+            object_uri(&config).await,
+            "https://portfolio.s3.us-gov-west-1.amazonaws.com/meow"
+        );
+    }
+
+    #[tokio::test]
+    async fn force_path_style_reflected_in_client_config() {
+        let config = parse_config(&format!("{BASE_CONFIG}force_path_style: true\n"));
+        assert!(config.force_path_style);
+        assert_eq!(
+            object_uri(&config).await,
+            "https://s3.us-gov-west-1.amazonaws.com/portfolio/meow"
+        );
+
+        let config = parse_config(&format!(
+            "{BASE_CONFIG}hostname: localhost:9000\nforce_path_style: true\n"
+        ));
+        assert_eq!(
+            object_uri(&config).await,
+            "https://localhost:9000/portfolio/meow"
+        );
+    }
+
+    #[tokio::test]
+    async fn sdk_retries_disabled() {
+        let config = parse_config(BASE_CONFIG);
+        let sdk_config = config.config_loader().unwrap().load().await;
+        let client_config = config.client_config(&sdk_config).build();
+        assert_eq!(
+            client_config.retry_config().map(|r| r.max_attempts()),
+            Some(1)
+        );
+    }
+
+    /// Returns the credentials the SDK config loaded for the config resolves, with
+    /// `default_credentials` standing in for the SDK's default provider chain.
+    async fn resolved_credentials(
+        config: &S3Config,
+        default_credentials: SharedCredentialsProvider,
+    ) -> Credentials {
+        use aws_credential_types::provider::ProvideCredentials;
+
+        let sdk_config = config
+            .config_loader_with(Some(default_credentials))
+            .unwrap()
+            .load()
+            .await;
+        sdk_config
+            .credentials_provider()
+            .expect("a credentials provider should always be configured")
+            .provide_credentials()
+            .await
+            .unwrap()
+    }
+
+    /// Stands in for whatever the default chain would find, eg an IRSA web identity token.
+    fn default_chain_stub() -> SharedCredentialsProvider {
+        SharedCredentialsProvider::new(Credentials::new(
+            "stub-access",
+            "stub-secret",
+            None,
+            None,
+            "test",
+        ))
+    }
+
+    #[tokio::test]
+    async fn static_credentials_used_when_keys_set() {
+        let credentials =
+            resolved_credentials(&parse_config(BASE_CONFIG), default_chain_stub()).await;
+        assert_eq!(credentials.access_key_id(), "access");
+        assert_eq!(credentials.secret_access_key(), "secret");
+    }
+
+    #[tokio::test]
+    async fn default_credential_chain_used_without_keys() {
+        let config = parse_config("bucket_name: portfolio\nregion: us-gov-west-1\n");
+        assert_eq!(config.access_key, None);
+        assert_eq!(config.secret_key, None);
+
+        let credentials = resolved_credentials(&config, default_chain_stub()).await;
+        assert_eq!(credentials.access_key_id(), "stub-access");
+        assert_eq!(credentials.secret_access_key(), "stub-secret");
+    }
+
+    #[test]
+    fn keys_must_be_set_together() {
+        let config =
+            parse_config("access_key: access\nbucket_name: portfolio\nregion: us-gov-west-1\n");
+        assert!(matches!(
+            config.config_loader(),
+            Err(Error::InvalidConfig(_))
+        ));
+    }
+
+    #[derive(Debug)]
+    struct MockError {
+        retryable: bool,
+    }
+
+    impl Retryable for MockError {
+        fn is_retryable(&self) -> bool {
+            self.retryable
+        }
+    }
+
+    /// Stands in for an S3 client whose first `failures` calls fail.
+    struct MockClient {
+        calls: std::sync::atomic::AtomicU32,
+        failures: u32,
+        retryable: bool,
+    }
+
+    impl MockClient {
+        async fn call(&self) -> std::result::Result<&'static str, MockError> {
+            let n = self.calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            if n < self.failures {
+                return Err(MockError {
+                    retryable: self.retryable,
+                });
+            }
+            Ok("ok")
+        }
+
+        fn calls(&self) -> u32 {
+            self.calls.load(std::sync::atomic::Ordering::SeqCst)
+        }
+    }
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 3,
+            base_delay: Duration::from_millis(1),
+        }
+    }
+
+    #[tokio::test]
+    async fn retry_succeeds_after_transient_failures() {
+        let client = MockClient {
+            calls: 0.into(),
+            failures: 2,
+            retryable: true,
+        };
+        let result = policy().retry(|| client.call()).await;
+        assert_eq!(result.unwrap(), "ok");
+        assert_eq!(client.calls(), 3);
+    }
+
+    #[tokio::test]
+    async fn retry_gives_up_after_max_retries() {
+        let client = MockClient {
+            calls: 0.into(),
+            failures: 10,
+            retryable: true,
+        };
+        let result = policy().retry(|| client.call()).await;
+        assert!(result.is_err());
+        assert_eq!(client.calls(), 4);
+    }
+
+    /// Stands in for an object that only becomes visible to reads after `delay` checks.
+    struct InconsistentObject {
+        checks: std::sync::atomic::AtomicU32,
+        delay: u32,
+    }
+
+    impl InconsistentObject {
+        async fn exists(&self) -> Result<bool> {
+            let n = self
+                .checks
+                .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Ok(n >= self.delay)
+        }
+
+        fn checks(&self) -> u32 {
+            self.checks.load(std::sync::atomic::Ordering::SeqCst)
+        }
+    }
+
+    #[tokio::test]
+    async fn poll_waits_for_briefly_inconsistent_reads() {
+        let object = InconsistentObject {
+            checks: 0.into(),
+            delay: 2,
+        };
+        assert!(policy().poll(|| object.exists()).await.unwrap());
+        assert_eq!(object.checks(), 3);
+    }
+
+    #[tokio::test]
+    async fn poll_gives_up_after_max_checks() {
+        let object = InconsistentObject {
+            checks: 0.into(),
+            delay: 10,
+        };
+        assert!(!policy().poll(|| object.exists()).await.unwrap());
+        assert_eq!(object.checks(), 3);
+    }
+
+    #[tokio::test]
+    async fn retry_skips_non_retryable_errors() {
+        let client = MockClient {
+            calls: 0.into(),
+            failures: 2,
+            retryable: false,
+        };
+        let result = policy().retry(|| client.call()).await;
+        assert!(result.is_err());
+        assert_eq!(client.calls(), 1);
+    }
+}